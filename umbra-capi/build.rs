@@ -0,0 +1,16 @@
+use std::env;
+use std::path::PathBuf;
+
+// Regenerates include/umbra.h, which is checked in so C users need no Rust
+// toolchain to read it
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("Unable to read cbindgen.toml");
+    cbindgen::generate_with_config(&crate_dir, config)
+        .expect("Unable to generate C header")
+        .write_to_file(crate_dir.join("include/umbra.h"));
+}
@@ -0,0 +1,216 @@
+#ifndef UMBRA_H
+#define UMBRA_H
+
+/* Generated by cbindgen from umbra-capi. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of every fallible call. Details of the last failure on the
+ * calling thread are available from `umbra_last_error`.
+ */
+typedef enum UmbraStatus {
+  UMBRA_STATUS_OK = 0,
+  /**
+   * A required pointer was null.
+   */
+  UMBRA_STATUS_NULL_ARGUMENT = 1,
+  /**
+   * A string was not valid UTF-8.
+   */
+  UMBRA_STATUS_INVALID_UTF8 = 2,
+  /**
+   * No conversation with the given id.
+   */
+  UMBRA_STATUS_NOT_FOUND = 3,
+  /**
+   * The SDK reported an error.
+   */
+  UMBRA_STATUS_FAILED = 4,
+} UmbraStatus;
+
+typedef enum UmbraEventKind {
+  /**
+   * `convo_id` was created, locally or from an invite.
+   */
+  UMBRA_EVENT_KIND_NEW_CONVERSATION = 0,
+  /**
+   * Content arrived in `convo_id`, as `domain`/`tag` and `data`.
+   */
+  UMBRA_EVENT_KIND_CONTENT_RECEIVED = 1,
+  /**
+   * `message_id` in `convo_id` changed to `status`.
+   */
+  UMBRA_EVENT_KIND_DELIVERY_UPDATE = 2,
+  /**
+   * An invite arrived; `text` lists the participants, one per line.
+   */
+  UMBRA_EVENT_KIND_INVITE_RECEIVED = 3,
+  /**
+   * The relay connection is up.
+   */
+  UMBRA_EVENT_KIND_CONNECTED = 4,
+  /**
+   * The relay connection is down; `text` gives the reason.
+   */
+  UMBRA_EVENT_KIND_DISCONNECTED = 5,
+  /**
+   * A frame this version cannot interpret arrived in `convo_id`.
+   */
+  UMBRA_EVENT_KIND_UNKNOWN_FRAME = 6,
+} UmbraEventKind;
+
+typedef enum UmbraDeliveryStatus {
+  UMBRA_DELIVERY_STATUS_PENDING = 0,
+  UMBRA_DELIVERY_STATUS_SENT = 1,
+  UMBRA_DELIVERY_STATUS_DELIVERED = 2,
+  UMBRA_DELIVERY_STATUS_FAILED = 3,
+  UMBRA_DELIVERY_STATUS_EXPIRED = 4,
+} UmbraDeliveryStatus;
+
+/**
+ * A client and its event stream.
+ */
+typedef struct UmbraClient UmbraClient;
+
+/**
+ * An event from `umbra_poll_event`. Fields which do not apply to `kind`
+ * are null or zero. Release with `umbra_event_free`.
+ */
+typedef struct UmbraEvent {
+  enum UmbraEventKind kind;
+  char *convo_id;
+  char *message_id;
+  char *text;
+  uint32_t domain;
+  uint32_t tag;
+  uint8_t *data;
+  size_t data_len;
+  /**
+   * Sender's clock in unix milliseconds, or 0 if not stamped.
+   */
+  uint64_t sent_at;
+  /**
+   * Local clock in unix milliseconds.
+   */
+  uint64_t received_at;
+  enum UmbraDeliveryStatus status;
+} UmbraEvent;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Message describing the last failure on the calling thread, or null.
+ * Valid until the next failing call on the same thread.
+ */
+const char *umbra_last_error(void);
+
+/**
+ * Create a client for `addr`, connected to the relay at `relay_url`, and
+ * start receiving. Returns null on failure, with `umbra_last_error` set if
+ * `addr` is not a valid address.
+ *
+ * # Safety
+ *
+ * `addr` and `relay_url` must be valid nul terminated strings.
+ */
+struct UmbraClient *umbra_client_new(const char *addr, const char *relay_url);
+
+/**
+ * Release a client. Its threads keep running until the process exits.
+ *
+ * # Safety
+ *
+ * `client` must come from `umbra_client_new` and not be used afterwards.
+ */
+void umbra_client_free(struct UmbraClient *client);
+
+/**
+ * The client's address. Release with `umbra_string_free`.
+ *
+ * # Safety
+ *
+ * `client` must be a live client.
+ */
+char *umbra_client_address(const struct UmbraClient *client);
+
+/**
+ * Invite `peer` to a new private conversation. On success its id is
+ * written to `convo_id_out`, to be released with `umbra_string_free`.
+ *
+ * # Safety
+ *
+ * `client` must be a live client, `peer` a valid nul terminated string and
+ * `convo_id_out` null or writable.
+ */
+enum UmbraStatus umbra_conversation_create(struct UmbraClient *client,
+                                           const char *peer,
+                                           char **convo_id_out);
+
+/**
+ * Send `len` bytes of content of type `domain`/`tag` to a conversation.
+ *
+ * # Safety
+ *
+ * `client` must be a live client, `convo_id` a valid nul terminated string
+ * and `data` valid for `len` bytes, or null if `len` is 0.
+ */
+enum UmbraStatus umbra_send(struct UmbraClient *client,
+                            const char *convo_id,
+                            uint32_t domain,
+                            uint32_t tag,
+                            const uint8_t *data,
+                            size_t len);
+
+/**
+ * Send a text message to a conversation.
+ *
+ * # Safety
+ *
+ * `client` must be a live client, `convo_id` and `text` valid nul
+ * terminated strings.
+ */
+enum UmbraStatus umbra_send_text(struct UmbraClient *client,
+                                 const char *convo_id,
+                                 const char *text);
+
+/**
+ * Take the next pending event without blocking. Returns false, leaving
+ * `event` untouched, if there is none.
+ *
+ * # Safety
+ *
+ * `client` must be a live client and `event` writable.
+ */
+bool umbra_poll_event(const struct UmbraClient *client, struct UmbraEvent *event);
+
+/**
+ * Release the strings and data held by an event filled by
+ * `umbra_poll_event`. The struct itself belongs to the caller.
+ *
+ * # Safety
+ *
+ * `event` must have been filled by `umbra_poll_event` and not freed.
+ */
+void umbra_event_free(struct UmbraEvent *event);
+
+/**
+ * Release a string returned by this library.
+ *
+ * # Safety
+ *
+ * `s` must come from this library and not be freed already.
+ */
+void umbra_string_free(char *s);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* UMBRA_H */
@@ -0,0 +1,480 @@
+//! C ABI for the Umbra SDK.
+//!
+//! Clients are opaque handles connected to an Umbra relay over WebSocket.
+//! Conversations are referred to by id. Events are pulled with
+//! `umbra_poll_event` rather than delivered through callbacks, so callers
+//! need no thread-safe callback plumbing. Strings and buffers returned by
+//! this library are owned by the caller and released with the matching
+//! `*_free` function. See `include/umbra.h`.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::ptr;
+use std::sync::Mutex;
+use std::sync::mpsc::Receiver;
+
+use umbra_content_types::ChatMessage;
+use umbra_sdk::UmbraEvent as SdkEvent;
+use umbra_sdk::{
+    ConnectionState, ContentType, Conversation, ConversationId, DeliveryStatus, UmbraError,
+    WebSocketDeliveryService,
+};
+
+type SdkClient = umbra_sdk::UmbraClient<WebSocketDeliveryService>;
+type SdkConversation = dyn Conversation<WebSocketDeliveryService> + Send + Sync;
+
+/// Result of every fallible call. Details of the last failure on the
+/// calling thread are available from `umbra_last_error`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UmbraStatus {
+    Ok = 0,
+    /// A required pointer was null.
+    NullArgument = 1,
+    /// A string was not valid UTF-8.
+    InvalidUtf8 = 2,
+    /// No conversation with the given id.
+    NotFound = 3,
+    /// The SDK reported an error.
+    Failed = 4,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UmbraEventKind {
+    /// `convo_id` was created, locally or from an invite.
+    NewConversation = 0,
+    /// Content arrived in `convo_id`, as `domain`/`tag` and `data`.
+    ContentReceived = 1,
+    /// `message_id` in `convo_id` changed to `status`.
+    DeliveryUpdate = 2,
+    /// An invite arrived; `text` lists the participants, one per line.
+    InviteReceived = 3,
+    /// The relay connection is up.
+    Connected = 4,
+    /// The relay connection is down; `text` gives the reason.
+    Disconnected = 5,
+    /// A frame this version cannot interpret arrived in `convo_id`.
+    UnknownFrame = 6,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UmbraDeliveryStatus {
+    Pending = 0,
+    Sent = 1,
+    Delivered = 2,
+    Failed = 3,
+    Expired = 4,
+}
+
+/// An event from `umbra_poll_event`. Fields which do not apply to `kind`
+/// are null or zero. Release with `umbra_event_free`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct UmbraEvent {
+    pub kind: UmbraEventKind,
+    pub convo_id: *mut c_char,
+    pub message_id: *mut c_char,
+    pub text: *mut c_char,
+    pub domain: u32,
+    pub tag: u32,
+    pub data: *mut u8,
+    pub data_len: usize,
+    /// Sender's clock in unix milliseconds, or 0 if not stamped.
+    pub sent_at: u64,
+    /// Local clock in unix milliseconds.
+    pub received_at: u64,
+    pub status: UmbraDeliveryStatus,
+}
+
+/// A client and its event stream.
+pub struct UmbraClient {
+    client: SdkClient,
+    events: Mutex<Receiver<SdkEvent>>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn fail(status: UmbraStatus, message: impl ToString) -> UmbraStatus {
+    // Interior nul bytes cannot be represented; drop them
+    let message = message.to_string().replace('\0', "");
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(message).ok());
+    status
+}
+
+fn sdk_error(e: UmbraError) -> UmbraStatus {
+    fail(UmbraStatus::Failed, e)
+}
+
+// Borrows a C string argument
+unsafe fn str_arg<'a>(ptr: *const c_char) -> Result<&'a str, UmbraStatus> {
+    if ptr.is_null() {
+        return Err(fail(UmbraStatus::NullArgument, "null string argument"));
+    }
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .map_err(|e| fail(UmbraStatus::InvalidUtf8, e))
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    CString::new(s.replace('\0', ""))
+        .map(CString::into_raw)
+        .unwrap_or(ptr::null_mut())
+}
+
+fn into_c_bytes(bytes: Vec<u8>) -> (*mut u8, usize) {
+    let len = bytes.len();
+    (Box::into_raw(bytes.into_boxed_slice()).cast(), len)
+}
+
+impl UmbraEvent {
+    fn new(kind: UmbraEventKind) -> Self {
+        Self {
+            kind,
+            convo_id: ptr::null_mut(),
+            message_id: ptr::null_mut(),
+            text: ptr::null_mut(),
+            domain: 0,
+            tag: 0,
+            data: ptr::null_mut(),
+            data_len: 0,
+            sent_at: 0,
+            received_at: 0,
+            status: UmbraDeliveryStatus::Pending,
+        }
+    }
+}
+
+impl From<SdkEvent> for UmbraEvent {
+    fn from(event: SdkEvent) -> Self {
+        match event {
+            SdkEvent::NewConversation(info) => UmbraEvent {
+                convo_id: into_c_string(info.convo_id.into()),
+                ..Self::new(UmbraEventKind::NewConversation)
+            },
+            SdkEvent::ContentReceived {
+                convo_id,
+                content,
+                sent_at,
+                received_at,
+            } => {
+                let (data, data_len) = into_c_bytes(content.bytes);
+                UmbraEvent {
+                    convo_id: into_c_string(convo_id),
+                    domain: content.domain,
+                    tag: content.tag,
+                    data,
+                    data_len,
+                    sent_at: sent_at.unwrap_or(0),
+                    received_at,
+                    ..Self::new(UmbraEventKind::ContentReceived)
+                }
+            }
+            SdkEvent::DeliveryUpdate(update) => UmbraEvent {
+                convo_id: into_c_string(update.convo_id),
+                message_id: into_c_string(update.message_id),
+                status: match update.status {
+                    DeliveryStatus::Pending => UmbraDeliveryStatus::Pending,
+                    DeliveryStatus::Sent => UmbraDeliveryStatus::Sent,
+                    DeliveryStatus::Delivered => UmbraDeliveryStatus::Delivered,
+                    DeliveryStatus::Failed => UmbraDeliveryStatus::Failed,
+                    DeliveryStatus::Expired => UmbraDeliveryStatus::Expired,
+                },
+                ..Self::new(UmbraEventKind::DeliveryUpdate)
+            },
+            SdkEvent::InviteReceived { participants } => UmbraEvent {
+                text: into_c_string(participants.join("\n")),
+                ..Self::new(UmbraEventKind::InviteReceived)
+            },
+            SdkEvent::ConnectionState(ConnectionState::Disconnected { reason }) => UmbraEvent {
+                text: into_c_string(reason),
+                ..Self::new(UmbraEventKind::Disconnected)
+            },
+            SdkEvent::ConnectionState(_) => Self::new(UmbraEventKind::Connected),
+            SdkEvent::UnknownFrame(frame) => {
+                let (data, data_len) = into_c_bytes(frame.bytes);
+                UmbraEvent {
+                    convo_id: into_c_string(frame.convo_id),
+                    message_id: into_c_string(frame.message_id),
+                    tag: frame.tag.unwrap_or(0),
+                    data,
+                    data_len,
+                    ..Self::new(UmbraEventKind::UnknownFrame)
+                }
+            }
+        }
+    }
+}
+
+/// Message describing the last failure on the calling thread, or null.
+/// Valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn umbra_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+/// Create a client for `addr`, connected to the relay at `relay_url`, and
+/// start receiving. Returns null on failure, with `umbra_last_error` set if
+/// `addr` is not a valid address.
+///
+/// # Safety
+///
+/// `addr` and `relay_url` must be valid nul terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn umbra_client_new(
+    addr: *const c_char,
+    relay_url: *const c_char,
+) -> *mut UmbraClient {
+    let (Ok(addr), Ok(relay_url)) = (unsafe { str_arg(addr) }, unsafe { str_arg(relay_url) })
+    else {
+        return ptr::null_mut();
+    };
+    let addr = match addr.parse() {
+        Ok(addr) => addr,
+        Err(e) => {
+            sdk_error(e);
+            return ptr::null_mut();
+        }
+    };
+    let mut client = SdkClient::new(WebSocketDeliveryService::connect(relay_url), addr);
+    // Subscribed before starting, so no event is missed
+    let events = Mutex::new(client.events());
+    client.start();
+    Box::into_raw(Box::new(UmbraClient { client, events }))
+}
+
+/// Release a client. Its threads keep running until the process exits.
+///
+/// # Safety
+///
+/// `client` must come from `umbra_client_new` and not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn umbra_client_free(client: *mut UmbraClient) {
+    if !client.is_null() {
+        drop(unsafe { Box::from_raw(client) });
+    }
+}
+
+/// The client's address. Release with `umbra_string_free`.
+///
+/// # Safety
+///
+/// `client` must be a live client.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn umbra_client_address(client: *const UmbraClient) -> *mut c_char {
+    match unsafe { client.as_ref() } {
+        Some(client) => into_c_string(client.client.address().into()),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Invite `peer` to a new private conversation. On success its id is
+/// written to `convo_id_out`, to be released with `umbra_string_free`.
+///
+/// # Safety
+///
+/// `client` must be a live client, `peer` a valid nul terminated string and
+/// `convo_id_out` null or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn umbra_conversation_create(
+    client: *mut UmbraClient,
+    peer: *const c_char,
+    convo_id_out: *mut *mut c_char,
+) -> UmbraStatus {
+    let Some(client) = (unsafe { client.as_ref() }) else {
+        return fail(UmbraStatus::NullArgument, "null client");
+    };
+    let peer = match unsafe { str_arg(peer) } {
+        Ok(peer) => peer,
+        Err(status) => return status,
+    };
+    let convo = match peer
+        .parse()
+        .and_then(|peer| client.client.create_private_conversation(peer))
+    {
+        Ok(convo) => convo,
+        Err(e) => return sdk_error(e),
+    };
+    if !convo_id_out.is_null() {
+        let convo_id = convo.lock().unwrap().convo_id();
+        unsafe { *convo_id_out = into_c_string(convo_id.into()) };
+    }
+    UmbraStatus::Ok
+}
+
+/// Send `len` bytes of content of type `domain`/`tag` to a conversation.
+///
+/// # Safety
+///
+/// `client` must be a live client, `convo_id` a valid nul terminated string
+/// and `data` valid for `len` bytes, or null if `len` is 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn umbra_send(
+    client: *mut UmbraClient,
+    convo_id: *const c_char,
+    domain: u32,
+    tag: u32,
+    data: *const u8,
+    len: usize,
+) -> UmbraStatus {
+    if data.is_null() && len > 0 {
+        return fail(UmbraStatus::NullArgument, "null data");
+    }
+    let bytes = match len {
+        0 => vec![],
+        _ => unsafe { std::slice::from_raw_parts(data, len) }.to_vec(),
+    };
+    unsafe {
+        with_conversation(client, convo_id, |convo| {
+            convo.send(ContentType::new(domain, tag), bytes)
+        })
+    }
+}
+
+/// Send a text message to a conversation.
+///
+/// # Safety
+///
+/// `client` must be a live client, `convo_id` and `text` valid nul
+/// terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn umbra_send_text(
+    client: *mut UmbraClient,
+    convo_id: *const c_char,
+    text: *const c_char,
+) -> UmbraStatus {
+    let text = match unsafe { str_arg(text) } {
+        Ok(text) => text,
+        Err(status) => return status,
+    };
+    unsafe {
+        with_conversation(client, convo_id, |convo| {
+            convo.send_typed(&ChatMessage::new(text.into()))
+        })
+    }
+}
+
+unsafe fn with_conversation<R>(
+    client: *mut UmbraClient,
+    convo_id: *const c_char,
+    f: impl FnOnce(&mut SdkConversation) -> Result<R, UmbraError>,
+) -> UmbraStatus {
+    let Some(client) = (unsafe { client.as_ref() }) else {
+        return fail(UmbraStatus::NullArgument, "null client");
+    };
+    let convo_id = match unsafe { str_arg(convo_id) } {
+        Ok(convo_id) => convo_id,
+        Err(status) => return status,
+    };
+    let convo = convo_id
+        .parse::<ConversationId>()
+        .ok()
+        .and_then(|id| client.client.get_conversation(&id));
+    let Some(convo) = convo else {
+        return fail(UmbraStatus::NotFound, format!("no conversation {convo_id}"));
+    };
+    match f(&mut *convo.lock().unwrap()) {
+        Ok(_) => UmbraStatus::Ok,
+        Err(e) => sdk_error(e),
+    }
+}
+
+/// Take the next pending event without blocking. Returns false, leaving
+/// `event` untouched, if there is none.
+///
+/// # Safety
+///
+/// `client` must be a live client and `event` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn umbra_poll_event(
+    client: *const UmbraClient,
+    event: *mut UmbraEvent,
+) -> bool {
+    let Some(client) = (unsafe { client.as_ref() }) else {
+        return false;
+    };
+    if event.is_null() {
+        return false;
+    }
+    match client.events.lock().unwrap().try_recv() {
+        Ok(next) => {
+            unsafe { event.write(next.into()) };
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Release the strings and data held by an event filled by
+/// `umbra_poll_event`. The struct itself belongs to the caller.
+///
+/// # Safety
+///
+/// `event` must have been filled by `umbra_poll_event` and not freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn umbra_event_free(event: *mut UmbraEvent) {
+    let Some(event) = (unsafe { event.as_mut() }) else {
+        return;
+    };
+    for s in [&mut event.convo_id, &mut event.message_id, &mut event.text] {
+        unsafe { umbra_string_free(std::mem::replace(s, ptr::null_mut())) };
+    }
+    if !event.data.is_null() {
+        let data = ptr::slice_from_raw_parts_mut(event.data, event.data_len);
+        drop(unsafe { Box::from_raw(data) });
+        event.data = ptr::null_mut();
+        event.data_len = 0;
+    }
+}
+
+/// Release a string returned by this library.
+///
+/// # Safety
+///
+/// `s` must come from this library and not be freed already.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn umbra_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use umbra_sdk::ContentFrame;
+
+    use super::*;
+
+    #[test]
+    fn events_own_their_buffers() {
+        let mut event = UmbraEvent::from(SdkEvent::ContentReceived {
+            convo_id: "convo".into(),
+            content: ContentFrame {
+                domain: 1,
+                tag: 2,
+                bytes: vec![1, 2, 3],
+            },
+            sent_at: None,
+            received_at: 5,
+        });
+        assert_eq!(event.kind, UmbraEventKind::ContentReceived);
+        let convo_id = unsafe { CStr::from_ptr(event.convo_id) };
+        assert_eq!(convo_id.to_str().unwrap(), "convo");
+        let data = unsafe { std::slice::from_raw_parts(event.data, event.data_len) };
+        assert_eq!(data, [1, 2, 3]);
+
+        unsafe { umbra_event_free(&mut event) };
+        assert!(event.convo_id.is_null() && event.data.is_null());
+    }
+
+    #[test]
+    fn reports_null_arguments() {
+        let status = unsafe { umbra_send_text(ptr::null_mut(), ptr::null(), ptr::null()) };
+        assert_eq!(status, UmbraStatus::NullArgument);
+        let message = unsafe { CStr::from_ptr(umbra_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "null string argument");
+    }
+}
@@ -2,6 +2,12 @@ extern crate protoc_rust;
 
 use std::io::Result;
 fn main() -> Result<()> {
-    prost_build::compile_protos(&["protos/content.proto"], &["protos/"])?;
+    let mut config = prost_build::Config::new();
+    if std::env::var_os("CARGO_FEATURE_SERDE").is_some() {
+        config.type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]");
+        // Fields missing from the JSON take their protobuf defaults
+        config.message_attribute(".", "#[serde(default)]");
+    }
+    config.compile_protos(&["protos/content.proto"], &["protos/"])?;
     Ok(())
 }
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use prost::Message;
 use types::*;
 
@@ -5,8 +7,50 @@ pub mod types {
     include!(concat!(env!("OUT_DIR"), "/umbra.contenttypes.rs"));
 }
 
+/// Domain of the content types defined in this crate.
+pub const STANDARD_DOMAIN: u32 = 0;
+
+/// Domains below this are reserved for the SDK. Application defined content
+/// types pick a domain of their own at or above it, so their tags cannot
+/// collide with SDK types.
+pub const FIRST_CUSTOM_DOMAIN: u32 = 1 << 16;
+
+/// Identifies a content type on the wire: `tag` is scoped to `domain`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ContentType {
+    pub domain: u32,
+    pub tag: u32,
+}
+
+impl ContentType {
+    pub const fn new(domain: u32, tag: u32) -> Self {
+        Self { domain, tag }
+    }
+
+    pub fn is_reserved(&self) -> bool {
+        self.domain < FIRST_CUSTOM_DOMAIN
+    }
+}
+
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.domain, self.tag)
+    }
+}
+
 pub trait TaggedContent {
+    /// Namespace of `TAG`. Types defined outside this crate must override it
+    /// with a domain at or above `FIRST_CUSTOM_DOMAIN`.
+    const DOMAIN: u32 = STANDARD_DOMAIN;
     const TAG: u32;
+    const CONTENT_TYPE: ContentType = ContentType::new(Self::DOMAIN, Self::TAG);
+
+    /// Plain text shown instead of this content by clients which cannot
+    /// decode it, e.g. older versions of the app.
+    fn fallback(&self) -> Option<String> {
+        None
+    }
 }
 
 impl ChatMessage {
@@ -19,6 +63,42 @@ impl TaggedContent for ChatMessage {
     const TAG: u32 = ContentTags::ContentTagChatMessage as u32;
 }
 
+impl TaggedContent for RemoteAttachment {
+    const TAG: u32 = ContentTags::ContentTagRemoteAttachment as u32;
+}
+
+impl TaggedContent for ImageMessage {
+    const TAG: u32 = ContentTags::ContentTagImageMessage as u32;
+}
+
+impl TaggedContent for VideoMessage {
+    const TAG: u32 = ContentTags::ContentTagVideoMessage as u32;
+}
+
+impl TaggedContent for Profile {
+    const TAG: u32 = ContentTags::ContentTagProfile as u32;
+}
+
+impl TaggedContent for Location {
+    const TAG: u32 = ContentTags::ContentTagLocation as u32;
+}
+
+impl TaggedContent for LiveLocation {
+    const TAG: u32 = ContentTags::ContentTagLiveLocation as u32;
+}
+
+impl TaggedContent for Poll {
+    const TAG: u32 = ContentTags::ContentTagPoll as u32;
+}
+
+impl TaggedContent for PollVote {
+    const TAG: u32 = ContentTags::ContentTagPollVote as u32;
+}
+
+impl TaggedContent for CompositeContent {
+    const TAG: u32 = ContentTags::ContentTagCompositeContent as u32;
+}
+
 impl From<ChatMessage> for Vec<u8> {
     fn from(msg: ChatMessage) -> Self {
         msg.encode_to_vec()
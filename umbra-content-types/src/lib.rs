@@ -2,6 +2,9 @@ pub mod content_types;
 
 // pub use prost::Message;
 
-pub use crate::content_types::TaggedContent;
-pub use content_types::types::ChatMessage;
+pub use crate::content_types::{ContentType, FIRST_CUSTOM_DOMAIN, STANDARD_DOMAIN, TaggedContent};
+pub use content_types::types::{
+    ChatMessage, CompositeContent, ImageMessage, LiveLocation, Location, Poll, PollVote, Profile,
+    RemoteAttachment, VideoMessage,
+};
 pub use prost::Message; // TODO: remove this
@@ -0,0 +1,21 @@
+import { EventEmitter } from 'events'
+import type { ClientEvent } from './binding'
+
+export type { ClientEvent }
+
+export type EventKind = 'conversation' | 'message' | 'delivery' | 'invite' | 'connection' | 'unknown'
+
+export declare class Client extends EventEmitter {
+  constructor(address: string, relayUrl: string)
+  get address(): string
+  start(): this
+  createConversation(peer: string): Promise<string>
+  sendText(convoId: string, text: string): Promise<void>
+  send(convoId: string, domain: number, tag: number, data: Uint8Array): Promise<void>
+  conversations(): string[]
+  next(kind: EventKind | 'event'): Promise<ClientEvent>
+
+  on(kind: EventKind | 'event', listener: (event: ClientEvent) => void): this
+  once(kind: EventKind | 'event', listener: (event: ClientEvent) => void): this
+  off(kind: EventKind | 'event', listener: (event: ClientEvent) => void): this
+}
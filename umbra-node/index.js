@@ -0,0 +1,64 @@
+'use strict'
+
+const { EventEmitter } = require('events')
+const { Client: NativeClient } = require('./binding.js')
+
+// Event kinds reported by the native client, emitted under the same name
+const KINDS = ['conversation', 'message', 'delivery', 'invite', 'connection', 'unknown']
+
+/**
+ * An Umbra client connected to a relay over WebSocket.
+ *
+ * Emits one event per kind, with the event object as the only argument,
+ * plus 'event' for every event.
+ */
+class Client extends EventEmitter {
+  constructor(address, relayUrl) {
+    super()
+    this._native = new NativeClient(address, relayUrl)
+    this._started = false
+  }
+
+  get address() {
+    return this._native.address
+  }
+
+  /** Start receiving. Listeners should be attached first. */
+  start() {
+    if (this._started) {
+      return this
+    }
+    this._started = true
+    this._native.start((event) => {
+      this.emit('event', event)
+      if (KINDS.includes(event.kind)) {
+        this.emit(event.kind, event)
+      }
+    })
+    return this
+  }
+
+  /** Invite `peer` to a new private conversation, resolving to its id. */
+  async createConversation(peer) {
+    return this._native.createConversation(peer)
+  }
+
+  async sendText(convoId, text) {
+    this._native.sendText(convoId, text)
+  }
+
+  async send(convoId, domain, tag, data) {
+    this._native.send(convoId, domain, tag, Buffer.from(data))
+  }
+
+  conversations() {
+    return this._native.conversations()
+  }
+
+  /** Resolves with the next event of `kind`. */
+  next(kind) {
+    return new Promise((resolve) => this.once(kind, resolve))
+  }
+}
+
+module.exports = { Client }
@@ -0,0 +1,238 @@
+//! Node.js bindings for the Umbra SDK, built with napi-rs.
+//!
+//! The native `Client` is wrapped by `index.js`, which turns the event
+//! callback into an `EventEmitter`. Events are delivered on the JS thread
+//! from a background thread draining the client's event stream, so
+//! handlers never block the protocol.
+
+use std::sync::mpsc::Receiver;
+use std::thread;
+
+use napi::bindgen_prelude::*;
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi_derive::napi;
+use umbra_content_types::{ChatMessage, Message, TaggedContent};
+use umbra_sdk::UmbraEvent as SdkEvent;
+use umbra_sdk::{
+    ConnectionState, ContentType, Conversation, ConversationId, DeliveryStatus, UmbraError,
+    WebSocketDeliveryService,
+};
+
+type SdkClient = umbra_sdk::UmbraClient<WebSocketDeliveryService>;
+type SdkConversation = dyn Conversation<WebSocketDeliveryService> + Send + Sync;
+
+/// An event from the client. Fields which do not apply to `kind` are
+/// undefined.
+#[napi(object)]
+pub struct ClientEvent {
+    /// One of `conversation`, `message`, `delivery`, `invite`,
+    /// `connection` or `unknown`.
+    pub kind: String,
+    pub convo_id: Option<String>,
+    pub message_id: Option<String>,
+    /// Text of a chat message.
+    pub text: Option<String>,
+    pub domain: Option<u32>,
+    pub tag: Option<u32>,
+    /// Raw content, for types other than chat messages.
+    pub data: Option<Buffer>,
+    /// Sender's clock in unix milliseconds, if stamped.
+    pub sent_at: Option<i64>,
+    /// Local clock in unix milliseconds.
+    pub received_at: Option<i64>,
+    /// Delivery status: `pending`, `sent`, `delivered`, `failed` or
+    /// `expired`.
+    pub status: Option<String>,
+    pub participants: Option<Vec<String>>,
+    pub connected: Option<bool>,
+    /// Why the relay connection was lost.
+    pub reason: Option<String>,
+}
+
+impl ClientEvent {
+    fn new(kind: &str) -> Self {
+        Self {
+            kind: kind.to_string(),
+            convo_id: None,
+            message_id: None,
+            text: None,
+            domain: None,
+            tag: None,
+            data: None,
+            sent_at: None,
+            received_at: None,
+            status: None,
+            participants: None,
+            connected: None,
+            reason: None,
+        }
+    }
+}
+
+impl From<SdkEvent> for ClientEvent {
+    fn from(event: SdkEvent) -> Self {
+        match event {
+            SdkEvent::NewConversation(info) => ClientEvent {
+                convo_id: Some(info.convo_id.into()),
+                ..Self::new("conversation")
+            },
+            SdkEvent::ContentReceived {
+                convo_id,
+                content,
+                sent_at,
+                received_at,
+            } => {
+                let content_type = ContentType::new(content.domain, content.tag);
+                let text = (content_type == ChatMessage::CONTENT_TYPE)
+                    .then(|| ChatMessage::decode(content.bytes.as_slice()).ok())
+                    .flatten()
+                    .map(|m| m.text);
+                ClientEvent {
+                    convo_id: Some(convo_id),
+                    domain: Some(content.domain),
+                    tag: Some(content.tag),
+                    data: text.is_none().then(|| content.bytes.into()),
+                    text,
+                    sent_at: sent_at.map(|t| t as i64),
+                    received_at: Some(received_at as i64),
+                    ..Self::new("message")
+                }
+            }
+            SdkEvent::DeliveryUpdate(update) => ClientEvent {
+                convo_id: Some(update.convo_id),
+                message_id: Some(update.message_id),
+                status: Some(
+                    match update.status {
+                        DeliveryStatus::Pending => "pending",
+                        DeliveryStatus::Sent => "sent",
+                        DeliveryStatus::Delivered => "delivered",
+                        DeliveryStatus::Failed => "failed",
+                        DeliveryStatus::Expired => "expired",
+                    }
+                    .to_string(),
+                ),
+                ..Self::new("delivery")
+            },
+            SdkEvent::InviteReceived { participants } => ClientEvent {
+                participants: Some(participants.into_iter().map(String::from).collect()),
+                ..Self::new("invite")
+            },
+            SdkEvent::ConnectionState(ConnectionState::Disconnected { reason }) => ClientEvent {
+                connected: Some(false),
+                reason: Some(reason),
+                ..Self::new("connection")
+            },
+            SdkEvent::ConnectionState(_) => ClientEvent {
+                connected: Some(true),
+                ..Self::new("connection")
+            },
+            SdkEvent::UnknownFrame(frame) => ClientEvent {
+                convo_id: Some(frame.convo_id),
+                message_id: Some(frame.message_id),
+                tag: frame.tag,
+                data: Some(frame.bytes.into()),
+                ..Self::new("unknown")
+            },
+        }
+    }
+}
+
+fn to_napi(e: UmbraError) -> Error {
+    Error::from_reason(e.to_string())
+}
+
+/// A client connected to an Umbra relay over WebSocket.
+#[napi]
+pub struct Client {
+    client: SdkClient,
+    events: Option<Receiver<SdkEvent>>,
+}
+
+#[napi]
+impl Client {
+    /// Create a client for `addr` using the relay at `relayUrl`. Nothing is
+    /// received until `start`. Throws if `addr` is not a valid address.
+    #[napi(constructor)]
+    pub fn new(addr: String, relay_url: String) -> Result<Self> {
+        let addr = addr.parse().map_err(to_napi)?;
+        let client = SdkClient::new(WebSocketDeliveryService::connect(&relay_url), addr);
+        let events = Some(client.events());
+        Ok(Self { client, events })
+    }
+
+    #[napi(getter)]
+    pub fn address(&self) -> String {
+        self.client.address().into()
+    }
+
+    /// Start receiving, passing every event to `callback`. Can only be
+    /// called once.
+    #[napi(ts_args_type = "callback: (event: ClientEvent) => void")]
+    pub fn start(&mut self, callback: JsFunction) -> Result<()> {
+        let events = self
+            .events
+            .take()
+            .ok_or_else(|| Error::from_reason("client already started"))?;
+        let callback: ThreadsafeFunction<ClientEvent, ErrorStrategy::Fatal> =
+            callback.create_threadsafe_function(0, |ctx| Ok(vec![ctx.value]))?;
+        // Ends once the client, and so its event sender, is dropped
+        thread::spawn(move || {
+            for event in events {
+                callback.call(event.into(), ThreadsafeFunctionCallMode::NonBlocking);
+            }
+        });
+        self.client.start();
+        Ok(())
+    }
+
+    /// Invite `peer` to a new private conversation, returning its id.
+    #[napi]
+    pub fn create_conversation(&self, peer: String) -> Result<String> {
+        let convo = self
+            .client
+            .create_private_conversation(peer.parse().map_err(to_napi)?)
+            .map_err(to_napi)?;
+        let convo_id = convo.lock().unwrap().convo_id();
+        Ok(convo_id.into())
+    }
+
+    /// Send a text message to a conversation.
+    #[napi]
+    pub fn send_text(&self, convo_id: String, text: String) -> Result<()> {
+        self.with_conversation(&convo_id, |convo| convo.send_typed(&ChatMessage::new(text)))
+    }
+
+    /// Send content of type `domain`/`tag` to a conversation.
+    #[napi]
+    pub fn send(&self, convo_id: String, domain: u32, tag: u32, data: Buffer) -> Result<()> {
+        self.with_conversation(&convo_id, |convo| {
+            convo.send(ContentType::new(domain, tag), data.to_vec())
+        })
+    }
+
+    /// Ids of every conversation the client is part of.
+    #[napi]
+    pub fn conversations(&self) -> Vec<String> {
+        self.client
+            .list_conversations()
+            .into_iter()
+            .map(|info| info.convo_id.into())
+            .collect()
+    }
+}
+
+impl Client {
+    fn with_conversation<R>(
+        &self,
+        convo_id: &str,
+        f: impl FnOnce(&mut SdkConversation) -> std::result::Result<R, UmbraError>,
+    ) -> Result<()> {
+        let convo = convo_id
+            .parse::<ConversationId>()
+            .ok()
+            .and_then(|id| self.client.get_conversation(&id))
+            .ok_or_else(|| Error::from_reason(format!("no conversation {convo_id}")))?;
+        f(&mut *convo.lock().unwrap()).map_err(to_napi)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tracing::warn;
+use umbra_content_types::{ChatMessage, Message, TaggedContent};
+use umbra_sdk::{
+    Address, ConnectionState, ContentFrame, ContentType, ConversationId, DeliveryService,
+    DeliveryStatus, LocalBroker, UmbraClient, UmbraEvent,
+};
+
+/// Address of the peer started alongside an in-memory chat.
+pub const ECHO: &str = "echo";
+
+const HELP: &str = "\
+Commands:
+  /new <peer>      start a conversation with <peer> and switch to it
+  /list            list conversations
+  /open <n>        switch to conversation <n> from /list
+  /history [count] show the last messages of the current conversation
+  /whoami          show your address
+  /help            show this help
+  /quit            exit
+Anything else is sent to the current conversation.";
+
+// Peers of each conversation, for labelling incoming messages
+type Peers = Arc<Mutex<HashMap<String, String>>>;
+
+/// Start a client on `broker` which answers every chat message it receives,
+/// so the in-memory transport has someone to talk to.
+pub fn spawn_echo_peer(broker: &LocalBroker) {
+    let addr = ECHO.parse().expect("valid address");
+    let mut echo = UmbraClient::new(broker.connect(), addr);
+    let (tx, rx) = mpsc::channel();
+    echo.add_typed_handler(move |convo_id, msg: ChatMessage| {
+        let _ = tx.send((convo_id, msg.text));
+    });
+    echo.start();
+
+    // Replies are sent from here, as handlers run while the conversation is locked
+    thread::spawn(move || {
+        for (convo_id, text) in rx {
+            let convo = convo_id
+                .parse()
+                .ok()
+                .and_then(|id| echo.get_conversation(&id));
+            let Some(convo) = convo else {
+                continue;
+            };
+            let reply = ChatMessage::new(format!("you said: {text}"));
+            if let Err(e) = convo.lock().unwrap().send_typed(&reply) {
+                warn!("Echo failed to reply: {}", e);
+            }
+        }
+    });
+}
+
+/// Run the interactive session until `/quit` or the end of input.
+pub fn run<T>(mut client: UmbraClient<T>)
+where
+    T: DeliveryService + Send + Sync + 'static,
+{
+    let peers: Peers = Arc::default();
+    for info in client.list_conversations() {
+        let peer = peer_of(&client.address(), &info.participants);
+        peers.lock().unwrap().insert(info.convo_id.into(), peer);
+    }
+    // Subscribed before starting, so no event is missed
+    let events = client.events();
+    let me = client.address();
+    let live_peers = peers.clone();
+    thread::spawn(move || show_events(&me, events, &live_peers));
+    client.start();
+
+    println!(
+        "Signed in as {}. Type /help for commands.",
+        client.address()
+    );
+    let mut session = Session {
+        client,
+        peers,
+        current: None,
+    };
+    session.prompt();
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        match session.handle(line.trim()) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => println!("! {e}"),
+        }
+        session.prompt();
+    }
+}
+
+struct Session<T: DeliveryService + Send + Sync + 'static> {
+    client: UmbraClient<T>,
+    peers: Peers,
+    current: Option<ConversationId>,
+}
+
+impl<T> Session<T>
+where
+    T: DeliveryService + Send + Sync + 'static,
+{
+    // Returns false once the user asks to quit
+    fn handle(&mut self, line: &str) -> Result<bool, String> {
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        match command {
+            "" => {}
+            "/quit" | "/exit" => return Ok(false),
+            "/help" => println!("{HELP}"),
+            "/whoami" => println!("{}", self.client.address()),
+            "/new" => self.create(arg)?,
+            "/list" => self.list(),
+            "/open" => self.open(arg)?,
+            "/history" => self.history(arg)?,
+            _ if command.starts_with('/') => return Err(format!("unknown command {command}")),
+            _ => self.send(line)?,
+        }
+        Ok(true)
+    }
+
+    fn prompt(&self) {
+        match self
+            .current
+            .as_ref()
+            .and_then(|c| self.peers.lock().unwrap().get(c.as_str()).cloned())
+        {
+            Some(peer) => print!("{peer}> "),
+            None => print!("> "),
+        }
+        let _ = io::stdout().flush();
+    }
+
+    fn create(&mut self, peer: &str) -> Result<(), String> {
+        if peer.is_empty() {
+            return Err("usage: /new <peer>".into());
+        }
+        let peer = peer.parse::<Address>().map_err(|e| e.to_string())?;
+        let convo = self
+            .client
+            .create_private_conversation(peer.clone())
+            .map_err(|e| e.to_string())?;
+        let convo_id = convo.lock().unwrap().convo_id();
+        self.peers
+            .lock()
+            .unwrap()
+            .insert(convo_id.to_string(), peer.to_string());
+        self.current = Some(convo_id);
+        println!("Invited {peer}");
+        Ok(())
+    }
+
+    fn list(&self) {
+        let convos = self.client.list_conversations();
+        if convos.is_empty() {
+            println!("No conversations yet; start one with /new <peer>");
+        }
+        for (i, info) in convos.iter().enumerate() {
+            let marker = if self.current.as_ref() == Some(&info.convo_id) {
+                "*"
+            } else {
+                " "
+            };
+            let peer = peer_of(&self.client.address(), &info.participants);
+            println!("{marker}{:>3}  {peer}", i + 1);
+        }
+    }
+
+    fn open(&mut self, arg: &str) -> Result<(), String> {
+        let convos = self.client.list_conversations();
+        let info = arg
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|i| convos.get(i))
+            .ok_or_else(|| format!("no conversation {arg:?}; see /list"))?;
+        self.current = Some(info.convo_id.clone());
+        self.history("")
+    }
+
+    fn history(&self, arg: &str) -> Result<(), String> {
+        let count = match arg {
+            "" => 20,
+            _ => arg.parse().map_err(|_| "usage: /history [count]")?,
+        };
+        let convo = self.current_conversation()?;
+        let messages = convo.lock().unwrap().messages();
+        for record in messages.iter().rev().take(count).rev() {
+            let status = match record.status {
+                Some(DeliveryStatus::Delivered) => " ✓✓",
+                Some(DeliveryStatus::Sent) => " ✓",
+                Some(DeliveryStatus::Failed | DeliveryStatus::Expired) => " !",
+                Some(DeliveryStatus::Pending) | None => "",
+            };
+            println!("{}: {}{status}", record.sender, describe(&record.content));
+        }
+        Ok(())
+    }
+
+    fn send(&self, text: &str) -> Result<(), String> {
+        let convo = self.current_conversation()?;
+        convo
+            .lock()
+            .unwrap()
+            .send_typed(&ChatMessage::new(text.to_string()))
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn current_conversation(
+        &self,
+    ) -> Result<Arc<Mutex<dyn umbra_sdk::Conversation<T> + Send + Sync>>, String> {
+        let convo_id = self
+            .current
+            .as_ref()
+            .ok_or("no conversation open; use /new or /open")?;
+        self.client
+            .get_conversation(convo_id)
+            .ok_or_else(|| "conversation no longer exists".to_string())
+    }
+}
+
+// Prints events as they arrive, above the prompt
+fn show_events(me: &str, events: Receiver<UmbraEvent>, peers: &Peers) {
+    for event in events {
+        match event {
+            UmbraEvent::NewConversation(info) => {
+                let peer = peer_of(me, &info.participants);
+                let known = peers
+                    .lock()
+                    .unwrap()
+                    .insert(info.convo_id.into(), peer.clone())
+                    .is_some();
+                if !known {
+                    println!("\r* {peer} started a conversation; see /list");
+                }
+            }
+            UmbraEvent::ContentReceived {
+                convo_id, content, ..
+            } => {
+                let peer = peers.lock().unwrap().get(&convo_id).cloned();
+                let peer = peer.unwrap_or(convo_id);
+                println!("\r{peer}: {}", describe(&content));
+            }
+            UmbraEvent::DeliveryUpdate(update) if update.status == DeliveryStatus::Failed => {
+                println!("\r! message {} could not be delivered", update.message_id);
+            }
+            UmbraEvent::ConnectionState(ConnectionState::Disconnected { reason }) => {
+                println!("\r! disconnected: {reason}");
+            }
+            _ => {}
+        }
+    }
+}
+
+fn peer_of(me: &str, participants: &[Address]) -> String {
+    let others: Vec<_> = participants.iter().filter(|p| **p != me).cloned().collect();
+    if others.is_empty() {
+        me.to_string()
+    } else {
+        others.join(", ")
+    }
+}
+
+fn describe(content: &ContentFrame) -> String {
+    if ContentType::new(content.domain, content.tag) != ChatMessage::CONTENT_TYPE {
+        return format!("[{}/{} content]", content.domain, content.tag);
+    }
+    match ChatMessage::decode(content.bytes.as_slice()) {
+        Ok(msg) => msg.text,
+        Err(_) => "[unreadable message]".to_string(),
+    }
+}
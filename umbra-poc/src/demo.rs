@@ -0,0 +1,73 @@
+use std::{thread, time::Duration};
+
+use tracing::info;
+
+use serde::{Deserialize, Serialize};
+use umbra_content_types::{ChatMessage, FIRST_CUSTOM_DOMAIN, TaggedContent};
+use umbra_sdk::{BincodeCodec, LocalBroker, UmbraClient};
+
+// User defined Message
+#[derive(Debug, Serialize, Deserialize)]
+struct UrlMessage {
+    url: String,
+    text: String,
+}
+
+impl TaggedContent for UrlMessage {
+    const DOMAIN: u32 = FIRST_CUSTOM_DOMAIN;
+    const TAG: u32 = 1;
+
+    fn fallback(&self) -> Option<String> {
+        Some(format!("{} {}", self.text, self.url))
+    }
+}
+
+/// Two in-process clients exchanging a scripted set of messages.
+pub fn run() {
+    info!("Starting Umbra POC");
+
+    // Create a Delivery Service
+    let broker = LocalBroker::new();
+    let mut amal = UmbraClient::new(broker.connect(), "amal".parse().unwrap());
+    let mut bola = UmbraClient::new(broker.connect(), "bola".parse().unwrap());
+    for (name, client) in [("Amal", &mut amal), ("Bola", &mut bola)] {
+        client
+            .register_codec::<UrlMessage, _>(BincodeCodec)
+            .unwrap();
+        client.add_typed_handler(move |convo, msg: ChatMessage| {
+            info!("{} Recv({}): {:?}", name, convo, msg)
+        });
+        client.add_typed_handler(move |convo, msg: UrlMessage| {
+            info!("{} Recv({}): {:?}", name, convo, msg)
+        });
+        client.add_conversation_handler(move |convo| {
+            info!("{} joined {}", name, convo.lock().unwrap().convo_id())
+        });
+    }
+
+    // Subscibe before starting the clients
+    let a2b = amal
+        .create_private_conversation("bola".parse().unwrap())
+        .unwrap();
+
+    amal.start();
+    bola.start();
+
+    // Give Bola time to accept the invite and subscribe to the conversation
+    thread::sleep(Duration::from_millis(500));
+
+    a2b.lock()
+        .unwrap()
+        .send_typed(&ChatMessage::new("Hello Bola!".to_string()))
+        .unwrap();
+
+    // User Defined using custom encoding
+    let url = UrlMessage {
+        url: "https://example.com".to_string(),
+        text: "Check this out!".to_string(),
+    };
+
+    a2b.lock().unwrap().send_typed(&url).unwrap();
+
+    thread::sleep(Duration::from_secs(20));
+}
@@ -1,170 +1,126 @@
-use std::{
-    sync::{Arc, Mutex},
-    thread,
-    time::Duration,
-};
+mod chat;
+mod demo;
 
-use tracing::{debug, error, info};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
 
-use serde::{Deserialize, Serialize};
-use umbra_content_types::{ChatMessage, Message, TaggedContent, content_types::types::ContentTags};
-use umbra_sdk::{Blob, ContentFrame, DeliveryService, UmbraClient};
+use clap::{Parser, Subcommand, ValueEnum};
+use tracing::Level;
+use umbra_sdk::{
+    Address, LocalBroker, SqliteMessageStore, StateStore, UmbraClient, WebSocketDeliveryService,
+};
 
-// User defined Message
-#[derive(Debug, Serialize, Deserialize)]
-struct UrlMessage {
-    url: String,
-    text: String,
-}
+#[derive(Parser)]
+#[command(name = "umbra-poc", about = "Chat over Umbra from the terminal")]
+struct Cli {
+    /// Log SDK activity, which is otherwise limited to warnings
+    #[arg(short, long, global = true)]
+    verbose: bool,
 
-impl TaggedContent for UrlMessage {
-    const TAG: u32 = 6;
+    #[command(subcommand)]
+    command: Command,
 }
 
-fn print_content(client: &str, conversation_id: String, content: ContentFrame) {
-    match content.tag {
-        val if val == ContentTags::ContentTagChatMessage as u32 => {
-            let msg: ChatMessage = content.bytes.into();
-            info!("{} Recv({}): {:?}", client, conversation_id, msg);
-        }
-        val if val == UrlMessage::TAG => {
-            let msg: UrlMessage = bincode::deserialize(&content.bytes).unwrap();
-            info!("{} Recv({}): {:?}", client, conversation_id, msg);
-        }
-        _ => {
-            info!("Unknown content type with tag: {}", content.tag);
-        }
-    }
+#[derive(Subcommand)]
+enum Command {
+    /// Create an identity, saved to a database for `chat --data`
+    Init {
+        /// Address peers use to reach you
+        name: Address,
+        /// Database to create
+        #[arg(long)]
+        data: PathBuf,
+    },
+    /// Chat interactively; type /help once started
+    Chat {
+        /// Database created by `init`, keeping conversations between runs
+        #[arg(long, conflicts_with = "name")]
+        data: Option<PathBuf>,
+        /// Address of a throwaway identity, forgotten on exit
+        #[arg(long, required_unless_present = "data")]
+        name: Option<Address>,
+        #[arg(long, value_enum, default_value_t = Transport::Memory)]
+        transport: Transport,
+        /// Relay to connect to with `--transport websocket`
+        #[arg(long, default_value = "ws://127.0.0.1:9000")]
+        relay: String,
+    },
+    /// Run the scripted two-client exchange
+    Demo,
 }
 
-struct QueueSub {
-    outbound: Vec<std::sync::mpsc::Sender<Blob>>,
-    inbound: Vec<std::sync::Arc<std::sync::Mutex<std::sync::mpsc::Receiver<Blob>>>>,
+#[derive(Clone, Copy, ValueEnum)]
+enum Transport {
+    /// In-process broker shared with a local echo peer, for trying things out
+    Memory,
+    /// Umbra relay over WebSocket
+    Websocket,
 }
 
-impl QueueSub {
-    pub fn new() -> Self {
-        QueueSub {
-            inbound: Vec::new(),
-            outbound: Vec::new(),
-        }
-    }
-
-    pub fn start(&self) {
-        let in_queues = self.inbound.clone();
-        let out_queues = self.outbound.clone();
-        thread::spawn(move || {
-            loop {
-                for rx in in_queues.iter() {
-                    let rx = rx.lock().unwrap();
-                    // This is a placeholder for actual message processing logic
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let level = match (cli.verbose, &cli.command) {
+        (true, _) | (false, Command::Demo) => Level::DEBUG,
+        (false, _) => Level::WARN,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_target(false)
+        .init();
 
-                    match rx.try_recv() {
-                        Ok(blob) => {
-                            debug!("Processing message: {:?}", blob);
-                            for tx in out_queues.iter() {
-                                if let Err(e) = tx.send(blob.clone()) {
-                                    tracing::error!("Failed to send message: {}", e);
-                                }
-                            }
-                            // Here you would typically process the message
-                        }
-                        Err(std::sync::mpsc::TryRecvError::Empty) => {
-                            // No messages to process, continue to the next queue
-                            continue;
-                        }
-                        Err(std::sync::mpsc::TryRecvError::Disconnected) => {
-                            error!("Receiver disconnected, stopping processing.");
-                            return; // Exit the loop if the receiver is disconnected
-                        }
-                    }
-                }
+    let result = match cli.command {
+        Command::Init { name, data } => init(&name, &data),
+        Command::Chat {
+            data,
+            name,
+            transport,
+            relay,
+        } => match transport {
+            Transport::Memory => {
+                let broker = LocalBroker::new();
+                chat::spawn_echo_peer(&broker);
+                open(broker.connect(), data, name).map(chat::run)
             }
-        });
-    }
-
-    pub fn register(&mut self) -> QueueSubscription {
-        let (tx, recv) = std::sync::mpsc::channel();
-        let (sender, rx) = std::sync::mpsc::channel();
-
-        self.outbound.push(tx);
-        self.inbound
-            .push(std::sync::Arc::new(std::sync::Mutex::new(rx)));
-        // This is a placeholder for actual subscription logic
-        QueueSubscription {
-            reciever: Arc::new(Mutex::new(recv)),
-            sender: Arc::new(Mutex::new(sender)),
+            Transport::Websocket => {
+                open(WebSocketDeliveryService::connect(&relay), data, name).map(chat::run)
+            }
+        },
+        Command::Demo => {
+            demo::run();
+            Ok(())
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
         }
     }
 }
 
-struct QueueSubscription {
-    reciever: Arc<Mutex<std::sync::mpsc::Receiver<Blob>>>,
-    sender: Arc<Mutex<std::sync::mpsc::Sender<Blob>>>,
-}
-
-impl DeliveryService for QueueSubscription {
-    fn send(&self, message: Blob) -> Result<(), umbra_sdk::UmbraError> {
-        debug!("Sending message: {:?}", message);
-        self.sender.lock().unwrap().send(message).map_err(|e| {
-            umbra_sdk::UmbraError::PublishError(format!("Failed to send message: {}", e))
-        })
-    }
-
-    fn recv(&self) -> Result<Option<Blob>, umbra_sdk::UmbraError> {
-        match self.reciever.lock().unwrap().try_recv() {
-            Ok(blob) => {
-                debug!("Received message: {:?}", blob);
-                Ok(Some(blob))
-            }
-            Err(std::sync::mpsc::TryRecvError::Empty) => Ok(None),
-            Err(std::sync::mpsc::TryRecvError::Disconnected) => Err(
-                umbra_sdk::UmbraError::PollError("Receiver disconnected".to_string()),
-            ),
-        }
+fn init(name: &Address, data: &PathBuf) -> Result<(), String> {
+    if data.exists() {
+        return Err(format!("{} already exists", data.display()));
     }
+    let store = SqliteMessageStore::open(data).map_err(|e| e.to_string())?;
+    store.save_address(name).map_err(|e| e.to_string())?;
+    println!("Created {} in {}", name, data.display());
+    Ok(())
 }
 
-fn main() {
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::DEBUG) // Set the maximum log level
-        .with_target(false)
-        .init();
-
-    info!("Starting Umbra POC");
-
-    // Create a Delivery Service
-    let mut queue_sub = QueueSub::new();
-    let amal_client = queue_sub.register();
-    let bola_client = queue_sub.register();
-    let mut amal = UmbraClient::new(amal_client, "amal".into());
-    let mut bola = UmbraClient::new(bola_client, "bola".into());
-    amal.add_content_handler(|convo, content_frame| print_content("Amal", convo, content_frame));
-    amal.add_content_handler(|convo, content_frame| print_content("Bola", convo, content_frame));
-
-    // Subscibe before starting the clients
-    let a2b = amal.create_private_conversation("bola".into()).unwrap();
-
-    amal.start();
-    bola.start();
-    queue_sub.start();
-
-    let msg = ChatMessage {
-        text: "Hello Bola!".to_string(),
+// Either loads the identity saved in `data` or creates a throwaway `name`
+fn open<T>(ds: T, data: Option<PathBuf>, name: Option<Address>) -> Result<UmbraClient<T>, String>
+where
+    T: umbra_sdk::DeliveryService + Send + Sync + 'static,
+{
+    match (data, name) {
+        (Some(data), _) => {
+            let store = SqliteMessageStore::open(&data).map_err(|e| e.to_string())?;
+            UmbraClient::load(ds, Arc::new(store)).map_err(|e| e.to_string())
+        }
+        (None, Some(name)) => Ok(UmbraClient::new(ds, name)),
+        (None, None) => Err("either --data or --name is required".into()),
     }
-    .encode_to_vec();
-
-    a2b.lock().unwrap().send(5, msg);
-
-    // User Defined using custom encoding
-    let url = UrlMessage {
-        url: "https://example.com".to_string(),
-        text: "Check this out!".to_string(),
-    };
-
-    a2b.lock()
-        .unwrap()
-        .send(UrlMessage::TAG, bincode::serialize(&url).unwrap());
-
-    thread::sleep(Duration::from_secs(20));
 }
@@ -0,0 +1,202 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tracing::debug;
+use umbra_sdk::RelayFrame;
+
+/// How much of each topic is kept for subscribers which join later.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Retention {
+    /// Envelopes kept per topic; 0 disables retention.
+    pub per_topic: usize,
+    /// Envelopes older than this are dropped, if set.
+    pub max_age: Option<Duration>,
+}
+
+struct Subscriber {
+    topics: HashSet<String>,
+    tx: UnboundedSender<Vec<u8>>,
+}
+
+struct Retained {
+    at: Instant,
+    frame: Vec<u8>,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    subscribers: HashMap<u64, Subscriber>,
+    retained: HashMap<String, VecDeque<Retained>>,
+}
+
+/// Topic based pub/sub shared by every connection. Publishes are delivered
+/// to every subscriber of the topic, the publisher included, as with
+/// `LocalBroker`.
+#[derive(Default)]
+pub struct Broker {
+    state: Mutex<State>,
+    retention: Retention,
+}
+
+impl Broker {
+    pub fn new(retention: Retention) -> Self {
+        Self {
+            state: Mutex::default(),
+            retention,
+        }
+    }
+
+    /// Register a connection, returning its id and the encoded frames to
+    /// write to it.
+    pub fn connect(&self) -> (u64, UnboundedReceiver<Vec<u8>>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.subscribers.insert(
+            id,
+            Subscriber {
+                topics: HashSet::new(),
+                tx,
+            },
+        );
+        (id, rx)
+    }
+
+    pub fn disconnect(&self, id: u64) {
+        self.state.lock().unwrap().subscribers.remove(&id);
+    }
+
+    /// Apply a frame received from connection `id`.
+    pub fn handle(&self, id: u64, frame: RelayFrame) {
+        let mut state = self.state.lock().unwrap();
+        match frame {
+            RelayFrame::Publish { topic, payload } => {
+                let frame = RelayFrame::Publish {
+                    topic: topic.clone(),
+                    payload,
+                }
+                .encode();
+                let subscribers = state
+                    .subscribers
+                    .values()
+                    .filter(|s| s.topics.contains(&topic));
+                let mut delivered = 0;
+                for subscriber in subscribers {
+                    // Closed connections are removed when their socket ends
+                    if subscriber.tx.send(frame.clone()).is_ok() {
+                        delivered += 1;
+                    }
+                }
+                debug!("Published to {} ({} subscribers)", topic, delivered);
+                self.retain(&mut state, topic, frame);
+            }
+            RelayFrame::Subscribe { topic } => {
+                let Some(subscriber) = state.subscribers.get_mut(&id) else {
+                    return;
+                };
+                if !subscriber.topics.insert(topic.clone()) {
+                    return;
+                }
+                let tx = subscriber.tx.clone();
+                self.expire(&mut state, &topic);
+                for retained in state.retained.get(&topic).into_iter().flatten() {
+                    let _ = tx.send(retained.frame.clone());
+                }
+            }
+            RelayFrame::Unsubscribe { topic } => {
+                if let Some(subscriber) = state.subscribers.get_mut(&id) {
+                    subscriber.topics.remove(&topic);
+                }
+            }
+        }
+    }
+
+    fn retain(&self, state: &mut State, topic: String, frame: Vec<u8>) {
+        if self.retention.per_topic == 0 {
+            return;
+        }
+        self.expire(state, &topic);
+        let retained = state.retained.entry(topic).or_default();
+        if retained.len() == self.retention.per_topic {
+            retained.pop_front();
+        }
+        retained.push_back(Retained {
+            at: Instant::now(),
+            frame,
+        });
+    }
+
+    fn expire(&self, state: &mut State, topic: &str) {
+        let (Some(max_age), Some(retained)) =
+            (self.retention.max_age, state.retained.get_mut(topic))
+        else {
+            return;
+        };
+        while retained.front().is_some_and(|r| r.at.elapsed() > max_age) {
+            retained.pop_front();
+        }
+        if retained.is_empty() {
+            state.retained.remove(topic);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn publish(topic: &str, payload: u8) -> RelayFrame {
+        RelayFrame::Publish {
+            topic: topic.into(),
+            payload: vec![payload],
+        }
+    }
+
+    fn subscribe(topic: &str) -> RelayFrame {
+        RelayFrame::Subscribe {
+            topic: topic.into(),
+        }
+    }
+
+    fn drain(rx: &mut UnboundedReceiver<Vec<u8>>) -> Vec<RelayFrame> {
+        std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|bytes| RelayFrame::decode(&bytes).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn delivers_to_subscribers_of_the_topic() {
+        let broker = Broker::default();
+        let (a, mut a_rx) = broker.connect();
+        let (b, mut b_rx) = broker.connect();
+        broker.handle(a, subscribe("/t"));
+        broker.handle(b, subscribe("/other"));
+
+        broker.handle(b, publish("/t", 1));
+
+        assert_eq!(drain(&mut a_rx), vec![publish("/t", 1)]);
+        assert!(drain(&mut b_rx).is_empty());
+    }
+
+    #[test]
+    fn replays_retained_envelopes_on_subscribe() {
+        let broker = Broker::new(Retention {
+            per_topic: 2,
+            max_age: None,
+        });
+        let (a, _) = broker.connect();
+        for payload in 1..=3 {
+            broker.handle(a, publish("/t", payload));
+        }
+
+        let (b, mut b_rx) = broker.connect();
+        broker.handle(b, subscribe("/t"));
+        broker.handle(b, subscribe("/t"));
+
+        assert_eq!(drain(&mut b_rx), vec![publish("/t", 2), publish("/t", 3)]);
+    }
+}
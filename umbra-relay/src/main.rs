@@ -0,0 +1,90 @@
+//! Relay for local development, speaking the protocol of
+//! `WebSocketDeliveryService`: each binary WebSocket message is one
+//! `RelayFrame`. There is no authentication or persistence, so it is not
+//! meant to be exposed beyond a development machine.
+
+mod broker;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Router;
+use axum::extract::State;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::response::Response;
+use axum::routing::get;
+use clap::Parser;
+use futures_util::{SinkExt, StreamExt};
+use tracing::{info, warn};
+use umbra_sdk::RelayFrame;
+
+use crate::broker::{Broker, Retention};
+
+#[derive(Parser)]
+#[command(name = "umbra-relay", about = "Umbra relay for local development")]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:9000")]
+    listen: SocketAddr,
+    /// Envelopes kept per topic and replayed to new subscribers
+    #[arg(long, default_value_t = 0)]
+    retain: usize,
+    /// Seconds after which retained envelopes are dropped
+    #[arg(long)]
+    retain_for: Option<u64>,
+}
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    tracing_subscriber::fmt().with_target(false).init();
+    let args = Args::parse();
+
+    let broker = Arc::new(Broker::new(Retention {
+        per_topic: args.retain,
+        max_age: args.retain_for.map(Duration::from_secs),
+    }));
+    let app = Router::new().route("/", get(upgrade)).with_state(broker);
+
+    let listener = tokio::net::TcpListener::bind(args.listen).await?;
+    info!("Relay listening on ws://{}", listener.local_addr()?);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+}
+
+async fn upgrade(ws: WebSocketUpgrade, State(broker): State<Arc<Broker>>) -> Response {
+    ws.on_upgrade(move |socket| serve(socket, broker))
+}
+
+async fn serve(socket: WebSocket, broker: Arc<Broker>) {
+    let (id, mut outgoing) = broker.connect();
+    info!("Connection {} opened", id);
+    let (mut sink, mut stream) = socket.split();
+
+    let writer = tokio::spawn(async move {
+        while let Some(frame) = outgoing.recv().await {
+            if sink.send(Message::Binary(frame)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // Pings are answered by the socket itself
+    while let Some(Ok(message)) = stream.next().await {
+        match message {
+            Message::Binary(bytes) => match RelayFrame::decode(&bytes) {
+                Ok(frame) => broker.handle(id, frame),
+                Err(e) => warn!("Dropping malformed frame from {}: {}", id, e),
+            },
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+
+    broker.disconnect(id);
+    writer.abort();
+    info!("Connection {} closed", id);
+}
@@ -0,0 +1,199 @@
+//! Throughput of the send and receive pipeline at a range of payload sizes.
+//!
+//! Run with `cargo bench -p umbra-sdk`.
+
+use std::io;
+use std::sync::{Arc, mpsc};
+use std::time::Duration;
+
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use prost::Message;
+use umbra_content_types::ChatMessage;
+use umbra_sdk::fixtures::{ContentFrameBuilder, EnvelopeBuilder, ReliableBytesBuilder};
+use umbra_sdk::{
+    Address, Blob, BlobStore, ConversationId, DeliveryService, InMemoryBlobStore, LocalBroker,
+    UmbraClient, UmbraError,
+};
+use umbra_types::base::UmbraEnvelopeV1;
+
+const KIB: usize = 1024;
+const FILE: &str = "file.bin";
+const MIME: &str = "application/octet-stream";
+
+// Conversation content stays under the default content size limit
+const CONTENT_SIZES: [usize; 4] = [64, KIB, 16 * KIB, 128 * KIB];
+const ATTACHMENT_SIZES: [usize; 3] = [64 * KIB, 1024 * KIB, 10 * 1024 * KIB];
+
+/// Drops everything it is given, so only the client's own work is measured.
+struct NullDeliveryService;
+
+impl DeliveryService for NullDeliveryService {
+    fn send(&self, _message: Blob) -> Result<(), UmbraError> {
+        Ok(())
+    }
+
+    fn recv(&self) -> Result<Option<Blob>, UmbraError> {
+        Ok(None)
+    }
+}
+
+fn addr(name: &str) -> Address {
+    name.parse().unwrap()
+}
+
+struct NullBlobStore;
+
+impl BlobStore for NullBlobStore {
+    fn upload(&self, _bytes: &[u8]) -> Result<String, UmbraError> {
+        Ok("null://".into())
+    }
+
+    fn download(&self, url: &str) -> Result<Blob, UmbraError> {
+        Err(UmbraError::StorageError(format!("no blob at {url}").into()))
+    }
+
+    fn upload_reader(&self, reader: &mut dyn io::Read) -> Result<String, UmbraError> {
+        io::copy(reader, &mut io::sink()).map_err(|e| UmbraError::StorageError(e.into()))?;
+        Ok("null://".into())
+    }
+}
+
+fn text(size: usize) -> ChatMessage {
+    ChatMessage::new("a".repeat(size))
+}
+
+fn envelope(size: usize) -> Blob {
+    let addrs = vec![addr("amal"), addr("bola")];
+    let frame = ContentFrameBuilder::new()
+        .bytes(text(size).encode_to_vec())
+        .build();
+    EnvelopeBuilder::private(addrs.clone())
+        .reliable(
+            ReliableBytesBuilder::new(ConversationId::private(&addrs))
+                .sender(addrs[0].clone())
+                .content(frame)
+                .build(),
+        )
+        .encode()
+}
+
+fn envelopes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("envelope");
+    for size in CONTENT_SIZES {
+        group.throughput(Throughput::Bytes(size as u64));
+        let bytes = envelope(size);
+        let decoded = UmbraEnvelopeV1::decode(bytes.as_slice()).unwrap();
+        group.bench_with_input(BenchmarkId::new("encode", size), &decoded, |b, envelope| {
+            b.iter(|| envelope.encode_to_vec())
+        });
+        group.bench_with_input(BenchmarkId::new("decode", size), &bytes, |b, bytes| {
+            b.iter(|| UmbraEnvelopeV1::decode(bytes.as_slice()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn encryption(c: &mut Criterion) {
+    let mut group = c.benchmark_group("attachment");
+    group.sample_size(20);
+    for size in ATTACHMENT_SIZES {
+        group.throughput(Throughput::Bytes(size as u64));
+        let file = vec![7u8; size];
+
+        let sink = UmbraClient::builder(NullDeliveryService, addr("amal"))
+            .blob_store(Arc::new(NullBlobStore))
+            .build();
+        group.bench_with_input(BenchmarkId::new("encrypt", size), &file, |b, file| {
+            b.iter(|| sink.upload_attachment(file, FILE, MIME))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("encrypt_stream", size),
+            &file,
+            |b, file| b.iter(|| sink.upload_attachment_stream(file.as_slice(), FILE, MIME)),
+        );
+
+        let store = UmbraClient::builder(NullDeliveryService, addr("amal"))
+            .blob_store(Arc::new(InMemoryBlobStore::new()))
+            .build();
+        let whole = store.upload_attachment(&file, FILE, MIME).unwrap();
+        let streamed = store
+            .upload_attachment_stream(file.as_slice(), FILE, MIME)
+            .unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("decrypt", size),
+            &whole,
+            |b, attachment| b.iter(|| store.download_attachment(attachment).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("decrypt_stream", size),
+            &streamed,
+            |b, attachment| {
+                b.iter(|| {
+                    let mut reader = store.download_attachment_stream(attachment).unwrap();
+                    io::copy(&mut reader, &mut io::sink()).unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn send(c: &mut Criterion) {
+    let mut group = c.benchmark_group("send");
+    for size in CONTENT_SIZES {
+        group.throughput(Throughput::Bytes(size as u64));
+        let message = text(size);
+        // A fresh conversation each time, so stored history does not grow
+        // across iterations
+        group.bench_with_input(BenchmarkId::from_parameter(size), &message, |b, message| {
+            b.iter_batched(
+                || {
+                    let client = UmbraClient::new(NullDeliveryService, addr("amal"));
+                    let convo = client.create_private_conversation(addr("bola")).unwrap();
+                    (client, convo)
+                },
+                // Returned, so dropping the client is not timed
+                |(client, convo)| {
+                    let id = convo.lock().unwrap().send_typed(message).unwrap();
+                    (client, convo, id)
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn round_trip(c: &mut Criterion) {
+    let broker = LocalBroker::new();
+    let mut amal = UmbraClient::new(broker.connect(), addr("amal"));
+    let mut bola = UmbraClient::new(broker.connect(), addr("bola"));
+    let (tx, rx) = mpsc::channel();
+    bola.add_typed_handler(move |_, message: ChatMessage| {
+        let _ = tx.send(message.text.len());
+    });
+
+    let convo = amal.create_private_conversation(addr("bola")).unwrap();
+    amal.start();
+    bola.start();
+    // Let Bola accept the invite before timing anything
+    std::thread::sleep(Duration::from_millis(500));
+
+    let mut group = c.benchmark_group("round_trip");
+    group.sample_size(20);
+    // History is kept in memory, so the largest payloads are left out
+    for size in &CONTENT_SIZES[..3] {
+        group.throughput(Throughput::Bytes(*size as u64));
+        let message = text(*size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &message, |b, message| {
+            b.iter(|| {
+                convo.lock().unwrap().send_typed(message).unwrap();
+                rx.recv_timeout(Duration::from_secs(5)).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, envelopes, encryption, send, round_trip);
+criterion_main!(benches);
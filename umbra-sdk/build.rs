@@ -0,0 +1,21 @@
+use std::io::Result;
+fn main() -> Result<()> {
+    let mut config = prost_build::Config::new();
+    if std::env::var_os("CARGO_FEATURE_SERDE").is_some() {
+        config.type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]");
+        // Fields missing from the JSON take their protobuf defaults
+        config.message_attribute(".", "#[serde(default)]");
+    }
+    // Wrappers around whole frames and envelopes borrow from the buffer they
+    // were decoded from instead of copying it
+    config.bytes([
+        ".umbra.sdk.frames.Timestamped.frame",
+        ".umbra.sdk.frames.Compressed.frame",
+        ".umbra.sdk.frames.HistoryResponse.envelopes",
+    ]);
+    config.compile_protos(
+        &["protos/frames.proto", "protos/snapshot.proto"],
+        &["protos/"],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,70 @@
+//! A chat client running in the browser, talking to an Umbra relay.
+//!
+//! Build with `wasm-pack build --target web`, then serve this directory and
+//! open `index.html`. There are no threads in the browser, so the page calls
+//! `poll` on a timer to process received envelopes and run maintenance.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use umbra_content_types::ChatMessage;
+use umbra_sdk::{BrowserDeliveryService, Conversation, UmbraClient};
+use wasm_bindgen::prelude::*;
+
+type Convo = Arc<Mutex<dyn Conversation<BrowserDeliveryService> + Send + Sync>>;
+
+#[wasm_bindgen]
+pub struct Chat {
+    client: UmbraClient<BrowserDeliveryService>,
+    convos: HashMap<String, Convo>,
+    // Lines received since the last poll
+    received: Arc<Mutex<Vec<String>>>,
+}
+
+#[wasm_bindgen]
+impl Chat {
+    #[wasm_bindgen(constructor)]
+    pub fn new(addr: String, relay_url: &str) -> Result<Chat, JsError> {
+        let addr = addr.parse()?;
+        let mut client = UmbraClient::new(BrowserDeliveryService::connect(relay_url), addr);
+        let received = Arc::new(Mutex::new(vec![]));
+        let lines = received.clone();
+        client.add_typed_handler(move |convo_id, msg: ChatMessage| {
+            lines
+                .lock()
+                .unwrap()
+                .push(format!("{}: {}", convo_id, msg.text));
+        });
+        Ok(Chat {
+            client,
+            convos: HashMap::new(),
+            received,
+        })
+    }
+
+    pub fn address(&self) -> String {
+        self.client.address().into()
+    }
+
+    /// Send `text` to `peer`, inviting them first if needed.
+    pub fn send(&mut self, peer: String, text: String) -> Result<(), JsError> {
+        let convo = match self.convos.get(&peer) {
+            Some(convo) => convo.clone(),
+            None => {
+                let convo = self.client.create_private_conversation(peer.parse()?)?;
+                self.convos.insert(peer, convo.clone());
+                convo
+            }
+        };
+        convo.lock().unwrap().send_typed(&ChatMessage::new(text))?;
+        Ok(())
+    }
+
+    /// Process everything received since the last call and return the new
+    /// messages.
+    pub fn poll(&self) -> Result<Vec<String>, JsError> {
+        self.client.poll()?;
+        self.client.tick();
+        Ok(std::mem::take(&mut *self.received.lock().unwrap()))
+    }
+}
@@ -0,0 +1,12 @@
+#![no_main]
+
+use std::sync::LazyLock;
+
+use libfuzzer_sys::fuzz_target;
+use umbra_sdk::fuzz::Target;
+
+static TARGET: LazyLock<Target> = LazyLock::new(Target::new);
+
+fuzz_target!(|data: &[u8]| {
+    let _ = TARGET.inbox_frame(data);
+});
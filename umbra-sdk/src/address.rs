@@ -0,0 +1,182 @@
+//! Participant addresses.
+//!
+//! An address names a participant's inbox topic, so it must be safe to
+//! publish on and compare equal however it was written. Two forms are
+//! accepted:
+//!
+//! - names such as `amal` or `relay-bot.1`: 1 to 64 ASCII letters, digits,
+//!   `.`, `-` and `_`, case insensitive and kept in lowercase
+//! - key addresses derived from an identity key by
+//!   [`Address::from_public_key`]: `0x`, 20 bytes of key hash and a 4 byte
+//!   checksum in hex, so a mistyped address is rejected rather than invited
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use sha3::{Digest, Sha3_256};
+
+use crate::UmbraError;
+
+const MAX_NAME_LEN: usize = 64;
+const KEY_PREFIX: &str = "0x";
+const KEY_HASH_LEN: usize = 20;
+const CHECKSUM_LEN: usize = 4;
+
+/// A validated participant address in canonical form. Parse one with
+/// `str::parse`; it derefs to its canonical string.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Address(String);
+
+impl Address {
+    /// The key address of an identity key.
+    pub fn from_public_key(public_key: &[u8]) -> Self {
+        let hash = Sha3_256::digest(public_key);
+        let hash = &hash[..KEY_HASH_LEN];
+        Self(format!(
+            "{KEY_PREFIX}{}{}",
+            hex::encode(hash),
+            hex::encode(checksum(hash))
+        ))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether this is a key address rather than a name.
+    pub fn is_key(&self) -> bool {
+        self.0.starts_with(KEY_PREFIX)
+    }
+}
+
+fn checksum(hash: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let digest = Sha3_256::digest([b"umbra/address/".as_slice(), hash].concat());
+    let mut checksum = [0; CHECKSUM_LEN];
+    checksum.copy_from_slice(&digest[..CHECKSUM_LEN]);
+    checksum
+}
+
+fn invalid(s: &str, reason: &str) -> UmbraError {
+    UmbraError::InvalidAddress(format!("{s:?}: {reason}"))
+}
+
+impl FromStr for Address {
+    type Err = UmbraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let canonical = s.to_ascii_lowercase();
+        if let Some(encoded) = canonical.strip_prefix(KEY_PREFIX) {
+            let bytes = hex::decode(encoded).map_err(|_| invalid(s, "not hex"))?;
+            if bytes.len() != KEY_HASH_LEN + CHECKSUM_LEN {
+                return Err(invalid(s, "wrong length for a key address"));
+            }
+            let (hash, sum) = bytes.split_at(KEY_HASH_LEN);
+            if checksum(hash).as_slice() != sum {
+                return Err(invalid(s, "checksum mismatch"));
+            }
+            return Ok(Self(canonical));
+        }
+
+        if canonical.is_empty() || canonical.len() > MAX_NAME_LEN {
+            return Err(invalid(s, "names are 1 to 64 characters"));
+        }
+        let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_');
+        if !canonical.chars().all(allowed) {
+            return Err(invalid(s, "unexpected character"));
+        }
+        Ok(Self(canonical))
+    }
+}
+
+impl TryFrom<&str> for Address {
+    type Error = UmbraError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl TryFrom<String> for Address {
+    type Error = UmbraError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Address> for String {
+    fn from(addr: Address) -> Self {
+        addr.0
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Deref for Address {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Address {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// Lets maps keyed by address be queried with a &str
+impl Borrow<str> for Address {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for Address {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Address {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_canonicalised_and_checked() {
+        let addr: Address = "Amal".parse().unwrap();
+        assert_eq!(addr, "amal");
+        assert_eq!(addr, "amal".parse::<Address>().unwrap());
+
+        let long = "x".repeat(65);
+        for garbage in ["", "amal/../bola", "bola|amal", "a b", long.as_str()] {
+            assert!(garbage.parse::<Address>().is_err(), "{garbage:?}");
+        }
+    }
+
+    #[test]
+    fn key_addresses_carry_a_checksum() {
+        let addr = Address::from_public_key(&[7; 32]);
+        assert!(addr.is_key());
+        assert_eq!(addr.to_uppercase().parse::<Address>().unwrap(), addr);
+
+        // Flip the last hex digit of the hash
+        let mut typo = addr.to_string().into_bytes();
+        let i = KEY_PREFIX.len() + 2 * KEY_HASH_LEN - 1;
+        typo[i] = if typo[i] == b'0' { b'1' } else { b'0' };
+        assert!(String::from_utf8(typo).unwrap().parse::<Address>().is_err());
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::sync::RwLock;
+
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::{Key, KeyInit, XChaCha20Poly1305, XNonce};
+use rand::Rng;
+use sha3::{Digest, Sha3_256};
+use umbra_content_types::RemoteAttachment;
+
+use crate::client::Blob;
+use crate::error::UmbraError;
+use crate::stream::{self, DecryptingReader, EncryptingReader, io_error};
+use crate::utils::generate_random_string;
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+
+/// External storage for attachments too large to send inline, e.g. S3 or
+/// IPFS. Blobs are encrypted before upload, so the store only ever sees
+/// ciphertext.
+pub trait BlobStore: Send + Sync {
+    /// Store `bytes`, returning the URL they can be downloaded from.
+    fn upload(&self, bytes: &[u8]) -> Result<String, UmbraError>;
+    fn download(&self, url: &str) -> Result<Blob, UmbraError>;
+
+    /// Store everything read from `reader`. The default buffers it and calls
+    /// `upload`; stores which can write incrementally should override this.
+    fn upload_reader(&self, reader: &mut dyn Read) -> Result<String, UmbraError> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).map_err(io_error)?;
+        self.upload(&bytes)
+    }
+
+    /// Read the blob at `url` incrementally. The default downloads it whole.
+    fn download_reader(&self, url: &str) -> Result<Box<dyn Read + Send>, UmbraError> {
+        Ok(Box::new(io::Cursor::new(self.download(url)?)))
+    }
+}
+
+/// BlobStore which keeps blobs for the lifetime of the process.
+#[derive(Default)]
+pub struct InMemoryBlobStore {
+    blobs: RwLock<HashMap<String, Blob>>,
+}
+
+impl InMemoryBlobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BlobStore for InMemoryBlobStore {
+    fn upload(&self, bytes: &[u8]) -> Result<String, UmbraError> {
+        let url = format!("memory://{}", generate_random_string(16));
+        self.blobs
+            .write()
+            .unwrap()
+            .insert(url.clone(), bytes.to_vec());
+        Ok(url)
+    }
+
+    fn download(&self, url: &str) -> Result<Blob, UmbraError> {
+        self.blobs
+            .read()
+            .unwrap()
+            .get(url)
+            .cloned()
+            .ok_or_else(|| UmbraError::StorageError(format!("no blob at {url}").into()))
+    }
+}
+
+fn crypto_error(reason: &'static str) -> UmbraError {
+    UmbraError::CryptoError(reason.into())
+}
+
+fn digest(bytes: &[u8]) -> Vec<u8> {
+    Sha3_256::digest(bytes).to_vec()
+}
+
+/// Encrypt `bytes` under a fresh key and upload them to `store`. The key is
+/// carried in the returned attachment, so it is only as private as the
+/// conversation it is sent in.
+pub(crate) fn upload(
+    store: &dyn BlobStore,
+    bytes: &[u8],
+    filename: &str,
+    mime_type: &str,
+) -> Result<RemoteAttachment, UmbraError> {
+    let mut rng = rand::rng();
+    let secret: [u8; KEY_LEN] = rng.random();
+    let nonce: [u8; NONCE_LEN] = rng.random();
+
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(&secret));
+    let encrypted = cipher
+        .encrypt(XNonce::from_slice(&nonce), bytes)
+        .map_err(|_| crypto_error("failed to encrypt attachment"))?;
+    let url = store.upload(&encrypted)?;
+
+    Ok(RemoteAttachment {
+        url,
+        secret: secret.to_vec(),
+        nonce: nonce.to_vec(),
+        content_digest: digest(&encrypted),
+        content_length: bytes.len() as u64,
+        filename: filename.to_string(),
+        mime_type: mime_type.to_string(),
+        chunk_size: 0,
+    })
+}
+
+/// Like `upload`, but `reader` is encrypted and uploaded a chunk at a time,
+/// so the file never has to fit in memory.
+pub(crate) fn upload_stream(
+    store: &dyn BlobStore,
+    reader: impl Read,
+    filename: &str,
+    mime_type: &str,
+) -> Result<RemoteAttachment, UmbraError> {
+    let mut rng = rand::rng();
+    let secret: [u8; stream::KEY_LEN] = rng.random();
+    let nonce: [u8; stream::NONCE_LEN] = rng.random();
+
+    let mut encrypting = EncryptingReader::new(reader, &secret, &nonce, stream::CHUNK_SIZE);
+    let url = store.upload_reader(&mut encrypting)?;
+    let (content_digest, content_length) = encrypting.finish();
+
+    Ok(RemoteAttachment {
+        url,
+        secret: secret.to_vec(),
+        nonce: nonce.to_vec(),
+        content_digest,
+        content_length,
+        filename: filename.to_string(),
+        mime_type: mime_type.to_string(),
+        chunk_size: stream::CHUNK_SIZE as u32,
+    })
+}
+
+/// Download and decrypt `attachment` as it is read. Attachments uploaded in
+/// one piece are downloaded whole before the first read.
+pub(crate) fn download_stream(
+    store: &dyn BlobStore,
+    attachment: &RemoteAttachment,
+) -> Result<Box<dyn Read + Send>, UmbraError> {
+    if attachment.chunk_size == 0 {
+        return Ok(Box::new(io::Cursor::new(download(store, attachment)?)));
+    }
+
+    let chunk_size = attachment.chunk_size as usize;
+    if chunk_size > stream::MAX_CHUNK_SIZE {
+        return Err(crypto_error("attachment chunk size too large"));
+    }
+    let secret: [u8; stream::KEY_LEN] = attachment
+        .secret
+        .as_slice()
+        .try_into()
+        .map_err(|_| crypto_error("invalid attachment key"))?;
+    let nonce: [u8; stream::NONCE_LEN] = attachment
+        .nonce
+        .as_slice()
+        .try_into()
+        .map_err(|_| crypto_error("invalid attachment key"))?;
+
+    let encrypted = store.download_reader(&attachment.url)?;
+    Ok(Box::new(DecryptingReader::new(
+        encrypted,
+        &secret,
+        &nonce,
+        chunk_size,
+        attachment.content_digest.clone(),
+        attachment.content_length,
+    )))
+}
+
+/// Download and decrypt `attachment`, rejecting blobs which were altered in
+/// storage.
+pub(crate) fn download(
+    store: &dyn BlobStore,
+    attachment: &RemoteAttachment,
+) -> Result<Blob, UmbraError> {
+    if attachment.chunk_size != 0 {
+        let mut bytes = vec![];
+        download_stream(store, attachment)?
+            .read_to_end(&mut bytes)
+            .map_err(io_error)?;
+        return Ok(bytes);
+    }
+    if attachment.secret.len() != KEY_LEN || attachment.nonce.len() != NONCE_LEN {
+        return Err(crypto_error("invalid attachment key"));
+    }
+
+    let encrypted = store.download(&attachment.url)?;
+    if digest(&encrypted) != attachment.content_digest {
+        return Err(crypto_error("attachment digest mismatch"));
+    }
+
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(&attachment.secret));
+    let bytes = cipher
+        .decrypt(XNonce::from_slice(&attachment.nonce), encrypted.as_slice())
+        .map_err(|_| crypto_error("failed to decrypt attachment"))?;
+    if bytes.len() as u64 != attachment.content_length {
+        return Err(crypto_error("attachment length mismatch"));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let store = InMemoryBlobStore::new();
+        let attachment = upload(&store, b"holiday photo", "beach.jpg", "image/jpeg").unwrap();
+
+        assert_ne!(store.download(&attachment.url).unwrap(), b"holiday photo");
+        assert_eq!(download(&store, &attachment).unwrap(), b"holiday photo");
+        assert_eq!(attachment.content_length, 13);
+    }
+
+    #[test]
+    fn streamed_round_trip() {
+        let store = InMemoryBlobStore::new();
+        let file = vec![7u8; stream::CHUNK_SIZE * 2 + 10];
+        let attachment = upload_stream(&store, file.as_slice(), "clip.mp4", "video/mp4").unwrap();
+        assert_eq!(attachment.content_length, file.len() as u64);
+
+        let mut streamed = vec![];
+        download_stream(&store, &attachment)
+            .unwrap()
+            .read_to_end(&mut streamed)
+            .unwrap();
+        assert_eq!(streamed, file);
+        assert_eq!(download(&store, &attachment).unwrap(), file);
+    }
+
+    #[test]
+    fn rejects_tampered_blobs() {
+        let store = InMemoryBlobStore::new();
+        let mut attachment = upload(&store, b"holiday photo", "beach.jpg", "image/jpeg").unwrap();
+
+        let mut blob = store.download(&attachment.url).unwrap();
+        blob[0] ^= 1;
+        attachment.url = store.upload(&blob).unwrap();
+        assert!(matches!(
+            download(&store, &attachment),
+            Err(UmbraError::CryptoError(_))
+        ));
+
+        // A matching digest does not help without the right key
+        attachment.content_digest = digest(&blob);
+        assert!(matches!(
+            download(&store, &attachment),
+            Err(UmbraError::CryptoError(_))
+        ));
+    }
+}
@@ -0,0 +1,73 @@
+use sha3::{Digest, Sha3_256};
+
+// Fixed parameters; both sides must agree to interpret a peer's filter.
+const BLOOM_BYTES: usize = 128;
+const BLOOM_HASHES: usize = 4;
+
+/// Fixed size bloom filter over message ids, carried in
+/// `ReliableBytes::bloom_filter`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BloomFilter {
+    bits: Vec<u8>,
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        Self {
+            bits: vec![0; BLOOM_BYTES],
+        }
+    }
+}
+
+impl BloomFilter {
+    /// Returns None if `bytes` was not produced with the same parameters.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        (bytes.len() == BLOOM_BYTES).then(|| Self {
+            bits: bytes.to_vec(),
+        })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+
+    fn indexes(item: &str) -> impl Iterator<Item = usize> {
+        let digest = Sha3_256::digest(item.as_bytes());
+        (0..BLOOM_HASHES).map(move |i| {
+            let chunk: [u8; 4] = digest[i * 4..i * 4 + 4].try_into().unwrap();
+            u32::from_be_bytes(chunk) as usize % (BLOOM_BYTES * 8)
+        })
+    }
+
+    pub fn insert(&mut self, item: &str) {
+        for idx in Self::indexes(item) {
+            self.bits[idx / 8] |= 1 << (idx % 8);
+        }
+    }
+
+    pub fn contains(&self, item: &str) -> bool {
+        Self::indexes(item).all(|idx| self.bits[idx / 8] & (1 << (idx % 8)) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inserted_items_are_found() {
+        let mut filter = BloomFilter::default();
+        filter.insert("a");
+        filter.insert("b");
+
+        let filter = BloomFilter::from_bytes(filter.as_bytes()).unwrap();
+        assert!(filter.contains("a"));
+        assert!(filter.contains("b"));
+        assert!(!filter.contains("c"));
+    }
+
+    #[test]
+    fn test_rejects_wrong_size() {
+        assert!(BloomFilter::from_bytes(&[0; 3]).is_none());
+    }
+}
@@ -0,0 +1,298 @@
+//! Auto-responders built from declarative routes.
+//!
+//! A [`Bot`] owns a client and routes every received message to the first
+//! matching handler: a chat command by prefix, a content type, or the
+//! fallback. Handlers run on the bot's own thread rather than inside the
+//! client's receive path, so they are free to reply through their
+//! [`BotContext`].
+//!
+//! ```ignore
+//! let bot = Bot::new(client)
+//!     .command("/ping", |ctx, _| ctx.reply_text("pong"))
+//!     .on(|ctx, location: Location| ctx.reply_text(&format!("{location:?}")))
+//!     .run();
+//! // ...
+//! bot.shutdown();
+//! ```
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use prost::Message;
+use tracing::{Level, span, warn};
+use umbra_content_types::{ChatMessage, TaggedContent};
+use umbra_types::common_frames::ContentFrame;
+
+use crate::{ContentType, DeliveryService, FrameKind, UmbraClient, UmbraError, UmbraEvent};
+
+// How often the bot's thread checks for shutdown while idle
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
+type Handler<T> =
+    Box<dyn Fn(&BotContext<'_, T>, &ContentFrame) -> Result<(), UmbraError> + Send + Sync>;
+
+enum Route<T: DeliveryService + Send + Sync + 'static> {
+    /// Chat messages starting with `prefix`, as a whole word.
+    Command { prefix: String, handler: Handler<T> },
+    Content {
+        content_type: ContentType,
+        handler: Handler<T>,
+    },
+}
+
+/// The message being handled, and the means to answer it.
+pub struct BotContext<'a, T: DeliveryService + Send + Sync + 'static> {
+    client: &'a UmbraClient<T>,
+    convo_id: &'a str,
+    sent_at: Option<u64>,
+}
+
+impl<T> BotContext<'_, T>
+where
+    T: DeliveryService + Send + Sync + 'static,
+{
+    pub fn client(&self) -> &UmbraClient<T> {
+        self.client
+    }
+
+    /// Conversation the message arrived in.
+    pub fn convo_id(&self) -> &str {
+        self.convo_id
+    }
+
+    /// Sender's clock when the message was sent, if stamped.
+    pub fn sent_at(&self) -> Option<u64> {
+        self.sent_at
+    }
+
+    /// Send `content` to the conversation the message arrived in.
+    pub fn reply<M: TaggedContent + 'static>(&self, content: &M) -> Result<(), UmbraError> {
+        let convo = self
+            .convo_id
+            .parse()
+            .ok()
+            .and_then(|id| self.client.get_conversation(&id))
+            .ok_or_else(|| UmbraError::ConversationNotFound(self.convo_id.to_string()))?;
+        convo.lock().unwrap().send_typed(content)?;
+        Ok(())
+    }
+
+    pub fn reply_text(&self, text: &str) -> Result<(), UmbraError> {
+        self.reply(&ChatMessage::new(text.to_string()))
+    }
+}
+
+/// A client and the routes its messages are dispatched to. Routes are tried
+/// in the order they were added.
+pub struct Bot<T: DeliveryService + Send + Sync + 'static> {
+    client: UmbraClient<T>,
+    routes: Vec<Route<T>>,
+    fallback: Option<Handler<T>>,
+}
+
+impl<T> Bot<T>
+where
+    T: DeliveryService + Send + Sync + 'static,
+{
+    /// Wrap `client`, which should not have been started yet.
+    pub fn new(client: UmbraClient<T>) -> Self {
+        Self {
+            client,
+            routes: vec![],
+            fallback: None,
+        }
+    }
+
+    /// Handle chat messages whose first word is `prefix`, e.g. `/help`. The
+    /// rest of the message, trimmed, is passed as the arguments.
+    pub fn command<F>(mut self, prefix: &str, handler: F) -> Self
+    where
+        F: Fn(&BotContext<'_, T>, &str) -> Result<(), UmbraError> + Send + Sync + 'static,
+    {
+        let prefix = prefix.to_string();
+        let matcher = prefix.clone();
+        self.routes.push(Route::Command {
+            prefix,
+            handler: Box::new(move |ctx, frame| {
+                let text = ChatMessage::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Content))?
+                    .text;
+                let args = text.strip_prefix(matcher.as_str()).unwrap_or_default();
+                handler(ctx, args.trim())
+            }),
+        });
+        self
+    }
+
+    /// Handle content of type `M`, decoded by the client's codecs.
+    pub fn on<M, F>(mut self, handler: F) -> Self
+    where
+        M: TaggedContent + 'static,
+        F: Fn(&BotContext<'_, T>, M) -> Result<(), UmbraError> + Send + Sync + 'static,
+    {
+        self.routes.push(Route::Content {
+            content_type: M::CONTENT_TYPE,
+            handler: Box::new(move |ctx, frame| {
+                handler(ctx, ctx.client.codecs().decode::<M>(&frame.bytes)?)
+            }),
+        });
+        self
+    }
+
+    /// Handle messages no other route matched.
+    pub fn fallback<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&BotContext<'_, T>, &ContentFrame) -> Result<(), UmbraError> + Send + Sync + 'static,
+    {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Start the client and dispatch messages until the returned handle is
+    /// shut down.
+    pub fn run(mut self) -> BotHandle<T> {
+        // Subscribed before starting, so no message is missed
+        let events = self.client.events();
+        self.client.start();
+
+        let client = Arc::new(self.client);
+        let stop = Arc::new(AtomicBool::new(false));
+        let dispatcher = Dispatcher {
+            client: client.clone(),
+            routes: self.routes,
+            fallback: self.fallback,
+        };
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || dispatcher.run(events, &stop))
+        };
+        BotHandle {
+            client,
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+struct Dispatcher<T: DeliveryService + Send + Sync + 'static> {
+    client: Arc<UmbraClient<T>>,
+    routes: Vec<Route<T>>,
+    fallback: Option<Handler<T>>,
+}
+
+impl<T> Dispatcher<T>
+where
+    T: DeliveryService + Send + Sync + 'static,
+{
+    fn run(&self, events: Receiver<UmbraEvent>, stop: &AtomicBool) {
+        let span = span!(Level::INFO, "Bot", addr = %self.client.address());
+        let _enter = span.enter();
+        while !stop.load(Ordering::Acquire) {
+            match events.recv_timeout(SHUTDOWN_POLL) {
+                Ok(UmbraEvent::ContentReceived {
+                    convo_id,
+                    content,
+                    sent_at,
+                    ..
+                }) => {
+                    let ctx = BotContext {
+                        client: &self.client,
+                        convo_id: &convo_id,
+                        sent_at,
+                    };
+                    if let Err(e) = self.dispatch(&ctx, &content) {
+                        warn!("Bot handler failed: {}", e);
+                    }
+                }
+                Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+
+    fn dispatch(&self, ctx: &BotContext<'_, T>, frame: &ContentFrame) -> Result<(), UmbraError> {
+        let content_type = ContentType::new(frame.domain, frame.tag);
+        let command = (content_type == ChatMessage::CONTENT_TYPE)
+            .then(|| ChatMessage::decode(frame.bytes.as_slice()).ok())
+            .flatten()
+            .map(|msg| msg.text);
+
+        let route = self.routes.iter().find_map(|route| match route {
+            Route::Command { prefix, handler } => command
+                .as_deref()
+                .is_some_and(|text| is_command(text, prefix))
+                .then_some(handler),
+            Route::Content {
+                content_type: routed,
+                handler,
+            } => (*routed == content_type).then_some(handler),
+        });
+        match route.or(self.fallback.as_ref()) {
+            Some(handler) => handler(ctx, frame),
+            None => Ok(()),
+        }
+    }
+}
+
+// `prefix` followed by the end of the text or whitespace
+fn is_command(text: &str, prefix: &str) -> bool {
+    text.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+/// A running bot. Dropping it shuts the bot down.
+pub struct BotHandle<T: DeliveryService + Send + Sync + 'static> {
+    client: Arc<UmbraClient<T>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<T> BotHandle<T>
+where
+    T: DeliveryService + Send + Sync + 'static,
+{
+    pub fn client(&self) -> &UmbraClient<T> {
+        &self.client
+    }
+
+    /// Stop dispatching once the handler in progress, if any, returns, then
+    /// send whatever replies are still queued.
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(Err(_)) = self.thread.take().map(JoinHandle::join) {
+            warn!("Bot dispatcher panicked");
+        }
+        if let Err(e) = self.client.flush() {
+            warn!("Failed to flush queued replies: {}", e);
+        }
+    }
+}
+
+impl<T> Drop for BotHandle<T>
+where
+    T: DeliveryService + Send + Sync + 'static,
+{
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_match_whole_words() {
+        assert!(is_command("/ping", "/ping"));
+        assert!(is_command("/ping now", "/ping"));
+        assert!(!is_command("/pingpong", "/ping"));
+        assert!(!is_command("say /ping", "/ping"));
+    }
+}
@@ -0,0 +1,226 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::DeliveryService;
+use crate::address::Address;
+use crate::attachment::BlobStore;
+use crate::capabilities::Capabilities;
+use crate::client::UmbraClient;
+use crate::config::ClientConfig;
+use crate::error::UmbraError;
+use crate::identity::{IdentityDirectory, KeyDirectoryVerifier};
+use crate::limits::SizeLimits;
+#[cfg(feature = "metrics")]
+use crate::metrics::MetricsRecorder;
+use crate::pending::PendingLimits;
+use crate::ratelimit::{RateLimit, RateLimitPolicy};
+use crate::redact::RedactionPolicy;
+use crate::retention::Retention;
+use crate::store::{MessageStore, StateStore};
+
+pub struct UmbraClientBuilder<T: DeliveryService + Send + Sync + 'static> {
+    ds: T,
+    addr: Address,
+    config: ClientConfig,
+    directory: Option<Arc<dyn IdentityDirectory>>,
+    verifier: Option<Arc<dyn KeyDirectoryVerifier>>,
+    identity_secret: Option<Vec<u8>>,
+    store: Option<Arc<dyn MessageStore>>,
+    state_store: Option<Arc<dyn StateStore>>,
+    blob_store: Option<Arc<dyn BlobStore>>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Arc<dyn MetricsRecorder>>,
+}
+
+impl<T> UmbraClientBuilder<T>
+where
+    T: DeliveryService + Send + Sync + 'static,
+{
+    pub fn new(ds: T, addr: Address) -> Self {
+        Self {
+            ds,
+            addr,
+            config: ClientConfig::default(),
+            directory: None,
+            verifier: None,
+            identity_secret: None,
+            store: None,
+            state_store: None,
+            blob_store: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Turn tolerated protocol errors into hard failures.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.config.strict = strict;
+        self
+    }
+
+    /// Allow `UmbraClient::set_presence` to publish presence frames.
+    pub fn share_presence(mut self, share_presence: bool) -> Self {
+        self.config.share_presence = share_presence;
+        self
+    }
+
+    /// Expire outgoing messages still unsent after `ttl`.
+    pub fn message_ttl(mut self, ttl: Duration) -> Self {
+        self.config.message_ttl = Some(ttl);
+        self
+    }
+
+    /// Surface frames from newer protocol versions as `UnknownFrame` events
+    /// instead of dropping them.
+    pub fn preserve_unknown(mut self, preserve_unknown: bool) -> Self {
+        self.config.preserve_unknown = preserve_unknown;
+        self
+    }
+
+    /// Drop incoming messages older than `max_age`.
+    pub fn max_message_age(mut self, max_age: Duration) -> Self {
+        self.config.max_message_age = Some(max_age);
+        self
+    }
+
+    /// Prune local history by `retention`, in conversations without a policy
+    /// of their own.
+    pub fn retention(mut self, retention: Retention) -> Self {
+        self.config.retention = retention;
+        self
+    }
+
+    /// Distrust sender timestamps more than `skew` in the future.
+    pub fn max_clock_skew(mut self, skew: Duration) -> Self {
+        self.config.max_clock_skew = Some(skew);
+        self
+    }
+
+    /// Limit outgoing messages across all conversations.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.config.rate_limit = Some(limit);
+        self
+    }
+
+    /// Limit outgoing messages in each conversation.
+    pub fn conversation_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.config.conversation_rate_limit = Some(limit);
+        self
+    }
+
+    /// Delay (the default) or reject messages sent over budget.
+    pub fn rate_limit_policy(mut self, policy: RateLimitPolicy) -> Self {
+        self.config.rate_limit_policy = policy;
+        self
+    }
+
+    /// Replace the default size limits, e.g. to allow larger inline content.
+    pub fn size_limits(mut self, limits: SizeLimits) -> Self {
+        self.config.size_limits = limits;
+        self
+    }
+
+    /// Advertise only `capabilities` to peers, e.g. to opt out of receipts.
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.config.capabilities = capabilities;
+        self
+    }
+
+    /// Process incoming envelopes on `workers` threads, so conversations are
+    /// handled in parallel.
+    pub fn recv_workers(mut self, workers: usize) -> Self {
+        self.config.recv_workers = workers;
+        self
+    }
+
+    /// Hold more or fewer envelopes for conversations not created yet, or
+    /// none with a capacity of 0.
+    pub fn pending_envelopes(mut self, limits: PendingLimits) -> Self {
+        self.config.pending_envelopes = limits;
+        self
+    }
+
+    /// Hash or hide identifiers and payloads in the SDK's logs.
+    pub fn redaction(mut self, policy: RedactionPolicy) -> Self {
+        self.config.redaction = policy;
+        self
+    }
+
+    /// Resolve peers through `directory` before creating conversations.
+    pub fn identity_directory(mut self, directory: Arc<dyn IdentityDirectory>) -> Self {
+        self.directory = Some(directory);
+        self
+    }
+
+    /// Check identity keys learned from the directory with `verifier`, e.g.
+    /// against a key transparency log or with `TofuVerifier`.
+    pub fn key_verifier(mut self, verifier: Arc<dyn KeyDirectoryVerifier>) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    /// The client's long-term secret, from which the pseudonyms of
+    /// pseudonymous conversations are derived. Keep it with the client's
+    /// other key material.
+    pub fn identity_secret(mut self, secret: Vec<u8>) -> Self {
+        self.identity_secret = Some(secret);
+        self
+    }
+
+    /// Persist message history to `store`.
+    pub fn message_store(mut self, store: Arc<dyn MessageStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Persist the client's address and conversations to `store`, so they
+    /// can be rebuilt with `restore` or `UmbraClient::load`.
+    pub fn state_store(mut self, store: Arc<dyn StateStore>) -> Self {
+        self.state_store = Some(store);
+        self
+    }
+
+    /// Upload and download remote attachments through `store`.
+    pub fn blob_store(mut self, store: Arc<dyn BlobStore>) -> Self {
+        self.blob_store = Some(store);
+        self
+    }
+
+    /// Report counters, gauges and histograms to `recorder`.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, recorder: Arc<dyn MetricsRecorder>) -> Self {
+        self.metrics = Some(recorder);
+        self
+    }
+
+    pub fn build(self) -> UmbraClient<T> {
+        let client = UmbraClient::with_config(
+            self.ds,
+            self.addr,
+            self.config,
+            self.directory,
+            self.store,
+            self.state_store,
+            self.blob_store,
+        );
+        if let Some(verifier) = self.verifier {
+            client.set_key_verifier(verifier);
+        }
+        if let Some(secret) = self.identity_secret {
+            client.set_identity_secret(secret);
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(recorder) = self.metrics {
+            client.set_metrics_recorder(recorder);
+        }
+        client
+    }
+
+    /// Build the client and rebuild the conversations saved in the state
+    /// store, with their history from the message store.
+    pub fn restore(self) -> Result<UmbraClient<T>, UmbraError> {
+        let client = self.build();
+        client.restore_conversations()?;
+        Ok(client)
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+
+/// Version of the SDK frame protocol spoken by this client.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Set of optional protocol features. Each conversation uses the features
+/// both participants advertise, so a client never sends frames its peer has
+/// said it cannot handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Capabilities(u64);
+
+impl Capabilities {
+    pub const RECEIPTS: Self = Self(1 << 0);
+    pub const EDITS: Self = Self(1 << 1);
+    pub const RETRACTS: Self = Self(1 << 2);
+    pub const REPLIES: Self = Self(1 << 3);
+    pub const PRESENCE: Self = Self(1 << 4);
+    pub const HISTORY: Self = Self(1 << 5);
+    pub const EXPIRATION: Self = Self(1 << 6);
+    pub const LZ4: Self = Self(1 << 7);
+    pub const ZSTD: Self = Self(1 << 8);
+    pub const RPC: Self = Self(1 << 9);
+    pub const STATE: Self = Self(1 << 10);
+    pub const RESYNC: Self = Self(1 << 11);
+
+    const NAMES: [(Self, &'static str); 12] = [
+        (Self::RECEIPTS, "receipts"),
+        (Self::EDITS, "edits"),
+        (Self::RETRACTS, "retracts"),
+        (Self::REPLIES, "replies"),
+        (Self::PRESENCE, "presence"),
+        (Self::HISTORY, "history"),
+        (Self::EXPIRATION, "expiration"),
+        (Self::LZ4, "lz4"),
+        (Self::ZSTD, "zstd"),
+        (Self::RPC, "rpc"),
+        (Self::STATE, "state"),
+        (Self::RESYNC, "resync"),
+    ];
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Every capability this version of the SDK knows of.
+    pub const fn all() -> Self {
+        Self((1 << 12) - 1)
+    }
+
+    /// What this build can use. Compression algorithms depend on the `lz4`
+    /// and `zstd` features.
+    pub const fn supported() -> Self {
+        let mut supported = Self::all().difference(Self::LZ4).difference(Self::ZSTD);
+        if cfg!(feature = "lz4") {
+            supported = supported.union(Self::LZ4);
+        }
+        if cfg!(feature = "zstd") {
+            supported = supported.union(Self::ZSTD);
+        }
+        supported
+    }
+
+    /// Assumed for peers which never advertised their capabilities, i.e.
+    /// clients older than capability negotiation.
+    pub const fn baseline() -> Self {
+        Self((1 << 7) - 1)
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    /// Unknown bits, e.g. from a newer peer, are dropped.
+    pub const fn from_bits_truncate(bits: u64) -> Self {
+        Self(bits & Self::all().0)
+    }
+
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::supported()
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = Self::NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect();
+        if names.is_empty() {
+            return f.write_str("none");
+        }
+        f.write_str(&names.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiation_keeps_shared_features() {
+        let ours = Capabilities::all().difference(Capabilities::PRESENCE);
+        let theirs = Capabilities::RECEIPTS.union(Capabilities::PRESENCE);
+
+        let shared = ours.intersection(theirs);
+        assert!(shared.contains(Capabilities::RECEIPTS));
+        assert!(!shared.contains(Capabilities::PRESENCE));
+        assert_eq!(shared.to_string(), "receipts");
+    }
+
+    #[test]
+    fn unknown_bits_are_dropped() {
+        let caps = Capabilities::from_bits_truncate(u64::MAX);
+        assert_eq!(caps, Capabilities::all());
+        assert_eq!(Capabilities::empty().to_string(), "none");
+    }
+}
@@ -1,285 +1,1700 @@
 use prost::Message;
-use std::sync::RwLock;
+use std::io::Read;
+use std::panic::{self, AssertUnwindSafe, Location};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{PoisonError, RwLock};
+use std::time::Duration;
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, mpsc::Receiver},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use tracing::{Level, span};
+use tracing::{debug, error, warn};
+use umbra_content_types::{
+    CompositeContent, ContentType, Profile, RemoteAttachment, TaggedContent,
 };
-use tracing::{Level, debug, error, span, warn};
 use umbra_types::base::{
     EncryptedBytes, InboxV1Frame, UmbraEnvelopeV1, encrypted_bytes, inbox_v1_frame,
 };
 use umbra_types::common_frames::ContentFrame;
-use umbra_types::convos::private_v1::{self};
 use umbra_types::encryption;
 use umbra_types::invite;
 use umbra_types::payload::ToEnvelope;
 
+use crate::address::Address;
+use crate::attachment::{self, BlobStore};
+use crate::builder::UmbraClientBuilder;
+use crate::capabilities::Capabilities;
+use crate::codec::{CodecRegistry, ContentCodec};
+use crate::composite::Composite;
+use crate::config::ClientConfig;
+use crate::context::ClientContext;
 use crate::convos::private::PrivateConversation;
-use crate::error::UmbraError;
+use crate::convos::{
+    ConversationId, ConversationInfo, ConversationOptions, MessageHandle, MessageRecord,
+};
+use crate::error::{FrameKind, UmbraError};
+use crate::events::{
+    DeliveryStatus, DeliveryUpdate, MessageContext, MessageDeleted, MessageEdited, Presence,
+    PresenceState, Registered, SyncEvent, UmbraEvent, UnknownFrame, panic_message,
+};
+use crate::frames::types::InviteTerms;
+use crate::hints::{self, HintTable};
+use crate::identity::{Identity, IdentityDirectory, KeyDirectoryVerifier, KeyEvent};
+use crate::intercept::{
+    InboundInterceptor, InboundMessage, OutboundInterceptor, OutboundMessage, Verdict,
+};
+use crate::invites::{self, Invites, SentInvite};
+use crate::limits;
+#[cfg(feature = "metrics")]
+use crate::metrics::MetricsRecorder;
+use crate::metrics::{Counter, Gauge};
+use crate::notify::{ContentMeta, NotifyLevel};
+use crate::pending::{PendingEnvelopes, PendingLimits};
+use crate::poll::PollResults;
+use crate::push::{PushNotification, PushRegistration};
+use crate::retention::{MessagesPruned, Retention};
+use crate::rpc::{PendingResponse, RequestHandler};
+use crate::schedule::ScheduledMessage;
+use crate::shared_state::{SharedState, StateChanged};
+use crate::snapshot::types::{ConversationSnapshot, MessageSnapshot};
+use crate::store::{MessageStore, SearchHit, StateStore};
+use crate::transcript::{Transcript, TranscriptFormat, TranscriptSource};
+use crate::transport::ConnectionState;
+use crate::utils::{generate_random_string, now_millis};
+use crate::watch::Diff;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::workers::WorkerPool;
 
-// Type Aliases for Identitifiers
-pub type Addr = String;
+// Type Aliases
 pub type Blob = Vec<u8>;
 
+// How often conversations run time based maintenance
+#[cfg(not(target_arch = "wasm32"))]
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+// Pause after the DeliveryService fails to receive, so a persistent failure
+// does not spin the receive thread
+#[cfg(not(target_arch = "wasm32"))]
+const RECV_ERROR_BACKOFF: Duration = Duration::from_millis(500);
+// Bounds on the pause while the DeliveryService has nothing ready. Transports
+// return Ok(None) at once rather than blocking, so the receive thread backs
+// off, doubling from the minimum, until the next envelope arrives
+#[cfg(not(target_arch = "wasm32"))]
+const RECV_IDLE_MIN: Duration = Duration::from_millis(1);
+#[cfg(not(target_arch = "wasm32"))]
+const RECV_IDLE_MAX: Duration = Duration::from_millis(50);
+
+/// Publishes and receives envelopes. The client calls it concurrently from
+/// its receive loop and every conversation without locking, so
+/// implementations synchronise any mutable state themselves.
 pub trait DeliveryService {
     fn send(&self, message: Blob) -> Result<(), UmbraError>;
     fn recv(&self) -> Result<Option<Blob>, UmbraError>;
+    /// Publish several envelopes at once. Transports which can pipeline or
+    /// batch requests should override the one by one default.
+    fn send_batch(&self, messages: &[Blob]) -> Result<(), UmbraError> {
+        for message in messages {
+            self.send(message.clone())?;
+        }
+        Ok(())
+    }
+    /// Whether the transport can currently publish. Messages sent while
+    /// disconnected are queued and flushed once this returns true again.
+    fn is_connected(&self) -> bool {
+        true
+    }
+    /// Start receiving messages published to `topic`. Transports which
+    /// deliver everything to everyone can rely on the default no-op.
+    fn subscribe(&self, _topic: &str) -> Result<(), UmbraError> {
+        Ok(())
+    }
+    /// Stop receiving messages published to `topic`.
+    fn unsubscribe(&self, _topic: &str) -> Result<(), UmbraError> {
+        Ok(())
+    }
+    /// Stream of connection state changes, for transports which track them.
+    /// Without one the client polls `is_connected`.
+    fn connection_states(&self) -> Option<Receiver<ConnectionState>> {
+        None
+    }
 }
 
 pub trait Conversation<T: DeliveryService + Send + Sync + 'static> {
-    fn convo_id(&self) -> String;
-    fn send(&self, tag: u32, message: Blob) -> Vec<u8>;
-    fn recv(&self, enc_bytes: EncryptedBytes) -> Result<(), UmbraError>;
+    fn convo_id(&self) -> ConversationId;
+    /// The client's content codecs, used by `send_typed`.
+    fn codecs(&self) -> &CodecRegistry;
+    /// Send content, returning a handle to follow its delivery. Fails with
+    /// `UmbraError::RateLimited` when over budget under
+    /// `RateLimitPolicy::Reject`.
+    fn send(
+        &mut self,
+        content_type: ContentType,
+        message: Blob,
+    ) -> Result<MessageHandle, UmbraError>;
+    /// Like `send`, with a plain text rendering of the content which peers
+    /// without a codec for `content_type` receive as a `ChatMessage`.
+    fn send_with_fallback(
+        &mut self,
+        content_type: ContentType,
+        message: Blob,
+        fallback: Option<String>,
+    ) -> Result<MessageHandle, UmbraError>;
+    /// Like `send_with_fallback`, also mentioning participants or hinting at
+    /// how urgently peers should be notified.
+    fn send_with_meta(
+        &mut self,
+        content_type: ContentType,
+        message: Blob,
+        meta: ContentMeta,
+    ) -> Result<MessageHandle, UmbraError>;
+    /// Send content once `send_at` (unix ms) has passed, on the first tick
+    /// after it. Scheduled messages are saved with the conversation, so
+    /// survive restarts with a StateStore. Returns the schedule id.
+    fn send_at(
+        &mut self,
+        content_type: ContentType,
+        message: Blob,
+        send_at: u64,
+    ) -> Result<String, UmbraError>;
+    /// Stop a message from being sent by `send_at`. Returns it, or None if it
+    /// was already sent or cancelled.
+    fn cancel_scheduled(&mut self, schedule_id: &str) -> Option<ScheduledMessage>;
+    /// Messages waiting to be sent by `send_at`, soonest first.
+    fn scheduled(&self) -> Vec<ScheduledMessage>;
+    /// Keep `draft` as the message being composed, replacing any earlier
+    /// draft. It is persisted through the MessageStore, if any, and cleared
+    /// once content is sent.
+    fn save_draft(&mut self, draft: Blob);
+    fn draft(&self) -> Option<Blob>;
+    fn clear_draft(&mut self);
+    /// Send many `(content_type, content)` messages, encrypting them in one pass and
+    /// handing them to the DeliveryService together. Returns their handles
+    /// in order. Under `RateLimitPolicy::Reject` the whole batch is refused
+    /// unless the budget covers all of it.
+    fn send_batch(
+        &mut self,
+        messages: Vec<(ContentType, Blob)>,
+    ) -> Result<Vec<MessageHandle>, UmbraError>;
+    fn recv(&mut self, enc_bytes: EncryptedBytes) -> Result<(), UmbraError>;
+    /// Decrypt a pushed payload just far enough to describe it, without
+    /// updating any conversation state. Returns None for frames that should
+    /// not raise a notification, such as receipts or already seen messages.
+    fn preview(&self, enc_bytes: EncryptedBytes) -> Result<Option<PushNotification>, UmbraError>;
+    /// Send content in reply to an earlier message in this conversation.
+    fn reply(
+        &mut self,
+        parent_id: &str,
+        content_type: ContentType,
+        message: Blob,
+    ) -> Result<MessageHandle, UmbraError>;
+    /// Send content the peer answers with the handler it registered through
+    /// `UmbraClient::on_request`. The response, or a failure once `timeout`
+    /// passes, resolves the returned handle.
+    fn request(
+        &mut self,
+        content_type: ContentType,
+        message: Blob,
+        timeout: Duration,
+    ) -> Result<PendingResponse, UmbraError>;
+    /// All replies descending from `parent_id`, in causal order.
+    fn thread(&self, parent_id: &str) -> Vec<MessageRecord>;
+    /// Current tallies of the `Poll` sent as `poll_id`, counting only each
+    /// participant's latest `PollVote`. None if the poll is not in history.
+    fn poll_results(&self, poll_id: &str) -> Option<PollResults>;
+    /// Make messages disappear `expire_after` they were sent or received. The
+    /// policy is shared with the peer; `Duration::ZERO` disables expiry.
+    fn set_expiration_policy(&mut self, expire_after: Duration) -> Result<(), UmbraError>;
+    fn expiration_policy(&self) -> Option<Duration>;
+    /// Keep local history as `retention` allows rather than as the client's
+    /// `ClientConfig::retention` does, or go back to the client's with None.
+    /// Unlike expiration, this only affects this client.
+    fn set_retention(&mut self, retention: Option<Retention>);
+    /// The retention policy in effect.
+    fn retention(&self) -> Retention;
+    /// Prune what the retention policy no longer allows now rather than on
+    /// the next tick. Returns how many messages were pruned.
+    fn purge(&mut self) -> usize;
+    /// Periodic maintenance: drops expired messages, prunes history by the
+    /// retention policy and releases frames that gave up waiting on causal
+    /// dependencies. `now` is unix ms.
+    fn tick(&mut self, now: u64) -> Result<(), UmbraError>;
+    /// Ask peers for up to `limit` messages they sent after Lamport timestamp
+    /// `since`, e.g. after being offline. Results arrive through the normal
+    /// receive path and already seen messages are dropped.
+    fn fetch_history(&mut self, since: u64, limit: u32) -> Result<(), UmbraError>;
+    /// Ask the peer to re-send its capabilities, the expiration policy, its
+    /// writes to the shared state and the recent messages it sent, e.g. after
+    /// restoring the conversation from a partial backup. What arrives is
+    /// merged through the normal receive path, so nothing is duplicated.
+    fn request_resync(&mut self) -> Result<(), UmbraError>;
+    /// Immediately send every queued message of this conversation, ignoring
+    /// retry backoff. Fails if the DeliveryService is disconnected.
+    fn flush(&mut self) -> Result<(), UmbraError>;
+    /// Replace the content of a message previously sent by this client.
+    fn edit(
+        &mut self,
+        message_id: &str,
+        content_type: ContentType,
+        message: Blob,
+    ) -> Result<(), UmbraError>;
+    /// Delete a message previously sent by this client for everyone.
+    fn retract(&mut self, message_id: &str) -> Result<(), UmbraError>;
+    fn message_status(&self, message_id: &str) -> Option<DeliveryStatus>;
+    /// Features both participants support. Until the peer advertises its
+    /// own, it is assumed to support `Capabilities::baseline`. Unsupported
+    /// replies are sent as plain messages; other unsupported operations fail
+    /// with `UmbraError::UnsupportedByPeer`.
+    fn capabilities(&self) -> Capabilities;
+    /// The highest protocol version both participants speak.
+    fn protocol_version(&self) -> u32;
+    fn info(&self) -> ConversationInfo;
+    /// The last presence received from `addr` in this conversation.
+    fn presence(&self, addr: &str) -> Option<Presence>;
+    /// State shared by every participant, converging on the latest write to
+    /// each key.
+    fn state(&self) -> &SharedState;
+    /// Set `key` in the shared state for every participant.
+    fn set_state(&mut self, key: &str, value: Blob) -> Result<(), UmbraError>;
+    /// Remove `key` from the shared state for every participant.
+    fn remove_state(&mut self, key: &str) -> Result<(), UmbraError>;
+    fn publish_presence(&mut self, state: PresenceState) -> Result<(), UmbraError>;
+    /// Serialize identity, membership, crypto state and optionally message
+    /// history into a versioned blob for `UmbraClient::import_conversation`.
+    fn export(&self, include_history: bool) -> Blob;
+    /// Stop invoking content handlers for this conversation for `duration`.
+    /// Messages are still received and stored.
+    fn mute(&mut self, duration: Duration);
+    fn unmute(&mut self);
+    fn is_muted(&self) -> bool;
+    /// Which received messages are flagged as worth a notification through
+    /// `MessageContext::notify` and push previews. Unlike muting, handlers
+    /// still see every message.
+    fn set_notify_level(&mut self, level: NotifyLevel);
+    fn notify_level(&self) -> NotifyLevel;
+    fn archive(&mut self);
+    fn unarchive(&mut self);
+    fn is_archived(&self) -> bool;
+    fn messages(&self) -> Vec<MessageRecord>;
+    /// Add messages from elsewhere to history, e.g. by
+    /// `UmbraClient::import_transcript`, without passing them to handlers.
+    /// Messages already in history are skipped. Returns how many were added.
+    fn import_messages(&mut self, records: Vec<MessageRecord>) -> usize;
+    fn watch_messages(&self) -> Receiver<Diff<MessageRecord>>;
+    /// The encoded envelope of the last content sent, for tests which
+    /// inspect or replay what went on the wire.
+    #[cfg(feature = "testing")]
+    fn last_envelope(&self) -> Option<Blob>;
+}
+
+impl<T> dyn Conversation<T> + Send + Sync
+where
+    T: DeliveryService + Send + Sync + 'static,
+{
+    /// Encode `content` with the codec registered for its type and send it,
+    /// along with its `TaggedContent::fallback` text.
+    pub fn send_typed<M: TaggedContent + 'static>(
+        &mut self,
+        content: &M,
+    ) -> Result<MessageHandle, UmbraError> {
+        let bytes = self.codecs().encode(content)?;
+        self.send_with_fallback(M::CONTENT_TYPE, bytes, content.fallback())
+    }
+
+    /// Render the conversation's history as a readable transcript, e.g. with
+    /// `PlainText`, for compliance exports or moving history to another app.
+    /// Unlike `export`, the result cannot be imported back.
+    pub fn export_transcript(&self, format: &dyn TranscriptFormat) -> Result<Blob, UmbraError> {
+        let transcript = Transcript::new(&self.info(), &self.messages(), self.codecs());
+        format.render(&transcript)
+    }
+}
+
+// A conversation envelope ready to be received: the conversation's id, the
+// conversation and its payload
+type Routed<T> = (
+    String,
+    Arc<Mutex<dyn Conversation<T> + Send + Sync>>,
+    EncryptedBytes,
+);
+
+// Where an envelope is headed, decided by its hint alone. Each envelope goes
+// to exactly one destination.
+enum Destination<T: DeliveryService + Send + Sync + 'static> {
+    // This client's inbox, which only carries invites
+    Inbox,
+    Conversation(String, Arc<Mutex<dyn Conversation<T> + Send + Sync>>),
+    // No conversation this client knows of uses the hint
+    Unknown,
 }
 
+type ConversationHandler<T> =
+    Box<dyn Fn(Arc<Mutex<dyn Conversation<T> + Send + Sync>>) + Send + Sync>;
+
 pub struct UmbraState<T: DeliveryService + Send + Sync + 'static> {
-    convos: HashMap<Addr, Arc<Mutex<dyn Conversation<T> + Send + Sync>>>,
+    convos: HashMap<ConversationId, Arc<Mutex<dyn Conversation<T> + Send + Sync>>>,
+    // Shared so handlers can run after the state lock is released
+    convo_handlers: Arc<RwLock<Vec<Registered<ConversationHandler<T>>>>>,
+    hints: HintTable,
+    pending: PendingEnvelopes,
+    invites: Invites,
 }
 
 impl<T> UmbraState<T>
 where
     T: DeliveryService + Send + Sync + 'static,
 {
-    pub fn new() -> Self {
+    pub fn new(pending: PendingLimits) -> Self {
         Self {
             convos: HashMap::new(),
+            convo_handlers: Arc::new(RwLock::new(Vec::new())),
+            hints: HintTable::new(now_millis()),
+            pending: PendingEnvelopes::new(pending),
+            invites: Invites::default(),
         }
     }
 
-    pub fn create_conversation(
+    // The invitee opens capability negotiation, as the inviter's frames may
+    // be published before the invitee is listening. Ids are derived from the
+    // participants, so a repeated invite names a conversation which may
+    // already exist; it is returned as is, with false, rather than replaced.
+    // Without a `hint_secret` from the invite, one is derived from the id.
+    pub(crate) fn create_conversation(
         &mut self,
-        ds: Arc<Mutex<T>>,
-        addrs: Vec<Addr>,
-    ) -> Option<Arc<Mutex<dyn Conversation<T> + Send + Sync>>> {
-        let convo_id = topic_private_convo(addrs); //TODO: conversations need to determine their ContentTopic
+        ctx: Arc<ClientContext<T>>,
+        self_addr: Address,
+        addrs: Vec<Address>,
+        hint_secret: Option<Vec<u8>>,
+        invited: bool,
+    ) -> (Arc<Mutex<dyn Conversation<T> + Send + Sync>>, bool) {
+        let convo_id = ConversationId::private(&addrs);
+        if let Some(convo) = self.convos.get(&convo_id) {
+            return (convo.clone(), false);
+        }
+        debug!("Register convo: {}", ctx.config.redaction.id(&convo_id));
+        let participants = sorted_pariticipants(addrs);
+        let hint_secret = hint_secret.unwrap_or_else(|| hints::derived_secret(&convo_id));
+        let mut convo =
+            PrivateConversation::new(convo_id, self_addr, participants, hint_secret, ctx.clone());
+        if invited {
+            convo.advertise_capabilities();
+        }
+        (self.insert_conversation(&ctx, convo), true)
+    }
 
-        debug!("Register convo: {}", convo_id);
-        self.convos.insert(
-            convo_id.clone(),
-            Arc::new(Mutex::new(PrivateConversation::new(convo_id.clone(), ds))),
-        );
+    fn insert_conversation(
+        &mut self,
+        ctx: &ClientContext<T>,
+        convo: PrivateConversation<T>,
+    ) -> Arc<Mutex<dyn Conversation<T> + Send + Sync>> {
+        let (convo_id, info) = (convo.convo_id(), convo.info());
+        ctx.save_conversation(&convo_id, &convo.export(false));
+        // Envelopes are published on their hint, so the topics change as
+        // hints rotate
+        let secret = convo.hint_secret().to_vec();
+        for hint in self.hints.insert(&convo_id, secret) {
+            if let Err(e) = ctx.ds.subscribe(&hint) {
+                warn!(
+                    "Failed to subscribe to {}: {:?}",
+                    ctx.config.redaction.id(&hint),
+                    e
+                );
+            }
+        }
+        let convo: Arc<Mutex<dyn Conversation<T> + Send + Sync>> = Arc::new(Mutex::new(convo));
+        // Only imports replace a conversation, which watchers see as a change
+        // to it rather than a new one
+        if self.convos.insert(convo_id, convo.clone()).is_some() {
+            ctx.convo_watchers.notify(Diff::Updated(info));
+        } else {
+            ctx.convo_watchers.notify(Diff::Added(info.clone()));
+            ctx.events.emit_event(UmbraEvent::NewConversation(info));
+        }
+        ctx.events
+            .emit_push_registration(self.push_registration(&topic_inbox_convo(&ctx.addr)));
+        convo
+    }
 
-        self.get_conversation(convo_id)
+    fn push_registration(&self, inbox_topic: &str) -> PushRegistration {
+        // Hints double as content topics
+        let mut topics = vec![inbox_topic.to_string()];
+        topics.extend(self.hints.hints().keys().cloned());
+        PushRegistration {
+            topics,
+            hints: self.hints.hints().clone(),
+        }
+    }
+
+    fn conversations(&self) -> Vec<Arc<Mutex<dyn Conversation<T> + Send + Sync>>> {
+        self.convos.values().cloned().collect()
+    }
+
+    fn list_conversations(&self) -> Vec<ConversationInfo> {
+        self.convos
+            .values()
+            .map(|c| c.lock().unwrap().info())
+            .collect()
     }
 
     fn get_conversation(
         &self,
-        addr: Addr,
+        convo_id: &str,
     ) -> Option<Arc<Mutex<dyn Conversation<T> + Send + Sync>>> {
-        self.convos.get(&addr).cloned()
+        self.convos.get(convo_id).cloned()
     }
 }
 
 pub struct UmbraClient<T: DeliveryService + Send + Sync + 'static> {
-    addr: Addr,
     inbox_topic: String,
-    ds: Arc<Mutex<T>>,
+    ctx: Arc<ClientContext<T>>,
     state: Arc<RwLock<UmbraState<T>>>,
-    on_content_handlers: Arc<RwLock<Vec<Box<dyn Fn(String, ContentFrame) + Send + Sync>>>>,
+    // Last snapshot written per conversation, to skip unchanged ones
+    saved: Arc<Mutex<HashMap<ConversationId, Blob>>>,
 }
 
 impl<T> UmbraClient<T>
 where
     T: DeliveryService + Send + Sync + 'static,
 {
-    pub fn new(ds: T, addr: Addr) -> Self {
+    pub fn new(ds: T, addr: Address) -> Self {
+        Self::builder(ds, addr).build()
+    }
+
+    pub fn builder(ds: T, addr: Address) -> UmbraClientBuilder<T> {
+        UmbraClientBuilder::new(ds, addr)
+    }
+
+    /// Rebuild a client persisted to `store` by an earlier run: its address,
+    /// conversations and message history. Topics are subscribed again before
+    /// this returns.
+    pub fn load<S>(ds: T, store: Arc<S>) -> Result<Self, UmbraError>
+    where
+        S: MessageStore + StateStore + 'static,
+    {
+        let addr = store
+            .load_address()?
+            .ok_or_else(|| UmbraError::StorageError("no saved client state".into()))?;
+        Self::builder(ds, addr)
+            .message_store(store.clone())
+            .state_store(store)
+            .restore()
+    }
+
+    pub(crate) fn with_config(
+        ds: T,
+        addr: Address,
+        config: ClientConfig,
+        directory: Option<Arc<dyn IdentityDirectory>>,
+        store: Option<Arc<dyn MessageStore>>,
+        state_store: Option<Arc<dyn StateStore>>,
+        blob_store: Option<Arc<dyn BlobStore>>,
+    ) -> Self {
         let inbox_topic = topic_inbox_convo(&addr);
+        if let Err(e) = ds.subscribe(&inbox_topic) {
+            let topic = config.redaction.id(&inbox_topic);
+            warn!("Failed to subscribe to {}: {:?}", topic, e);
+        }
+        if let Some(Err(e)) = state_store.as_ref().map(|s| s.save_address(&addr)) {
+            warn!("Failed to store address: {}", e);
+        }
 
+        let pending = config.pending_envelopes;
         Self {
-            addr,
             inbox_topic,
-            ds: Arc::new(Mutex::new(ds)),
-            state: Arc::new(RwLock::new(UmbraState::new())),
-            on_content_handlers: Arc::new(RwLock::new(Vec::new())),
+            ctx: Arc::new(ClientContext::new(
+                addr,
+                ds,
+                config,
+                directory,
+                store,
+                state_store,
+                blob_store,
+            )),
+            state: Arc::new(RwLock::new(UmbraState::new(pending))),
+            saved: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
-    pub fn start(&mut self) {
-        {
-            let x = self.state.write().unwrap();
-        }
+    pub(crate) fn set_identity_secret(&self, secret: Vec<u8>) {
+        self.ctx.pseudonyms.set_secret(secret);
+    }
+
+    pub(crate) fn set_key_verifier(&self, verifier: Arc<dyn KeyDirectoryVerifier>) {
+        self.ctx.keys.set_verifier(verifier);
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn set_metrics_recorder(&self, recorder: Arc<dyn MetricsRecorder>) {
+        self.ctx.metrics.set_recorder(recorder);
+    }
 
+    /// Receive and process envelopes on background threads, and run
+    /// maintenance every second. Without threads, as on wasm32, drive the
+    /// client with `receive` or `poll` and `tick` instead.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start(&mut self) {
         let self_topic = self.inbox_topic.clone();
-        let ds = self.ds.clone();
+        let ctx = self.ctx.clone();
         let state = self.state.clone();
-        let handler = self.on_content_handlers.clone();
         let addr = self.address();
+        // Without workers, conversations are processed on the receive thread
+        let workers =
+            (ctx.config.recv_workers > 0).then(|| WorkerPool::new(ctx.config.recv_workers, &addr));
+        // Set by jobs on worker threads which hit an error in strict mode
+        let stopped = Arc::new(AtomicBool::new(false));
         std::thread::spawn(move || {
-            let span = span!(Level::INFO, "RecvThread", addr = addr);
+            let span = span!(Level::INFO, "RecvThread", addr = %addr);
             let _enter = span.enter();
+            // Errors are reported and skipped; a single bad envelope or
+            // transport hiccup must never stop the receive loop, unless in
+            // strict mode
+            let mut idle = RECV_IDLE_MIN;
             loop {
-                let incomming_bytes = ds.lock().unwrap().recv().unwrap();
+                if stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                let incoming_bytes = match ctx.ds.recv() {
+                    Ok(Some(bytes)) => {
+                        idle = RECV_IDLE_MIN;
+                        bytes
+                    }
+                    Ok(None) => {
+                        std::thread::sleep(idle);
+                        idle = (idle * 2).min(RECV_IDLE_MAX);
+                        continue;
+                    }
+                    Err(e) => {
+                        error!("DeliveryService failed to receive: {:?}", e);
+                        ctx.events.emit_error(&e);
+                        std::thread::sleep(RECV_ERROR_BACKOFF);
+                        continue;
+                    }
+                };
+                ctx.metrics.increment(Counter::EnvelopesReceived, 1);
 
-                if incomming_bytes.is_none() {
-                    continue;
+                let routed = Self::route(&state, &ctx, &self_topic, incoming_bytes.as_slice());
+                let (convo_id, convo, enc) = match routed {
+                    Ok(Some(routed)) => routed,
+                    Ok(None) => continue,
+                    Err(e) => match report_recv_error(&ctx, e) {
+                        Ok(()) => continue,
+                        Err(_) => break,
+                    },
+                };
+                let (ctx, stopped) = (ctx.clone(), stopped.clone());
+                let key = convo_id.clone();
+                let job = move || {
+                    // A panic poisons the conversation's lock. Its state is
+                    // used as it was left rather than never processing the
+                    // conversation again
+                    let recv = panic::catch_unwind(AssertUnwindSafe(|| {
+                        convo
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .recv(enc)
+                    }));
+                    let e = match recv {
+                        Ok(Ok(())) => return,
+                        Ok(Err(e)) => e,
+                        Err(payload) => UmbraError::RecvPanicked {
+                            convo_id,
+                            message: panic_message(&*payload),
+                        },
+                    };
+                    if report_recv_error(&ctx, e).is_err() {
+                        stopped.store(true, Ordering::Relaxed);
+                    }
+                };
+                // Keyed by conversation, so each conversation's envelopes
+                // are still processed in order
+                match &workers {
+                    Some(workers) => workers.dispatch(&key, job),
+                    None => job(),
                 }
+            }
+        });
+
+        self.start_tick_timer();
+        self.start_connection_monitor();
+    }
 
-                let incoming_bytes = incomming_bytes.unwrap();
-                Self::recv(
-                    &state,
-                    &ds,
-                    &handler,
-                    &self_topic,
-                    incoming_bytes.as_slice(),
-                )
-                .unwrap_or_else(|e| error!("Error receiving bytes: {:?}", e));
+    // Forward transport connection state to handlers. Queued sends resume on
+    // their own since the outbox checks `is_connected` before retrying.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start_connection_monitor(&self) {
+        let ctx = self.ctx.clone();
+        let states = ctx.ds.connection_states();
+        std::thread::spawn(move || match states {
+            Some(states) => {
+                for state in states {
+                    ctx.events.emit_connection_state(state);
+                }
+            }
+            None => {
+                let mut connected = true;
+                loop {
+                    std::thread::sleep(TICK_INTERVAL);
+                    let now_connected = ctx.ds.is_connected();
+                    if now_connected == connected {
+                        continue;
+                    }
+                    connected = now_connected;
+                    ctx.events.emit_connection_state(if connected {
+                        ConnectionState::Connected
+                    } else {
+                        ConnectionState::Disconnected {
+                            reason: "delivery service unreachable".into(),
+                        }
+                    });
+                }
             }
         });
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start_tick_timer(&self) {
+        let state = self.state.clone();
+        let ctx = self.ctx.clone();
+        let saved = self.saved.clone();
+        std::thread::spawn(move || {
+            loop {
+                std::thread::sleep(TICK_INTERVAL);
+                Self::run_tick(&state, &ctx, &saved);
+            }
+        });
+    }
+
+    /// Run time based maintenance once: retries, receipts, expiry, hint
+    /// rotation and snapshots. `start` does this every second; without it,
+    /// call this on a timer.
+    pub fn tick(&self) {
+        Self::run_tick(&self.state, &self.ctx, &self.saved);
+    }
+
+    fn run_tick(
+        state: &Arc<RwLock<UmbraState<T>>>,
+        ctx: &Arc<ClientContext<T>>,
+        saved: &Mutex<HashMap<ConversationId, Blob>>,
+    ) {
+        let now = now_millis();
+        Self::rotate_hints(state, ctx, now);
+        let expired = state.write().unwrap().pending.expire(now);
+        if expired > 0 {
+            debug!("Dropped {} envelopes for unknown conversations", expired);
+        }
+        let depth = ctx.outbox.lock().unwrap().len();
+        ctx.metrics.gauge(Gauge::QueueDepth, depth as f64);
+        for convo in state.read().unwrap().conversations() {
+            let mut convo = convo.lock().unwrap_or_else(PoisonError::into_inner);
+            match panic::catch_unwind(AssertUnwindSafe(|| convo.tick(now))) {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!("Conversation maintenance failed: {:?}", e),
+                Err(payload) => {
+                    let message = panic_message(&*payload);
+                    error!("Conversation maintenance panicked: {}", message);
+                }
+            }
+            if ctx.state_store.is_none() {
+                continue;
+            }
+            let snapshot = convo.export(false);
+            let mut saved = saved.lock().unwrap();
+            if saved.get(&convo.convo_id()) != Some(&snapshot) {
+                ctx.save_conversation(&convo.convo_id(), &snapshot);
+                saved.insert(convo.convo_id(), snapshot);
+            }
+        }
+    }
+
+    // Follow conversations onto the hints of a new epoch
+    fn rotate_hints(state: &Arc<RwLock<UmbraState<T>>>, ctx: &Arc<ClientContext<T>>, now: u64) {
+        let (added, removed) = state.write().unwrap().hints.rotate(now);
+        if added.is_empty() && removed.is_empty() {
+            return;
+        }
+        let (ds, redaction) = (&ctx.ds, &ctx.config.redaction);
+        for hint in &added {
+            if let Err(e) = ds.subscribe(hint) {
+                warn!("Failed to subscribe to {}: {:?}", redaction.id(hint), e);
+            }
+        }
+        for hint in &removed {
+            if let Err(e) = ds.unsubscribe(hint) {
+                warn!("Failed to unsubscribe from {}: {:?}", redaction.id(hint), e);
+            }
+        }
+
+        let inbox_topic = topic_inbox_convo(&ctx.addr);
+        let registration = state.read().unwrap().push_registration(&inbox_topic);
+        ctx.events.emit_push_registration(registration);
+    }
+
+    // Rebuild conversations saved in the state store. History comes from
+    // the message store, since saved snapshots don't include it.
+    pub(crate) fn restore_conversations(&self) -> Result<(), UmbraError> {
+        let Some(state_store) = &self.ctx.state_store else {
+            return Ok(());
+        };
+
+        for bytes in state_store.load_conversations()? {
+            let mut snapshot = ConversationSnapshot::decode(bytes.as_slice())
+                .map_err(UmbraError::decoding(FrameKind::Snapshot))?;
+            if let Some(store) = &self.ctx.store {
+                snapshot.messages = store
+                    .range(&snapshot.convo_id, 0, u64::MAX, usize::MAX)?
+                    .iter()
+                    .map(MessageSnapshot::from)
+                    .collect();
+            }
+
+            let redaction = &self.ctx.config.redaction;
+            debug!("Restoring convo: {}", redaction.id(&snapshot.convo_id));
+            let convo = PrivateConversation::from_snapshot(snapshot, self.ctx.clone())?;
+            let convo = self
+                .state
+                .write()
+                .unwrap()
+                .insert_conversation(&self.ctx, convo);
+            Self::announce_conversation(&self.state, &self.ctx, &convo);
+        }
+        Ok(())
+    }
+
+    #[track_caller]
     pub fn add_content_handler<F>(&mut self, handler: F)
     where
         F: Fn(String, ContentFrame) + Send + Sync + 'static,
     {
-        self.on_content_handlers
+        self.ctx
+            .events
+            .add_content_handler(Location::caller(), Box::new(handler));
+    }
+
+    /// Like `add_content_handler`, with the message's id, sender and
+    /// reliability metadata alongside the content.
+    #[track_caller]
+    pub fn add_message_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&MessageContext, &ContentFrame) + Send + Sync + 'static,
+    {
+        self.ctx
+            .events
+            .add_message_handler(Location::caller(), Box::new(handler));
+    }
+
+    /// Register the codec used to encode and decode content of type `M`.
+    /// Fails if `M` collides with another registered type or uses a domain
+    /// reserved for the SDK; see `TaggedContent::DOMAIN`.
+    pub fn register_codec<M, C>(&mut self, codec: C) -> Result<(), UmbraError>
+    where
+        M: TaggedContent + 'static,
+        C: ContentCodec<M>,
+    {
+        self.ctx.codecs.register::<M, C>(codec)
+    }
+
+    /// Register a handler for a single content type. Frames of other types
+    /// are skipped and matching frames arrive already decoded by the codec
+    /// registered for the type.
+    #[track_caller]
+    pub fn add_typed_handler<M, F>(&mut self, handler: F)
+    where
+        M: TaggedContent + 'static,
+        F: Fn(String, M) + Send + Sync + 'static,
+    {
+        // Handlers live inside the context, so only hold a weak reference
+        let ctx = Arc::downgrade(&self.ctx);
+        self.add_content_handler(move |convo_id, frame| {
+            if frame.domain != M::DOMAIN || frame.tag != M::TAG {
+                return;
+            }
+            let Some(ctx) = ctx.upgrade() else {
+                return;
+            };
+
+            match ctx.codecs.decode::<M>(&frame.bytes) {
+                Ok(msg) => handler(convo_id, msg),
+                Err(e) => warn!("Failed to decode content {}: {}", M::CONTENT_TYPE, e),
+            }
+        });
+    }
+
+    /// Register a handler for `CompositeContent`, with each part decoded on
+    /// demand by the client's codecs.
+    #[track_caller]
+    pub fn add_composite_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(String, &Composite<'_>) + Send + Sync + 'static,
+    {
+        let ctx = Arc::downgrade(&self.ctx);
+        self.add_typed_handler(move |convo_id, content: CompositeContent| {
+            let Some(ctx) = ctx.upgrade() else {
+                return;
+            };
+            handler(convo_id, &Composite::new(&ctx.codecs, content));
+        });
+    }
+
+    /// Answer requests of `content_type` sent with `Conversation::request`,
+    /// replacing any earlier handler for it. The handler is given the
+    /// conversation id and request, and returns the response content or an
+    /// error message for the requester. It runs while the conversation is
+    /// locked, so must not use the conversation itself.
+    #[track_caller]
+    pub fn on_request<F>(&mut self, content_type: ContentType, handler: F)
+    where
+        F: Fn(String, ContentFrame) -> Result<(ContentType, Blob), String> + Send + Sync + 'static,
+    {
+        let handler = Registered::new(Location::caller(), Box::new(handler) as RequestHandler);
+        self.ctx.requests.insert(content_type, handler);
+    }
+
+    /// See every received message before it is stored or dispatched, and
+    /// rewrite or drop it. Interceptors run in the order they were added,
+    /// while the conversation is locked.
+    #[track_caller]
+    pub fn add_inbound_interceptor<F>(&mut self, interceptor: F)
+    where
+        F: Fn(&mut InboundMessage) -> Verdict + Send + Sync + 'static,
+    {
+        let interceptor: InboundInterceptor = Box::new(interceptor);
+        self.ctx
+            .interceptors
+            .add_inbound(Registered::new(Location::caller(), interceptor));
+    }
+
+    /// See, rewrite or reject content before it is sent. A rejected send
+    /// fails with the interceptor's error, e.g. `UmbraError::SendRejected`.
+    /// Interceptors run in the order they were added, while the
+    /// conversation is locked.
+    #[track_caller]
+    pub fn add_outbound_interceptor<F>(&mut self, interceptor: F)
+    where
+        F: Fn(&mut OutboundMessage) -> Result<(), UmbraError> + Send + Sync + 'static,
+    {
+        let interceptor: OutboundInterceptor = Box::new(interceptor);
+        self.ctx
+            .interceptors
+            .add_outbound(Registered::new(Location::caller(), interceptor));
+    }
+
+    #[track_caller]
+    pub fn add_delivery_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(DeliveryUpdate) + Send + Sync + 'static,
+    {
+        self.ctx
+            .events
+            .add_delivery_handler(Location::caller(), Box::new(handler));
+    }
+
+    #[track_caller]
+    pub fn add_edit_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(MessageEdited) + Send + Sync + 'static,
+    {
+        self.ctx
+            .events
+            .add_edit_handler(Location::caller(), Box::new(handler));
+    }
+
+    #[track_caller]
+    pub fn add_delete_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(MessageDeleted) + Send + Sync + 'static,
+    {
+        self.ctx
+            .events
+            .add_delete_handler(Location::caller(), Box::new(handler));
+    }
+
+    #[track_caller]
+    pub fn add_presence_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(String, Presence) + Send + Sync + 'static,
+    {
+        self.ctx
+            .events
+            .add_presence_handler(Location::caller(), Box::new(handler));
+    }
+
+    /// Publish this client's presence to every conversation. Does nothing
+    /// unless presence sharing was enabled on the builder.
+    pub fn set_presence(&self, state: PresenceState) -> Result<(), UmbraError> {
+        if !self.ctx.config.share_presence {
+            debug!("Presence sharing disabled, not publishing {:?}", state);
+            return Ok(());
+        }
+
+        for convo in self.state.read().unwrap().conversations() {
+            convo.lock().unwrap().publish_presence(state)?;
+        }
+        Ok(())
+    }
+
+    /// Send `profile` to every conversation which is not archived or
+    /// pseudonymous. Peers cache it and return it from `profile_for`.
+    /// Conversations created later only see the profile once it is set again.
+    pub fn set_profile(&self, mut profile: Profile) -> Result<(), UmbraError> {
+        if profile.updated_at == 0 {
+            profile.updated_at = now_millis();
+        }
+        self.ctx.cache_profile(&self.ctx.addr, profile.clone());
+
+        for convo in self.state.read().unwrap().conversations() {
+            let mut convo = convo.lock().unwrap();
+            // A profile would tie the pseudonym to this client
+            if convo.is_archived() || convo.info().pseudonym.is_some() {
+                continue;
+            }
+            convo.send_typed(&profile)?;
+        }
+        Ok(())
+    }
+
+    /// The latest profile received from `addr`, or this client's own profile.
+    pub fn profile_for(&self, addr: &str) -> Option<Profile> {
+        self.ctx.profiles.read().unwrap().get(addr).cloned()
+    }
+
+    /// Be notified of changes to any conversation's shared state, including
+    /// this client's own writes.
+    #[track_caller]
+    pub fn add_state_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(StateChanged) + Send + Sync + 'static,
+    {
+        self.ctx
+            .events
+            .add_state_handler(Location::caller(), Box::new(handler));
+    }
+
+    #[track_caller]
+    pub fn add_sync_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(SyncEvent) + Send + Sync + 'static,
+    {
+        self.ctx
+            .events
+            .add_sync_handler(Location::caller(), Box::new(handler));
+    }
+
+    /// Be told which messages retention policies prune from history.
+    #[track_caller]
+    pub fn add_prune_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(MessagesPruned) + Send + Sync + 'static,
+    {
+        self.ctx
+            .events
+            .add_prune_handler(Location::caller(), Box::new(handler));
+    }
+
+    /// Import history exported by another messenger, e.g. through
+    /// `JsonTranscriptSource`. Messages go into the history of the
+    /// conversation named by the transcript if this client has it, and
+    /// otherwise straight into the MessageStore. They are marked as
+    /// `MessageRecord::imported` and skipped if already present, so importing
+    /// again is harmless. Returns how many messages were added.
+    pub fn import_transcript(&self, source: &dyn TranscriptSource) -> Result<usize, UmbraError> {
+        let transcript = source.read()?;
+        let convo = self
+            .state
+            .read()
+            .unwrap()
+            .get_conversation(&transcript.convo_id);
+        if let Some(convo) = convo {
+            return Ok(convo
+                .lock()
+                .unwrap()
+                .import_messages(transcript.into_records()));
+        }
+        let Some(store) = &self.ctx.store else {
+            return Err(UmbraError::ConversationNotFound(transcript.convo_id));
+        };
+        let mut imported = 0;
+        for record in transcript.into_records() {
+            if store.get_by_id(&record.message_id)?.is_none() {
+                store.append(&record)?;
+                imported += 1;
+            }
+        }
+        Ok(imported)
+    }
+
+    /// Prune every conversation's history by its retention policy now,
+    /// rather than on the next tick. Returns how many messages were pruned.
+    pub fn purge_now(&self) -> usize {
+        self.state
+            .read()
+            .unwrap()
+            .conversations()
+            .iter()
+            .map(|convo| convo.lock().unwrap().purge())
+            .sum()
+    }
+
+    /// Be told when a peer's identity key changes or is flagged or rejected
+    /// by the `KeyDirectoryVerifier`, e.g. to warn the user.
+    #[track_caller]
+    pub fn add_key_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(KeyEvent) + Send + Sync + 'static,
+    {
+        self.ctx
+            .events
+            .add_key_handler(Location::caller(), Box::new(handler));
+    }
+
+    /// Be handed frames this client cannot interpret. Requires
+    /// `UmbraClientBuilder::preserve_unknown`.
+    #[track_caller]
+    pub fn add_unknown_frame_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(UnknownFrame) + Send + Sync + 'static,
+    {
+        self.ctx
+            .events
+            .add_unknown_frame_handler(Location::caller(), Box::new(handler));
+    }
+
+    /// Be notified when the DeliveryService connects, degrades or drops, e.g.
+    /// to show an offline banner.
+    #[track_caller]
+    pub fn on_connection_state<F>(&mut self, handler: F)
+    where
+        F: Fn(ConnectionState) + Send + Sync + 'static,
+    {
+        self.ctx
+            .events
+            .add_connection_handler(Location::caller(), Box::new(handler));
+    }
+
+    /// A stream of every client event, as an alternative to registering
+    /// callbacks. Each call returns an independent stream which only sees
+    /// events emitted after it was created; dropping it unsubscribes.
+    pub fn events(&self) -> Receiver<UmbraEvent> {
+        self.ctx.events.subscribe()
+    }
+
+    /// Be notified of errors hit while receiving, e.g. malformed envelopes or
+    /// transport failures. The offending input is skipped and the client
+    /// keeps running.
+    #[track_caller]
+    pub fn add_error_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&UmbraError) + Send + Sync + 'static,
+    {
+        self.ctx
+            .events
+            .add_error_handler(Location::caller(), Box::new(handler));
+    }
+
+    /// Be notified whenever the set of topics to register with a push server
+    /// changes, i.e. when a conversation is created or imported.
+    #[track_caller]
+    pub fn on_push_registration<F>(&mut self, handler: F)
+    where
+        F: Fn(PushRegistration) + Send + Sync + 'static,
+    {
+        self.ctx
+            .events
+            .add_push_registration_handler(Location::caller(), Box::new(handler));
+    }
+
+    /// The topics and hints a push server needs to wake this client.
+    pub fn push_registration(&self) -> PushRegistration {
+        self.state
+            .read()
+            .unwrap()
+            .push_registration(&self.inbox_topic)
+    }
+
+    /// Describe an envelope delivered by a push server, e.g. from a mobile
+    /// notification extension, without running the receive loop. Nothing is
+    /// marked as received; the envelope is processed normally once it arrives
+    /// through the DeliveryService.
+    pub fn handle_push(&self, bytes: &[u8]) -> Result<Option<PushNotification>, UmbraError> {
+        let max_envelope = self.ctx.config.size_limits.max_envelope;
+        limits::check(FrameKind::Envelope, bytes.len(), max_envelope)?;
+        let envelope =
+            UmbraEnvelopeV1::decode(bytes).map_err(UmbraError::decoding(FrameKind::Envelope))?;
+        let enc_bytes = EncryptedBytes::decode(&*envelope.payload)
+            .map_err(UmbraError::decoding(FrameKind::EncryptedBytes))?;
+
+        match Self::destination(&self.state, &self.inbox_topic, &envelope) {
+            Destination::Inbox => {
+                let Some(encrypted_bytes::Encryption::Plaintext(plaintext)) = enc_bytes.encryption
+                else {
+                    return Err(UmbraError::InvalidInvite("unsupported encryption"));
+                };
+                let (frame, terms) = invites::decode(&plaintext.payload)?;
+                let state = self.state.read().unwrap();
+                if terms.revoked || state.invites.check(&terms, now_millis()).is_err() {
+                    return Ok(None);
+                }
+                Ok(match frame.frame_type {
+                    Some(inbox_v1_frame::FrameType::InvitePrivateV1(invite)) => {
+                        Some(PushNotification::Invite {
+                            participants: parse_participants(invite.participants)?,
+                        })
+                    }
+                    None => None,
+                })
+            }
+            Destination::Conversation(_, convo) => convo.lock().unwrap().preview(enc_bytes),
+            Destination::Unknown => {
+                Err(UmbraError::ConversationNotFound(envelope.conversation_hint))
+            }
+        }
+    }
+
+    /// Be handed every conversation as it is created, whether locally, from
+    /// an invite or by import, e.g. to attach handlers or persist it.
+    #[track_caller]
+    pub fn add_conversation_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(Arc<Mutex<dyn Conversation<T> + Send + Sync>>) + Send + Sync + 'static,
+    {
+        let handlers = self.state.read().unwrap().convo_handlers.clone();
+        let handler: ConversationHandler<T> = Box::new(handler);
+        handlers
             .write()
             .unwrap()
-            .push(Box::new(handler));
+            .push(Registered::new(Location::caller(), handler));
     }
 
-    pub fn address(&self) -> Addr {
-        self.addr.clone()
+    // Must be called without holding the state lock, as handlers are free to
+    // call back into the client
+    fn announce_conversation(
+        state: &Arc<RwLock<UmbraState<T>>>,
+        ctx: &ClientContext<T>,
+        convo: &Arc<Mutex<dyn Conversation<T> + Send + Sync>>,
+    ) {
+        let handlers = state.read().unwrap().convo_handlers.clone();
+        for handler in handlers.read().unwrap().iter() {
+            if let Err(e) = handler.invoke(|h| h(convo.clone())) {
+                ctx.events.emit_panic(&e);
+            }
+        }
+        Self::replay_pending(state, ctx, convo);
+    }
+
+    // Receive envelopes which arrived before the conversation did, once
+    // handlers have had the chance to attach to it
+    fn replay_pending(
+        state: &Arc<RwLock<UmbraState<T>>>,
+        ctx: &ClientContext<T>,
+        convo: &Arc<Mutex<dyn Conversation<T> + Send + Sync>>,
+    ) {
+        let convo_id = convo.lock().unwrap().convo_id();
+        let envelopes = {
+            let mut state = state.write().unwrap();
+            let UmbraState { hints, pending, .. } = &mut *state;
+            pending.take(|hint, salt| hints.resolve(hint, salt) == Some(convo_id.as_str()))
+        };
+        if !envelopes.is_empty() {
+            let redaction = &ctx.config.redaction;
+            debug!(
+                "Replaying {} envelopes for {}",
+                envelopes.len(),
+                redaction.id(&convo_id)
+            );
+        }
+        for envelope in envelopes {
+            let res = EncryptedBytes::decode(envelope.payload.as_slice())
+                .map_err(UmbraError::decoding(FrameKind::EncryptedBytes))
+                .and_then(|enc| convo.lock().unwrap().recv(enc));
+            // Replays have no caller to hand an error back to
+            if let Err(e) = res.or_else(|e| report_recv_error(ctx, e)) {
+                panic!("Strict mode: failed to replay a held envelope: {e}");
+            }
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        self.ctx.addr.clone()
+    }
+
+    pub(crate) fn codecs(&self) -> &CodecRegistry {
+        &self.ctx.codecs
     }
 
     pub fn get_conversation(
         &self,
-        addr: Addr,
+        convo_id: &ConversationId,
     ) -> Option<Arc<Mutex<dyn Conversation<T> + Send + Sync>>> {
         let state = self.state.read().unwrap();
-        state.get_conversation(addr)
+        state.get_conversation(convo_id)
+    }
+
+    /// Messages scheduled with `Conversation::send_at` in any conversation,
+    /// soonest first.
+    pub fn scheduled(&self) -> Vec<ScheduledMessage> {
+        let mut scheduled: Vec<ScheduledMessage> = self
+            .state
+            .read()
+            .unwrap()
+            .conversations()
+            .iter()
+            .flat_map(|convo| convo.lock().unwrap().scheduled())
+            .collect();
+        scheduled.sort_by_key(|m| m.send_at);
+        scheduled
+    }
+
+    /// Cancel a scheduled message in whichever conversation it belongs to.
+    pub fn cancel_scheduled(&self, schedule_id: &str) -> Option<ScheduledMessage> {
+        self.state
+            .read()
+            .unwrap()
+            .conversations()
+            .iter()
+            .find_map(|convo| convo.lock().unwrap().cancel_scheduled(schedule_id))
+    }
+
+    /// Send every queued message now instead of waiting for the next retry.
+    pub fn flush(&self) -> Result<(), UmbraError> {
+        for convo in self.state.read().unwrap().conversations() {
+            convo.lock().unwrap().flush()?;
+        }
+        Ok(())
+    }
+
+    /// Number of messages waiting to be handed to the DeliveryService, e.g.
+    /// while it is disconnected.
+    pub fn queue_depth(&self) -> usize {
+        self.ctx.outbox.lock().unwrap().len()
+    }
+
+    /// Snapshot of every known conversation, most recently active first.
+    pub fn list_conversations(&self) -> Vec<ConversationInfo> {
+        let mut convos = self.state.read().unwrap().list_conversations();
+        convos.sort_by(|a, b| b.last_activity.cmp(&a.last_activity));
+        convos
+    }
+
+    /// Streams changes to the conversation list, starting with the
+    /// conversations that already exist. Conversations are updated when
+    /// muted, archived or active, or replaced by an import.
+    pub fn watch_conversations(&self) -> Receiver<Diff<ConversationInfo>> {
+        let state = self.state.read().unwrap();
+        self.ctx.convo_watchers.watch(state.list_conversations())
+    }
+
+    /// Restore a conversation from `Conversation::export`, replacing any
+    /// existing conversation with the same id.
+    pub fn import_conversation(
+        &self,
+        bytes: &[u8],
+    ) -> Result<Arc<Mutex<dyn Conversation<T> + Send + Sync + 'static>>, UmbraError> {
+        let snapshot = ConversationSnapshot::decode(bytes)
+            .map_err(UmbraError::decoding(FrameKind::Snapshot))?;
+        let convo = PrivateConversation::from_snapshot(snapshot, self.ctx.clone())?;
+        let convo = self
+            .state
+            .write()
+            .unwrap()
+            .insert_conversation(&self.ctx, convo);
+        Self::announce_conversation(&self.state, &self.ctx, &convo);
+        Ok(convo)
+    }
+
+    /// Encrypt `bytes` with a fresh key and upload them to the configured
+    /// BlobStore. Send the returned attachment like any other content; its
+    /// key is protected by the conversation's encryption.
+    pub fn upload_attachment(
+        &self,
+        bytes: &[u8],
+        filename: &str,
+        mime_type: &str,
+    ) -> Result<RemoteAttachment, UmbraError> {
+        attachment::upload(self.blob_store()?, bytes, filename, mime_type)
+    }
+
+    /// Fetch, verify and decrypt a received attachment.
+    pub fn download_attachment(&self, attachment: &RemoteAttachment) -> Result<Blob, UmbraError> {
+        attachment::download(self.blob_store()?, attachment)
+    }
+
+    /// Like `upload_attachment`, but encrypts and uploads `reader` a chunk at
+    /// a time, so memory use stays bounded however large the file is.
+    pub fn upload_attachment_stream(
+        &self,
+        reader: impl Read,
+        filename: &str,
+        mime_type: &str,
+    ) -> Result<RemoteAttachment, UmbraError> {
+        attachment::upload_stream(self.blob_store()?, reader, filename, mime_type)
+    }
+
+    /// Read an attachment as it is downloaded and decrypted. Tampering is
+    /// reported as an error from `read`, at the latest when the end of the
+    /// file is reached.
+    pub fn download_attachment_stream(
+        &self,
+        attachment: &RemoteAttachment,
+    ) -> Result<Box<dyn Read + Send>, UmbraError> {
+        attachment::download_stream(self.blob_store()?, attachment)
+    }
+
+    fn blob_store(&self) -> Result<&dyn BlobStore, UmbraError> {
+        self.ctx
+            .blob_store
+            .as_deref()
+            .ok_or_else(|| UmbraError::StorageError("no blob store configured".into()))
+    }
+
+    /// Search the text of stored messages across every conversation, e.g.
+    /// `ChatMessage` text. Requires a MessageStore; messages which were never
+    /// stored, or whose content type has no text, are not found.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, UmbraError> {
+        match &self.ctx.store {
+            Some(store) => store.search(query, limit),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Look up `addr` in the configured IdentityDirectory. Returns None when
+    /// the client was built without one. The key is checked by the
+    /// `KeyDirectoryVerifier`, failing with `UmbraError::KeyRejected` if it
+    /// refuses it.
+    pub fn resolve_identity(&self, addr: &str) -> Result<Option<Identity>, UmbraError> {
+        self.ctx.resolve_identity(addr)
     }
 
     pub fn create_private_conversation(
         &self,
-        addr: Addr,
+        addr: Address,
+    ) -> Result<Arc<Mutex<dyn Conversation<T> + Send + Sync + 'static>>, UmbraError> {
+        self.create_private_conversation_with(addr, ConversationOptions::default())
+    }
+
+    /// Like `create_private_conversation`, with `options`, e.g. to take part
+    /// under a pseudonym.
+    pub fn create_private_conversation_with(
+        &self,
+        addr: Address,
+        options: ConversationOptions,
     ) -> Result<Arc<Mutex<dyn Conversation<T> + Send + Sync + 'static>>, UmbraError> {
-        let topic = format!("/inbox/{}", addr);
+        if addr == self.address() && !options.pseudonymous {
+            return Ok(self.note_to_self());
+        }
+
+        // With a directory configured, only known identities can be invited
+        if self.ctx.directory.is_some() {
+            let identity = self
+                .resolve_identity(&addr)?
+                .ok_or_else(|| UmbraError::IdentityNotFound(addr.to_string()))?;
+            let redaction = &self.ctx.config.redaction;
+            debug!(
+                "Resolved {} to key {}",
+                redaction.id(&identity.addr),
+                redaction.id(&hex::encode(&identity.public_key))
+            );
+        }
 
-        let addrs = vec![self.address(), addr.clone()];
+        let self_addr = if options.pseudonymous {
+            self.ctx.pseudonyms.fresh()?
+        } else {
+            self.address()
+        };
+        let addrs = vec![self_addr.clone(), addr.clone()];
 
-        // Create Local side
-        let mut state = self.state.write().unwrap();
-        let convo = state.create_conversation(self.ds.clone(), addrs.clone());
-        let convo = convo.ok_or_else(|| UmbraError::UnexpectedError)?;
+        // Create Local side. Inviting an existing peer again only resends the
+        // invite, in case theirs was lost
+        let (convo, created) = self.state.write().unwrap().create_conversation(
+            self.ctx.clone(),
+            self_addr.clone(),
+            addrs,
+            Some(hints::random_secret()),
+            false,
+        );
+        if created {
+            Self::announce_conversation(&self.state, &self.ctx, &convo);
+        }
 
-        self.send_invite(addr)?;
+        self.send_invite(self_addr, addr, options.invite_ttl)?;
 
         Ok(convo)
     }
 
-    fn send_invite(&self, recipient: String) -> Result<(), UmbraError> {
+    /// The conversation with this client's own address, for notes to self.
+    /// It is created on first use without an invite: every device using the
+    /// address derives the same conversation, so each one that has opened it
+    /// receives what the others send. Without another device, sent messages
+    /// stay `DeliveryStatus::Sent`.
+    pub fn note_to_self(&self) -> Arc<Mutex<dyn Conversation<T> + Send + Sync + 'static>> {
+        let self_addr = self.address();
+        let (convo, created) = self.state.write().unwrap().create_conversation(
+            self.ctx.clone(),
+            self_addr.clone(),
+            vec![self_addr],
+            None,
+            false,
+        );
+        if created {
+            Self::announce_conversation(&self.state, &self.ctx, &convo);
+        }
+        convo
+    }
+
+    fn send_invite(
+        &self,
+        sender: Address,
+        recipient: Address,
+        ttl: Option<Duration>,
+    ) -> Result<(), UmbraError> {
+        let participants = sorted_pariticipants(vec![sender, recipient.clone()]);
+        let convo_id = ConversationId::private(&participants);
         let invite = inbox_v1_frame::FrameType::InvitePrivateV1(invite::InvitePrivateV1 {
-            participants: sorted_pariticipants(vec![self.address(), recipient.clone()]),
+            participants: participants.into_iter().map(String::from).collect(),
         });
+        // Invites resent to an existing conversation carry the secret it uses
+        let hint_secret = self
+            .state
+            .read()
+            .unwrap()
+            .hints
+            .secret(&convo_id)
+            .map(<[u8]>::to_vec);
+        let terms = InviteTerms {
+            invite_id: generate_random_string(16),
+            expires_at: ttl.map_or(0, |ttl| now_millis() + ttl.as_millis() as u64),
+            revoked: false,
+            hint_secret: hint_secret.unwrap_or_default(),
+        };
 
-        let frame = InboxV1Frame::new("conversationID".into(), invite);
+        let frame = InboxV1Frame::new(convo_id.to_string(), invite);
+        self.send_inbox(&recipient, invites::encode(&frame, &terms))?;
+        self.state
+            .write()
+            .unwrap()
+            .invites
+            .record_sent(convo_id, SentInvite { recipient, terms });
+        Ok(())
+    }
 
+    /// Withdraw the invite sent when creating the conversation `convo_id`.
+    /// The peer refuses it with `UmbraError::InviteRevoked` unless it has
+    /// already joined. Invites can only be revoked until the client
+    /// restarts.
+    pub fn revoke_invite(&self, convo_id: &ConversationId) -> Result<(), UmbraError> {
+        let SentInvite {
+            recipient,
+            mut terms,
+        } = self
+            .state
+            .write()
+            .unwrap()
+            .invites
+            .take_sent(convo_id)
+            .ok_or_else(|| UmbraError::InviteNotFound(convo_id.to_string()))?;
+        terms.revoked = true;
+        terms.hint_secret.clear();
+        let frame = InboxV1Frame {
+            frame_type: None,
+            ..Default::default()
+        };
+        self.send_inbox(&recipient, invites::encode(&frame, &terms))
+    }
+
+    fn send_inbox(&self, recipient: &Address, payload: Blob) -> Result<(), UmbraError> {
         let encrypted_bytes = EncryptedBytes {
             encryption: Some(encrypted_bytes::Encryption::Plaintext(
-                encryption::Plaintext {
-                    payload: frame.encode_to_vec(),
-                },
+                encryption::Plaintext { payload },
             )),
         };
 
-        self.ds.lock().unwrap().send(
+        self.ctx.ds.send(
             encrypted_bytes
-                .to_envelope(topic_inbox_convo(&recipient), 0)
+                .to_envelope(topic_inbox_convo(recipient), 0)
                 .encode_to_vec(),
         )
     }
 
-    pub fn recv(
+    /// Process one envelope from the DeliveryService on the calling thread,
+    /// for transports which hand over envelopes as they arrive instead of
+    /// being polled by `start`.
+    pub fn receive(&self, bytes: &[u8]) -> Result<(), UmbraError> {
+        self.ctx.metrics.increment(Counter::EnvelopesReceived, 1);
+        match Self::route(&self.state, &self.ctx, &self.inbox_topic, bytes)? {
+            Some((_, convo, enc)) => convo.lock().unwrap().recv(enc),
+            None => Ok(()),
+        }
+    }
+
+    /// Process every envelope the DeliveryService has ready, returning how
+    /// many there were. Only for transports whose `recv` returns `Ok(None)`
+    /// rather than blocking when nothing is queued. Errors in individual
+    /// envelopes are reported to error handlers, as with `start`; in strict
+    /// mode the first one is also returned, leaving the rest queued.
+    pub fn poll(&self) -> Result<usize, UmbraError> {
+        let mut received = 0;
+        while let Some(bytes) = self.ctx.ds.recv()? {
+            received += 1;
+            if let Err(e) = self.receive(&bytes) {
+                report_recv_error(&self.ctx, e)?;
+            }
+        }
+        Ok(received)
+    }
+
+    // Decodes an envelope and handles invites. Conversation envelopes are
+    // returned with their conversation rather than received here, so the
+    // caller decides which thread processes them.
+    fn route(
         state: &Arc<RwLock<UmbraState<T>>>,
-        ds: &Arc<Mutex<T>>,
-        handler: &Arc<RwLock<Vec<Box<dyn Fn(String, ContentFrame) + Send + Sync>>>>,
+        ctx: &Arc<ClientContext<T>>,
         topic: &str,
         bytes: &[u8],
-    ) -> Result<(), UmbraError> {
-        // Placeholder for receiving messages
+    ) -> Result<Option<Routed<T>>, UmbraError> {
+        let max_envelope = ctx.config.size_limits.max_envelope;
+        limits::check(FrameKind::Envelope, bytes.len(), max_envelope)?;
 
-        let envelope = UmbraEnvelopeV1::decode(bytes)
-            .map_err(|e| UmbraError::DecodingError(e.to_string()))
-            .expect(format!("Failed to decode UmbraEnvelopeV1: {:?}", bytes).as_str());
+        let envelope =
+            UmbraEnvelopeV1::decode(bytes).map_err(UmbraError::decoding(FrameKind::Envelope))?;
 
-        Self::handle_envelope(state, ds, handler, envelope, topic)
+        Self::route_envelope(state, ctx, envelope, topic)
     }
 
-    fn get_conversation_by_hint(
+    // The inbox topic is checked first, so an invite is never also offered
+    // to a conversation
+    fn destination(
         state: &Arc<RwLock<UmbraState<T>>>,
-        hint: String,
-        salt: u64,
-    ) -> Option<Arc<Mutex<dyn Conversation<T> + Send + Sync>>> {
-        state.read().unwrap().get_conversation(hint)
+        inbox_topic: &str,
+        envelope: &UmbraEnvelopeV1,
+    ) -> Destination<T> {
+        if envelope.conversation_hint == inbox_topic {
+            return Destination::Inbox;
+        }
+        let state = state.read().unwrap();
+        let Some(convo_id) = state
+            .hints
+            .resolve(&envelope.conversation_hint, envelope.salt)
+        else {
+            return Destination::Unknown;
+        };
+        match state.get_conversation(convo_id) {
+            Some(convo) => Destination::Conversation(convo_id.to_string(), convo),
+            None => Destination::Unknown,
+        }
     }
 
-    // In the future the payload type will be tightly coupled to the Conversation
-    fn handle_envelope(
+    // Hands invites to `handle_invite` and returns conversation envelopes
+    // for the caller to receive
+    fn route_envelope(
         state: &Arc<RwLock<UmbraState<T>>>,
-        ds: &Arc<Mutex<T>>,
-        handler: &Arc<RwLock<Vec<Box<dyn Fn(String, ContentFrame) + Send + Sync>>>>,
+        ctx: &Arc<ClientContext<T>>,
         payload: UmbraEnvelopeV1,
         self_topic: &str,
-    ) -> Result<(), UmbraError> {
-        debug!("ReceivedEnvelope: {:?}", payload);
-
-        if payload.conversation_hint == self_topic {
-            debug!("Received Inbox Envelope: {:?}", payload);
-            let enc_bytes = EncryptedBytes::decode(&*payload.payload)?;
+    ) -> Result<Option<Routed<T>>, UmbraError> {
+        let redaction = &ctx.config.redaction;
+        debug!(
+            "ReceivedEnvelope: {} ({} bytes)",
+            redaction.id(&payload.conversation_hint),
+            payload.payload.len()
+        );
 
-            Self::handle_invite(state, ds, enc_bytes)?;
+        match Self::destination(state, self_topic, &payload) {
+            Destination::Inbox => {
+                debug!("Received Inbox Envelope");
+                let enc_bytes = EncryptedBytes::decode(payload.payload.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::EncryptedBytes))?;
+                Self::handle_invite(state, ctx, enc_bytes)?;
+                Ok(None)
+            }
+            Destination::Conversation(convo_id, convo) => {
+                let enc = EncryptedBytes::decode(payload.payload.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::EncryptedBytes))?;
+                Ok(Some((convo_id, convo, enc)))
+            }
+            Destination::Unknown => {
+                Self::handle_unknown(state, ctx, payload)?;
+                Ok(None)
+            }
         }
+    }
 
-        let res_convo =
-            Self::get_conversation_by_hint(state, payload.conversation_hint.clone(), payload.salt);
-
-        // TODO: Don't ignore missing conversations
-        if let None = res_convo {
-            debug!("No matching Conversation ({})", payload.conversation_hint);
-            return Ok(());
+    // The invite for a conversation may still be on its way, so its
+    // envelopes are held for a while rather than dropped
+    fn handle_unknown(
+        state: &Arc<RwLock<UmbraState<T>>>,
+        ctx: &ClientContext<T>,
+        payload: UmbraEnvelopeV1,
+    ) -> Result<(), UmbraError> {
+        let hint = ctx.config.redaction.id(&payload.conversation_hint);
+        let mut state = state.write().unwrap();
+        if !state.pending.is_enabled() {
+            debug!("No matching Conversation ({})", hint);
+            return ctx.tolerate(UmbraError::StrictModeViolation(format!(
+                "dropped envelope for unknown conversation {hint}"
+            )));
         }
-        let enc = EncryptedBytes::decode(&*payload.payload)?;
-        let convo = res_convo.unwrap().clone();
-
-        convo.lock().unwrap().recv(enc)
+        debug!("Holding envelope for unknown conversation ({})", hint);
+        if state.pending.push(payload, now_millis()) {
+            debug!("Dropped the oldest envelope for an unknown conversation");
+        }
+        Ok(())
     }
 
     fn handle_invite(
         state: &Arc<RwLock<UmbraState<T>>>,
-        ds: &Arc<Mutex<T>>,
+        ctx: &Arc<ClientContext<T>>,
         encrypted_invite: EncryptedBytes,
     ) -> Result<(), UmbraError> {
-        if !matches!(
-            encrypted_invite.encryption,
-            Some(encrypted_bytes::Encryption::Plaintext(_))
-        ) {
-            warn!("Invalid Encryption Type for Invite");
-        }
-
-        let bytes = if let encrypted_bytes::Encryption::Plaintext(b) =
-            encrypted_invite.encryption.unwrap()
-        {
-            b.payload
-        } else {
-            return Err(UmbraError::DecodingError(
-                "Invalid Encryption Type for Invite".into(),
-            ));
+        let Some(encrypted_bytes::Encryption::Plaintext(plaintext)) = encrypted_invite.encryption
+        else {
+            return Err(UmbraError::InvalidInvite("unsupported encryption"));
         };
 
-        let convo_frame = InboxV1Frame::decode(bytes.as_slice())
-            .map_err(|e| UmbraError::DecodingError(e.to_string()))?;
+        let (convo_frame, terms) = invites::decode(&plaintext.payload)?;
+        let now = now_millis();
+        if terms.revoked {
+            let invite_id = ctx.config.redaction.id(&terms.invite_id);
+            debug!("Invite {} revoked", invite_id);
+            state.write().unwrap().invites.revoke(&terms, now);
+            return Ok(());
+        }
+        state.read().unwrap().invites.check(&terms, now)?;
 
         match convo_frame
             .frame_type
-            .as_ref()
-            .ok_or(UmbraError::DecodingError("bad packet".into()))?
+            .ok_or(UmbraError::InvalidInvite("missing frame type"))?
         {
             inbox_v1_frame::FrameType::InvitePrivateV1(invite) => {
-                state
-                    .write()
-                    .unwrap()
-                    .create_conversation(ds.clone(), invite.participants.clone())
-                    .ok_or_else(|| UmbraError::UnexpectedError)?;
+                let participants = parse_participants(invite.participants)?;
+                if !participants.contains(&ctx.addr) {
+                    return Err(UmbraError::InvalidInvite("recipient is not a participant"));
+                }
+                // Peers whose key is rejected cannot draw us into a
+                // conversation; a directory which cannot be reached can
+                for peer in participants.iter().filter(|p| **p != ctx.addr) {
+                    match ctx.resolve_identity(peer) {
+                        Err(e @ UmbraError::KeyRejected { .. }) => return Err(e),
+                        Err(e) => {
+                            let peer = ctx.config.redaction.id(peer);
+                            warn!("Failed to resolve {}: {}", peer, e);
+                        }
+                        Ok(_) => {}
+                    }
+                }
+                ctx.events.emit_event(UmbraEvent::InviteReceived {
+                    participants: participants.clone(),
+                });
+                // Redelivered or repeated invites leave the conversation as it is
+                let hint_secret = (!terms.hint_secret.is_empty()).then_some(terms.hint_secret);
+                let (convo, created) = state.write().unwrap().create_conversation(
+                    ctx.clone(),
+                    ctx.addr.clone(),
+                    participants,
+                    hint_secret,
+                    true,
+                );
+                if created {
+                    Self::announce_conversation(state, ctx, &convo);
+                }
             }
         };
 
@@ -287,17 +1702,40 @@ where
     }
 }
 
-fn topic_private_convo(mut addrs: Vec<String>) -> String {
-    addrs.sort();
-    let topic = addrs.join("|");
-    format!("/private/{}", topic)
+// Errors are handed back in strict mode, for the caller to stop receiving
+fn report_recv_error<T>(ctx: &ClientContext<T>, e: UmbraError) -> Result<(), UmbraError>
+where
+    T: DeliveryService + Send + Sync + 'static,
+{
+    error!("Error receiving bytes: {:?}", e);
+    match e {
+        UmbraError::DecodeError { .. } => ctx.metrics.increment(Counter::DecodeFailures, 1),
+        UmbraError::CryptoError(_) => ctx.metrics.increment(Counter::DecryptFailures, 1),
+        _ => {}
+    }
+    ctx.events.emit_error(&e);
+    if ctx.config.strict {
+        error!("Strict mode: stopping on receive error");
+        return Err(e);
+    }
+    Ok(())
 }
 
-fn topic_inbox_convo(addr: &str) -> String {
+pub(crate) fn topic_inbox_convo(addr: &str) -> String {
     format!("/inbox/{}", addr)
 }
 
-fn sorted_pariticipants(mut participants: Vec<String>) -> Vec<String> {
+fn sorted_pariticipants(mut participants: Vec<Address>) -> Vec<Address> {
     participants.sort();
     participants
 }
+
+// Invites come from anyone, so their addresses are checked before any
+// becomes a topic
+fn parse_participants(participants: Vec<String>) -> Result<Vec<Address>, UmbraError> {
+    participants
+        .into_iter()
+        .map(Address::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(|_| UmbraError::InvalidInvite("invalid participant address"))
+}
@@ -0,0 +1,244 @@
+use std::any::{Any, TypeId, type_name};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use prost::Message;
+use umbra_content_types::{
+    ChatMessage, CompositeContent, ContentType, ImageMessage, LiveLocation, Location, Poll,
+    PollVote, Profile, RemoteAttachment, TaggedContent, VideoMessage,
+};
+
+use crate::error::FrameKind;
+use crate::{Blob, UmbraError};
+
+/// Serializes a content type to and from the bytes of a ContentFrame.
+pub trait ContentCodec<M>: Send + Sync + 'static {
+    fn encode(&self, content: &M) -> Result<Blob, UmbraError>;
+    fn decode(&self, bytes: &[u8]) -> Result<M, UmbraError>;
+}
+
+pub struct ProtobufCodec;
+
+impl<M: Message + Default> ContentCodec<M> for ProtobufCodec {
+    fn encode(&self, content: &M) -> Result<Blob, UmbraError> {
+        Ok(content.encode_to_vec())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<M, UmbraError> {
+        M::decode(bytes).map_err(UmbraError::decoding(FrameKind::Content))
+    }
+}
+
+#[cfg(feature = "bincode")]
+pub struct BincodeCodec;
+
+#[cfg(feature = "bincode")]
+impl<M: serde::Serialize + serde::de::DeserializeOwned> ContentCodec<M> for BincodeCodec {
+    fn encode(&self, content: &M) -> Result<Blob, UmbraError> {
+        bincode::serialize(content).map_err(UmbraError::encoding(FrameKind::Content))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<M, UmbraError> {
+        bincode::deserialize(bytes).map_err(UmbraError::decoding(FrameKind::Content))
+    }
+}
+
+#[cfg(feature = "json")]
+pub struct JsonCodec;
+
+#[cfg(feature = "json")]
+impl<M: serde::Serialize + serde::de::DeserializeOwned> ContentCodec<M> for JsonCodec {
+    fn encode(&self, content: &M) -> Result<Blob, UmbraError> {
+        serde_json::to_vec(content).map_err(UmbraError::encoding(FrameKind::Content))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<M, UmbraError> {
+        serde_json::from_slice(bytes).map_err(UmbraError::decoding(FrameKind::Content))
+    }
+}
+
+#[cfg(feature = "cbor")]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl<M: serde::Serialize + serde::de::DeserializeOwned> ContentCodec<M> for CborCodec {
+    fn encode(&self, content: &M) -> Result<Blob, UmbraError> {
+        let mut buf = Vec::new();
+        ciborium::into_writer(content, &mut buf)
+            .map_err(UmbraError::encoding(FrameKind::Content))?;
+        Ok(buf)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<M, UmbraError> {
+        ciborium::from_reader(bytes).map_err(UmbraError::decoding(FrameKind::Content))
+    }
+}
+
+// A codec together with the type it was registered for, so that a second
+// type claiming the same content type can be detected
+struct Registration {
+    type_id: TypeId,
+    type_name: &'static str,
+    // `Arc<dyn ContentCodec<M>>` for the registered type M
+    codec: Arc<dyn Any + Send + Sync>,
+}
+
+/// Maps content types to the codec used for them.
+pub struct CodecRegistry {
+    codecs: RwLock<HashMap<ContentType, Registration>>,
+}
+
+impl Default for CodecRegistry {
+    fn default() -> Self {
+        let registry = Self {
+            codecs: RwLock::new(HashMap::new()),
+        };
+        registry.insert::<ChatMessage, _>(ProtobufCodec);
+        registry.insert::<RemoteAttachment, _>(ProtobufCodec);
+        registry.insert::<ImageMessage, _>(ProtobufCodec);
+        registry.insert::<VideoMessage, _>(ProtobufCodec);
+        registry.insert::<Profile, _>(ProtobufCodec);
+        registry.insert::<Location, _>(ProtobufCodec);
+        registry.insert::<LiveLocation, _>(ProtobufCodec);
+        registry.insert::<Poll, _>(ProtobufCodec);
+        registry.insert::<PollVote, _>(ProtobufCodec);
+        registry.insert::<CompositeContent, _>(ProtobufCodec);
+        registry
+    }
+}
+
+impl CodecRegistry {
+    /// Register the codec for `M`. Registering `M` again replaces its codec,
+    /// including for the built-in types. Fails if another type already uses
+    /// `M::CONTENT_TYPE`, or if a new type claims a reserved domain.
+    pub fn register<M, C>(&self, codec: C) -> Result<(), UmbraError>
+    where
+        M: TaggedContent + 'static,
+        C: ContentCodec<M>,
+    {
+        let content_type = M::CONTENT_TYPE;
+        match self.codecs.read().unwrap().get(&content_type) {
+            Some(existing) if existing.type_id != TypeId::of::<M>() => {
+                return Err(UmbraError::ContentTypeCollision {
+                    content_type,
+                    existing: existing.type_name,
+                });
+            }
+            None if content_type.is_reserved() => {
+                return Err(UmbraError::ReservedContentType(content_type));
+            }
+            _ => {}
+        }
+        self.insert::<M, C>(codec);
+        Ok(())
+    }
+
+    fn insert<M, C>(&self, codec: C)
+    where
+        M: TaggedContent + 'static,
+        C: ContentCodec<M>,
+    {
+        let codec: Arc<dyn ContentCodec<M>> = Arc::new(codec);
+        self.codecs.write().unwrap().insert(
+            M::CONTENT_TYPE,
+            Registration {
+                type_id: TypeId::of::<M>(),
+                type_name: type_name::<M>(),
+                codec: Arc::new(codec),
+            },
+        );
+    }
+
+    fn codec<M: TaggedContent + 'static>(&self) -> Result<Arc<dyn ContentCodec<M>>, UmbraError> {
+        self.codecs
+            .read()
+            .unwrap()
+            .get(&M::CONTENT_TYPE)
+            .and_then(|r| r.codec.downcast_ref::<Arc<dyn ContentCodec<M>>>())
+            .cloned()
+            .ok_or(UmbraError::CodecNotFound(M::CONTENT_TYPE))
+    }
+
+    /// Whether a codec is registered for `content_type`.
+    pub fn is_registered(&self, content_type: ContentType) -> bool {
+        self.codecs.read().unwrap().contains_key(&content_type)
+    }
+
+    pub fn encode<M: TaggedContent + 'static>(&self, content: &M) -> Result<Blob, UmbraError> {
+        self.codec::<M>()?.encode(content)
+    }
+
+    pub fn decode<M: TaggedContent + 'static>(&self, bytes: &[u8]) -> Result<M, UmbraError> {
+        self.codec::<M>()?.decode(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use umbra_content_types::FIRST_CUSTOM_DOMAIN;
+
+    use super::*;
+
+    #[test]
+    fn test_protobuf_roundtrip() {
+        let registry = CodecRegistry::default();
+        let msg = ChatMessage::new("hi".into());
+
+        let bytes = registry.encode(&msg).unwrap();
+        assert_eq!(registry.decode::<ChatMessage>(&bytes).unwrap(), msg);
+    }
+
+    struct Unknown;
+    impl TaggedContent for Unknown {
+        const DOMAIN: u32 = FIRST_CUSTOM_DOMAIN;
+        const TAG: u32 = 9999;
+    }
+
+    #[test]
+    fn test_unregistered_tag() {
+        let registry = CodecRegistry::default();
+        assert!(matches!(
+            registry.encode(&Unknown),
+            Err(UmbraError::CodecNotFound(ContentType {
+                domain: FIRST_CUSTOM_DOMAIN,
+                tag: 9999
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_registration_collisions() {
+        struct Impostor;
+        impl TaggedContent for Impostor {
+            const DOMAIN: u32 = FIRST_CUSTOM_DOMAIN;
+            const TAG: u32 = 9999;
+        }
+        struct Builtin;
+        impl TaggedContent for Builtin {
+            const TAG: u32 = 9999;
+        }
+        struct NoCodec;
+        impl<M> ContentCodec<M> for NoCodec {
+            fn encode(&self, _: &M) -> Result<Blob, UmbraError> {
+                Ok(vec![])
+            }
+            fn decode(&self, _: &[u8]) -> Result<M, UmbraError> {
+                Err(UmbraError::malformed(FrameKind::Content, "no codec"))
+            }
+        }
+
+        let registry = CodecRegistry::default();
+        registry.register::<Unknown, _>(NoCodec).unwrap();
+        registry.register::<Unknown, _>(NoCodec).unwrap();
+        registry.register::<ChatMessage, _>(ProtobufCodec).unwrap();
+
+        assert!(matches!(
+            registry.register::<Impostor, _>(NoCodec),
+            Err(UmbraError::ContentTypeCollision { .. })
+        ));
+        assert!(matches!(
+            registry.register::<Builtin, _>(NoCodec),
+            Err(UmbraError::ReservedContentType(_))
+        ));
+    }
+}
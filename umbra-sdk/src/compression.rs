@@ -0,0 +1,155 @@
+use bytes::Bytes;
+use prost::Message;
+use umbra_types::common_frames::ContentFrame;
+
+use crate::capabilities::Capabilities;
+use crate::error::{FrameKind, UmbraError};
+use crate::frames::{
+    SDK_DOMAIN,
+    types::{Compressed, SdkFrameTags},
+};
+use crate::limits;
+
+// Frames smaller than this are sent as is; the savings would not cover the
+// wrapper and the CPU spent
+pub(crate) const COMPRESSION_THRESHOLD: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Algorithm {
+    Lz4 = 1,
+    Zstd = 2,
+}
+
+impl Algorithm {
+    /// The best algorithm both sides of a conversation support, if any.
+    pub fn negotiate(capabilities: Capabilities) -> Option<Self> {
+        if capabilities.contains(Capabilities::ZSTD) {
+            Some(Self::Zstd)
+        } else if capabilities.contains(Capabilities::LZ4) {
+            Some(Self::Lz4)
+        } else {
+            None
+        }
+    }
+
+    fn from_wire(algorithm: u32) -> Option<Self> {
+        match algorithm {
+            1 => Some(Self::Lz4),
+            2 => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    fn compress(self, bytes: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => Some(lz4_flex::block::compress(bytes)),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => zstd::bulk::compress(bytes, 0).ok(),
+            #[allow(unreachable_patterns)]
+            _ => {
+                let _ = bytes;
+                None
+            }
+        }
+    }
+
+    fn decompress(self, bytes: &[u8], size: usize) -> Result<Vec<u8>, UmbraError> {
+        let decoding = UmbraError::decoding(FrameKind::Sdk);
+        match self {
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => lz4_flex::block::decompress(bytes, size).map_err(decoding),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => zstd::bulk::decompress(bytes, size).map_err(decoding),
+            #[allow(unreachable_patterns)]
+            _ => {
+                let _ = (bytes, size, decoding);
+                Err(UmbraError::malformed(
+                    FrameKind::Sdk,
+                    "compression algorithm not supported",
+                ))
+            }
+        }
+    }
+}
+
+/// Wrap `frame` in a Compressed frame if it is large enough to be worth it
+/// and actually shrinks.
+pub(crate) fn compressed(frame: ContentFrame, algorithm: Option<Algorithm>) -> ContentFrame {
+    let Some(algorithm) = algorithm else {
+        return frame;
+    };
+    let encoded = frame.encode_to_vec();
+    if encoded.len() < COMPRESSION_THRESHOLD {
+        return frame;
+    }
+    match algorithm.compress(&encoded) {
+        Some(bytes) if bytes.len() < encoded.len() => ContentFrame {
+            domain: SDK_DOMAIN,
+            tag: SdkFrameTags::SdkFrameTagCompressed as u32,
+            bytes: Compressed {
+                algorithm: algorithm as u32,
+                size: encoded.len() as u64,
+                frame: bytes.into(),
+            }
+            .encode_to_vec(),
+        },
+        _ => frame,
+    }
+}
+
+/// Unwrap a Compressed frame, passing any other frame through untouched.
+/// Frames which would decompress to more than `max_size` are rejected
+/// without being decompressed.
+pub(crate) fn uncompressed(
+    frame: ContentFrame,
+    max_size: usize,
+) -> Result<ContentFrame, UmbraError> {
+    if frame.domain != SDK_DOMAIN || frame.tag != SdkFrameTags::SdkFrameTagCompressed as u32 {
+        return Ok(frame);
+    }
+    let compressed = Compressed::decode(Bytes::from(frame.bytes))
+        .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+    let size = usize::try_from(compressed.size).unwrap_or(usize::MAX);
+    limits::check(FrameKind::Conversation, size, max_size)?;
+    let algorithm = Algorithm::from_wire(compressed.algorithm)
+        .ok_or_else(|| UmbraError::malformed(FrameKind::Sdk, "unknown compression algorithm"))?;
+
+    let bytes = algorithm.decompress(&compressed.frame, size)?;
+    ContentFrame::decode(bytes.as_slice()).map_err(UmbraError::decoding(FrameKind::Sdk))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(len: usize) -> ContentFrame {
+        ContentFrame {
+            domain: 0,
+            tag: 5,
+            bytes: vec![b'a'; len],
+        }
+    }
+
+    #[test]
+    fn small_frames_are_not_compressed() {
+        let small = frame(16);
+        assert_eq!(compressed(small.clone(), Some(Algorithm::Zstd)), small);
+        assert_eq!(compressed(frame(4096), None), frame(4096));
+    }
+
+    #[test]
+    #[cfg(any(feature = "lz4", feature = "zstd"))]
+    fn round_trip_within_limit() {
+        let algorithm = Algorithm::negotiate(Capabilities::supported()).unwrap();
+        let wrapped = compressed(frame(4096), Some(algorithm));
+        assert_eq!(wrapped.tag, SdkFrameTags::SdkFrameTagCompressed as u32);
+        assert!(wrapped.bytes.len() < 4096);
+
+        assert_eq!(uncompressed(wrapped.clone(), 8192).unwrap(), frame(4096));
+        assert!(matches!(
+            uncompressed(wrapped, 1024),
+            Err(UmbraError::PayloadTooLarge { .. })
+        ));
+    }
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use crate::capabilities::Capabilities;
+use crate::limits::SizeLimits;
+use crate::pending::PendingLimits;
+use crate::ratelimit::{RateLimit, RateLimitPolicy};
+use crate::redact::RedactionPolicy;
+use crate::retention::Retention;
+
+/// Client behaviour knobs, set through `UmbraClientBuilder`.
+#[derive(Debug, Clone, Default)]
+pub struct ClientConfig {
+    /// Fail fast on conditions that are otherwise logged and tolerated, such
+    /// as unknown frame tags, envelopes with no matching conversation which
+    /// are not held, and content nobody handles. Intended for development.
+    /// Panics in handlers are reported to error handlers and then resumed
+    /// rather than contained, and receive errors stop the receive loop, or
+    /// are returned from `UmbraClient::poll`.
+    pub strict: bool,
+
+    /// Publish this client's presence (online/away, last seen) to its
+    /// conversations. Off by default for privacy.
+    pub share_presence: bool,
+
+    /// Give up on queued outgoing messages which could not be sent within
+    /// this long. They are reported as `DeliveryStatus::Expired`.
+    pub message_ttl: Option<Duration>,
+
+    /// Report frames this client does not understand, and frames carrying
+    /// fields it does not know, as `UnknownFrame` events with their original
+    /// bytes. Unknown frames are otherwise dropped, or refused in strict mode.
+    pub preserve_unknown: bool,
+
+    /// How much local history conversations keep, unless they set their own
+    /// with `Conversation::set_retention`. Kept forever by default.
+    pub retention: Retention,
+
+    /// Discard incoming messages sent longer ago than this.
+    pub max_message_age: Option<Duration>,
+
+    /// How far ahead of the local clock a sender's timestamp may be. Later
+    /// timestamps are a strict mode violation and are otherwise replaced by
+    /// the time of receipt. Unchecked if unset.
+    pub max_clock_skew: Option<Duration>,
+
+    /// Budget for outgoing content across all conversations. Control frames
+    /// such as receipts, acks and edits are not counted.
+    pub rate_limit: Option<RateLimit>,
+
+    /// Budget for outgoing messages in each conversation.
+    pub conversation_rate_limit: Option<RateLimit>,
+
+    /// Whether messages over budget are delayed or refused.
+    pub rate_limit_policy: RateLimitPolicy,
+
+    /// Largest envelopes, frames and content sent or accepted.
+    pub size_limits: SizeLimits,
+
+    /// Optional features advertised to peers. Conversations only use the
+    /// features both sides advertise.
+    pub capabilities: Capabilities,
+
+    /// Threads processing incoming envelopes. Each conversation is always
+    /// processed by the same thread, in order, so a slow handler only holds
+    /// up its own conversation. With 0, the default, everything is processed
+    /// on the receive thread.
+    pub recv_workers: usize,
+
+    /// Envelopes which arrive before their conversation, e.g. ahead of the
+    /// invite, are held and received once it is created.
+    pub pending_envelopes: PendingLimits,
+
+    /// How identifiers and payloads appear in the SDK's logs.
+    pub redaction: RedactionPolicy,
+}
@@ -0,0 +1,354 @@
+//! Wire format conformance vectors.
+//!
+//! Each vector is the canonical encoding of one message, built from fixed
+//! inputs. The encodings are checked into `vectors/` as hex, one file per
+//! vector, so a change which alters the wire format fails the tests and
+//! implementations in other languages can validate against the same files.
+
+use prost::Message;
+use umbra_content_types::ChatMessage;
+use umbra_types::base::{
+    EncryptedBytes, InboxV1Frame, ReliableBytes, UmbraEnvelopeV1, encrypted_bytes,
+};
+use umbra_types::common_frames::ContentFrame;
+use umbra_types::convos::private_v1::{PrivateV1Frame, private_v1_frame};
+
+use crate::capabilities::{Capabilities as CapabilityFlags, PROTOCOL_VERSION};
+use crate::error::{FrameKind, UmbraError};
+use crate::frames::{
+    SDK_DOMAIN,
+    types::{
+        Capabilities, Compressed, CumulativeAck, DeliveryReceipt, Edit, ExpirationPolicy,
+        HistoryQuery, HistoryResponse, InviteTerms, Presence, RepairRequest, Reply, Request,
+        Response, ResyncRequest, Retract, SdkFrameTags, StateUpdate, Timestamped,
+    },
+};
+
+// Fixed inputs, so vectors never depend on the clock
+const SENT_AT: u64 = 1_700_000_000_000;
+
+/// A named, canonically encoded message.
+#[derive(Debug, Clone)]
+pub struct Vector {
+    pub name: &'static str,
+    pub bytes: Vec<u8>,
+    kind: FrameKind,
+    check: fn(&[u8], FrameKind) -> Result<(), UmbraError>,
+}
+
+impl Vector {
+    fn new<M: Message + Default>(name: &'static str, kind: FrameKind, message: M) -> Self {
+        Self {
+            name,
+            bytes: message.encode_to_vec(),
+            kind,
+            check: check::<M>,
+        }
+    }
+
+    /// Decode the vector as its message type and check it re-encodes to the
+    /// same bytes.
+    pub fn verify(&self) -> Result<(), UmbraError> {
+        (self.check)(&self.bytes, self.kind)
+    }
+}
+
+fn chat(text: &str) -> Vec<u8> {
+    ChatMessage::new(text.into()).encode_to_vec()
+}
+
+/// Every vector, built by this version of the SDK.
+pub fn vectors() -> Vec<Vector> {
+    use FrameKind::{Content, Sdk};
+    vec![
+        Vector::new("chat_message", Content, ChatMessage::new("hello".into())),
+        Vector::new(
+            "delivery_receipt",
+            Sdk,
+            DeliveryReceipt {
+                message_ids: vec!["m1".into(), "m2".into()],
+            },
+        ),
+        Vector::new(
+            "edit",
+            Sdk,
+            Edit {
+                message_id: "m1".into(),
+                tag: 1,
+                bytes: chat("edited"),
+                domain: 0,
+            },
+        ),
+        Vector::new(
+            "retract",
+            Sdk,
+            Retract {
+                message_id: "m1".into(),
+            },
+        ),
+        Vector::new(
+            "reply",
+            Sdk,
+            Reply {
+                parent_id: "m1".into(),
+                tag: 1,
+                bytes: chat("reply"),
+                domain: 0,
+            },
+        ),
+        Vector::new(
+            "expiration_policy",
+            Sdk,
+            ExpirationPolicy {
+                expire_after_ms: 24 * 60 * 60 * 1000,
+            },
+        ),
+        Vector::new(
+            "presence",
+            Sdk,
+            Presence {
+                state: 2,
+                last_seen: SENT_AT,
+            },
+        ),
+        Vector::new(
+            "repair_request",
+            Sdk,
+            RepairRequest {
+                message_ids: vec!["m1".into(), "m2".into()],
+            },
+        ),
+        Vector::new(
+            "history_query",
+            Sdk,
+            HistoryQuery {
+                since: 7,
+                limit: 50,
+            },
+        ),
+        Vector::new(
+            "timestamped",
+            Sdk,
+            Timestamped {
+                sent_at: SENT_AT,
+                frame: ContentFrame::default().encode_to_vec().into(),
+                fallback: "hello".into(),
+                ..Default::default()
+            },
+        ),
+        Vector::new(
+            "cumulative_ack",
+            Sdk,
+            CumulativeAck {
+                lamport_timestamp: 42,
+            },
+        ),
+        Vector::new(
+            "capabilities",
+            Sdk,
+            Capabilities {
+                version: PROTOCOL_VERSION,
+                flags: CapabilityFlags::baseline().bits(),
+            },
+        ),
+        Vector::new(
+            "request",
+            Sdk,
+            Request {
+                request_id: "r1".into(),
+                domain: 0,
+                tag: 1,
+                bytes: chat("ping"),
+            },
+        ),
+        Vector::new(
+            "response",
+            Sdk,
+            Response {
+                request_id: "r1".into(),
+                domain: 0,
+                tag: 1,
+                bytes: chat("pong"),
+                error: String::new(),
+            },
+        ),
+        Vector::new(
+            "state_update",
+            Sdk,
+            StateUpdate {
+                key: "title".into(),
+                value: b"umbra".to_vec(),
+                timestamp: SENT_AT,
+                removed: false,
+            },
+        ),
+        Vector::new("resync_request", Sdk, ResyncRequest { history_limit: 50 }),
+    ]
+}
+
+/// Check an encoded envelope, as handed over by a DeliveryService. Every
+/// layer must decode and re-encode to exactly the bytes it was decoded from,
+/// so unknown fields and non-canonical encodings are rejected. Encrypted
+/// payloads are checked up to the layer that can be read without keys.
+pub fn verify(bytes: &[u8]) -> Result<(), UmbraError> {
+    let envelope = canonical::<UmbraEnvelopeV1>(bytes, FrameKind::Envelope)?;
+    let encrypted = canonical::<EncryptedBytes>(&envelope.payload, FrameKind::EncryptedBytes)?;
+    let Some(encrypted_bytes::Encryption::Plaintext(plaintext)) = encrypted.encryption else {
+        return Ok(());
+    };
+    if envelope.conversation_hint.starts_with("/inbox/") {
+        return inbox_frame(&plaintext.payload);
+    }
+
+    let reliable = canonical::<ReliableBytes>(&plaintext.payload, FrameKind::ReliableBytes)?;
+    let frame = canonical::<PrivateV1Frame>(reliable.content(), FrameKind::Conversation)?;
+    match frame.frame_type {
+        Some(private_v1_frame::FrameType::Content(content)) => content_frame(&content),
+        _ => Ok(()),
+    }
+}
+
+fn canonical<M: Message + Default>(bytes: &[u8], kind: FrameKind) -> Result<M, UmbraError> {
+    let message = M::decode(bytes).map_err(UmbraError::decoding(kind))?;
+    if message.encode_to_vec() != bytes {
+        return Err(UmbraError::malformed(kind, "not canonically encoded"));
+    }
+    Ok(message)
+}
+
+fn check<M: Message + Default>(bytes: &[u8], kind: FrameKind) -> Result<(), UmbraError> {
+    canonical::<M>(bytes, kind).map(|_| ())
+}
+
+// Invite terms are appended to the frame, and must be canonical on their own
+fn inbox_frame(bytes: &[u8]) -> Result<(), UmbraError> {
+    let frame = InboxV1Frame::decode(bytes).map_err(UmbraError::decoding(FrameKind::Inbox))?;
+    let Some(terms) = bytes.strip_prefix(frame.encode_to_vec().as_slice()) else {
+        return Err(UmbraError::malformed(
+            FrameKind::Inbox,
+            "not canonically encoded",
+        ));
+    };
+    check::<InviteTerms>(terms, FrameKind::Inbox)
+}
+
+fn content_frame(frame: &ContentFrame) -> Result<(), UmbraError> {
+    if frame.domain != SDK_DOMAIN {
+        return Ok(());
+    }
+    let Ok(tag) = SdkFrameTags::try_from(frame.tag as i32) else {
+        return Err(UmbraError::malformed(FrameKind::Sdk, "unknown SDK frame"));
+    };
+    let (bytes, kind) = (frame.bytes.as_slice(), FrameKind::Sdk);
+    match tag {
+        SdkFrameTags::SdkFrameTagDeliveryReceipt => check::<DeliveryReceipt>(bytes, kind),
+        SdkFrameTags::SdkFrameTagEdit => check::<Edit>(bytes, kind),
+        SdkFrameTags::SdkFrameTagRetract => check::<Retract>(bytes, kind),
+        SdkFrameTags::SdkFrameTagReply => check::<Reply>(bytes, kind),
+        SdkFrameTags::SdkFrameTagExpirationPolicy => check::<ExpirationPolicy>(bytes, kind),
+        SdkFrameTags::SdkFrameTagPresence => check::<Presence>(bytes, kind),
+        SdkFrameTags::SdkFrameTagRepairRequest => check::<RepairRequest>(bytes, kind),
+        SdkFrameTags::SdkFrameTagHistoryQuery => check::<HistoryQuery>(bytes, kind),
+        SdkFrameTags::SdkFrameTagHistoryResponse => canonical::<HistoryResponse>(bytes, kind)?
+            .envelopes
+            .iter()
+            .try_for_each(|envelope| verify(envelope)),
+        SdkFrameTags::SdkFrameTagTimestamped => {
+            let stamped = canonical::<Timestamped>(bytes, kind)?;
+            content_frame(&canonical::<ContentFrame>(&stamped.frame, kind)?)
+        }
+        SdkFrameTags::SdkFrameTagCumulativeAck => check::<CumulativeAck>(bytes, kind),
+        SdkFrameTags::SdkFrameTagCapabilities => check::<Capabilities>(bytes, kind),
+        // The compressed frame is opaque without the algorithm's feature
+        SdkFrameTags::SdkFrameTagCompressed => check::<Compressed>(bytes, kind),
+        SdkFrameTags::SdkFrameTagRequest => check::<Request>(bytes, kind),
+        SdkFrameTags::SdkFrameTagResponse => check::<Response>(bytes, kind),
+        SdkFrameTags::SdkFrameTagStateUpdate => check::<StateUpdate>(bytes, kind),
+        SdkFrameTags::SdkFrameTagResyncRequest => check::<ResyncRequest>(bytes, kind),
+        SdkFrameTags::SdkFrameTagUnknown => {
+            Err(UmbraError::malformed(FrameKind::Sdk, "unknown SDK frame"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::{env, fs};
+
+    use umbra_types::encryption;
+    use umbra_types::payload::ToEnvelope;
+
+    use super::*;
+    use crate::frames::timestamped;
+    use crate::notify::ContentMeta;
+
+    // Set to rewrite the checked in vectors after an intentional wire change
+    const UPDATE: &str = "UMBRA_UPDATE_VECTORS";
+
+    #[test]
+    fn vectors_match_checked_in_files() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("vectors");
+        for vector in vectors() {
+            let path = dir.join(format!("{}.hex", vector.name));
+            let encoded = hex::encode(&vector.bytes);
+            if env::var_os(UPDATE).is_some() {
+                fs::write(&path, format!("{encoded}\n")).unwrap();
+            }
+            let expected = fs::read_to_string(&path).unwrap();
+            assert_eq!(
+                encoded,
+                expected.trim(),
+                "{} changed on the wire; set {UPDATE} if intended",
+                vector.name
+            );
+            vector.verify().unwrap();
+        }
+    }
+
+    #[test]
+    fn verifies_envelopes_strictly() {
+        let frame = timestamped(
+            ContentFrame {
+                domain: 0,
+                tag: 1,
+                bytes: chat("hello"),
+            },
+            SENT_AT,
+            ContentMeta::default(),
+        );
+        let reliable = ReliableBytes {
+            message_id: "m1".into(),
+            channel_id: "convo".into(),
+            content: Some(
+                PrivateV1Frame {
+                    conversation_id: "convo".into(),
+                    frame_type: Some(private_v1_frame::FrameType::Content(frame)),
+                }
+                .encode_to_vec(),
+            ),
+            ..Default::default()
+        };
+        let envelope = EncryptedBytes {
+            encryption: Some(encrypted_bytes::Encryption::Plaintext(
+                encryption::Plaintext {
+                    payload: reliable.encode_to_vec(),
+                },
+            )),
+        }
+        .to_envelope("/hint/convo".into(), 0)
+        .encode_to_vec();
+        verify(&envelope).unwrap();
+
+        // An unknown field on the outermost layer
+        let mut extended = envelope.clone();
+        extended.extend_from_slice(&[0xf8, 0x07, 0x01]);
+        assert!(matches!(
+            verify(&extended),
+            Err(UmbraError::DecodeError {
+                frame_kind: FrameKind::Envelope,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use tracing::warn;
+use umbra_content_types::Profile;
+
+use crate::address::Address;
+use crate::attachment::BlobStore;
+use crate::codec::CodecRegistry;
+use crate::config::ClientConfig;
+use crate::convos::{ConversationInfo, MessageRecord};
+use crate::events::EventHandlers;
+use crate::identity::{Identity, IdentityDirectory, KeyBook};
+use crate::intercept::Interceptors;
+use crate::metrics::Metrics;
+use crate::outbox::Outbox;
+use crate::pseudonym::Pseudonyms;
+use crate::ratelimit::TokenBucket;
+use crate::rpc::RequestHandlers;
+use crate::store::{MessageStore, StateStore};
+use crate::watch::Watchers;
+use crate::{Blob, DeliveryService, UmbraError};
+
+/// Client-wide state shared with every conversation.
+pub(crate) struct ClientContext<T: DeliveryService + Send + Sync + 'static> {
+    pub addr: Address,
+    // Shared by every conversation and the receive loop without a lock;
+    // transports synchronise internally, as `Sync` requires
+    pub ds: T,
+    pub events: EventHandlers,
+    pub config: ClientConfig,
+    pub codecs: CodecRegistry,
+    pub directory: Option<Arc<dyn IdentityDirectory>>,
+    pub keys: KeyBook,
+    pub pseudonyms: Pseudonyms,
+    pub store: Option<Arc<dyn MessageStore>>,
+    pub state_store: Option<Arc<dyn StateStore>>,
+    pub blob_store: Option<Arc<dyn BlobStore>>,
+    pub metrics: Metrics,
+    pub outbox: Mutex<Outbox>,
+    pub rate_limiter: Mutex<Option<TokenBucket>>,
+    pub requests: RequestHandlers,
+    pub interceptors: Interceptors,
+    // Latest profile seen from each participant, including this client
+    pub profiles: RwLock<HashMap<Address, Profile>>,
+    // Here rather than in the client state, so conversations can report
+    // their own changes
+    pub convo_watchers: Watchers<ConversationInfo>,
+}
+
+impl<T> ClientContext<T>
+where
+    T: DeliveryService + Send + Sync + 'static,
+{
+    pub fn new(
+        addr: Address,
+        ds: T,
+        config: ClientConfig,
+        directory: Option<Arc<dyn IdentityDirectory>>,
+        store: Option<Arc<dyn MessageStore>>,
+        state_store: Option<Arc<dyn StateStore>>,
+        blob_store: Option<Arc<dyn BlobStore>>,
+    ) -> Self {
+        let rate_limiter = config.rate_limit.map(TokenBucket::new);
+        let keys = KeyBook::default();
+        if let Some(store) = &state_store {
+            match store.load_peer_keys() {
+                Ok(stored) => keys.restore(stored),
+                Err(e) => warn!("Failed to load peer keys: {}", e),
+            }
+        }
+        Self {
+            addr,
+            ds,
+            events: EventHandlers::new(config.strict),
+            config,
+            codecs: CodecRegistry::default(),
+            directory,
+            keys,
+            pseudonyms: Pseudonyms::default(),
+            store,
+            state_store,
+            blob_store,
+            metrics: Metrics::default(),
+            outbox: Mutex::new(Outbox::default()),
+            rate_limiter: Mutex::new(rate_limiter),
+            requests: RequestHandlers::default(),
+            interceptors: Interceptors::default(),
+            profiles: RwLock::new(HashMap::new()),
+            convo_watchers: Watchers::default(),
+        }
+    }
+
+    // Conditions which are survivable in production but indicate a protocol
+    // mistake. Strict mode surfaces them as errors, otherwise they are logged.
+    pub fn tolerate(&self, err: UmbraError) -> Result<(), UmbraError> {
+        if self.config.strict {
+            return Err(err);
+        }
+        warn!("{}", err);
+        Ok(())
+    }
+
+    // Mirror changes to local history into the MessageStore. Storage failures
+    // are logged; the in-memory history stays authoritative.
+    pub fn store_message(&self, record: &MessageRecord) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        if let Err(e) = store.append(record) {
+            let message_id = self.config.redaction.id(&record.message_id);
+            warn!("Failed to store message {}: {}", message_id, e);
+        }
+    }
+
+    pub fn save_conversation(&self, convo_id: &str, snapshot: &[u8]) {
+        let Some(store) = &self.state_store else {
+            return;
+        };
+        if let Err(e) = store.save_conversation(convo_id, snapshot) {
+            let convo_id = self.config.redaction.id(&convo_id);
+            warn!("Failed to store conversation {}: {}", convo_id, e);
+        }
+    }
+
+    // Every key learned from the directory passes the verifier
+    pub fn resolve_identity(&self, addr: &str) -> Result<Option<Identity>, UmbraError> {
+        let Some(directory) = &self.directory else {
+            return Ok(None);
+        };
+        let Some(identity) = directory.resolve(addr)? else {
+            return Ok(None);
+        };
+        let changed = self
+            .keys
+            .learn(&identity, |event| self.events.emit_key(event))?;
+        if let Some(store) = self.state_store.as_ref().filter(|_| changed) {
+            if let Err(e) = store.save_peer_key(&identity.addr, &identity.public_key) {
+                let addr = self.config.redaction.id(&identity.addr);
+                warn!("Failed to store key of {}: {}", addr, e);
+            }
+        }
+        Ok(Some(identity))
+    }
+
+    pub fn forget_message(&self, message_id: &str) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        if let Err(e) = store.delete(message_id) {
+            let message_id = self.config.redaction.id(&message_id);
+            warn!("Failed to delete stored message {}: {}", message_id, e);
+        }
+    }
+
+    pub fn save_draft(&self, convo_id: &str, draft: Option<&[u8]>) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        if let Err(e) = store.save_draft(convo_id, draft) {
+            let convo_id = self.config.redaction.id(&convo_id);
+            warn!("Failed to store draft of {}: {}", convo_id, e);
+        }
+    }
+
+    pub fn load_draft(&self, convo_id: &str) -> Option<Blob> {
+        let store = self.store.as_ref()?;
+        store.load_draft(convo_id).unwrap_or_else(|e| {
+            let convo_id = self.config.redaction.id(&convo_id);
+            warn!("Failed to load draft of {}: {}", convo_id, e);
+            None
+        })
+    }
+
+    // Profiles can arrive out of order across conversations; keep the newest
+    pub fn cache_profile(&self, addr: &Address, profile: Profile) {
+        let mut profiles = self.profiles.write().unwrap();
+        if profiles
+            .get(addr)
+            .is_some_and(|cached| cached.updated_at > profile.updated_at)
+        {
+            return;
+        }
+        profiles.insert(addr.clone(), profile);
+    }
+}
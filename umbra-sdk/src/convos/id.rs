@@ -0,0 +1,145 @@
+//! Conversation ids.
+//!
+//! An id is derived from the kind of conversation, its participants and a
+//! salt, so every participant computes the same id without agreeing on it,
+//! and an invite naming the wrong id can be spotted. Ids are what frames
+//! carry, what the client looks conversations up by, and what envelope hints
+//! are derived from.
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use sha3::{Digest, Sha3_256};
+
+use crate::UmbraError;
+use crate::address::Address;
+use crate::convos::ConversationKind;
+
+// Bytes of the digest kept, rendered as hex
+const ID_LEN: usize = 16;
+
+/// Identifies a conversation. Parse one with `str::parse`; it derefs to its
+/// hex encoding.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConversationId(String);
+
+impl ConversationId {
+    /// The id of a conversation of `kind` between `participants`, whatever
+    /// order they are given in. Conversations between the same participants
+    /// are told apart by `salt`.
+    pub fn derive(kind: ConversationKind, participants: &[Address], salt: &[u8]) -> Self {
+        let mut participants: Vec<&Address> = participants.iter().collect();
+        participants.sort();
+        participants.dedup();
+
+        let mut hasher = Sha3_256::new();
+        hasher.update(b"umbra/conversation/");
+        hasher.update(kind.label().as_bytes());
+        // Length prefixed, so no two participant lists hash the same
+        for participant in participants {
+            hasher.update((participant.len() as u32).to_be_bytes());
+            hasher.update(participant.as_bytes());
+        }
+        hasher.update(salt);
+        Self(hex::encode(&hasher.finalize()[..ID_LEN]))
+    }
+
+    /// The private conversation between `participants`. There is one per
+    /// pair, so it is unsalted.
+    pub fn private(participants: &[Address]) -> Self {
+        Self::derive(ConversationKind::Private, participants, &[])
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ConversationKind {
+    fn label(self) -> &'static str {
+        match self {
+            ConversationKind::Private => "private",
+        }
+    }
+}
+
+impl FromStr for ConversationId {
+    type Err = UmbraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let is_hex = s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+        if s.len() != 2 * ID_LEN || !is_hex {
+            return Err(UmbraError::InvalidConversationId(s.to_string()));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl From<ConversationId> for String {
+    fn from(id: ConversationId) -> Self {
+        id.0
+    }
+}
+
+impl fmt::Display for ConversationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Deref for ConversationId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for ConversationId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// Lets maps keyed by id be queried with a &str
+impl Borrow<str> for ConversationId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for ConversationId {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<String> for ConversationId {
+    fn eq(&self, other: &String) -> bool {
+        self.0 == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs(names: &[&str]) -> Vec<Address> {
+        names.iter().map(|name| name.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn ids_depend_on_participants_not_their_order() {
+        let id = ConversationId::private(&addrs(&["amal", "bola"]));
+        assert_eq!(id, ConversationId::private(&addrs(&["bola", "amal"])));
+        assert_ne!(id, ConversationId::private(&addrs(&["amal", "cara"])));
+        assert_ne!(
+            id,
+            ConversationId::derive(ConversationKind::Private, &addrs(&["amal", "bola"]), b"2")
+        );
+        assert_eq!(id.parse::<ConversationId>().unwrap(), id);
+        assert!("/private/amal|bola".parse::<ConversationId>().is_err());
+    }
+}
@@ -1 +1,88 @@
+mod id;
 pub mod private;
+
+pub use id::ConversationId;
+
+use std::time::Duration;
+
+use umbra_types::common_frames::ContentFrame;
+
+use crate::address::Address;
+use crate::events::DeliveryStatus;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConversationKind {
+    Private,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversationInfo {
+    pub convo_id: ConversationId,
+    pub kind: ConversationKind,
+    pub participants: Vec<Address>,
+    // The address this client takes part as, when created pseudonymously
+    pub pseudonym: Option<Address>,
+    // Time of the most recent message sent or received (unix ms)
+    pub last_activity: Option<u64>,
+    // Content handlers are not invoked until this time (unix ms)
+    pub mute_until: Option<u64>,
+    pub archived: bool,
+}
+
+/// Choices made when creating a conversation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConversationOptions {
+    /// Take part under a fresh pseudonym derived from the identity secret
+    /// rather than the client's own address, so the conversation cannot be
+    /// linked to the client's other conversations. Profiles are not shared
+    /// with it. Requires `UmbraClientBuilder::identity_secret`.
+    pub pseudonymous: bool,
+    /// How long the peer has to accept the invite. It never expires by
+    /// default.
+    pub invite_ttl: Option<Duration>,
+}
+
+/// A message accepted for sending by a conversation. Its delivery can be
+/// followed through `Conversation::message_status` and delivery updates.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MessageHandle {
+    pub message_id: String,
+    // Local wall clock time it was sent, in ms, as in its MessageRecord
+    pub timestamp: u64,
+}
+
+/// A message held in a conversation's local history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageRecord {
+    pub message_id: String,
+    pub convo_id: String,
+    pub sender: Address,
+    pub lamport_timestamp: u64,
+    // Local wall clock time the message was sent or received, in ms
+    pub timestamp: u64,
+    // Sender's wall clock time when it was sent, in ms. None for messages
+    // from peers which do not stamp their frames
+    pub sent_at: Option<u64>,
+    // Set when the conversation had an expiration policy at the time
+    pub expires_at: Option<u64>,
+    pub content: ContentFrame,
+    // The message this one replies to, if any
+    pub reply_to: Option<String>,
+    // Only set for messages sent by this client
+    pub status: Option<DeliveryStatus>,
+    // Previous versions of the content, oldest first
+    pub edit_history: Vec<ContentFrame>,
+    // Tombstoned by its sender; content is cleared and it is hidden from history
+    pub retracted: bool,
+    // Copied in by `UmbraClient::import_transcript` rather than sent or
+    // received by this client
+    pub imported: bool,
+}
+
+impl MessageRecord {
+    /// When the message was written, for displaying history in wall clock
+    /// order. Falls back to the local time for unstamped messages.
+    pub fn display_time(&self) -> u64 {
+        self.sent_at.unwrap_or(self.timestamp)
+    }
+}
@@ -1,29 +1,276 @@
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, mpsc::Receiver};
+use std::time::Duration;
 
+use bytes::Bytes;
 use prost::Message;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+use umbra_content_types::{ChatMessage, ContentType, Profile, TaggedContent};
 use umbra_types::{
-    base::{EncryptedBytes, ReliableBytes, encrypted_bytes},
+    base::{EncryptedBytes, ReliableBytes, UmbraEnvelopeV1, encrypted_bytes},
     common_frames::ContentFrame,
     convos::private_v1::{PrivateV1Frame, private_v1_frame},
     encryption,
     payload::ToEnvelope,
 };
 
+use crate::address::Address;
+use crate::capabilities::{Capabilities, PROTOCOL_VERSION};
+use crate::codec::CodecRegistry;
+use crate::compression::{Algorithm, compressed, uncompressed};
+use crate::context::ClientContext;
+use crate::convos::{
+    ConversationId, ConversationInfo, ConversationKind, MessageHandle, MessageRecord,
+};
+use crate::error::{FrameKind, TransportOp};
+use crate::events::{
+    DeliveryStatus, DeliveryUpdate, MessageContext, MessageDeleted, MessageEdited, Presence,
+    PresenceState, SyncEvent, UnknownFrame,
+};
+use crate::frames::{
+    SDK_DOMAIN, Stamp, is_ephemeral, timestamped,
+    types::{
+        self as frames, CumulativeAck, DeliveryReceipt, Edit, ExpirationPolicy, HistoryQuery,
+        HistoryResponse, RepairRequest, Reply, Request, Response, ResyncRequest, Retract,
+        SdkFrameTags, StateUpdate,
+    },
+    untimestamped,
+};
+use crate::hints;
+use crate::intercept::{InboundMessage, OutboundMessage, Verdict};
+use crate::limits;
+use crate::metrics::{Counter, Histogram};
+use crate::notify::{ContentMeta, NotifyLevel};
+use crate::outbox::OutboxEntry;
+use crate::poll::{self, PollResults};
+use crate::push::PushNotification;
+use crate::ratelimit::{RateLimitPolicy, TokenBucket};
+use crate::redact::RedactionPolicy;
+use crate::retention::{MessagesPruned, Retention};
+use crate::rpc::{PendingRequest, PendingResponse};
+use crate::schedule::{Schedule, ScheduledMessage};
+use crate::sds::SdsState;
+use crate::shared_state::{SharedState, StateChanged, Version};
+use crate::snapshot::{
+    SNAPSHOT_VERSION, parse_address,
+    types::{ConversationSnapshot, MessageSnapshot, OutboxSnapshot},
+};
+use crate::utils::{Instant, generate_random_string, now_millis};
+use crate::watch::{Diff, Watchers};
 use crate::{Blob, Conversation, DeliveryService, UmbraError, crypto};
 
+// A frame ready for the wire
+struct Sealed {
+    message_id: String,
+    bytes: Vec<u8>,
+    lamport_timestamp: u64,
+    ephemeral: bool,
+    expires_at: Option<u64>,
+    // When the rate limit allows the frame to go out
+    send_at: u64,
+}
+
+fn content_type(frame: &ContentFrame) -> ContentType {
+    ContentType::new(frame.domain, frame.tag)
+}
+
 /// Represents a conversation in the Umbra client.
 pub struct PrivateConversation<T: DeliveryService + Send + Sync + 'static> {
-    convo_id: String,
-    ds: Arc<Mutex<T>>,
+    convo_id: ConversationId,
+    // The client's own address, or its pseudonym in this conversation
+    self_addr: Address,
+    participants: Vec<Address>,
+    ctx: Arc<ClientContext<T>>,
+    sds: SdsState,
+    outbound: HashMap<String, DeliveryStatus>,
+    messages: Vec<MessageRecord>,
+    message_watchers: Watchers<MessageRecord>,
+    expire_after: Option<Duration>,
+    // Overrides the client's retention policy
+    retention: Option<Retention>,
+    mute_until: Option<u64>,
+    notify_level: NotifyLevel,
+    archived: bool,
+    last_activity: Option<u64>,
+    presence: HashMap<Address, Presence>,
+    rate_limiter: Option<TokenBucket>,
+    // Protocol version and capabilities the peer advertised, if any
+    peer_capabilities: Option<(u32, Capabilities)>,
+    advertised: bool,
+    // Envelope hints are derived from this, see `hints::derive`
+    hint_secret: Vec<u8>,
+    // Requests sent by this client awaiting a Response, by request_id
+    pending_requests: HashMap<String, PendingRequest>,
+    state: SharedState,
+    schedule: Schedule,
+    // Unsent message being composed, mirrored to the MessageStore
+    draft: Option<Blob>,
+    #[cfg(feature = "testing")]
+    last_envelope: Option<Blob>,
 }
 
 impl<T> PrivateConversation<T>
 where
     T: DeliveryService + Send + Sync + 'static,
 {
-    pub fn new(convo_id: String, ds: Arc<Mutex<T>>) -> Self {
-        Self { convo_id, ds }
+    pub(crate) fn new(
+        convo_id: ConversationId,
+        self_addr: Address,
+        participants: Vec<Address>,
+        hint_secret: Vec<u8>,
+        ctx: Arc<ClientContext<T>>,
+    ) -> Self {
+        let rate_limiter = ctx.config.conversation_rate_limit.map(TokenBucket::new);
+        let draft = ctx.load_draft(&convo_id);
+        Self {
+            convo_id,
+            self_addr,
+            participants,
+            ctx,
+            sds: SdsState::default(),
+            outbound: HashMap::new(),
+            messages: Vec::new(),
+            message_watchers: Watchers::default(),
+            expire_after: None,
+            retention: None,
+            mute_until: None,
+            notify_level: NotifyLevel::default(),
+            archived: false,
+            last_activity: None,
+            presence: HashMap::new(),
+            rate_limiter,
+            peer_capabilities: None,
+            advertised: false,
+            hint_secret,
+            pending_requests: HashMap::new(),
+            state: SharedState::default(),
+            schedule: Schedule::default(),
+            draft,
+            #[cfg(feature = "testing")]
+            last_envelope: None,
+        }
+    }
+
+    pub(crate) fn from_snapshot(
+        snapshot: ConversationSnapshot,
+        ctx: Arc<ClientContext<T>>,
+    ) -> Result<Self, UmbraError> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(UmbraError::UnsupportedVersion(snapshot.version));
+        }
+
+        let participants: Vec<Address> = snapshot
+            .participants
+            .into_iter()
+            .map(parse_address)
+            .collect::<Result<_, _>>()?;
+        // Snapshots from before ids were derived used the conversation's topic
+        let convo_id = snapshot
+            .convo_id
+            .parse()
+            .unwrap_or_else(|_| ConversationId::private(&participants));
+        // Snapshots from before pseudonyms have no address of their own
+        let self_addr = match snapshot.self_addr.as_str() {
+            "" => ctx.addr.clone(),
+            _ => parse_address(snapshot.self_addr)?,
+        };
+        let hint_secret = if snapshot.hint_secret.is_empty() {
+            hints::derived_secret(&convo_id)
+        } else {
+            snapshot.hint_secret
+        };
+        let mut convo = Self::new(convo_id, self_addr, participants, hint_secret, ctx);
+        convo.sds.set_lamport_timestamp(snapshot.lamport_timestamp);
+        for message_id in &snapshot.sent_ids {
+            convo.sds.mark_seen(message_id, false);
+        }
+        convo.expire_after = (snapshot.expire_after_ms != 0)
+            .then(|| Duration::from_millis(snapshot.expire_after_ms));
+        convo.retention = Retention::from_snapshot(snapshot.retention, snapshot.retention_limit);
+        convo.mute_until = (snapshot.mute_until != 0).then_some(snapshot.mute_until);
+        convo.notify_level = NotifyLevel::from_snapshot(snapshot.notify_level);
+        convo.archived = snapshot.archived;
+        convo.state = SharedState::from_snapshot(snapshot.state)?;
+        convo.schedule = Schedule::from_snapshot(&convo.convo_id, snapshot.scheduled);
+        convo.peer_capabilities = (snapshot.peer_version != 0).then(|| {
+            (
+                snapshot.peer_version,
+                Capabilities::from_bits_truncate(snapshot.peer_capabilities),
+            )
+        });
+        // Only the first exchange needs answering
+        convo.advertised = convo.peer_capabilities.is_some();
+
+        for message in snapshot.messages {
+            let record = MessageRecord::from_snapshot(&convo.convo_id, message)?;
+            if let Some(status) = record.status {
+                convo.outbound.insert(record.message_id.clone(), status);
+            }
+            convo.last_activity = convo.last_activity.max(Some(record.timestamp));
+            convo.sds.mark_seen(&record.message_id, false);
+            convo.messages.push(record);
+        }
+        // After history, which makes up the causal history, so redeliveries
+        // of anything else seen before the restart are still dropped
+        convo.sds.restore_seen(snapshot.seen_ids);
+
+        // Pending sends are retried on the next tick
+        for pending in snapshot.outbox {
+            convo.ctx.outbox.lock().unwrap().restore(OutboxEntry {
+                convo_id: convo.convo_id.to_string(),
+                message_id: pending.message_id,
+                envelope: pending.envelope,
+                attempts: pending.attempts,
+                next_attempt: 0,
+                expires_at: (pending.expires_at != 0).then_some(pending.expires_at),
+            });
+        }
+
+        Ok(convo)
+    }
+
+    pub fn participants(&self) -> &[Address] {
+        &self.participants
+    }
+
+    pub(crate) fn hint_secret(&self) -> &[u8] {
+        &self.hint_secret
+    }
+
+    // In a private conversation anything not sent by us came from the peer
+    fn peer(&self) -> Address {
+        self.participants
+            .iter()
+            .find(|p| **p != self.self_addr)
+            .cloned()
+            .unwrap_or_else(|| self.self_addr.clone())
+    }
+
+    fn redaction(&self) -> RedactionPolicy {
+        self.ctx.config.redaction
+    }
+
+    // Messages are stamped locally on both sides from the shared policy
+    fn expiry_for(&self, timestamp: u64) -> Option<u64> {
+        self.expire_after
+            .map(|d| timestamp.saturating_add(d.as_millis() as u64))
+    }
+
+    fn push_message(&mut self, record: MessageRecord) {
+        let last_activity = self.last_activity.max(Some(record.timestamp));
+        self.ctx.store_message(&record);
+        self.messages.push(record.clone());
+        self.message_watchers.notify(Diff::Added(record));
+        if last_activity != self.last_activity {
+            self.last_activity = last_activity;
+            self.info_changed();
+        }
+    }
+
+    // Watchers of the client's conversation list follow mute, archive and
+    // activity changes
+    fn info_changed(&self) {
+        self.ctx.convo_watchers.notify(Diff::Updated(self.info()));
     }
 
     fn encrypt(&self, frame: &[u8]) -> EncryptedBytes {
@@ -38,82 +285,1827 @@ where
 
     fn decrypt(enc_bytes: EncryptedBytes) -> Result<ReliableBytes, UmbraError> {
         // Ensure the encryption type was "???"
-        let buf = if let encrypted_bytes::Encryption::Plaintext(r) = enc_bytes.encryption.unwrap() {
-            Ok(r.payload)
-        } else {
-            Err(UmbraError::DecodingError("Unsupported Enc".into()))
-        }?;
+        let Some(encrypted_bytes::Encryption::Plaintext(r)) = enc_bytes.encryption else {
+            return Err(UmbraError::CryptoError(
+                "unsupported encryption scheme".into(),
+            ));
+        };
 
-        let plaintext = buf;
+        let plaintext = r.payload;
 
         ReliableBytes::decode(plaintext.as_slice())
-            .map_err(|e| UmbraError::DecodingError(e.to_string()))
+            .map_err(UmbraError::decoding(FrameKind::ReliableBytes))
     }
-}
 
-impl<T> Conversation<T> for PrivateConversation<T>
-where
-    T: DeliveryService + Send + Sync + 'static,
-{
-    // Returns an encoded payload for testing.
-    fn send(&self, tag: u32, message: Blob) -> Vec<u8> {
+    // Wraps a ContentFrame for the wire: assigns it a message_id and lamport
+    // timestamp, encrypts it and records it with the reliability layer.
+    fn seal(&mut self, content: ContentFrame, meta: ContentMeta, now: u64, send_at: u64) -> Sealed {
+        let ephemeral = is_ephemeral(&content);
+        let content = if ephemeral {
+            content
+        } else {
+            timestamped(content, now, meta)
+        };
+        let content = compressed(content, Algorithm::negotiate(self.capabilities()));
+        let expires_at = self
+            .ctx
+            .config
+            .message_ttl
+            .map(|ttl| now.saturating_add(ttl.as_millis() as u64));
+
         // Build Frame
         let frame = PrivateV1Frame {
-            conversation_id: self.convo_id(),
-            frame_type: Some(private_v1_frame::FrameType::Content(ContentFrame {
-                domain: 0,
-                tag,
-                bytes: message,
-            })),
+            conversation_id: self.convo_id.to_string(),
+            frame_type: Some(private_v1_frame::FrameType::Content(content)),
         };
 
         let encoded_frame = frame.encode_to_vec();
 
+        let lamport_timestamp = self.sds.next_lamport_timestamp();
+
+        // Identical frames sent twice must still be distinguishable
+        let mut id_preimage = encoded_frame.clone();
+        id_preimage.extend_from_slice(self.self_addr.as_bytes());
+        id_preimage.extend_from_slice(&lamport_timestamp.to_be_bytes());
+        let message_id = crypto::hash_to_string(&id_preimage);
+
         // Wrap in Reliable Bytes
         let reliable_bytes = ReliableBytes {
-            message_id: crypto::hash_to_string(&encoded_frame),
-            channel_id: self.convo_id(),
-            lamport_timestamp: 0,
-            causal_history: vec![],
-            bloom_filter: vec![],
+            message_id: message_id.clone(),
+            channel_id: self.convo_id.to_string(),
+            lamport_timestamp,
+            causal_history: self.sds.causal_history(),
+            bloom_filter: self.sds.bloom_filter(),
             content: Some(encoded_frame),
         };
 
-        // Encrypt and Wrap in Envelope
+        // Encrypt and Wrap in Envelope. The salt tells the receiver which
+        // epoch's hint was used.
+        let epoch = hints::epoch(now);
         let bytes = self
             .encrypt(&reliable_bytes.encode_to_vec())
-            .to_envelope(self.convo_id(), 0)
+            .to_envelope(hints::derive(&self.hint_secret, epoch), epoch)
             .encode_to_vec();
 
-        self.ds.lock().unwrap().send(bytes.clone()).unwrap();
-        bytes
+        self.sds.mark_seen(&message_id, ephemeral);
+        if !ephemeral {
+            self.sds.record_sent(&message_id, lamport_timestamp);
+            self.sds
+                .cache_sent(&message_id, lamport_timestamp, bytes.clone());
+        }
+
+        Sealed {
+            message_id,
+            bytes,
+            lamport_timestamp,
+            ephemeral,
+            expires_at,
+            send_at,
+        }
     }
 
-    // returns any message which was not handled by this conversation
-    fn recv(&self, enc_bytes: EncryptedBytes) -> Result<(), UmbraError> {
-        let sds_frame = Self::decrypt(enc_bytes)?;
+    // Charges `n` content messages against the client and conversation
+    // budgets. Returns when each may be sent; anything later than `now` is
+    // delayed by the rate limit.
+    fn admit(&mut self, n: u32, now: u64) -> Result<Vec<u64>, UmbraError> {
+        let policy = self.ctx.config.rate_limit_policy;
+        let mut global = self.ctx.rate_limiter.lock().unwrap();
+        let mut buckets: Vec<&mut TokenBucket> = global
+            .iter_mut()
+            .chain(self.rate_limiter.iter_mut())
+            .collect();
 
-        info!("Received SDS Frame: {:?}", sds_frame);
-        // Handle SDS data
-        let convo_frame = PrivateV1Frame::decode(sds_frame.content())?;
+        match policy {
+            RateLimitPolicy::Reject => {
+                let wait = buckets
+                    .iter_mut()
+                    .map(|b| b.wait_time(n, now))
+                    .max()
+                    .unwrap_or(0);
+                if wait > 0 {
+                    return Err(UmbraError::RateLimited(Duration::from_millis(wait)));
+                }
+                for bucket in buckets {
+                    bucket.take(n, now);
+                }
+                Ok(vec![now; n as usize])
+            }
+            RateLimitPolicy::Delay => Ok((0..n)
+                .map(|_| {
+                    let wait = buckets
+                        .iter_mut()
+                        .map(|b| {
+                            b.take(1, now);
+                            b.wait_time(0, now)
+                        })
+                        .max()
+                        .unwrap_or(0);
+                    now.saturating_add(wait)
+                })
+                .collect()),
+        }
+    }
+
+    // Hands sealed frames to the DeliveryService under a single lock. Durable
+    // frames which cannot be sent now, because the DeliveryService is down or
+    // the rate limit delays them, are queued in the outbox and reported as
+    // Pending; only ephemeral frames surface the error.
+    fn publish(&mut self, sealed: &[Sealed], now: u64) -> Vec<Result<DeliveryStatus, UmbraError>> {
+        let (online, res) = {
+            let ds = &self.ctx.ds;
+            let online = ds.is_connected();
+            let outgoing: Vec<Blob> = sealed
+                .iter()
+                .filter(|s| s.ephemeral || (online && s.send_at <= now))
+                .map(|s| s.bytes.clone())
+                .collect();
+            let res = if outgoing.is_empty() {
+                Ok(())
+            } else {
+                ds.send_batch(&outgoing)
+            };
+            if res.is_ok() {
+                let sent = outgoing.len() as u64;
+                self.ctx.metrics.increment(Counter::EnvelopesSent, sent);
+            }
+            (online, res)
+        };
+
+        let mut outbox = self.ctx.outbox.lock().unwrap();
+        sealed
+            .iter()
+            .map(|s| {
+                // Hold durable frames until the DeliveryService comes back
+                if !s.ephemeral && (!online || s.send_at > now) {
+                    let message_id = self.redaction().id(&s.message_id);
+                    debug!("Queueing {} until {}", message_id, s.send_at);
+                    outbox.defer(
+                        &self.convo_id,
+                        &s.message_id,
+                        s.bytes.clone(),
+                        s.expires_at,
+                        s.send_at,
+                    );
+                    return Ok(DeliveryStatus::Pending);
+                }
+                match &res {
+                    Ok(()) => Ok(DeliveryStatus::Sent),
+                    // One failure covers the whole batch, so each frame gets
+                    // its own copy of the cause
+                    Err(e) if s.ephemeral || !e.is_retryable() => {
+                        Err(UmbraError::transport(TransportOp::Publish, e.to_string()))
+                    }
+                    Err(e) => {
+                        let message_id = self.redaction().id(&s.message_id);
+                        warn!("Failed to send {}, queued for retry: {:?}", message_id, e);
+                        outbox.push(
+                            &self.convo_id,
+                            &s.message_id,
+                            s.bytes.clone(),
+                            s.expires_at,
+                            now,
+                        );
+                        Ok(DeliveryStatus::Pending)
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn publish_one(&mut self, sealed: &Sealed, now: u64) -> Result<DeliveryStatus, UmbraError> {
+        // publish returns one result per frame
+        self.publish(std::slice::from_ref(sealed), now).remove(0)
+    }
+
+    // Seals and publishes a single frame. Returns the message_id, the encoded
+    // envelope and the publish result.
+    fn send_frame(
+        &mut self,
+        content: ContentFrame,
+    ) -> (String, Vec<u8>, Result<DeliveryStatus, UmbraError>) {
+        let now = now_millis();
+        let sealed = self.seal(content, ContentMeta::default(), now, now);
+        let res = self.publish_one(&sealed, now);
+        (sealed.message_id, sealed.bytes, res)
+    }
+
+    fn send_sdk_frame<M: Message>(
+        &mut self,
+        tag: SdkFrameTags,
+        frame: &M,
+    ) -> Result<String, UmbraError> {
+        let frame = ContentFrame {
+            domain: SDK_DOMAIN,
+            tag: tag as u32,
+            bytes: frame.encode_to_vec(),
+        };
+        self.check_frame_size(&frame, None)?;
+        let (message_id, _, res) = self.send_frame(frame);
+        res.map(|_| message_id)
+    }
+
+    // Runs outbound interceptors over app content before it is wrapped
+    fn intercept(
+        &self,
+        content: ContentFrame,
+        mut meta: ContentMeta,
+        reply_to: Option<&str>,
+    ) -> Result<(ContentFrame, ContentMeta), UmbraError> {
+        let mut message = OutboundMessage {
+            convo_id: self.convo_id.to_string(),
+            content,
+            fallback: meta.fallback.take(),
+            reply_to: reply_to.map(str::to_string),
+        };
+        self.ctx.interceptors.outbound(&mut message)?;
+        meta.fallback = message.fallback;
+        Ok((message.content, meta))
+    }
+
+    // Publishes app content and records it in local history. `wire` is the
+    // frame actually sent, which may wrap `content` (e.g. in a Reply).
+    fn send_content(
+        &mut self,
+        wire: ContentFrame,
+        content: ContentFrame,
+        meta: ContentMeta,
+        reply_to: Option<String>,
+    ) -> Result<MessageHandle, UmbraError> {
+        self.check_content_size(content.bytes.len())?;
+        self.check_frame_size(&wire, Some(&meta))?;
+        let now = now_millis();
+        let send_at = self.admit(1, now)?.pop().unwrap_or(now);
+        let sealed = self.seal(wire, meta, now, send_at);
+        self.record_outgoing(&sealed, content, reply_to, now);
+
+        let res = self.publish_one(&sealed, now);
+        self.settle(&sealed.message_id, res);
+        Ok(self.handle(&sealed, now))
+    }
+
+    fn handle(&mut self, sealed: &Sealed, timestamp: u64) -> MessageHandle {
+        #[cfg(feature = "testing")]
+        {
+            self.last_envelope = Some(sealed.bytes.clone());
+        }
+        MessageHandle {
+            message_id: sealed.message_id.clone(),
+            timestamp,
+        }
+    }
+
+    fn record_outgoing(
+        &mut self,
+        sealed: &Sealed,
+        content: ContentFrame,
+        reply_to: Option<String>,
+        timestamp: u64,
+    ) {
+        self.push_message(MessageRecord {
+            message_id: sealed.message_id.clone(),
+            convo_id: self.convo_id.to_string(),
+            sender: self.self_addr.clone(),
+            lamport_timestamp: sealed.lamport_timestamp,
+            timestamp,
+            sent_at: Some(timestamp),
+            expires_at: self.expiry_for(timestamp),
+            content,
+            reply_to,
+            status: None,
+            edit_history: vec![],
+            retracted: false,
+            imported: false,
+        });
+    }
+
+    fn settle(&mut self, message_id: &str, res: Result<DeliveryStatus, UmbraError>) {
+        match res {
+            Ok(status) => self.set_status(message_id, status),
+            Err(e) => {
+                let redacted = self.redaction().id(&message_id);
+                warn!("Failed to send message {}: {:?}", redacted, e);
+                self.set_status(message_id, DeliveryStatus::Failed);
+            }
+        }
+    }
+
+    fn deliver_content(
+        &mut self,
+        sds_frame: &ReliableBytes,
+        frame: ContentFrame,
+        stamp: Option<Stamp>,
+        reply_to: Option<String>,
+    ) -> Result<(), UmbraError> {
+        // Already marked seen, so the sender is not asked for it again
+        if let Err(e) = self.check_content_size(frame.bytes.len()) {
+            let message_id = self.redaction().id(&sds_frame.message_id);
+            warn!("Dropping {}: {}", message_id, e);
+            self.ctx.events.emit_error(&e);
+            return Ok(());
+        }
+
+        let (sent_at, mut meta) = match stamp {
+            Some(stamp) => (Some(stamp.sent_at), stamp.meta),
+            None => (None, ContentMeta::default()),
+        };
+        let mut message = InboundMessage {
+            convo_id: self.convo_id.to_string(),
+            message_id: sds_frame.message_id.clone(),
+            sender: self.peer(),
+            content: frame,
+            sent_at,
+            reply_to,
+        };
+        let verdict = self
+            .ctx
+            .interceptors
+            .inbound(&self.ctx.events, &mut message);
+        if verdict == Verdict::Drop {
+            let message_id = self.redaction().id(&sds_frame.message_id);
+            debug!("Interceptor dropped {}", message_id);
+            return Ok(());
+        }
+        let InboundMessage {
+            content: frame,
+            sent_at,
+            reply_to,
+            ..
+        } = message;
+
+        info!("conttent {}", self.redaction().payload(&frame));
+        self.send_receipt(vec![sds_frame.message_id.clone()]);
+        let timestamp = now_millis();
+        self.push_message(MessageRecord {
+            message_id: sds_frame.message_id.clone(),
+            convo_id: self.convo_id.to_string(),
+            sender: self.peer(),
+            lamport_timestamp: sds_frame.lamport_timestamp,
+            timestamp,
+            sent_at,
+            expires_at: self.expiry_for(timestamp),
+            content: frame.clone(),
+            reply_to,
+            status: None,
+            edit_history: vec![],
+            retracted: false,
+            imported: false,
+        });
+
+        let is_profile = frame.domain == Profile::DOMAIN && frame.tag == Profile::TAG;
+        if is_profile {
+            match Profile::decode(frame.bytes.as_slice()) {
+                Ok(profile) => self.ctx.cache_profile(&self.peer(), profile),
+                Err(e) => self
+                    .ctx
+                    .tolerate(UmbraError::decoding(FrameKind::Content)(e))?,
+            }
+        }
+
+        // Muted conversations still store messages, they just stay quiet
+        if self.is_muted() {
+            let message_id = self.redaction().id(&sds_frame.message_id);
+            debug!("Conversation muted, not dispatching {}", message_id);
+            return Ok(());
+        }
+
+        // History keeps the original; handlers see the fallback text as a
+        // ChatMessage when this client cannot decode the content
+        let frame = match meta.fallback.take() {
+            Some(text) if !self.ctx.codecs.is_registered(content_type(&frame)) => {
+                debug!("No codec for {}, delivering fallback", content_type(&frame));
+                match self.ctx.codecs.encode(&ChatMessage::new(text)) {
+                    Ok(bytes) => ContentFrame {
+                        domain: ChatMessage::DOMAIN,
+                        tag: ChatMessage::TAG,
+                        bytes,
+                    },
+                    Err(e) => {
+                        warn!("Failed to encode fallback: {}", e);
+                        frame
+                    }
+                }
+            }
+            _ => frame,
+        };
+
+        // Profiles are cached whether or not the app handles them
+        let started = Instant::now();
+        let context = MessageContext {
+            convo_id: self.convo_id.to_string(),
+            message_id: sds_frame.message_id.clone(),
+            sender: self.peer(),
+            lamport_timestamp: sds_frame.lamport_timestamp,
+            causal_history: sds_frame.causal_history.clone(),
+            sent_at,
+            received_at: timestamp,
+            notify: self.notify_level.notifies(&meta, &self.self_addr),
+            mentions: meta.mentions,
+            priority: meta.priority,
+        };
+        let delivered = self.ctx.events.emit_content(&context, &frame);
+        let elapsed = started.elapsed().as_secs_f64();
+        self.ctx.metrics.observe(Histogram::HandlerLatency, elapsed);
+        if delivered == 0 && !is_profile {
+            self.ctx.tolerate(UmbraError::StrictModeViolation(format!(
+                "no content handler for tag {}",
+                frame.tag
+            )))?;
+        }
+        Ok(())
+    }
+
+    // Resend queued envelopes whose backoff has elapsed
+    fn retry_outbox(&mut self, now: u64) {
+        self.expire_outbox(now);
+
+        // Waiting out a disconnect should not use up retry attempts
+        if !self.ctx.ds.is_connected() {
+            return;
+        }
+
+        let due = self
+            .ctx
+            .outbox
+            .lock()
+            .unwrap()
+            .take_due(&self.convo_id, now);
+        self.send_queued(due, now);
+    }
 
-        match convo_frame
-            .frame_type
-            .as_ref()
-            .ok_or(UmbraError::DecodingError("bad packet".into()))?
+    // Drop queued messages whose TTL passed before they could be sent
+    fn expire_outbox(&mut self, now: u64) {
+        let expired = self
+            .ctx
+            .outbox
+            .lock()
+            .unwrap()
+            .take_expired(&self.convo_id, now);
+        for entry in expired {
+            debug!(
+                "Unsent message expired: {}",
+                self.redaction().id(&entry.message_id)
+            );
+            if self.outbound.contains_key(&entry.message_id) {
+                self.set_status(&entry.message_id, DeliveryStatus::Expired);
+            }
+        }
+    }
+
+    fn send_queued(&mut self, entries: Vec<OutboxEntry>, now: u64) {
+        for entry in entries {
+            let res = self.ctx.ds.send(entry.envelope.clone());
+            if res.is_ok() {
+                self.ctx.metrics.increment(Counter::EnvelopesSent, 1);
+            }
+            let message_id = entry.message_id.clone();
+            // Edits and retractions are retried but carry no status of their own
+            let tracked = self.outbound.contains_key(&message_id);
+            match res {
+                Ok(()) if tracked => self.set_status(&message_id, DeliveryStatus::Sent),
+                Ok(()) => {}
+                Err(e) => {
+                    let redacted = self.redaction().id(&message_id);
+                    warn!("Retry of {} failed: {:?}", redacted, e);
+                    let requeued =
+                        e.is_retryable() && self.ctx.outbox.lock().unwrap().reschedule(entry, now);
+                    if !requeued && tracked {
+                        warn!("Giving up on {}", redacted);
+                        self.set_status(&message_id, DeliveryStatus::Failed);
+                    }
+                }
+            }
+        }
+    }
+
+    // Replays envelopes from a HistoryResponse through the normal receive
+    // path, so anything already seen is dropped as a duplicate
+    fn recv_history(&mut self, response: HistoryResponse) -> Result<(), UmbraError> {
+        for bytes in response.envelopes {
+            let envelope = UmbraEnvelopeV1::decode(bytes)
+                .map_err(UmbraError::decoding(FrameKind::Envelope))?;
+            if envelope.conversation_hint != hints::derive(&self.hint_secret, envelope.salt) {
+                self.ctx.tolerate(UmbraError::StrictModeViolation(format!(
+                    "history response carried envelope for {}",
+                    self.redaction().id(&envelope.conversation_hint)
+                )))?;
+                continue;
+            }
+            let enc_bytes = EncryptedBytes::decode(envelope.payload.as_slice())
+                .map_err(UmbraError::decoding(FrameKind::EncryptedBytes))?;
+            self.recv(enc_bytes)?;
+        }
+        Ok(())
+    }
+
+    // Ask peers to re-send missing messages, at most once per repair interval
+    fn request_repair(&mut self, missing: Vec<String>) {
+        let message_ids = self.sds.repair_candidates(missing, now_millis());
+        if message_ids.is_empty() {
+            return;
+        }
+
+        debug!(
+            "Requesting repair of {}",
+            self.redaction().ids(&message_ids)
+        );
+        let request = RepairRequest { message_ids };
+        if let Err(e) = self.send_sdk_frame(SdkFrameTags::SdkFrameTagRepairRequest, &request) {
+            warn!("Failed to send repair request: {:?}", e);
+        }
+    }
+
+    // Re-publish cached envelopes; receivers drop any they already have
+    fn retransmit(&mut self, message_ids: &[String]) {
+        for message_id in message_ids {
+            let redacted = self.redaction().id(message_id);
+            let Some(envelope) = self.sds.cached_envelope(message_id).cloned() else {
+                debug!("Cannot repair {}, no longer cached", redacted);
+                continue;
+            };
+
+            debug!("Retransmitting {}", redacted);
+            match self.ctx.ds.send(envelope) {
+                Ok(()) => {
+                    self.ctx.metrics.increment(Counter::EnvelopesSent, 1);
+                    self.ctx.metrics.increment(Counter::Retransmissions, 1);
+                }
+                Err(e) => warn!("Failed to retransmit {}: {:?}", redacted, e),
+            }
+        }
+    }
+
+    // Answer a ResyncRequest with everything the peer can rebuild its view
+    // from. Only this client's writes to the shared state are sent, as
+    // StateUpdates are attributed to their sender
+    fn resync(&mut self, history_limit: usize) -> Result<(), UmbraError> {
+        debug!("Resyncing {}", self.redaction().id(&self.convo_id));
+        self.advertise_capabilities();
+        if let Some(expire_after) = self
+            .expire_after
+            .filter(|_| self.supports(Capabilities::EXPIRATION))
         {
+            let policy = ExpirationPolicy {
+                expire_after_ms: expire_after.as_millis() as u64,
+            };
+            self.send_sdk_frame(SdkFrameTags::SdkFrameTagExpirationPolicy, &policy)?;
+        }
+        if self.supports(Capabilities::STATE) {
+            let updates: Vec<StateUpdate> = self
+                .state
+                .written_by(&self.self_addr)
+                .map(|(key, value, timestamp)| StateUpdate {
+                    key: key.to_string(),
+                    value: value.map(<[u8]>::to_vec).unwrap_or_default(),
+                    timestamp,
+                    removed: value.is_none(),
+                })
+                .collect();
+            for update in updates {
+                self.send_sdk_frame(SdkFrameTags::SdkFrameTagStateUpdate, &update)?;
+            }
+        }
+        let envelopes = self.sds.recent_history(history_limit);
+        if self.supports(Capabilities::HISTORY) && !envelopes.is_empty() {
+            let response = HistoryResponse {
+                envelopes: envelopes.into_iter().map(Bytes::from).collect(),
+            };
+            self.send_sdk_frame(SdkFrameTags::SdkFrameTagHistoryResponse, &response)?;
+        }
+        Ok(())
+    }
+
+    // Tell the peer which features this client supports
+    pub(crate) fn advertise_capabilities(&mut self) {
+        let frame = frames::Capabilities {
+            version: PROTOCOL_VERSION,
+            flags: self.ctx.config.capabilities.bits(),
+        };
+        self.advertised = true;
+        if let Err(e) = self.send_sdk_frame(SdkFrameTags::SdkFrameTagCapabilities, &frame) {
+            warn!("Failed to advertise capabilities: {:?}", e);
+        }
+    }
+
+    fn check_content_size(&self, size: usize) -> Result<(), UmbraError> {
+        let limit = self.ctx.config.size_limits.max_content;
+        limits::check(FrameKind::Content, size, limit)
+    }
+
+    // Fallback text and mentions travel next to the frame, so they count
+    // towards its size
+    fn check_frame_size(
+        &self,
+        frame: &ContentFrame,
+        meta: Option<&ContentMeta>,
+    ) -> Result<(), UmbraError> {
+        let size = frame.encoded_len() + meta.map_or(0, ContentMeta::encoded_len);
+        limits::check(
+            FrameKind::Conversation,
+            size,
+            self.ctx.config.size_limits.max_frame,
+        )
+    }
+
+    fn supports(&self, capability: Capabilities) -> bool {
+        self.capabilities().contains(capability)
+    }
+
+    fn require(&self, capability: Capabilities) -> Result<(), UmbraError> {
+        if !self.supports(capability) {
+            return Err(UmbraError::UnsupportedByPeer(capability));
+        }
+        Ok(())
+    }
+
+    fn send_receipt(&mut self, message_ids: Vec<String>) {
+        if !self.supports(Capabilities::RECEIPTS) {
+            return;
+        }
+        let receipt = DeliveryReceipt { message_ids };
+        if let Err(e) = self.send_sdk_frame(SdkFrameTags::SdkFrameTagDeliveryReceipt, &receipt) {
+            warn!("Failed to send delivery receipt: {:?}", e);
+        }
+    }
+
+    // Everything we sent up to `lamport_timestamp` reached the peer's device
+    fn apply_cumulative_ack(&mut self, lamport_timestamp: u64) {
+        self.sds.prune_acked(lamport_timestamp);
+
+        let delivered: Vec<String> = self
+            .messages
+            .iter()
+            .filter(|m| {
+                m.sender == self.self_addr
+                    && m.lamport_timestamp <= lamport_timestamp
+                    && m.status == Some(DeliveryStatus::Sent)
+            })
+            .map(|m| m.message_id.clone())
+            .collect();
+        for message_id in delivered {
+            self.set_status(&message_id, DeliveryStatus::Delivered);
+        }
+    }
+
+    // Only the original sender of a message may edit it
+    fn apply_edit(&mut self, editor: &Address, edit: Edit) -> Result<(), UmbraError> {
+        let record = self
+            .messages
+            .iter_mut()
+            .find(|m| m.message_id == edit.message_id && !m.retracted)
+            .ok_or_else(|| UmbraError::MessageNotFound(edit.message_id.clone()))?;
+
+        if record.sender != *editor {
+            return Err(UmbraError::PermissionDenied(format!(
+                "{} cannot edit message {}",
+                editor, edit.message_id
+            )));
+        }
+
+        let new = ContentFrame {
+            domain: edit.domain,
+            tag: edit.tag,
+            bytes: edit.bytes,
+        };
+        let old = std::mem::replace(&mut record.content, new.clone());
+        record.edit_history.push(old.clone());
+        self.ctx.store_message(record);
+        self.message_watchers.notify(Diff::Updated(record.clone()));
+
+        self.ctx.events.emit_message_edited(MessageEdited {
+            convo_id: self.convo_id.to_string(),
+            message_id: edit.message_id,
+            editor: editor.clone(),
+            old,
+            new,
+        });
+        Ok(())
+    }
+
+    fn set_status(&mut self, message_id: &str, status: DeliveryStatus) {
+        self.outbound.insert(message_id.to_string(), status);
+        if let Some(record) = self
+            .messages
+            .iter_mut()
+            .find(|m| m.message_id == message_id)
+        {
+            record.status = Some(status);
+            self.ctx.store_message(record);
+            self.message_watchers.notify(Diff::Updated(record.clone()));
+        }
+        self.ctx.events.emit_delivery_update(DeliveryUpdate {
+            convo_id: self.convo_id.to_string(),
+            message_id: message_id.to_string(),
+            status,
+        });
+    }
+
+    // Only the original sender of a message may retract it
+    fn apply_retract(&mut self, sender: &Address, retract: Retract) -> Result<(), UmbraError> {
+        let record = self
+            .messages
+            .iter_mut()
+            .find(|m| m.message_id == retract.message_id && !m.retracted)
+            .ok_or_else(|| UmbraError::MessageNotFound(retract.message_id.clone()))?;
+
+        if record.sender != *sender {
+            return Err(UmbraError::PermissionDenied(format!(
+                "{} cannot retract message {}",
+                sender, retract.message_id
+            )));
+        }
+
+        record.retracted = true;
+        record.content.bytes.clear();
+        record.edit_history.clear();
+        // Tombstones are kept so the retraction survives a restart
+        self.ctx.store_message(record);
+        self.message_watchers.notify(Diff::Removed(record.clone()));
+
+        self.ctx.events.emit_message_deleted(MessageDeleted {
+            convo_id: self.convo_id.to_string(),
+            message_id: retract.message_id,
+            sender: sender.clone(),
+        });
+        Ok(())
+    }
+
+    // A sender clock running ahead would sort its messages after ones
+    // written later, so such timestamps are replaced by the time of receipt
+    fn check_clock_skew(&self, sent_at: u64, now: u64) -> Result<u64, UmbraError> {
+        let Some(skew) = self.ctx.config.max_clock_skew else {
+            return Ok(sent_at);
+        };
+        if sent_at <= now.saturating_add(skew.as_millis() as u64) {
+            return Ok(sent_at);
+        }
+        self.ctx.tolerate(UmbraError::StrictModeViolation(format!(
+            "sender clock {}ms ahead",
+            sent_at - now
+        )))?;
+        Ok(now)
+    }
+
+    fn process(&mut self, sds_frame: ReliableBytes) -> Result<(), UmbraError> {
+        info!(
+            "Received SDS Frame: {}",
+            self.redaction().payload(&sds_frame)
+        );
+
+        // Handle SDS data
+        let convo_frame = PrivateV1Frame::decode(sds_frame.content())
+            .map_err(UmbraError::decoding(FrameKind::Conversation))?;
+        // prost skips fields it does not know, so a frame which re-encodes
+        // shorter than it arrived carried some
+        let has_unknown_fields = convo_frame.encoded_len() < sds_frame.content().len();
+        let Some(frame_type) = convo_frame.frame_type else {
+            if !self.ctx.config.preserve_unknown {
+                return Err(UmbraError::malformed(
+                    FrameKind::Conversation,
+                    "missing frame type",
+                ));
+            }
+            // A frame type added after this client was built
+            self.sds.mark_seen(&sds_frame.message_id, false);
+            self.report_unknown(
+                &sds_frame,
+                FrameKind::Conversation,
+                None,
+                sds_frame.content(),
+            );
+            return Ok(());
+        };
+        if has_unknown_fields && self.ctx.config.preserve_unknown {
+            self.report_unknown(
+                &sds_frame,
+                FrameKind::Conversation,
+                None,
+                sds_frame.content(),
+            );
+        }
+
+        let mut stamp = None;
+        let frame_type = match frame_type {
+            private_v1_frame::FrameType::Content(frame) => {
+                let frame = uncompressed(frame, self.ctx.config.size_limits.max_frame)?;
+                let (frame, s) =
+                    untimestamped(frame).map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                stamp = s;
+                private_v1_frame::FrameType::Content(frame)
+            }
+            other => other,
+        };
+        if let Some(stamp) = stamp.as_mut() {
+            stamp.sent_at = self.check_clock_skew(stamp.sent_at, now_millis())?;
+        }
+        let sent_at = stamp.as_ref().map(|s| s.sent_at);
+
+        let ephemeral = matches!(
+            &frame_type,
+            private_v1_frame::FrameType::Content(frame) if is_ephemeral(frame)
+        );
+        self.sds.mark_seen(&sds_frame.message_id, ephemeral);
+
+        if !ephemeral {
+            self.sds.note_delivered(sds_frame.lamport_timestamp);
+        }
+
+        // Stale messages still count as seen so they are not requested again
+        let too_old =
+            sent_at
+                .zip(self.ctx.config.max_message_age)
+                .is_some_and(|(sent_at, max_age)| {
+                    now_millis().saturating_sub(sent_at) > max_age.as_millis() as u64
+                });
+        if too_old {
+            let message_id = self.redaction().id(&sds_frame.message_id);
+            debug!("Discarding stale message: {}", message_id);
+            return Ok(());
+        }
+
+        match frame_type {
+            private_v1_frame::FrameType::Content(frame) if frame.domain == SDK_DOMAIN => {
+                self.handle_sdk_frame(&sds_frame, &frame, stamp)?;
+            }
             private_v1_frame::FrameType::Content(frame) => {
-                info!("conttent {:?}", frame);
+                self.deliver_content(&sds_frame, frame, stamp, None)?;
             }
             private_v1_frame::FrameType::Placeholder(frame) => {
-                info!("placeholder {:?}", frame);
+                info!("placeholder {}", self.redaction().payload(&frame));
             }
         };
 
         Ok(())
     }
 
-    fn convo_id(&self) -> String {
+    // Deliver buffered frames unblocked by the last processed frame
+    fn process_ready(&mut self) -> Result<(), UmbraError> {
+        while let Some(frame) = self.sds.take_ready() {
+            self.ctx.events.emit_sync(SyncEvent::OutOfOrder {
+                convo_id: self.convo_id.to_string(),
+                message_id: frame.message_id.clone(),
+            });
+            self.process(frame)?;
+        }
+        Ok(())
+    }
+
+    // Deliver a frame whose dependencies are not coming, reporting the gap
+    fn release(&mut self, sds_frame: ReliableBytes) -> Result<(), UmbraError> {
+        let missing = self.sds.missing_dependencies(&sds_frame);
+        let redaction = self.redaction();
+        warn!(
+            "Delivering {} without dependencies {}",
+            redaction.id(&sds_frame.message_id),
+            redaction.ids(&missing)
+        );
+        self.ctx.events.emit_sync(SyncEvent::MissingMessages {
+            convo_id: self.convo_id.to_string(),
+            ids: missing,
+        });
+
+        self.process(sds_frame)?;
+        self.process_ready()
+    }
+
+    fn expire_messages(&mut self, now: u64) {
+        let (expired, kept): (Vec<MessageRecord>, Vec<MessageRecord>) =
+            std::mem::take(&mut self.messages)
+                .into_iter()
+                .partition(|m| m.expires_at.is_some_and(|t| t <= now));
+        self.messages = kept;
+        self.forget_messages(expired, "Expired");
+    }
+
+    // Drops what the retention policy no longer allows, returning how many
+    fn prune(&mut self, now: u64) -> usize {
+        let message_ids = self.retention().pruned(&self.messages, now);
+        if message_ids.is_empty() {
+            return 0;
+        }
+        let pruned_ids: HashSet<&str> = message_ids.iter().map(String::as_str).collect();
+        let (pruned, kept): (Vec<MessageRecord>, Vec<MessageRecord>) =
+            std::mem::take(&mut self.messages)
+                .into_iter()
+                .partition(|m| pruned_ids.contains(m.message_id.as_str()));
+        self.messages = kept;
+        self.forget_messages(pruned, "Pruned");
+
+        let count = message_ids.len();
+        self.ctx.events.emit_pruned(MessagesPruned {
+            convo_id: self.convo_id.to_string(),
+            message_ids,
+        });
+        count
+    }
+
+    // Removes messages already taken out of history from everywhere else
+    fn forget_messages(&mut self, records: Vec<MessageRecord>, reason: &str) {
+        for record in records {
+            debug!(
+                "{} message: {}",
+                reason,
+                self.redaction().id(&record.message_id)
+            );
+            self.outbound.remove(&record.message_id);
+            self.ctx.forget_message(&record.message_id);
+            if !record.retracted {
+                self.message_watchers.notify(Diff::Removed(record));
+            }
+        }
+    }
+
+    // Runs the handler registered for the request's content type and sends
+    // its result back
+    fn answer(&mut self, request: Request) -> Result<(), UmbraError> {
+        let content = ContentFrame {
+            domain: request.domain,
+            tag: request.tag,
+            bytes: request.bytes,
+        };
+        let handled =
+            self.ctx
+                .requests
+                .handle(&self.ctx.events, self.convo_id.to_string(), content);
+        let response = match handled {
+            Ok(content) => Response {
+                request_id: request.request_id,
+                domain: content.domain,
+                tag: content.tag,
+                bytes: content.bytes,
+                error: String::new(),
+            },
+            Err(error) => Response {
+                request_id: request.request_id,
+                error,
+                ..Default::default()
+            },
+        };
+        self.send_sdk_frame(SdkFrameTags::SdkFrameTagResponse, &response)?;
+        Ok(())
+    }
+
+    fn resolve(&mut self, response: Response) {
+        let Some(pending) = self.pending_requests.remove(&response.request_id) else {
+            // Answered after it timed out, or a request from another device
+            debug!("No pending request {}", response.request_id);
+            return;
+        };
+        let res = if response.error.is_empty() {
+            Ok(ContentFrame {
+                domain: response.domain,
+                tag: response.tag,
+                bytes: response.bytes,
+            })
+        } else {
+            Err(UmbraError::RequestFailed(response.error))
+        };
+        pending.resolve(res);
+    }
+
+    // Messages which cannot go out yet are tried again on the next tick
+    fn send_scheduled(&mut self, now: u64) {
+        for message in self.schedule.take_due(now) {
+            let content = ContentFrame {
+                domain: message.content_type.domain,
+                tag: message.content_type.tag,
+                bytes: message.bytes.clone(),
+            };
+            // Unlike sending directly, this leaves any draft in place
+            let sent = self
+                .intercept(content, ContentMeta::default(), None)
+                .and_then(|(content, meta)| {
+                    self.send_content(content.clone(), content, meta, None)
+                });
+            match sent {
+                Ok(_) => {}
+                Err(e) if e.is_retryable() => self.schedule.insert(message),
+                Err(e) => warn!("Dropping scheduled message {}: {}", message.schedule_id, e),
+            }
+        }
+    }
+
+    fn expire_requests(&mut self, now: u64) {
+        let overdue: Vec<String> = self
+            .pending_requests
+            .iter()
+            .filter(|(_, pending)| pending.is_overdue(now))
+            .map(|(request_id, _)| request_id.clone())
+            .collect();
+        for request_id in overdue {
+            if let Some(pending) = self.pending_requests.remove(&request_id) {
+                pending.resolve(Err(UmbraError::RequestTimedOut(request_id)));
+            }
+        }
+    }
+
+    fn write_state(&mut self, key: &str, value: Option<Blob>) -> Result<(), UmbraError> {
+        self.require(Capabilities::STATE)?;
+        self.check_content_size(value.as_ref().map_or(0, Vec::len))?;
+        let version = self.state.next_version(key, &self.self_addr, now_millis());
+        let update = StateUpdate {
+            key: key.to_string(),
+            removed: value.is_none(),
+            value: value.clone().unwrap_or_default(),
+            timestamp: version.timestamp,
+        };
+        self.send_sdk_frame(SdkFrameTags::SdkFrameTagStateUpdate, &update)?;
+        let writer = self.self_addr.clone();
+        self.apply_state(update.key, value, version.timestamp, writer);
+        Ok(())
+    }
+
+    fn apply_state(&mut self, key: String, value: Option<Blob>, timestamp: u64, writer: Address) {
+        let version = Version {
+            timestamp,
+            writer: writer.clone(),
+        };
+        if !self.state.apply(&key, value.clone(), version) {
+            debug!("Ignoring stale write to {}", key);
+            return;
+        }
+        self.ctx.events.emit_state_changed(StateChanged {
+            convo_id: self.convo_id.to_string(),
+            key,
+            value,
+            writer,
+        });
+    }
+
+    fn report_unknown(
+        &self,
+        sds_frame: &ReliableBytes,
+        frame_kind: FrameKind,
+        tag: Option<u32>,
+        bytes: &[u8],
+    ) {
+        let message_id = self.redaction().id(&sds_frame.message_id);
+        debug!("Unknown {} in {}", frame_kind, message_id);
+        self.ctx.events.emit_unknown_frame(UnknownFrame {
+            convo_id: self.convo_id.to_string(),
+            message_id: sds_frame.message_id.clone(),
+            frame_kind,
+            tag,
+            bytes: bytes.to_vec(),
+        });
+    }
+
+    fn handle_sdk_frame(
+        &mut self,
+        sds_frame: &ReliableBytes,
+        frame: &ContentFrame,
+        stamp: Option<Stamp>,
+    ) -> Result<(), UmbraError> {
+        match SdkFrameTags::try_from(frame.tag as i32) {
+            Ok(SdkFrameTags::SdkFrameTagDeliveryReceipt) => {
+                let receipt = DeliveryReceipt::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                for message_id in receipt.message_ids {
+                    // Receipts for messages we did not send are ignored
+                    if self.outbound.get(&message_id) == Some(&DeliveryStatus::Sent) {
+                        self.set_status(&message_id, DeliveryStatus::Delivered);
+                    }
+                }
+            }
+            Ok(SdkFrameTags::SdkFrameTagCumulativeAck) => {
+                let ack = CumulativeAck::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                self.apply_cumulative_ack(ack.lamport_timestamp);
+            }
+            Ok(SdkFrameTags::SdkFrameTagEdit) => {
+                let edit = Edit::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                let sender = self.peer();
+                if let Err(e) = self.apply_edit(&sender, edit) {
+                    self.ctx.tolerate(e)?;
+                }
+            }
+            Ok(SdkFrameTags::SdkFrameTagRetract) => {
+                let retract = Retract::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                let sender = self.peer();
+                if let Err(e) = self.apply_retract(&sender, retract) {
+                    self.ctx.tolerate(e)?;
+                }
+            }
+            Ok(SdkFrameTags::SdkFrameTagReply) => {
+                let reply = Reply::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                let content = ContentFrame {
+                    domain: reply.domain,
+                    tag: reply.tag,
+                    bytes: reply.bytes,
+                };
+                self.deliver_content(sds_frame, content, stamp, Some(reply.parent_id))?;
+            }
+            Ok(SdkFrameTags::SdkFrameTagExpirationPolicy) => {
+                let policy = ExpirationPolicy::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                debug!("Peer set expiration policy: {}ms", policy.expire_after_ms);
+                self.expire_after = match policy.expire_after_ms {
+                    0 => None,
+                    ms => Some(Duration::from_millis(ms)),
+                };
+            }
+            Ok(SdkFrameTags::SdkFrameTagPresence) => {
+                let frame = frames::Presence::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                let state = match frame.state {
+                    1 => PresenceState::Online,
+                    2 => PresenceState::Away,
+                    _ => PresenceState::Offline,
+                };
+                let presence = Presence {
+                    addr: self.peer(),
+                    state,
+                    last_seen: frame.last_seen,
+                };
+                self.presence
+                    .insert(presence.addr.clone(), presence.clone());
+                self.ctx.events.emit_presence(&self.convo_id, presence);
+            }
+            Ok(SdkFrameTags::SdkFrameTagRepairRequest) => {
+                let request = RepairRequest::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                self.retransmit(&request.message_ids);
+            }
+            Ok(SdkFrameTags::SdkFrameTagHistoryQuery) => {
+                let query = HistoryQuery::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                let envelopes = self.sds.history_since(query.since, query.limit as usize);
+                if !envelopes.is_empty() {
+                    let response = HistoryResponse {
+                        envelopes: envelopes.into_iter().map(Bytes::from).collect(),
+                    };
+                    self.send_sdk_frame(SdkFrameTags::SdkFrameTagHistoryResponse, &response)?;
+                }
+            }
+            Ok(SdkFrameTags::SdkFrameTagHistoryResponse) => {
+                let response = HistoryResponse::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                self.recv_history(response)?;
+            }
+            Ok(SdkFrameTags::SdkFrameTagRequest) => {
+                let request = Request::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                self.answer(request)?;
+            }
+            Ok(SdkFrameTags::SdkFrameTagResponse) => {
+                let response = Response::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                self.resolve(response);
+            }
+            Ok(SdkFrameTags::SdkFrameTagStateUpdate) => {
+                let update = StateUpdate::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                let value = (!update.removed).then_some(update.value);
+                let writer = self.peer();
+                self.apply_state(update.key, value, update.timestamp, writer);
+            }
+            Ok(SdkFrameTags::SdkFrameTagResyncRequest) => {
+                let request = ResyncRequest::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                self.resync(request.history_limit as usize)?;
+            }
+            Ok(SdkFrameTags::SdkFrameTagCapabilities) => {
+                let frame = frames::Capabilities::decode(frame.bytes.as_slice())
+                    .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+                let capabilities = Capabilities::from_bits_truncate(frame.flags);
+                debug!(
+                    "Peer speaks protocol v{} with {}",
+                    frame.version, capabilities
+                );
+                self.peer_capabilities = Some((frame.version, capabilities));
+                if !self.advertised {
+                    self.advertise_capabilities();
+                }
+            }
+            _ if self.ctx.config.preserve_unknown => {
+                self.report_unknown(sds_frame, FrameKind::Sdk, Some(frame.tag), &frame.bytes);
+            }
+            _ => {
+                self.ctx.tolerate(UmbraError::StrictModeViolation(format!(
+                    "unknown SDK frame tag {}",
+                    frame.tag
+                )))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Conversation<T> for PrivateConversation<T>
+where
+    T: DeliveryService + Send + Sync + 'static,
+{
+    fn send(
+        &mut self,
+        content_type: ContentType,
+        message: Blob,
+    ) -> Result<MessageHandle, UmbraError> {
+        self.send_with_fallback(content_type, message, None)
+    }
+
+    fn send_with_fallback(
+        &mut self,
+        content_type: ContentType,
+        message: Blob,
+        fallback: Option<String>,
+    ) -> Result<MessageHandle, UmbraError> {
+        let meta = ContentMeta {
+            fallback,
+            ..Default::default()
+        };
+        self.send_with_meta(content_type, message, meta)
+    }
+
+    fn send_with_meta(
+        &mut self,
+        content_type: ContentType,
+        message: Blob,
+        meta: ContentMeta,
+    ) -> Result<MessageHandle, UmbraError> {
+        let content = ContentFrame {
+            domain: content_type.domain,
+            tag: content_type.tag,
+            bytes: message,
+        };
+        let (content, meta) = self.intercept(content, meta, None)?;
+        let handle = self.send_content(content.clone(), content, meta, None)?;
+        self.clear_draft();
+        Ok(handle)
+    }
+
+    fn send_at(
+        &mut self,
+        content_type: ContentType,
+        message: Blob,
+        send_at: u64,
+    ) -> Result<String, UmbraError> {
+        self.check_content_size(message.len())?;
+        let convo_id = self.convo_id.to_string();
+        Ok(self.schedule.add(&convo_id, content_type, message, send_at))
+    }
+
+    fn cancel_scheduled(&mut self, schedule_id: &str) -> Option<ScheduledMessage> {
+        self.schedule.cancel(schedule_id)
+    }
+
+    fn scheduled(&self) -> Vec<ScheduledMessage> {
+        self.schedule.list()
+    }
+
+    fn save_draft(&mut self, draft: Blob) {
+        self.ctx.save_draft(&self.convo_id, Some(&draft));
+        self.draft = Some(draft);
+    }
+
+    fn draft(&self) -> Option<Blob> {
+        self.draft.clone()
+    }
+
+    fn clear_draft(&mut self) {
+        if self.draft.take().is_some() {
+            self.ctx.save_draft(&self.convo_id, None);
+        }
+    }
+
+    fn send_batch(
+        &mut self,
+        messages: Vec<(ContentType, Blob)>,
+    ) -> Result<Vec<MessageHandle>, UmbraError> {
+        // Nothing is sent unless every message fits and is let through
+        let contents = messages
+            .into_iter()
+            .map(|(content_type, bytes)| {
+                let content = ContentFrame {
+                    domain: content_type.domain,
+                    tag: content_type.tag,
+                    bytes,
+                };
+                let (content, _) = self.intercept(content, ContentMeta::default(), None)?;
+                self.check_content_size(content.bytes.len())?;
+                Ok(content)
+            })
+            .collect::<Result<Vec<_>, UmbraError>>()?;
+        let now = now_millis();
+        let send_at = self.admit(contents.len() as u32, now)?;
+        let sealed: Vec<Sealed> = contents
+            .into_iter()
+            .zip(send_at)
+            .map(|(content, send_at)| {
+                let sealed = self.seal(content.clone(), ContentMeta::default(), now, send_at);
+                self.record_outgoing(&sealed, content, None, now);
+                sealed
+            })
+            .collect();
+
+        let results = self.publish(&sealed, now);
+        for (s, res) in sealed.iter().zip(results) {
+            self.settle(&s.message_id, res);
+        }
+        self.clear_draft();
+        Ok(sealed.iter().map(|s| self.handle(s, now)).collect())
+    }
+
+    fn reply(
+        &mut self,
+        parent_id: &str,
+        content_type: ContentType,
+        message: Blob,
+    ) -> Result<MessageHandle, UmbraError> {
+        if !self
+            .messages
+            .iter()
+            .any(|m| m.message_id == parent_id && !m.retracted)
+        {
+            return Err(UmbraError::MessageNotFound(parent_id.to_string()));
+        }
+
+        let content = ContentFrame {
+            domain: content_type.domain,
+            tag: content_type.tag,
+            bytes: message,
+        };
+        let (content, _) = self.intercept(content, ContentMeta::default(), Some(parent_id))?;
+        // The peer would not understand a Reply frame, so it gets the content
+        // without its parent; local history still records the thread
+        let wire = if self.supports(Capabilities::REPLIES) {
+            let reply = Reply {
+                parent_id: parent_id.to_string(),
+                domain: content.domain,
+                tag: content.tag,
+                bytes: content.bytes.clone(),
+            };
+            ContentFrame {
+                domain: SDK_DOMAIN,
+                tag: SdkFrameTags::SdkFrameTagReply as u32,
+                bytes: reply.encode_to_vec(),
+            }
+        } else {
+            content.clone()
+        };
+        let reply_to = Some(parent_id.to_string());
+        let handle = self.send_content(wire, content, ContentMeta::default(), reply_to)?;
+        self.clear_draft();
+        Ok(handle)
+    }
+
+    fn request(
+        &mut self,
+        content_type: ContentType,
+        message: Blob,
+        timeout: Duration,
+    ) -> Result<PendingResponse, UmbraError> {
+        self.require(Capabilities::RPC)?;
+        self.check_content_size(message.len())?;
+        let request = Request {
+            request_id: generate_random_string(16),
+            domain: content_type.domain,
+            tag: content_type.tag,
+            bytes: message,
+        };
+        self.send_sdk_frame(SdkFrameTags::SdkFrameTagRequest, &request)?;
+
+        let (pending, response) = PendingRequest::new(request.request_id.clone(), timeout);
+        self.pending_requests.insert(request.request_id, pending);
+        Ok(response)
+    }
+
+    fn thread(&self, parent_id: &str) -> Vec<MessageRecord> {
+        let mut messages = self.messages();
+        messages.sort_by(|a, b| {
+            (a.lamport_timestamp, &a.message_id).cmp(&(b.lamport_timestamp, &b.message_id))
+        });
+
+        // A reply always follows its parent causally, so one ordered pass
+        // collects replies to replies as well.
+        let mut in_thread = HashSet::from([parent_id.to_string()]);
+        messages
+            .into_iter()
+            .filter(|m| match &m.reply_to {
+                Some(parent) if in_thread.contains(parent) => {
+                    in_thread.insert(m.message_id.clone());
+                    true
+                }
+                _ => false,
+            })
+            .collect()
+    }
+
+    fn poll_results(&self, poll_id: &str) -> Option<PollResults> {
+        poll::tally(poll_id, &self.messages)
+    }
+
+    // returns any message which was not handled by this conversation
+    fn recv(&mut self, enc_bytes: EncryptedBytes) -> Result<(), UmbraError> {
+        let sds_frame = Self::decrypt(enc_bytes)?;
+        let max_frame = self.ctx.config.size_limits.max_frame;
+        limits::check(
+            FrameKind::Conversation,
+            sds_frame.content().len(),
+            max_frame,
+        )?;
+        // Peers from before ids were derived still send the old ids
+        if sds_frame.channel_id != *self.convo_id {
+            self.ctx.tolerate(UmbraError::StrictModeViolation(format!(
+                "frame for channel {:?}",
+                sds_frame.channel_id
+            )))?;
+        }
+
+        // Covers our own messages echoed back by the DeliveryService too
+        if self.sds.is_known(&sds_frame.message_id) {
+            let message_id = self.redaction().id(&sds_frame.message_id);
+            debug!("Ignoring known message: {}", message_id);
+            return Ok(());
+        }
+
+        self.sds
+            .observe_lamport_timestamp(sds_frame.lamport_timestamp);
+
+        let peer_missing = self.sds.scan_bloom_filter(&sds_frame);
+        if !peer_missing.is_empty() {
+            let redacted = self.redaction().ids(&peer_missing);
+            debug!("Peer likely missed messages: {}", redacted);
+            self.retransmit(&peer_missing);
+        }
+
+        let missing = self.sds.missing_dependencies(&sds_frame);
+        if !missing.is_empty() {
+            let message_id = self.redaction().id(&sds_frame.message_id);
+            debug!("Buffering {} until its dependencies arrive", message_id);
+            self.ctx.events.emit_sync(SyncEvent::GapDetected {
+                convo_id: self.convo_id.to_string(),
+                message_id: sds_frame.message_id.clone(),
+                missing: missing.clone(),
+            });
+            self.request_repair(missing);
+            if let Some(evicted) = self.sds.buffer(sds_frame, now_millis()) {
+                self.release(evicted)?;
+            }
+            return Ok(());
+        }
+
+        self.process(sds_frame)?;
+        self.process_ready()
+    }
+
+    fn preview(&self, enc_bytes: EncryptedBytes) -> Result<Option<PushNotification>, UmbraError> {
+        let sds_frame = Self::decrypt(enc_bytes)?;
+        if self.sds.is_known(&sds_frame.message_id) {
+            return Ok(None);
+        }
+
+        let convo_frame = PrivateV1Frame::decode(sds_frame.content())
+            .map_err(UmbraError::decoding(FrameKind::Conversation))?;
+        let Some(private_v1_frame::FrameType::Content(frame)) = convo_frame.frame_type else {
+            return Ok(None);
+        };
+        let frame = uncompressed(frame, self.ctx.config.size_limits.max_frame)?;
+        let (frame, stamp) = untimestamped(frame).map_err(UmbraError::decoding(FrameKind::Sdk))?;
+        let (sent_at, meta) = match stamp {
+            Some(stamp) => (Some(stamp.sent_at), stamp.meta),
+            None => (None, ContentMeta::default()),
+        };
+
+        let (content, reply_to) = if frame.domain != SDK_DOMAIN {
+            (frame, None)
+        } else if let Ok(SdkFrameTags::SdkFrameTagReply) = SdkFrameTags::try_from(frame.tag as i32)
+        {
+            let reply = Reply::decode(frame.bytes.as_slice())
+                .map_err(UmbraError::decoding(FrameKind::Sdk))?;
+            let content = ContentFrame {
+                domain: reply.domain,
+                tag: reply.tag,
+                bytes: reply.bytes,
+            };
+            (content, Some(reply.parent_id))
+        } else {
+            // Receipts, edits and other protocol frames don't notify
+            return Ok(None);
+        };
+
+        Ok(Some(PushNotification::Message {
+            convo_id: self.convo_id.to_string(),
+            message_id: sds_frame.message_id,
+            sender: self.peer(),
+            content,
+            sent_at,
+            reply_to,
+            notify: self.notify_level.notifies(&meta, &self.self_addr),
+            mentions: meta.mentions,
+            priority: meta.priority,
+        }))
+    }
+
+    fn edit(
+        &mut self,
+        message_id: &str,
+        content_type: ContentType,
+        message: Blob,
+    ) -> Result<(), UmbraError> {
+        self.require(Capabilities::EDITS)?;
+        self.check_content_size(message.len())?;
+        let edit = Edit {
+            message_id: message_id.to_string(),
+            domain: content_type.domain,
+            tag: content_type.tag,
+            bytes: message,
+        };
+
+        let editor = self.self_addr.clone();
+        self.apply_edit(&editor, edit.clone())?;
+        self.send_sdk_frame(SdkFrameTags::SdkFrameTagEdit, &edit)?;
+        Ok(())
+    }
+
+    fn retract(&mut self, message_id: &str) -> Result<(), UmbraError> {
+        self.require(Capabilities::RETRACTS)?;
+        let retract = Retract {
+            message_id: message_id.to_string(),
+        };
+
+        let sender = self.self_addr.clone();
+        self.apply_retract(&sender, retract.clone())?;
+        self.send_sdk_frame(SdkFrameTags::SdkFrameTagRetract, &retract)?;
+        Ok(())
+    }
+
+    fn set_expiration_policy(&mut self, expire_after: Duration) -> Result<(), UmbraError> {
+        self.require(Capabilities::EXPIRATION)?;
+        let policy = ExpirationPolicy {
+            expire_after_ms: expire_after.as_millis() as u64,
+        };
+        self.send_sdk_frame(SdkFrameTags::SdkFrameTagExpirationPolicy, &policy)?;
+        self.expire_after = (!expire_after.is_zero()).then_some(expire_after);
+        Ok(())
+    }
+
+    fn expiration_policy(&self) -> Option<Duration> {
+        self.expire_after
+    }
+
+    fn set_retention(&mut self, retention: Option<Retention>) {
+        self.retention = retention;
+    }
+
+    fn retention(&self) -> Retention {
+        self.retention.unwrap_or(self.ctx.config.retention)
+    }
+
+    fn purge(&mut self) -> usize {
+        self.prune(now_millis())
+    }
+
+    fn fetch_history(&mut self, since: u64, limit: u32) -> Result<(), UmbraError> {
+        self.require(Capabilities::HISTORY)?;
+        let query = HistoryQuery { since, limit };
+        self.send_sdk_frame(SdkFrameTags::SdkFrameTagHistoryQuery, &query)?;
+        Ok(())
+    }
+
+    fn request_resync(&mut self) -> Result<(), UmbraError> {
+        // Capabilities lost with the rest of the state are learned again
+        // from the answer, so only a peer known to lack resync is refused
+        if self.peer_capabilities.is_some() {
+            self.require(Capabilities::RESYNC)?;
+        }
+        self.advertise_capabilities();
+        let request = ResyncRequest { history_limit: 0 };
+        self.send_sdk_frame(SdkFrameTags::SdkFrameTagResyncRequest, &request)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), UmbraError> {
+        if !self.ctx.ds.is_connected() {
+            return Err(UmbraError::Disconnected);
+        }
+
+        let now = now_millis();
+        self.expire_outbox(now);
+        let queued = self.ctx.outbox.lock().unwrap().take_all(&self.convo_id);
+        self.send_queued(queued, now);
+        Ok(())
+    }
+
+    fn tick(&mut self, now: u64) -> Result<(), UmbraError> {
+        self.expire_messages(now);
+        self.prune(now);
+        self.expire_requests(now);
+        self.send_scheduled(now);
+        self.retry_outbox(now);
+
+        let missing = self.sds.pending_missing();
+        if !missing.is_empty() {
+            self.request_repair(missing);
+        }
+
+        if let Some(lamport_timestamp) = self.sds.ack_due() {
+            let ack = CumulativeAck { lamport_timestamp };
+            if let Err(e) = self.send_sdk_frame(SdkFrameTags::SdkFrameTagCumulativeAck, &ack) {
+                warn!("Failed to send cumulative ack: {:?}", e);
+            }
+        }
+
+        for frame in self.sds.take_expired(now) {
+            self.release(frame)?;
+        }
+        Ok(())
+    }
+
+    fn export(&self, include_history: bool) -> Blob {
+        let (retention, retention_limit) = Retention::to_snapshot(self.retention);
+        let messages = if include_history {
+            self.messages.iter().map(MessageSnapshot::from).collect()
+        } else {
+            vec![]
+        };
+
+        let outbox = self
+            .ctx
+            .outbox
+            .lock()
+            .unwrap()
+            .pending(&self.convo_id)
+            .map(|e| OutboxSnapshot {
+                message_id: e.message_id.clone(),
+                envelope: e.envelope.clone(),
+                attempts: e.attempts,
+                expires_at: e.expires_at.unwrap_or(0),
+            })
+            .collect();
+
+        ConversationSnapshot {
+            version: SNAPSHOT_VERSION,
+            convo_id: self.convo_id.to_string(),
+            participants: self.participants.iter().map(Address::to_string).collect(),
+            lamport_timestamp: self.sds.lamport_timestamp(),
+            expire_after_ms: self.expire_after.map_or(0, |d| d.as_millis() as u64),
+            mute_until: self.mute_until.unwrap_or(0),
+            archived: self.archived,
+            crypto_state: vec![],
+            sent_ids: self.sds.sent_ids(),
+            messages,
+            outbox,
+            peer_version: self.peer_capabilities.map_or(0, |(version, _)| version),
+            peer_capabilities: self
+                .peer_capabilities
+                .map_or(0, |(_, capabilities)| capabilities.bits()),
+            state: self.state.to_snapshot(),
+            scheduled: self.schedule.to_snapshot(),
+            self_addr: self.self_addr.to_string(),
+            retention,
+            retention_limit,
+            notify_level: self.notify_level.to_snapshot(),
+            hint_secret: self.hint_secret.clone(),
+            seen_ids: self.sds.seen_ids(),
+        }
+        .encode_to_vec()
+    }
+
+    fn presence(&self, addr: &str) -> Option<Presence> {
+        self.presence.get(addr).cloned()
+    }
+
+    fn publish_presence(&mut self, state: PresenceState) -> Result<(), UmbraError> {
+        // Presence is best effort, so peers without it are skipped silently
+        if !self.supports(Capabilities::PRESENCE) {
+            return Ok(());
+        }
+        let frame = frames::Presence {
+            state: match state {
+                PresenceState::Online => 1,
+                PresenceState::Away => 2,
+                PresenceState::Offline => 3,
+            },
+            last_seen: now_millis(),
+        };
+        self.send_sdk_frame(SdkFrameTags::SdkFrameTagPresence, &frame)?;
+        Ok(())
+    }
+
+    fn state(&self) -> &SharedState {
+        &self.state
+    }
+
+    fn set_state(&mut self, key: &str, value: Blob) -> Result<(), UmbraError> {
+        self.write_state(key, Some(value))
+    }
+
+    fn remove_state(&mut self, key: &str) -> Result<(), UmbraError> {
+        self.write_state(key, None)
+    }
+
+    fn convo_id(&self) -> ConversationId {
         self.convo_id.clone()
     }
+
+    fn codecs(&self) -> &CodecRegistry {
+        &self.ctx.codecs
+    }
+
+    fn message_status(&self, message_id: &str) -> Option<DeliveryStatus> {
+        self.outbound.get(message_id).copied()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        let peer = self
+            .peer_capabilities
+            .map_or(Capabilities::baseline(), |(_, capabilities)| capabilities);
+        self.ctx.config.capabilities.intersection(peer)
+    }
+
+    fn protocol_version(&self) -> u32 {
+        self.peer_capabilities
+            .map_or(PROTOCOL_VERSION, |(version, _)| {
+                version.min(PROTOCOL_VERSION)
+            })
+    }
+
+    fn info(&self) -> ConversationInfo {
+        ConversationInfo {
+            convo_id: self.convo_id.clone(),
+            kind: ConversationKind::Private,
+            participants: self.participants.clone(),
+            pseudonym: (self.self_addr != self.ctx.addr).then(|| self.self_addr.clone()),
+            last_activity: self.last_activity,
+            mute_until: self.mute_until,
+            archived: self.archived,
+        }
+    }
+
+    fn mute(&mut self, duration: Duration) {
+        self.mute_until = Some(now_millis().saturating_add(duration.as_millis() as u64));
+        self.info_changed();
+    }
+
+    fn unmute(&mut self) {
+        if self.mute_until.take().is_some() {
+            self.info_changed();
+        }
+    }
+
+    fn is_muted(&self) -> bool {
+        self.mute_until.is_some_and(|t| t > now_millis())
+    }
+
+    fn set_notify_level(&mut self, level: NotifyLevel) {
+        self.notify_level = level;
+    }
+
+    fn notify_level(&self) -> NotifyLevel {
+        self.notify_level
+    }
+
+    fn archive(&mut self) {
+        if !self.archived {
+            self.archived = true;
+            self.info_changed();
+        }
+    }
+
+    fn unarchive(&mut self) {
+        if self.archived {
+            self.archived = false;
+            self.info_changed();
+        }
+    }
+
+    fn is_archived(&self) -> bool {
+        self.archived
+    }
+
+    fn messages(&self) -> Vec<MessageRecord> {
+        self.messages
+            .iter()
+            .filter(|m| !m.retracted)
+            .cloned()
+            .collect()
+    }
+
+    fn import_messages(&mut self, records: Vec<MessageRecord>) -> usize {
+        let mut known: HashSet<String> =
+            self.messages.iter().map(|m| m.message_id.clone()).collect();
+        let mut imported = 0;
+        for mut record in records {
+            if !known.insert(record.message_id.clone()) {
+                continue;
+            }
+            record.convo_id = self.convo_id.to_string();
+            record.imported = true;
+            self.push_message(record);
+            imported += 1;
+        }
+        imported
+    }
+
+    fn watch_messages(&self) -> Receiver<Diff<MessageRecord>> {
+        self.message_watchers.watch(self.messages())
+    }
+
+    #[cfg(feature = "testing")]
+    fn last_envelope(&self) -> Option<Blob> {
+        self.last_envelope.clone()
+    }
 }
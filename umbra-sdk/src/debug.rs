@@ -0,0 +1,298 @@
+//! Human readable JSON renderings of wire data, for protocol debugging and
+//! bug reports.
+//!
+//! Every layer an envelope wraps is decoded and shown nested inside it. Byte
+//! fields are shown as hex. Layers which fail to decode are shown as their
+//! raw bytes next to the error instead of failing the whole rendering.
+
+use prost::Message;
+use serde_json::{Value, json};
+use umbra_types::base::{
+    EncryptedBytes, InboxV1Frame, ReliableBytes, UmbraEnvelopeV1, encrypted_bytes, inbox_v1_frame,
+};
+use umbra_types::common_frames::ContentFrame;
+use umbra_types::convos::private_v1::{PrivateV1Frame, private_v1_frame};
+
+use crate::frames::{
+    SDK_DOMAIN,
+    types::{
+        Capabilities, Compressed, CumulativeAck, DeliveryReceipt, Edit, ExpirationPolicy,
+        HistoryQuery, HistoryResponse, InviteTerms, Presence, RepairRequest, Reply, Request,
+        Response, ResyncRequest, Retract, SdkFrameTags, StateUpdate, Timestamped,
+    },
+};
+
+/// Render wire data as pretty printed JSON.
+pub trait JsonDebug {
+    fn to_json_debug(&self) -> String;
+}
+
+impl JsonDebug for UmbraEnvelopeV1 {
+    fn to_json_debug(&self) -> String {
+        pretty(envelope(self))
+    }
+}
+
+impl JsonDebug for ContentFrame {
+    fn to_json_debug(&self) -> String {
+        pretty(content_frame(self))
+    }
+}
+
+/// Render an encoded envelope, as handed over by a DeliveryService.
+pub fn envelope_json(bytes: &[u8]) -> Value {
+    decoded::<UmbraEnvelopeV1>(bytes, envelope)
+}
+
+fn pretty(value: Value) -> String {
+    serde_json::to_string_pretty(&value).unwrap_or_default()
+}
+
+fn undecodable(bytes: &[u8], error: impl ToString) -> Value {
+    json!({ "undecodable": hex::encode(bytes), "error": error.to_string() })
+}
+
+fn decoded<M: Message + Default>(bytes: &[u8], render: impl FnOnce(&M) -> Value) -> Value {
+    match M::decode(bytes) {
+        Ok(message) => render(&message),
+        Err(e) => undecodable(bytes, e),
+    }
+}
+
+fn envelope(envelope: &UmbraEnvelopeV1) -> Value {
+    // Inbox envelopes carry invites, everything else a conversation frame
+    let inbox = envelope.conversation_hint.starts_with("/inbox/");
+    json!({
+        "conversation_hint": envelope.conversation_hint,
+        "salt": envelope.salt,
+        "payload": decoded::<EncryptedBytes>(&envelope.payload, |e| encrypted(e, inbox)),
+    })
+}
+
+fn encrypted(encrypted: &EncryptedBytes, inbox: bool) -> Value {
+    match &encrypted.encryption {
+        Some(encrypted_bytes::Encryption::Plaintext(plaintext)) => {
+            let payload = if inbox {
+                let bytes = &plaintext.payload;
+                decoded::<InboxV1Frame>(bytes, |frame| inbox_frame(frame, bytes))
+            } else {
+                decoded::<ReliableBytes>(&plaintext.payload, reliable)
+            };
+            json!({ "plaintext": payload })
+        }
+        None => json!({ "encryption": null }),
+        #[allow(unreachable_patterns)]
+        other => json!({ "encryption": format!("{other:?}") }),
+    }
+}
+
+// Invite terms are appended to the frame, see `invites`
+fn inbox_frame(frame: &InboxV1Frame, bytes: &[u8]) -> Value {
+    let mut value = match &frame.frame_type {
+        Some(inbox_v1_frame::FrameType::InvitePrivateV1(invite)) => {
+            json!({ "invite_private_v1": { "participants": invite.participants } })
+        }
+        None => json!({ "frame_type": null }),
+    };
+    let terms = decoded::<InviteTerms>(bytes, |terms| {
+        json!({
+            "invite_id": terms.invite_id,
+            "expires_at": terms.expires_at,
+            "revoked": terms.revoked,
+        })
+    });
+    value["invite_terms"] = terms;
+    value
+}
+
+fn reliable(reliable: &ReliableBytes) -> Value {
+    json!({
+        "message_id": reliable.message_id,
+        "channel_id": reliable.channel_id,
+        "lamport_timestamp": reliable.lamport_timestamp,
+        "causal_history": reliable.causal_history,
+        "bloom_filter": hex::encode(&reliable.bloom_filter),
+        "content": decoded::<PrivateV1Frame>(reliable.content(), private_frame),
+    })
+}
+
+fn private_frame(frame: &PrivateV1Frame) -> Value {
+    let frame_type = match &frame.frame_type {
+        Some(private_v1_frame::FrameType::Content(content)) => {
+            json!({ "content": content_frame(content) })
+        }
+        Some(other) => json!(format!("{other:?}")),
+        None => Value::Null,
+    };
+    json!({ "conversation_id": frame.conversation_id, "frame_type": frame_type })
+}
+
+fn content_frame(frame: &ContentFrame) -> Value {
+    if frame.domain != SDK_DOMAIN {
+        return json!({
+            "content_type": format!("{}/{}", frame.domain, frame.tag),
+            "bytes": hex::encode(&frame.bytes),
+        });
+    }
+    let Ok(tag) = SdkFrameTags::try_from(frame.tag as i32) else {
+        return json!({ "sdk_frame": frame.tag, "bytes": hex::encode(&frame.bytes) });
+    };
+    json!({ "sdk_frame": tag.as_str_name(), "frame": sdk_frame(tag, &frame.bytes) })
+}
+
+fn inner_frame(bytes: &[u8]) -> Value {
+    decoded::<ContentFrame>(bytes, content_frame)
+}
+
+fn sdk_frame(tag: SdkFrameTags, bytes: &[u8]) -> Value {
+    match tag {
+        SdkFrameTags::SdkFrameTagDeliveryReceipt => {
+            decoded::<DeliveryReceipt>(bytes, |r| json!({ "message_ids": r.message_ids }))
+        }
+        SdkFrameTags::SdkFrameTagEdit => decoded::<Edit>(bytes, |e| {
+            json!({
+                "message_id": e.message_id,
+                "content_type": format!("{}/{}", e.domain, e.tag),
+                "bytes": hex::encode(&e.bytes),
+            })
+        }),
+        SdkFrameTags::SdkFrameTagRetract => {
+            decoded::<Retract>(bytes, |r| json!({ "message_id": r.message_id }))
+        }
+        SdkFrameTags::SdkFrameTagReply => decoded::<Reply>(bytes, |r| {
+            json!({
+                "parent_id": r.parent_id,
+                "content_type": format!("{}/{}", r.domain, r.tag),
+                "bytes": hex::encode(&r.bytes),
+            })
+        }),
+        SdkFrameTags::SdkFrameTagExpirationPolicy => {
+            decoded::<ExpirationPolicy>(bytes, |p| json!({ "expire_after_ms": p.expire_after_ms }))
+        }
+        SdkFrameTags::SdkFrameTagPresence => decoded::<Presence>(
+            bytes,
+            |p| json!({ "state": p.state, "last_seen": p.last_seen }),
+        ),
+        SdkFrameTags::SdkFrameTagRepairRequest => {
+            decoded::<RepairRequest>(bytes, |r| json!({ "message_ids": r.message_ids }))
+        }
+        SdkFrameTags::SdkFrameTagHistoryQuery => {
+            decoded::<HistoryQuery>(bytes, |q| json!({ "since": q.since, "limit": q.limit }))
+        }
+        SdkFrameTags::SdkFrameTagHistoryResponse => decoded::<HistoryResponse>(bytes, |r| {
+            let envelopes: Vec<Value> = r.envelopes.iter().map(|e| envelope_json(e)).collect();
+            json!({ "envelopes": envelopes })
+        }),
+        SdkFrameTags::SdkFrameTagTimestamped => decoded::<Timestamped>(bytes, |t| {
+            json!({
+                "sent_at": t.sent_at,
+                "fallback": t.fallback,
+                "mentions": t.mentions,
+                "priority": t.priority,
+                "frame": inner_frame(&t.frame),
+            })
+        }),
+        SdkFrameTags::SdkFrameTagCumulativeAck => decoded::<CumulativeAck>(
+            bytes,
+            |a| json!({ "lamport_timestamp": a.lamport_timestamp }),
+        ),
+        SdkFrameTags::SdkFrameTagCapabilities => decoded::<Capabilities>(bytes, |c| {
+            json!({
+                "version": c.version,
+                "flags": crate::Capabilities::from_bits_truncate(c.flags).to_string(),
+            })
+        }),
+        // Shown compressed, so the rendering matches what was on the wire
+        SdkFrameTags::SdkFrameTagCompressed => decoded::<Compressed>(bytes, |c| {
+            json!({
+                "algorithm": c.algorithm,
+                "size": c.size,
+                "frame": hex::encode(&c.frame),
+            })
+        }),
+        SdkFrameTags::SdkFrameTagRequest => decoded::<Request>(bytes, |r| {
+            json!({
+                "request_id": r.request_id,
+                "content_type": format!("{}/{}", r.domain, r.tag),
+                "bytes": hex::encode(&r.bytes),
+            })
+        }),
+        SdkFrameTags::SdkFrameTagResponse => decoded::<Response>(bytes, |r| {
+            json!({
+                "request_id": r.request_id,
+                "content_type": format!("{}/{}", r.domain, r.tag),
+                "bytes": hex::encode(&r.bytes),
+                "error": r.error,
+            })
+        }),
+        SdkFrameTags::SdkFrameTagStateUpdate => decoded::<StateUpdate>(bytes, |u| {
+            json!({
+                "key": u.key,
+                "value": hex::encode(&u.value),
+                "timestamp": u.timestamp,
+                "removed": u.removed,
+            })
+        }),
+        SdkFrameTags::SdkFrameTagResyncRequest => {
+            decoded::<ResyncRequest>(bytes, |r| json!({ "history_limit": r.history_limit }))
+        }
+        SdkFrameTags::SdkFrameTagUnknown => json!({ "bytes": hex::encode(bytes) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use umbra_types::encryption;
+    use umbra_types::payload::ToEnvelope;
+
+    use super::*;
+    use crate::frames::timestamped;
+    use crate::notify::ContentMeta;
+
+    fn plaintext(payload: Vec<u8>) -> EncryptedBytes {
+        EncryptedBytes {
+            encryption: Some(encrypted_bytes::Encryption::Plaintext(
+                encryption::Plaintext { payload },
+            )),
+        }
+    }
+
+    #[test]
+    fn renders_nested_layers() {
+        let frame = timestamped(
+            ContentFrame {
+                domain: 0,
+                tag: 5,
+                bytes: vec![0xab, 0xcd],
+            },
+            42,
+            ContentMeta::default(),
+        );
+        let reliable = ReliableBytes {
+            message_id: "m1".into(),
+            channel_id: "convo".into(),
+            content: Some(
+                PrivateV1Frame {
+                    conversation_id: "convo".into(),
+                    frame_type: Some(private_v1_frame::FrameType::Content(frame)),
+                }
+                .encode_to_vec(),
+            ),
+            ..Default::default()
+        };
+        let envelope = plaintext(reliable.encode_to_vec()).to_envelope("hint".into(), 0);
+
+        let value = envelope_json(&envelope.encode_to_vec());
+        let stamped = &value["payload"]["plaintext"]["content"]["frame_type"]["content"];
+        assert_eq!(stamped["sdk_frame"], "SdkFrameTag_Timestamped");
+        assert_eq!(stamped["frame"]["sent_at"], 42);
+        assert_eq!(stamped["frame"]["frame"]["bytes"], "abcd");
+    }
+
+    #[test]
+    fn undecodable_layers_keep_their_bytes() {
+        let envelope = plaintext(vec![0xff, 0xff]).to_envelope("hint".into(), 0);
+        let value = envelope_json(&envelope.encode_to_vec());
+        assert_eq!(value["payload"]["plaintext"]["undecodable"], "ffff");
+    }
+}
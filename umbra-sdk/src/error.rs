@@ -1,25 +1,248 @@
+use std::fmt;
+use std::time::Duration;
+
 use thiserror::Error;
+use umbra_content_types::ContentType;
+
+use crate::capabilities::Capabilities;
+
+/// Boxed underlying cause of an `UmbraError`.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Layer of the wire format which failed to encode or decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Envelope,
+    EncryptedBytes,
+    ReliableBytes,
+    Inbox,
+    Conversation,
+    /// SDK control frames such as receipts, edits and acks.
+    Sdk,
+    /// Application content, through a codec.
+    Content,
+    Snapshot,
+    Relay,
+    Transcript,
+}
+
+impl fmt::Display for FrameKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FrameKind::Envelope => "envelope",
+            FrameKind::EncryptedBytes => "encrypted bytes",
+            FrameKind::ReliableBytes => "reliable bytes",
+            FrameKind::Inbox => "inbox frame",
+            FrameKind::Conversation => "conversation frame",
+            FrameKind::Sdk => "SDK frame",
+            FrameKind::Content => "content",
+            FrameKind::Snapshot => "snapshot",
+            FrameKind::Relay => "relay frame",
+            FrameKind::Transcript => "transcript",
+        })
+    }
+}
+
+/// DeliveryService operation which failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportOp {
+    Publish,
+    Receive,
+    Subscribe,
+    Unsubscribe,
+}
+
+impl fmt::Display for TransportOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TransportOp::Publish => "publish",
+            TransportOp::Receive => "receive",
+            TransportOp::Subscribe => "subscribe",
+            TransportOp::Unsubscribe => "unsubscribe",
+        })
+    }
+}
 
 #[derive(Debug, Error)]
 pub enum UmbraError {
-    #[error("Failed to publish message to topic: {0}")]
-    PublishError(String),
+    #[error("Transport failed to {op}")]
+    TransportError {
+        op: TransportOp,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("Delivery service disconnected")]
+    Disconnected,
+
+    #[error("Failed to encode {frame_kind}")]
+    EncodeError {
+        frame_kind: FrameKind,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("Failed to decode {frame_kind}")]
+    DecodeError {
+        frame_kind: FrameKind,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("Encryption error")]
+    CryptoError(#[source] BoxError),
+
+    #[error("Invalid invite: {0}")]
+    InvalidInvite(&'static str),
+
+    #[error("Invite expired at {0} (unix ms)")]
+    InviteExpired(u64),
+
+    #[error("Invite {0} was revoked by its sender")]
+    InviteRevoked(String),
+
+    #[error("No revocable invite for conversation {0}")]
+    InviteNotFound(String),
+
+    #[error("Storage error")]
+    StorageError(#[source] BoxError),
+
+    #[error("No codec registered for content type {0}")]
+    CodecNotFound(ContentType),
+
+    #[error("Content type {0} is in the range reserved for the SDK")]
+    ReservedContentType(ContentType),
+
+    #[error("Content type {content_type} is already registered to {existing}")]
+    ContentTypeCollision {
+        content_type: ContentType,
+        existing: &'static str,
+    },
+
+    #[error("Unsupported version: {0}")]
+    UnsupportedVersion(u32),
 
-    #[error("Failed to poll messages for client: {0}")]
-    PollError(String),
+    #[error("Conversation not found: {0}")]
+    ConversationNotFound(String),
+
+    #[error("Invalid address {0}")]
+    InvalidAddress(String),
+
+    #[error("Invalid conversation id {0:?}")]
+    InvalidConversationId(String),
+
+    #[error("Identity not found: {0}")]
+    IdentityNotFound(String),
+
+    #[error("No identity secret to derive a pseudonym from")]
+    NoIdentitySecret,
+
+    #[error("Identity key of {addr} rejected: {reason}")]
+    KeyRejected { addr: String, reason: String },
+
+    #[error("Message not found: {0}")]
+    MessageNotFound(String),
+
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+
+    #[error("Strict mode violation: {0}")]
+    StrictModeViolation(String),
+
+    #[error("Not supported in this conversation: {0}")]
+    UnsupportedByPeer(Capabilities),
+
+    #[error("{frame_kind} of {size} bytes exceeds the {limit} byte limit")]
+    PayloadTooLarge {
+        frame_kind: FrameKind,
+        size: usize,
+        limit: usize,
+    },
+
+    #[error("Rate limited, retry in {0:?}")]
+    RateLimited(Duration),
+
+    #[error("No response to request {0} in time")]
+    RequestTimedOut(String),
+
+    #[error("Request failed: {0}")]
+    RequestFailed(String),
+
+    /// A handler panicked; `site` is where it was registered. The client
+    /// skips it for that event and keeps dispatching.
+    #[error("Handler registered at {site} panicked: {message}")]
+    HandlerPanicked { site: String, message: String },
+
+    /// Processing an envelope for `convo_id` panicked. The client keeps
+    /// receiving for it and every other conversation.
+    #[error("Processing an envelope for {convo_id} panicked: {message}")]
+    RecvPanicked { convo_id: String, message: String },
+
+    /// For outbound interceptors refusing to let a message be sent.
+    #[error("Send rejected: {0}")]
+    SendRejected(String),
+}
+
+impl UmbraError {
+    pub fn transport(op: TransportOp, source: impl Into<BoxError>) -> Self {
+        UmbraError::TransportError {
+            op,
+            source: source.into(),
+        }
+    }
+
+    /// For use with `map_err` when encoding a frame of `frame_kind`.
+    pub fn encoding<E: Into<BoxError>>(frame_kind: FrameKind) -> impl FnOnce(E) -> Self {
+        move |e| UmbraError::EncodeError {
+            frame_kind,
+            source: e.into(),
+        }
+    }
+
+    /// For use with `map_err` when decoding a frame of `frame_kind`.
+    pub fn decoding<E: Into<BoxError>>(frame_kind: FrameKind) -> impl FnOnce(E) -> Self {
+        move |e| UmbraError::DecodeError {
+            frame_kind,
+            source: e.into(),
+        }
+    }
+
+    /// A frame which parsed but is missing required data.
+    pub fn malformed(frame_kind: FrameKind, reason: &'static str) -> Self {
+        UmbraError::decoding(frame_kind)(reason)
+    }
+
+    /// Whether the same operation may succeed if tried again later. The
+    /// outbox only retries sends which fail this way.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            UmbraError::TransportError { .. }
+                | UmbraError::Disconnected
+                | UmbraError::RateLimited(_)
+        )
+    }
+}
 
-    #[error("Problem encoding type: {0}")]
-    EncodingError(String),
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
 
-    #[error("Problem decoding type: {0}")]
-    DecodingError(String),
+    use super::*;
 
-    #[error("ProstError: {0}")]
-    ProtobufDecode(#[from] prost::DecodeError),
+    #[test]
+    fn source_is_chained() {
+        let err = UmbraError::decoding(FrameKind::Envelope)("truncated");
 
-    #[error("Unknown error occurred")]
-    UnexpectedError,
+        assert_eq!(err.to_string(), "Failed to decode envelope");
+        assert_eq!(err.source().unwrap().to_string(), "truncated");
+    }
 
-    #[error("Unknown error occurred")]
-    TodoError,
+    #[test]
+    fn only_transient_failures_are_retryable() {
+        assert!(UmbraError::transport(TransportOp::Publish, "timeout").is_retryable());
+        assert!(UmbraError::Disconnected.is_retryable());
+        assert!(!UmbraError::malformed(FrameKind::Sdk, "empty").is_retryable());
+        assert!(!UmbraError::CodecNotFound(ContentType::new(0, 7)).is_retryable());
+    }
 }
@@ -0,0 +1,488 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe, Location};
+use std::sync::{
+    Mutex, RwLock,
+    mpsc::{Receiver, Sender, channel},
+};
+
+use tracing::error;
+
+use umbra_types::common_frames::ContentFrame;
+
+use crate::address::Address;
+use crate::convos::ConversationInfo;
+use crate::error::{FrameKind, UmbraError};
+use crate::identity::KeyEvent;
+use crate::notify::NotificationPriority;
+use crate::push::PushRegistration;
+use crate::retention::MessagesPruned;
+use crate::shared_state::StateChanged;
+use crate::transport::ConnectionState;
+
+/// Delivery state of an outgoing message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// Rejected by the DeliveryService and queued for another attempt.
+    Pending,
+    /// Handed off to the DeliveryService.
+    Sent,
+    /// Acknowledged by a recipient.
+    Delivered,
+    /// The DeliveryService kept rejecting the message and retries ran out.
+    Failed,
+    /// The message could not be sent before its TTL passed.
+    Expired,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryUpdate {
+    pub convo_id: String,
+    pub message_id: String,
+    pub status: DeliveryStatus,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageEdited {
+    pub convo_id: String,
+    pub message_id: String,
+    pub editor: Address,
+    pub old: ContentFrame,
+    pub new: ContentFrame,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageDeleted {
+    pub convo_id: String,
+    pub message_id: String,
+    pub sender: Address,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresenceState {
+    Online,
+    Away,
+    Offline,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Presence {
+    pub addr: Address,
+    pub state: PresenceState,
+    // When the participant was last active (unix ms)
+    pub last_seen: u64,
+}
+
+/// A frame this client does not understand, most likely from a newer
+/// version of the protocol. `bytes` is exactly what was received, so the
+/// frame can be stored or forwarded without losing anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownFrame {
+    pub convo_id: String,
+    pub message_id: String,
+    pub frame_kind: FrameKind,
+    /// SDK frame tag, for unknown SDK frames.
+    pub tag: Option<u32>,
+    pub bytes: Vec<u8>,
+}
+
+/// A received message's metadata from the reliability layer, for apps which
+/// order or thread messages themselves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageContext {
+    pub convo_id: String,
+    pub message_id: String,
+    pub sender: Address,
+    /// Sender's Lamport clock when the message was sent.
+    pub lamport_timestamp: u64,
+    /// Ids of the messages the sender had seen, i.e. the message's causal
+    /// dependencies.
+    pub causal_history: Vec<String>,
+    /// Sender's clock, if the sender stamped the message.
+    pub sent_at: Option<u64>,
+    /// Local clock when the message arrived.
+    pub received_at: u64,
+    /// Participants the sender mentioned.
+    pub mentions: Vec<Address>,
+    pub priority: NotificationPriority,
+    /// Whether the message is worth a notification, given its priority and
+    /// the conversation's `NotifyLevel`.
+    pub notify: bool,
+}
+
+/// Reliability layer diagnostics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncEvent {
+    /// A message arrived before some of its causal dependencies and is held
+    /// back while they are requested from peers.
+    GapDetected {
+        convo_id: String,
+        message_id: String,
+        missing: Vec<String>,
+    },
+    /// A held back message was delivered once its dependencies arrived.
+    OutOfOrder {
+        convo_id: String,
+        message_id: String,
+    },
+    /// Messages were delivered without these causal dependencies, which did
+    /// not arrive in time.
+    MissingMessages { convo_id: String, ids: Vec<String> },
+}
+
+/// Everything the client reports, as a single stream for applications with
+/// their own event loop. See `UmbraClient::events`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UmbraEvent {
+    /// A conversation was created locally, from an invite or by import.
+    NewConversation(ConversationInfo),
+    ContentReceived {
+        convo_id: String,
+        content: ContentFrame,
+        /// Sender's clock, if the sender stamped the message.
+        sent_at: Option<u64>,
+        /// Local clock when the message arrived.
+        received_at: u64,
+    },
+    DeliveryUpdate(DeliveryUpdate),
+    /// An invite arrived. It is followed by `NewConversation` once the
+    /// conversation has been set up.
+    InviteReceived {
+        participants: Vec<Address>,
+    },
+    ConnectionState(ConnectionState),
+    /// Only emitted with `ClientConfig::preserve_unknown`.
+    UnknownFrame(UnknownFrame),
+}
+
+pub type ContentHandler = Box<dyn Fn(String, ContentFrame) + Send + Sync>;
+pub type MessageHandler = Box<dyn Fn(&MessageContext, &ContentFrame) + Send + Sync>;
+pub type DeliveryHandler = Box<dyn Fn(DeliveryUpdate) + Send + Sync>;
+pub type EditHandler = Box<dyn Fn(MessageEdited) + Send + Sync>;
+pub type DeleteHandler = Box<dyn Fn(MessageDeleted) + Send + Sync>;
+pub type PresenceHandler = Box<dyn Fn(String, Presence) + Send + Sync>;
+pub type StateHandler = Box<dyn Fn(StateChanged) + Send + Sync>;
+pub type SyncHandler = Box<dyn Fn(SyncEvent) + Send + Sync>;
+pub type UnknownFrameHandler = Box<dyn Fn(UnknownFrame) + Send + Sync>;
+pub type ConnectionHandler = Box<dyn Fn(ConnectionState) + Send + Sync>;
+pub type KeyHandler = Box<dyn Fn(KeyEvent) + Send + Sync>;
+pub type PruneHandler = Box<dyn Fn(MessagesPruned) + Send + Sync>;
+pub type ErrorHandler = Box<dyn Fn(&UmbraError) + Send + Sync>;
+pub type PushRegistrationHandler = Box<dyn Fn(PushRegistration) + Send + Sync>;
+
+// A handler and where it was registered, for reporting its panics
+pub(crate) struct Registered<H> {
+    pub site: &'static Location<'static>,
+    pub handler: H,
+}
+
+impl<H> Registered<H> {
+    pub fn new(site: &'static Location<'static>, handler: H) -> Self {
+        Self { site, handler }
+    }
+
+    // Runs `call` with the handler, turning a panic into an error so one
+    // faulty handler cannot take down the thread dispatching to it
+    pub fn invoke(&self, call: impl FnOnce(&H)) -> Result<(), UmbraError> {
+        panic::catch_unwind(AssertUnwindSafe(|| call(&self.handler)))
+            .map_err(|payload| self.panicked(&*payload))
+    }
+
+    fn panicked(&self, payload: &(dyn Any + Send)) -> UmbraError {
+        UmbraError::HandlerPanicked {
+            site: self.site.to_string(),
+            message: panic_message(payload),
+        }
+    }
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "non-string panic payload".into())
+}
+
+type Handlers<H> = RwLock<Vec<Registered<H>>>;
+
+fn register<H>(handlers: &Handlers<H>, site: &'static Location<'static>, handler: H) {
+    handlers
+        .write()
+        .unwrap()
+        .push(Registered::new(site, handler));
+}
+
+#[derive(Default)]
+pub(crate) struct EventHandlers {
+    on_content: Handlers<ContentHandler>,
+    on_message: Handlers<MessageHandler>,
+    on_delivery_update: Handlers<DeliveryHandler>,
+    on_message_edited: Handlers<EditHandler>,
+    on_message_deleted: Handlers<DeleteHandler>,
+    on_presence: Handlers<PresenceHandler>,
+    on_state: Handlers<StateHandler>,
+    on_sync: Handlers<SyncHandler>,
+    on_unknown_frame: Handlers<UnknownFrameHandler>,
+    on_connection_state: Handlers<ConnectionHandler>,
+    on_key: Handlers<KeyHandler>,
+    on_pruned: Handlers<PruneHandler>,
+    on_push_registration: Handlers<PushRegistrationHandler>,
+    on_error: Handlers<ErrorHandler>,
+    // Receivers handed out by `subscribe`, pruned once dropped
+    streams: Mutex<Vec<Sender<UmbraEvent>>>,
+    // Handler panics unwind to whoever emitted the event rather than being
+    // reported and skipped
+    strict: bool,
+}
+
+// Registration takes the site of the public method the user called, which
+// passes it along with `#[track_caller]`
+impl EventHandlers {
+    pub fn new(strict: bool) -> Self {
+        Self {
+            strict,
+            ..Default::default()
+        }
+    }
+
+    pub fn add_content_handler(&self, site: &'static Location<'static>, h: ContentHandler) {
+        register(&self.on_content, site, h);
+    }
+
+    pub fn add_message_handler(&self, site: &'static Location<'static>, h: MessageHandler) {
+        register(&self.on_message, site, h);
+    }
+
+    pub fn add_delivery_handler(&self, site: &'static Location<'static>, h: DeliveryHandler) {
+        register(&self.on_delivery_update, site, h);
+    }
+
+    pub fn add_edit_handler(&self, site: &'static Location<'static>, h: EditHandler) {
+        register(&self.on_message_edited, site, h);
+    }
+
+    pub fn add_delete_handler(&self, site: &'static Location<'static>, h: DeleteHandler) {
+        register(&self.on_message_deleted, site, h);
+    }
+
+    pub fn add_presence_handler(&self, site: &'static Location<'static>, h: PresenceHandler) {
+        register(&self.on_presence, site, h);
+    }
+
+    pub fn add_state_handler(&self, site: &'static Location<'static>, h: StateHandler) {
+        register(&self.on_state, site, h);
+    }
+
+    pub fn add_sync_handler(&self, site: &'static Location<'static>, h: SyncHandler) {
+        register(&self.on_sync, site, h);
+    }
+
+    pub fn add_unknown_frame_handler(
+        &self,
+        site: &'static Location<'static>,
+        h: UnknownFrameHandler,
+    ) {
+        register(&self.on_unknown_frame, site, h);
+    }
+
+    pub fn add_connection_handler(&self, site: &'static Location<'static>, h: ConnectionHandler) {
+        register(&self.on_connection_state, site, h);
+    }
+
+    pub fn add_key_handler(&self, site: &'static Location<'static>, h: KeyHandler) {
+        register(&self.on_key, site, h);
+    }
+
+    pub fn add_prune_handler(&self, site: &'static Location<'static>, h: PruneHandler) {
+        register(&self.on_pruned, site, h);
+    }
+
+    pub fn add_push_registration_handler(
+        &self,
+        site: &'static Location<'static>,
+        h: PushRegistrationHandler,
+    ) {
+        register(&self.on_push_registration, site, h);
+    }
+
+    pub fn add_error_handler(&self, site: &'static Location<'static>, h: ErrorHandler) {
+        register(&self.on_error, site, h);
+    }
+
+    pub fn subscribe(&self) -> Receiver<UmbraEvent> {
+        let (tx, rx) = channel();
+        self.streams.lock().unwrap().push(tx);
+        rx
+    }
+
+    // Calls every handler, reporting the ones which panic as errors. In
+    // strict mode the panic is reported and then resumed, so it surfaces
+    // where the event was emitted
+    fn dispatch<H>(&self, handlers: &Handlers<H>, call: impl Fn(&H)) -> usize {
+        let handlers = handlers.read().unwrap();
+        for handler in handlers.iter() {
+            let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| call(&handler.handler)))
+            else {
+                continue;
+            };
+            self.emit_error(&handler.panicked(&*payload));
+            if self.strict {
+                panic::resume_unwind(payload);
+            }
+        }
+        handlers.len()
+    }
+
+    // Returns the number of streams the event was delivered to
+    pub fn emit_event(&self, event: UmbraEvent) -> usize {
+        let mut streams = self.streams.lock().unwrap();
+        streams.retain(|tx| tx.send(event.clone()).is_ok());
+        streams.len()
+    }
+
+    // Returns the number of handlers the frame was delivered to
+    pub fn emit_content(&self, context: &MessageContext, frame: &ContentFrame) -> usize {
+        let convo_id = &context.convo_id;
+        let handlers = self.dispatch(&self.on_content, |h| h(convo_id.clone(), frame.clone()))
+            + self.dispatch(&self.on_message, |h| h(context, frame));
+        let streams = self.emit_event(UmbraEvent::ContentReceived {
+            convo_id: convo_id.clone(),
+            content: frame.clone(),
+            sent_at: context.sent_at,
+            received_at: context.received_at,
+        });
+        handlers + streams
+    }
+
+    pub fn emit_delivery_update(&self, update: DeliveryUpdate) {
+        self.dispatch(&self.on_delivery_update, |h| h(update.clone()));
+        self.emit_event(UmbraEvent::DeliveryUpdate(update));
+    }
+
+    pub fn emit_message_edited(&self, event: MessageEdited) {
+        self.dispatch(&self.on_message_edited, |h| h(event.clone()));
+    }
+
+    pub fn emit_message_deleted(&self, event: MessageDeleted) {
+        self.dispatch(&self.on_message_deleted, |h| h(event.clone()));
+    }
+
+    pub fn emit_presence(&self, convo_id: &str, presence: Presence) {
+        self.dispatch(&self.on_presence, |h| {
+            h(convo_id.to_string(), presence.clone())
+        });
+    }
+
+    pub fn emit_state_changed(&self, event: StateChanged) {
+        self.dispatch(&self.on_state, |h| h(event.clone()));
+    }
+
+    pub fn emit_sync(&self, event: SyncEvent) {
+        self.dispatch(&self.on_sync, |h| h(event.clone()));
+    }
+
+    pub fn emit_unknown_frame(&self, frame: UnknownFrame) {
+        self.dispatch(&self.on_unknown_frame, |h| h(frame.clone()));
+        self.emit_event(UmbraEvent::UnknownFrame(frame));
+    }
+
+    pub fn emit_connection_state(&self, state: ConnectionState) {
+        self.dispatch(&self.on_connection_state, |h| h(state.clone()));
+        self.emit_event(UmbraEvent::ConnectionState(state));
+    }
+
+    pub fn emit_key(&self, event: KeyEvent) {
+        self.dispatch(&self.on_key, |h| h(event.clone()));
+    }
+
+    pub fn emit_pruned(&self, event: MessagesPruned) {
+        self.dispatch(&self.on_pruned, |h| h(event.clone()));
+    }
+
+    // A panicking error handler is only logged, so reporting it cannot recurse
+    pub fn emit_error(&self, err: &UmbraError) {
+        for handler in self.on_error.read().unwrap().iter() {
+            if let Err(e) = handler.invoke(|h| h(err)) {
+                error!("{}", e);
+            }
+        }
+    }
+
+    // For panics contained by `Registered::invoke`. Strict mode panics again
+    // with the report, as the original payload has been turned into it
+    pub fn emit_panic(&self, err: &UmbraError) {
+        self.emit_error(err);
+        if self.strict {
+            panic!("{err}");
+        }
+    }
+
+    pub fn emit_push_registration(&self, registration: PushRegistration) {
+        self.dispatch(&self.on_push_registration, |h| h(registration.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn panicking_handlers_are_reported_and_skipped() {
+        let events = EventHandlers::default();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let errors = Arc::new(Mutex::new(vec![]));
+
+        let site = Location::caller();
+        events.add_sync_handler(site, Box::new(|_| panic!("boom")));
+        let counter = calls.clone();
+        events.add_sync_handler(
+            Location::caller(),
+            Box::new(move |_| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }),
+        );
+        let reported = errors.clone();
+        events.add_error_handler(
+            Location::caller(),
+            Box::new(move |e| reported.lock().unwrap().push(e.to_string())),
+        );
+
+        let event = SyncEvent::MissingMessages {
+            convo_id: "c".into(),
+            ids: vec![],
+        };
+        events.emit_sync(event.clone());
+        events.emit_sync(event);
+
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains(&site.to_string()) && errors[0].contains("boom"));
+    }
+
+    #[test]
+    fn strict_mode_resumes_handler_panics() {
+        let events = EventHandlers::new(true);
+        let errors = Arc::new(AtomicUsize::new(0));
+        events.add_sync_handler(Location::caller(), Box::new(|_| panic!("boom")));
+        let counter = errors.clone();
+        events.add_error_handler(
+            Location::caller(),
+            Box::new(move |_| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }),
+        );
+
+        let event = SyncEvent::MissingMessages {
+            convo_id: "c".into(),
+            ids: vec![],
+        };
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| events.emit_sync(event)))
+            .expect_err("the handler's panic is resumed");
+        assert_eq!(panic_message(&*payload), "boom");
+        assert_eq!(errors.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,295 @@
+//! Builders for wire-level test data.
+//!
+//! Everything here produces the same encodings the SDK emits so downstream
+//! apps and DeliveryService authors can exercise a client without hand
+//! encoding protobufs. The `malformed` helpers produce inputs the receive
+//! path must reject.
+
+use prost::Message;
+use umbra_types::base::{
+    EncryptedBytes, InboxV1Frame, ReliableBytes, UmbraEnvelopeV1, encrypted_bytes, inbox_v1_frame,
+};
+use umbra_types::common_frames::ContentFrame;
+use umbra_types::convos::private_v1::{PrivateV1Frame, private_v1_frame};
+use umbra_types::encryption;
+use umbra_types::invite;
+use umbra_types::payload::ToEnvelope;
+
+use crate::address::Address;
+use crate::client::topic_inbox_convo;
+use crate::convos::ConversationId;
+use crate::frames::timestamped;
+use crate::notify::ContentMeta;
+use crate::utils::now_millis;
+use crate::{Blob, crypto, hints};
+
+pub fn plaintext(payload: Vec<u8>) -> EncryptedBytes {
+    EncryptedBytes {
+        encryption: Some(encrypted_bytes::Encryption::Plaintext(
+            encryption::Plaintext { payload },
+        )),
+    }
+}
+
+pub struct ContentFrameBuilder {
+    domain: u32,
+    tag: u32,
+    bytes: Vec<u8>,
+}
+
+impl Default for ContentFrameBuilder {
+    fn default() -> Self {
+        Self {
+            domain: 0,
+            tag: 5,
+            bytes: b"hello".to_vec(),
+        }
+    }
+}
+
+impl ContentFrameBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn domain(mut self, domain: u32) -> Self {
+        self.domain = domain;
+        self
+    }
+
+    pub fn tag(mut self, tag: u32) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    pub fn bytes(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.bytes = bytes.into();
+        self
+    }
+
+    pub fn build(self) -> ContentFrame {
+        ContentFrame {
+            domain: self.domain,
+            tag: self.tag,
+            bytes: self.bytes,
+        }
+    }
+}
+
+enum Content {
+    Frame(ContentFrame),
+    Raw(Vec<u8>),
+}
+
+pub struct ReliableBytesBuilder {
+    convo_id: String,
+    sender: Option<Address>,
+    message_id: Option<String>,
+    lamport_timestamp: u64,
+    sent_at: u64,
+    causal_history: Vec<String>,
+    content: Content,
+}
+
+impl ReliableBytesBuilder {
+    pub fn new(convo_id: impl Into<String>) -> Self {
+        Self {
+            convo_id: convo_id.into(),
+            sender: None,
+            message_id: None,
+            lamport_timestamp: 1,
+            sent_at: now_millis(),
+            causal_history: vec![],
+            content: Content::Frame(ContentFrameBuilder::new().build()),
+        }
+    }
+
+    /// Wraps the ContentFrame in a Timestamped frame, then in a PrivateV1Frame
+    /// for this conversation.
+    pub fn content(mut self, frame: ContentFrame) -> Self {
+        self.content = Content::Frame(frame);
+        self
+    }
+
+    /// Sets the content to arbitrary bytes, bypassing frame encoding.
+    pub fn raw_content(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.content = Content::Raw(bytes.into());
+        self
+    }
+
+    /// The address the message id is derived for. Without one the id is
+    /// derived as if the sender's address were empty.
+    pub fn sender(mut self, sender: Address) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    /// The sender's wall clock time (unix ms) in the Timestamped wrapper.
+    pub fn sent_at(mut self, sent_at: u64) -> Self {
+        self.sent_at = sent_at;
+        self
+    }
+
+    pub fn message_id(mut self, message_id: impl Into<String>) -> Self {
+        self.message_id = Some(message_id.into());
+        self
+    }
+
+    pub fn lamport_timestamp(mut self, lamport_timestamp: u64) -> Self {
+        self.lamport_timestamp = lamport_timestamp;
+        self
+    }
+
+    pub fn causal_history(mut self, causal_history: Vec<String>) -> Self {
+        self.causal_history = causal_history;
+        self
+    }
+
+    pub fn build(self) -> ReliableBytes {
+        let content = match self.content {
+            Content::Frame(frame) => {
+                let stamped = timestamped(frame, self.sent_at, ContentMeta::default());
+                PrivateV1Frame {
+                    conversation_id: self.convo_id.clone(),
+                    frame_type: Some(private_v1_frame::FrameType::Content(stamped)),
+                }
+                .encode_to_vec()
+            }
+            Content::Raw(bytes) => bytes,
+        };
+
+        // Unless given, derive the id the same way the SDK does
+        let message_id = self.message_id.unwrap_or_else(|| {
+            let mut preimage = content.clone();
+            if let Some(sender) = &self.sender {
+                preimage.extend_from_slice(sender.as_bytes());
+            }
+            preimage.extend_from_slice(&self.lamport_timestamp.to_be_bytes());
+            crypto::hash_to_string(&preimage)
+        });
+
+        ReliableBytes {
+            message_id,
+            channel_id: self.convo_id,
+            lamport_timestamp: self.lamport_timestamp,
+            causal_history: self.causal_history,
+            bloom_filter: vec![],
+            content: Some(content),
+        }
+    }
+}
+
+pub struct EnvelopeBuilder {
+    hint: String,
+    salt: u64,
+    payload: EncryptedBytes,
+}
+
+impl EnvelopeBuilder {
+    pub fn new(hint: impl Into<String>) -> Self {
+        Self {
+            hint: hint.into(),
+            salt: 0,
+            payload: plaintext(vec![]),
+        }
+    }
+
+    /// An envelope addressed to the private conversation between `addrs`,
+    /// with the hint for the current epoch.
+    pub fn private(addrs: Vec<Address>) -> Self {
+        let secret = hints::derived_secret(&ConversationId::private(&addrs));
+        let epoch = hints::epoch(now_millis());
+        Self::new(hints::derive(&secret, epoch)).salt(epoch)
+    }
+
+    /// An envelope addressed to `addr`'s inbox.
+    pub fn inbox(addr: &str) -> Self {
+        Self::new(topic_inbox_convo(addr))
+    }
+
+    pub fn salt(mut self, salt: u64) -> Self {
+        self.salt = salt;
+        self
+    }
+
+    pub fn reliable(mut self, reliable_bytes: ReliableBytes) -> Self {
+        self.payload = plaintext(reliable_bytes.encode_to_vec());
+        self
+    }
+
+    pub fn encrypted(mut self, payload: EncryptedBytes) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    pub fn build(self) -> UmbraEnvelopeV1 {
+        self.payload.to_envelope(self.hint, self.salt)
+    }
+
+    pub fn encode(self) -> Blob {
+        self.build().encode_to_vec()
+    }
+}
+
+/// An inbox envelope inviting `recipient` to a private conversation with `sender`.
+pub fn private_invite(sender: &Address, recipient: &Address) -> Blob {
+    let mut participants = vec![sender.clone(), recipient.clone()];
+    participants.sort();
+    let convo_id = ConversationId::private(&participants);
+    let participants = participants.into_iter().map(String::from).collect();
+
+    let frame = InboxV1Frame::new(
+        convo_id.into(),
+        inbox_v1_frame::FrameType::InvitePrivateV1(invite::InvitePrivateV1 { participants }),
+    );
+
+    EnvelopeBuilder::inbox(recipient)
+        .encrypted(plaintext(frame.encode_to_vec()))
+        .encode()
+}
+
+/// Inputs which are deliberately invalid at some layer of the pipeline.
+pub mod malformed {
+    use super::*;
+
+    /// Bytes which do not decode as any protobuf message.
+    pub fn garbage() -> Blob {
+        vec![0xff; 16]
+    }
+
+    /// A valid encoding cut short.
+    pub fn truncated(bytes: &[u8]) -> Blob {
+        bytes[..bytes.len() / 2].to_vec()
+    }
+
+    /// An envelope whose payload is not a valid EncryptedBytes.
+    pub fn envelope_with_payload(hint: impl Into<String>, payload: Vec<u8>) -> Blob {
+        let mut envelope = EnvelopeBuilder::new(hint).build();
+        envelope.payload = payload;
+        envelope.encode_to_vec()
+    }
+
+    /// An envelope whose EncryptedBytes carries no encryption variant.
+    pub fn missing_encryption(hint: impl Into<String>) -> Blob {
+        EnvelopeBuilder::new(hint)
+            .encrypted(EncryptedBytes { encryption: None })
+            .encode()
+    }
+
+    /// An envelope whose PrivateV1Frame has no frame_type set.
+    pub fn empty_frame(addrs: Vec<Address>) -> Blob {
+        let convo_id = ConversationId::private(&addrs).to_string();
+        let frame = PrivateV1Frame {
+            conversation_id: convo_id.clone(),
+            frame_type: None,
+        };
+
+        EnvelopeBuilder::private(addrs)
+            .reliable(
+                ReliableBytesBuilder::new(convo_id)
+                    .raw_content(frame.encode_to_vec())
+                    .build(),
+            )
+            .encode()
+    }
+}
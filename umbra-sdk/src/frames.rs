@@ -0,0 +1,78 @@
+use bytes::Bytes;
+use prost::{DecodeError, Message};
+use umbra_types::common_frames::ContentFrame;
+
+use types::{SdkFrameTags, Timestamped};
+
+use crate::address::Address;
+use crate::notify::{ContentMeta, NotificationPriority};
+
+pub mod types {
+    include!(concat!(env!("OUT_DIR"), "/umbra.sdk.frames.rs"));
+}
+
+// ContentFrames in this domain carry SDK control frames rather than app content.
+pub const SDK_DOMAIN: u32 = 1;
+
+// Ephemeral frames are fire-and-forget: they are not acknowledged, not
+// retransmitted and never referenced as a causal dependency.
+pub fn is_ephemeral(frame: &ContentFrame) -> bool {
+    frame.domain == SDK_DOMAIN
+        && matches!(
+            SdkFrameTags::try_from(frame.tag as i32),
+            Ok(SdkFrameTags::SdkFrameTagDeliveryReceipt
+                | SdkFrameTags::SdkFrameTagPresence
+                | SdkFrameTags::SdkFrameTagRepairRequest
+                | SdkFrameTags::SdkFrameTagHistoryQuery
+                | SdkFrameTags::SdkFrameTagHistoryResponse
+                | SdkFrameTags::SdkFrameTagCumulativeAck)
+        )
+}
+
+// Durable frames travel wrapped with their send time, fallback text,
+// mentions and priority
+pub fn timestamped(frame: ContentFrame, sent_at: u64, meta: ContentMeta) -> ContentFrame {
+    ContentFrame {
+        domain: SDK_DOMAIN,
+        tag: SdkFrameTags::SdkFrameTagTimestamped as u32,
+        bytes: Timestamped {
+            sent_at,
+            frame: frame.encode_to_vec().into(),
+            fallback: meta.fallback.unwrap_or_default(),
+            mentions: meta.mentions.into_iter().map(String::from).collect(),
+            priority: meta.priority.to_wire(),
+        }
+        .encode_to_vec(),
+    }
+}
+
+/// What a Timestamped wrapper says about the frame inside it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stamp {
+    pub sent_at: u64,
+    pub meta: ContentMeta,
+}
+
+// Unwraps a Timestamped frame, passing any other frame through untouched
+pub fn untimestamped(frame: ContentFrame) -> Result<(ContentFrame, Option<Stamp>), DecodeError> {
+    if frame.domain != SDK_DOMAIN || frame.tag != SdkFrameTags::SdkFrameTagTimestamped as u32 {
+        return Ok((frame, None));
+    }
+    let stamped = Timestamped::decode(Bytes::from(frame.bytes))?;
+    let inner = ContentFrame::decode(stamped.frame)?;
+    // Mentions which are not valid addresses could never match anyone
+    let mentions = stamped
+        .mentions
+        .into_iter()
+        .filter_map(|m| Address::try_from(m).ok())
+        .collect();
+    let stamp = Stamp {
+        sent_at: stamped.sent_at,
+        meta: ContentMeta {
+            fallback: Some(stamped.fallback).filter(|f| !f.is_empty()),
+            mentions,
+            priority: NotificationPriority::from_wire(stamped.priority),
+        },
+    };
+    Ok((inner, Some(stamp)))
+}
@@ -0,0 +1,74 @@
+//! Entry points for the fuzz targets in `fuzz/`.
+//!
+//! Each target feeds arbitrary bytes to one decoding layer of the receive
+//! path, wrapped in valid outer layers so the input reaches that layer. Any
+//! input must be rejected with an error; a panic is a bug.
+
+use prost::Message;
+
+use crate::address::Address;
+use crate::convos::ConversationId;
+use crate::crypto;
+use crate::fixtures::{EnvelopeBuilder, ReliableBytesBuilder, plaintext};
+use crate::transport::{InMemoryDeliveryService, LocalBroker};
+use crate::{UmbraClient, UmbraError};
+
+const PEER: &str = "peer";
+
+/// A client with one conversation, receiving whatever the fuzzer produces.
+pub struct Target {
+    client: UmbraClient<InMemoryDeliveryService>,
+    addrs: Vec<Address>,
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Target {
+    pub fn new() -> Self {
+        let addr = "fuzz".parse().expect("valid address");
+        let peer: Address = PEER.parse().expect("valid address");
+        let client = UmbraClient::new(LocalBroker::new().connect(), addr);
+        client
+            .create_private_conversation(peer.clone())
+            .expect("conversation with a fixed peer");
+        let addrs = vec![client.address(), peer];
+        Self { client, addrs }
+    }
+
+    /// Raw bytes from the DeliveryService, as an UmbraEnvelopeV1.
+    pub fn envelope(&self, data: &[u8]) -> Result<(), UmbraError> {
+        let push = self.client.handle_push(data).map(|_| ());
+        push.and(self.client.receive(data))
+    }
+
+    /// The plaintext of an envelope on this client's inbox, as an
+    /// InboxV1Frame.
+    pub fn inbox_frame(&self, data: &[u8]) -> Result<(), UmbraError> {
+        let envelope = EnvelopeBuilder::inbox(&self.client.address())
+            .encrypted(plaintext(data.to_vec()))
+            .encode();
+        self.envelope(&envelope)
+    }
+
+    /// The plaintext of a conversation envelope, as ReliableBytes.
+    pub fn reliable_bytes(&self, data: &[u8]) -> Result<(), UmbraError> {
+        let envelope = EnvelopeBuilder::private(self.addrs.clone())
+            .encrypted(plaintext(data.to_vec()))
+            .encode();
+        self.envelope(&envelope)
+    }
+
+    /// The content of ReliableBytes, as a PrivateV1Frame.
+    pub fn private_frame(&self, data: &[u8]) -> Result<(), UmbraError> {
+        // A fresh id per input, so earlier inputs are not deduplicated away
+        let reliable = ReliableBytesBuilder::new(ConversationId::private(&self.addrs))
+            .message_id(crypto::hash_to_string(data))
+            .raw_content(data.to_vec())
+            .build();
+        self.reliable_bytes(&reliable.encode_to_vec())
+    }
+}
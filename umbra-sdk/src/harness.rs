@@ -0,0 +1,675 @@
+//! Deterministic multi-client scenarios for tests.
+//!
+//! A [`Harness`] runs named clients over one in-memory broker, entirely on
+//! the calling thread: envelopes are only processed by [`Harness::settle`],
+//! and time only moves with [`Harness::advance`], which drives every
+//! client's `tick` against a virtual clock. Every client sends through a
+//! `SimulatedDeliveryService` whose loss, duplication and delays are drawn
+//! from a seeded random source and measured against the same clock, so a
+//! failing scenario fails the same way every run.
+//!
+//! ```ignore
+//! let mut h = Harness::new(&["amal", "bola"]);
+//! let convo = h.invite("amal", "bola");
+//! h.set_drop_rate(0.1);
+//! for i in 0..100 {
+//!     h.send_text("amal", &convo, &format!("{i}"));
+//! }
+//! h.set_drop_rate(0.0);
+//! h.advance(Duration::from_secs(60));
+//! assert_eq!(h.received_texts("bola", &convo).len(), 100);
+//! ```
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+use prost::Message;
+use umbra_content_types::{ChatMessage, TaggedContent};
+
+use crate::transport::{
+    InMemoryDeliveryService, LocalBroker, NetworkConditions, SimulatedDeliveryService,
+};
+use crate::utils::VIRTUAL_NOW;
+use crate::{
+    Blob, ContentType, ConversationId, DeliveryService, MessageHandle, UmbraClient, UmbraError,
+    UmbraEvent,
+};
+
+// Virtual time at which every scenario starts, in unix ms
+const START_MILLIS: u64 = 1_700_000_000_000;
+// Clients are ticked this often while time advances, as `start` would
+const TICK_STEP: Duration = Duration::from_secs(1);
+// Delivery rounds after which `settle` assumes clients are livelocked
+const MAX_ROUNDS: usize = 10_000;
+
+type Simulated = SimulatedDeliveryService<InMemoryDeliveryService>;
+
+/// DeliveryService of a harness client: a simulated connection to the
+/// harness's broker, whose conditions the harness controls.
+pub struct HarnessTransport {
+    inner: Arc<Simulated>,
+}
+
+impl DeliveryService for HarnessTransport {
+    fn send(&self, message: Blob) -> Result<(), UmbraError> {
+        self.inner.send(message)
+    }
+
+    fn recv(&self) -> Result<Option<Blob>, UmbraError> {
+        self.inner.recv()
+    }
+
+    fn subscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        self.inner.subscribe(topic)
+    }
+
+    fn unsubscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        self.inner.unsubscribe(topic)
+    }
+}
+
+struct Peer {
+    client: UmbraClient<HarnessTransport>,
+    transport: Arc<Simulated>,
+    events: Receiver<UmbraEvent>,
+    log: Vec<UmbraEvent>,
+}
+
+impl Peer {
+    fn collect(&mut self) -> &[UmbraEvent] {
+        self.log.extend(self.events.try_iter());
+        &self.log
+    }
+}
+
+/// Named clients on a shared network and virtual clock. Panics on misuse,
+/// e.g. an unknown client name, as befits a test.
+pub struct Harness {
+    broker: LocalBroker,
+    conditions: NetworkConditions,
+    seed: u64,
+    peers: BTreeMap<String, Peer>,
+    now: u64,
+    // The virtual clock is per thread, so the harness must stay on one
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Harness {
+    /// Clients for each of `names` on a lossless network, seeded from 0.
+    pub fn new(names: &[&str]) -> Self {
+        Self::with_seed(names, 0)
+    }
+
+    pub fn with_seed(names: &[&str], seed: u64) -> Self {
+        // Before any client exists, as clients read the clock when created
+        VIRTUAL_NOW.with(|now| now.set(Some(START_MILLIS)));
+        let mut harness = Self {
+            broker: LocalBroker::new(),
+            conditions: NetworkConditions::default(),
+            seed,
+            peers: BTreeMap::new(),
+            now: START_MILLIS,
+            _not_send: PhantomData,
+        };
+        for name in names {
+            harness.add_client(name);
+        }
+        harness
+    }
+
+    /// Add a client to the running scenario, subject to the current
+    /// network conditions.
+    pub fn add_client(&mut self, name: &str) {
+        // Each client draws from its own stream, fixed by the order added
+        let conditions = NetworkConditions {
+            seed: Some(self.seed.wrapping_add(self.peers.len() as u64)),
+            ..self.conditions.clone()
+        };
+        let transport = Arc::new(SimulatedDeliveryService::new(
+            self.broker.connect(),
+            conditions,
+        ));
+        let client = UmbraClient::new(
+            HarnessTransport {
+                inner: transport.clone(),
+            },
+            name.parse().expect("valid address"),
+        );
+        let events = client.events();
+        let peer = Peer {
+            client,
+            transport,
+            events,
+            log: vec![],
+        };
+        self.peers.insert(name.to_string(), peer);
+    }
+
+    pub fn client(&self, name: &str) -> &UmbraClient<HarnessTransport> {
+        &self.peer(name).client
+    }
+
+    /// For registering handlers.
+    pub fn client_mut(&mut self, name: &str) -> &mut UmbraClient<HarnessTransport> {
+        &mut self.peer_mut(name).client
+    }
+
+    /// Virtual time in unix ms.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Apply `conditions` to every envelope sent from now on. Their seed is
+    /// ignored in favour of the harness's.
+    pub fn set_conditions(&mut self, conditions: NetworkConditions) {
+        for peer in self.peers.values() {
+            peer.transport.set_conditions(conditions.clone());
+        }
+        self.conditions = conditions;
+    }
+
+    /// Chance, between 0 and 1, that any envelope sent from now on is lost.
+    pub fn set_drop_rate(&mut self, drop_rate: f64) {
+        self.set_conditions(NetworkConditions {
+            drop_rate,
+            ..self.conditions.clone()
+        });
+    }
+
+    /// Envelopes lost so far, across every client.
+    pub fn dropped(&self) -> u64 {
+        self.peers.values().map(|p| p.transport.dropped()).sum()
+    }
+
+    /// Have `from` invite `to` to a private conversation and settle,
+    /// returning the conversation's id.
+    pub fn invite(&mut self, from: &str, to: &str) -> ConversationId {
+        let convo = self
+            .client(from)
+            .create_private_conversation(to.parse().expect("valid address"))
+            .expect("invite sent");
+        let convo_id = convo.lock().unwrap().convo_id();
+        self.settle();
+        convo_id
+    }
+
+    /// Send a chat message from `from` and settle, returning its id.
+    pub fn send_text(&mut self, from: &str, convo_id: &str, text: &str) -> String {
+        self.send(from, convo_id, |convo| {
+            convo.send_typed(&ChatMessage::new(text.to_string()))
+        })
+    }
+
+    /// Send raw content of `content_type` from `from` and settle, returning
+    /// its id.
+    pub fn send_content(
+        &mut self,
+        from: &str,
+        convo_id: &str,
+        content_type: ContentType,
+        bytes: Blob,
+    ) -> String {
+        self.send(from, convo_id, |convo| convo.send(content_type, bytes))
+    }
+
+    fn send(
+        &mut self,
+        from: &str,
+        convo_id: &str,
+        f: impl FnOnce(
+            &mut (dyn crate::Conversation<HarnessTransport> + Send + Sync),
+        ) -> Result<MessageHandle, UmbraError>,
+    ) -> String {
+        let convo = self
+            .client(from)
+            .get_conversation(&convo_id.parse().expect("valid conversation id"))
+            .unwrap_or_else(|| panic!("{from} has no conversation {convo_id}"));
+        let handle = f(&mut *convo.lock().unwrap()).expect("message sent");
+        self.settle();
+        handle.message_id
+    }
+
+    /// Publish `envelope` as if some other client had sent it, e.g. one
+    /// built with `fixtures::malformed`, and settle.
+    pub fn inject(&mut self, envelope: Blob) {
+        self.broker
+            .connect()
+            .send(envelope)
+            .expect("envelope names a topic");
+        self.settle();
+    }
+
+    /// Deliver envelopes until no client has any left, returning how many
+    /// were processed. Time does not move, so delayed envelopes stay in
+    /// flight.
+    pub fn settle(&mut self) -> usize {
+        let mut total = 0;
+        for _ in 0..MAX_ROUNDS {
+            let mut received = 0;
+            for peer in self.peers.values() {
+                received += peer.client.poll().expect("in-memory receive");
+            }
+            if received == 0 {
+                return total;
+            }
+            total += received;
+        }
+        panic!("clients still exchanging envelopes after {MAX_ROUNDS} rounds");
+    }
+
+    /// Move the clock forward by `by`, ticking every client once per
+    /// virtual second and settling after each tick.
+    pub fn advance(&mut self, by: Duration) {
+        let end = self.now + by.as_millis() as u64;
+        while self.now < end {
+            self.now = (self.now + TICK_STEP.as_millis() as u64).min(end);
+            VIRTUAL_NOW.with(|now| now.set(Some(self.now)));
+            for peer in self.peers.values() {
+                peer.client.tick();
+            }
+            self.settle();
+        }
+    }
+
+    /// Every event `name` has emitted so far, oldest first.
+    pub fn events(&mut self, name: &str) -> &[UmbraEvent] {
+        self.peer_mut(name).collect()
+    }
+
+    /// Text of every chat message `name` received in `convo_id`, in the
+    /// order they were handed to the application.
+    pub fn received_texts(&mut self, name: &str, convo_id: &str) -> Vec<String> {
+        self.events(name)
+            .iter()
+            .filter_map(|event| match event {
+                UmbraEvent::ContentReceived {
+                    convo_id: id,
+                    content,
+                    ..
+                } if id == convo_id
+                    && ContentType::new(content.domain, content.tag)
+                        == ChatMessage::CONTENT_TYPE =>
+                {
+                    ChatMessage::decode(content.bytes.as_slice()).ok()
+                }
+                _ => None,
+            })
+            .map(|msg| msg.text)
+            .collect()
+    }
+
+    fn peer(&self, name: &str) -> &Peer {
+        self.peers
+            .get(name)
+            .unwrap_or_else(|| panic!("no client named {name}"))
+    }
+
+    fn peer_mut(&mut self, name: &str) -> &mut Peer {
+        self.peers
+            .get_mut(name)
+            .unwrap_or_else(|| panic!("no client named {name}"))
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        VIRTUAL_NOW.with(|now| now.set(None));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::Address;
+    use crate::client::topic_inbox_convo;
+    use crate::convos::MessageRecord;
+    use crate::fixtures::{EnvelopeBuilder, malformed, plaintext, private_invite};
+    use crate::hints;
+    use crate::watch::Diff;
+
+    fn texts(messages: &[MessageRecord]) -> Vec<String> {
+        messages
+            .iter()
+            .filter(|m| {
+                ContentType::new(m.content.domain, m.content.tag) == ChatMessage::CONTENT_TYPE
+            })
+            .filter_map(|m| ChatMessage::decode(m.content.bytes.as_slice()).ok())
+            .map(|msg| msg.text)
+            .collect()
+    }
+
+    // Hints `name` accepts on envelopes for `convo_id`, one per epoch in the
+    // window
+    fn expected_hints(h: &Harness, name: &str, convo_id: &str) -> HashSet<String> {
+        h.client(name)
+            .push_registration()
+            .hints
+            .into_iter()
+            .filter(|(_, id)| id == convo_id)
+            .map(|(hint, _)| hint)
+            .collect()
+    }
+
+    #[test]
+    fn invite_and_exchange() {
+        let mut h = Harness::new(&["amal", "bola"]);
+        let convo = h.invite("amal", "bola");
+        assert!(h.client("bola").get_conversation(&convo).is_some());
+
+        h.send_text("amal", &convo, "hello");
+        h.send_text("bola", &convo, "hi");
+
+        assert_eq!(h.received_texts("bola", &convo), ["hello"]);
+        assert_eq!(h.received_texts("amal", &convo), ["hi"]);
+    }
+
+    #[test]
+    fn restored_conversations_drop_redelivered_messages() {
+        let mut h = Harness::new(&["amal", "bola"]);
+        let convo = h.invite("amal", "bola");
+        h.send_text("amal", &convo, "once");
+        let amal = h.client("amal").get_conversation(&convo).unwrap();
+        let envelope = amal.lock().unwrap().last_envelope().unwrap();
+
+        let bola = h.client("bola").get_conversation(&convo).unwrap();
+        let snapshot = bola.lock().unwrap().export(false);
+        h.client("bola").import_conversation(&snapshot).unwrap();
+        // As a store node would after the restart
+        h.inject(envelope);
+
+        assert_eq!(h.received_texts("bola", &convo), ["once"]);
+    }
+
+    #[test]
+    fn hints_are_not_derived_from_the_conversation_id() {
+        let mut h = Harness::new(&["amal", "bola"]);
+        let convo = h.invite("amal", "bola");
+
+        let expected = expected_hints(&h, "bola", &convo);
+        assert_eq!(expected, expected_hints(&h, "amal", &convo));
+        let derived = hints::derive(&hints::derived_secret(&convo), hints::epoch(h.now()));
+        assert!(!expected.contains(&derived));
+
+        h.send_text("amal", &convo, "hello");
+        assert_eq!(h.received_texts("bola", &convo), ["hello"]);
+    }
+
+    #[test]
+    fn conversation_watchers_see_updates() {
+        let mut h = Harness::new(&["amal", "bola"]);
+        let convo = h.invite("amal", "bola");
+        let watcher = h.client("bola").watch_conversations();
+        assert!(matches!(watcher.try_recv(), Ok(Diff::Added(_))));
+
+        h.send_text("amal", &convo, "hello");
+        let bola = h.client("bola").get_conversation(&convo).unwrap();
+        bola.lock().unwrap().archive();
+        let snapshot = bola.lock().unwrap().export(false);
+        h.client("bola").import_conversation(&snapshot).unwrap();
+
+        let updates: Vec<_> = watcher
+            .try_iter()
+            .map(|diff| match diff {
+                Diff::Updated(info) => (info.last_activity.is_some(), info.archived),
+                diff => panic!("unexpected {diff:?}"),
+            })
+            .collect();
+        assert_eq!(updates, [(true, false), (true, true), (true, true)]);
+    }
+
+    #[test]
+    fn repeated_invites_keep_the_conversation() {
+        let mut h = Harness::new(&["amal", "bola"]);
+        let convo = h.invite("amal", "bola");
+        h.send_text("amal", &convo, "hello");
+        let amal_convo = h.client("amal").get_conversation(&convo).unwrap();
+        let bola_convo = h.client("bola").get_conversation(&convo).unwrap();
+
+        let (amal, bola): (Address, Address) = ("amal".parse().unwrap(), "bola".parse().unwrap());
+        let invite = private_invite(&amal, &bola);
+        h.inject(invite.clone());
+        h.inject(invite);
+        h.client("amal").create_private_conversation(bola).unwrap();
+        h.settle();
+
+        for (name, before) in [("amal", amal_convo), ("bola", bola_convo)] {
+            let after = h.client(name).get_conversation(&convo).unwrap();
+            assert!(Arc::ptr_eq(&before, &after));
+            assert_eq!(texts(&after.lock().unwrap().messages()), ["hello"]);
+        }
+        assert_eq!(h.received_texts("bola", &convo), ["hello"]);
+    }
+
+    #[test]
+    fn recovers_from_loss() {
+        let mut h = Harness::new(&["amal", "bola"]);
+        let convo = h.invite("amal", "bola");
+
+        h.set_drop_rate(0.1);
+        for i in 0..100 {
+            h.send_text("amal", &convo, &i.to_string());
+        }
+        h.set_drop_rate(0.0);
+        assert!(h.dropped() > 0);
+        // References the tail of the burst, so losses there are noticed too
+        h.send_text("amal", &convo, "done");
+        h.advance(Duration::from_secs(60));
+
+        let received: HashSet<_> = h.received_texts("bola", &convo).into_iter().collect();
+        let expected: HashSet<_> = (0..100)
+            .map(|i| i.to_string())
+            .chain(["done".to_string()])
+            .collect();
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn repairs_a_dropped_message() {
+        let mut h = Harness::new(&["amal", "bola"]);
+        let convo = h.invite("amal", "bola");
+
+        h.set_drop_rate(1.0);
+        h.send_text("amal", &convo, "lost");
+        h.set_drop_rate(0.0);
+        // Depends on the lost message, so bola asks for it at once
+        h.send_text("amal", &convo, "after");
+
+        assert_eq!(h.received_texts("bola", &convo), ["lost", "after"]);
+    }
+
+    #[test]
+    fn fetches_history_missed_while_offline() {
+        let mut h = Harness::new(&["amal", "bola"]);
+        let convo = h.invite("amal", "bola");
+
+        h.set_drop_rate(1.0);
+        h.send_text("amal", &convo, "missed");
+        h.set_drop_rate(0.0);
+        assert!(h.received_texts("bola", &convo).is_empty());
+
+        let bola = h.client("bola").get_conversation(&convo).unwrap();
+        bola.lock().unwrap().fetch_history(0, 10).unwrap();
+        h.settle();
+        assert_eq!(h.received_texts("bola", &convo), ["missed"]);
+    }
+
+    #[test]
+    fn resyncs_after_restoring_without_history() {
+        let mut h = Harness::new(&["amal", "bola"]);
+        let convo = h.invite("amal", "bola");
+        let bola = h.client("bola").get_conversation(&convo).unwrap();
+        let backup = bola.lock().unwrap().export(false);
+
+        h.send_text("amal", &convo, "one");
+        let amal = h.client("amal").get_conversation(&convo).unwrap();
+        amal.lock()
+            .unwrap()
+            .set_state("title", b"umbra".to_vec())
+            .unwrap();
+        h.settle();
+        h.send_text("amal", &convo, "two");
+
+        let restored = h.client("bola").import_conversation(&backup).unwrap();
+        assert!(restored.lock().unwrap().messages().is_empty());
+        restored.lock().unwrap().request_resync().unwrap();
+        h.settle();
+
+        let restored = restored.lock().unwrap();
+        assert_eq!(texts(&restored.messages()), ["one", "two"]);
+        assert_eq!(restored.state().get("title"), Some(&b"umbra"[..]));
+    }
+
+    #[test]
+    fn keeps_receiving_after_malformed_envelopes() {
+        let mut h = Harness::new(&["amal", "bola"]);
+        let convo = h.invite("amal", "bola");
+        let errors = Arc::new(AtomicUsize::new(0));
+        let counter = errors.clone();
+        h.client_mut("bola").add_error_handler(move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+
+        let hint = expected_hints(&h, "bola", &convo)
+            .into_iter()
+            .next()
+            .unwrap();
+        h.inject(
+            EnvelopeBuilder::new(hint)
+                .encrypted(plaintext(malformed::garbage()))
+                .encode(),
+        );
+        h.inject(malformed::envelope_with_payload(
+            topic_inbox_convo("bola"),
+            malformed::garbage(),
+        ));
+        assert!(errors.load(Ordering::Relaxed) > 0);
+
+        h.send_text("amal", &convo, "still here");
+        assert_eq!(h.received_texts("bola", &convo), ["still here"]);
+    }
+
+    #[test]
+    fn strict_poll_returns_receive_errors() {
+        let broker = LocalBroker::new();
+        let client = UmbraClient::builder(broker.connect(), "bola".parse().unwrap())
+            .strict(true)
+            .build();
+        let garbage =
+            || malformed::envelope_with_payload(topic_inbox_convo("bola"), malformed::garbage());
+        broker.connect().send(garbage()).unwrap();
+        broker.connect().send(garbage()).unwrap();
+
+        assert!(client.poll().is_err());
+        // The second envelope is left for the next poll
+        assert!(client.poll().is_err());
+        assert_eq!(client.poll().unwrap(), 0);
+    }
+
+    #[test]
+    fn note_to_self_needs_no_invite() {
+        let mut h = Harness::new(&["amal", "bola"]);
+        let note = h.client("amal").note_to_self();
+        let convo_id = note.lock().unwrap().convo_id();
+
+        h.send_text("amal", &convo_id, "remember");
+        let again = h.client("amal").note_to_self();
+        let messages = again.lock().unwrap().messages();
+        // The echo of the message is not taken for a second one
+        assert_eq!(texts(&messages), ["remember"]);
+        assert!(h.client("bola").get_conversation(&convo_id).is_none());
+    }
+
+    mod properties {
+        use proptest::collection::vec;
+        use proptest::prelude::*;
+
+        use super::*;
+        use crate::transport::Latency;
+
+        const NAMES: [&str; 2] = ["amal", "bola"];
+
+        #[derive(Debug, Clone)]
+        enum Op {
+            Send(usize),
+            Advance(Duration),
+        }
+
+        fn op() -> impl Strategy<Value = Op> {
+            prop_oneof![
+                3 => (0..NAMES.len()).prop_map(Op::Send),
+                1 => (0..500u64).prop_map(|ms| Op::Advance(Duration::from_millis(ms))),
+            ]
+        }
+
+        fn conditions() -> impl Strategy<Value = NetworkConditions> {
+            (0.0..0.3, 0.0..0.3, 0.0..0.3, 0..200u64).prop_map(
+                |(drop_rate, duplicate_rate, reorder_rate, max_ms)| NetworkConditions {
+                    latency: Latency::Uniform {
+                        min: Duration::ZERO,
+                        max: Duration::from_millis(max_ms),
+                    },
+                    drop_rate,
+                    duplicate_rate,
+                    reorder_rate,
+                    seed: None,
+                },
+            )
+        }
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(32))]
+
+            // Each sender's messages reach the other exactly once and in the
+            // order sent, once the network heals long enough for repair
+            #[test]
+            fn delivery_is_exactly_once_and_in_order(
+                conditions in conditions(),
+                ops in vec(op(), 1..40),
+                seed in any::<u64>(),
+            ) {
+                let mut h = Harness::with_seed(&NAMES, seed);
+                let convo = h.invite(NAMES[0], NAMES[1]);
+
+                h.set_conditions(conditions);
+                let mut sent: [Vec<String>; 2] = Default::default();
+                for op in ops {
+                    match op {
+                        Op::Send(from) => {
+                            let text = format!("{}-{}", NAMES[from], sent[from].len());
+                            h.send_text(NAMES[from], &convo, &text);
+                            sent[from].push(text);
+                        }
+                        Op::Advance(by) => h.advance(by),
+                    }
+                }
+
+                // Final messages reference the tail of each sender's burst
+                h.set_conditions(NetworkConditions::default());
+                for (from, name) in NAMES.iter().enumerate() {
+                    let text = format!("{name}-last");
+                    h.send_text(name, &convo, &text);
+                    sent[from].push(text);
+                }
+                h.advance(Duration::from_secs(120));
+
+                for (to, name) in NAMES.iter().enumerate() {
+                    let from = 1 - to;
+                    let received: Vec<_> = h
+                        .received_texts(name, &convo)
+                        .into_iter()
+                        .filter(|text| text.starts_with(NAMES[from]))
+                        .collect();
+                    let unique: HashSet<_> = received.iter().collect();
+                    prop_assert_eq!(unique.len(), received.len(), "duplicates delivered");
+                    prop_assert_eq!(&received, &sent[from]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use sha3::{Digest, Sha3_256};
+
+use crate::crypto::hash_to_string;
+
+// How long a conversation keeps the same hint
+pub(crate) const HINT_EPOCH: Duration = Duration::from_secs(60 * 60);
+
+pub(crate) fn epoch(now: u64) -> u64 {
+    now / HINT_EPOCH.as_millis() as u64
+}
+
+// Hints are accepted one epoch either side of the current one, to allow for
+// clock skew and envelopes in flight across a rotation
+fn window(epoch: u64) -> RangeInclusive<u64> {
+    epoch.saturating_sub(1)..=epoch.saturating_add(1)
+}
+
+// Bytes of randomness in a conversation's secret
+const SECRET_LEN: usize = 32;
+
+/// A fresh secret for a new conversation's hints. The inviter sends it to
+/// the peer with the invite, so observers cannot derive the hints.
+pub(crate) fn random_secret() -> Vec<u8> {
+    rand::random::<[u8; SECRET_LEN]>().to_vec()
+}
+
+// For conversations without a shared secret: invites from peers which do not
+// send one, snapshots from before secrets were shared, and notes to self,
+// which have no invite to carry one. Derived from the conversation id, such
+// hints only hide participants from observers who cannot guess who is talking.
+pub(crate) fn derived_secret(convo_id: &str) -> Vec<u8> {
+    Sha3_256::digest(format!("umbra/hint/{convo_id}")).to_vec()
+}
+
+/// The hint a conversation's envelopes carry during `epoch`. Envelopes also
+/// carry the epoch as their salt, so late arrivals can still be resolved.
+pub(crate) fn derive(secret: &[u8], epoch: u64) -> String {
+    let mut preimage = secret.to_vec();
+    preimage.extend_from_slice(&epoch.to_be_bytes());
+    format!("/hint/{}", hash_to_string(preimage))
+}
+
+/// Hints expected on incoming envelopes, for every conversation.
+#[derive(Debug, Default)]
+pub(crate) struct HintTable {
+    epoch: u64,
+    // convo_id -> secret
+    secrets: HashMap<String, Vec<u8>>,
+    // hint -> convo_id
+    hints: HashMap<String, String>,
+}
+
+impl HintTable {
+    pub fn new(now: u64) -> Self {
+        Self {
+            epoch: epoch(now),
+            ..Default::default()
+        }
+    }
+
+    /// Start expecting hints for `convo_id`. Returns the hints to subscribe to.
+    pub fn insert(&mut self, convo_id: &str, secret: Vec<u8>) -> Vec<String> {
+        let hints: Vec<String> = window(self.epoch)
+            .map(|epoch| derive(&secret, epoch))
+            .collect();
+        for hint in &hints {
+            self.hints.insert(hint.clone(), convo_id.to_string());
+        }
+        self.secrets.insert(convo_id.to_string(), secret);
+        hints
+    }
+
+    /// Move to the epoch of `now`. Returns the hints which entered and left
+    /// the window, to subscribe and unsubscribe.
+    pub fn rotate(&mut self, now: u64) -> (Vec<String>, Vec<String>) {
+        let current = epoch(now);
+        if current == self.epoch {
+            return (vec![], vec![]);
+        }
+        let (old, new) = (window(self.epoch), window(current));
+        self.epoch = current;
+
+        let mut added = vec![];
+        let mut removed = vec![];
+        for (convo_id, secret) in &self.secrets {
+            for epoch in old.clone().filter(|e| !new.contains(e)) {
+                let hint = derive(secret, epoch);
+                self.hints.remove(&hint);
+                removed.push(hint);
+            }
+            for epoch in new.clone().filter(|e| !old.contains(e)) {
+                let hint = derive(secret, epoch);
+                self.hints.insert(hint.clone(), convo_id.clone());
+                added.push(hint);
+            }
+        }
+        (added, removed)
+    }
+
+    /// The conversation an envelope belongs to. Hints outside the window,
+    /// e.g. from history replays, are checked against the epoch in `salt`.
+    pub fn resolve(&self, hint: &str, salt: u64) -> Option<&str> {
+        if let Some(convo_id) = self.hints.get(hint) {
+            return Some(convo_id);
+        }
+        self.secrets
+            .iter()
+            .find(|(_, secret)| derive(secret, salt) == hint)
+            .map(|(convo_id, _)| convo_id.as_str())
+    }
+
+    pub fn secret(&self, convo_id: &str) -> Option<&[u8]> {
+        self.secrets.get(convo_id).map(Vec::as_slice)
+    }
+
+    /// Every hint currently expected, mapped to its conversation.
+    pub fn hints(&self) -> &HashMap<String, String> {
+        &self.hints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 60 * 60 * 1000;
+
+    #[test]
+    fn hints_rotate_and_do_not_reveal_the_conversation() {
+        let secret = derived_secret("/private/amal|bola");
+        let first = derive(&secret, 1);
+        assert_ne!(first, derive(&secret, 2));
+        assert!(!first.contains("amal"));
+        assert_ne!(first, derive(&derived_secret("/private/amal|cara"), 1));
+    }
+
+    #[test]
+    fn resolves_within_window_and_by_salt() {
+        let secret = derived_secret("convo");
+        let mut table = HintTable::new(10 * HOUR);
+        assert_eq!(table.insert("convo", secret.clone()).len(), 3);
+        assert_eq!(table.resolve(&derive(&secret, 11), 0), Some("convo"));
+
+        let (added, removed) = table.rotate(12 * HOUR);
+        assert_eq!(added, vec![derive(&secret, 13)]);
+        assert_eq!(removed, vec![derive(&secret, 9)]);
+        assert_eq!(table.hints().len(), 3);
+
+        // Out of window, but the salt names its epoch
+        assert_eq!(table.resolve(&derive(&secret, 9), 9), Some("convo"));
+        assert_eq!(table.resolve(&derive(&secret, 9), 10), None);
+    }
+}
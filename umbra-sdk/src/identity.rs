@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::UmbraError;
+use crate::address::Address;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub addr: Address,
+    pub public_key: Vec<u8>,
+}
+
+/// Resolves addresses to identity keys. Implement this to plug in a real
+/// key lookup backend.
+pub trait IdentityDirectory: Send + Sync {
+    fn resolve(&self, addr: &str) -> Result<Option<Identity>, UmbraError>;
+    fn reverse_lookup(&self, public_key: &[u8]) -> Result<Option<Address>, UmbraError>;
+}
+
+/// Directory backed by a map populated by the application.
+#[derive(Default)]
+pub struct InMemoryDirectory {
+    identities: RwLock<HashMap<Address, Identity>>,
+}
+
+impl InMemoryDirectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, identity: Identity) {
+        self.identities
+            .write()
+            .unwrap()
+            .insert(identity.addr.clone(), identity);
+    }
+
+    pub fn remove(&self, addr: &str) -> Option<Identity> {
+        self.identities.write().unwrap().remove(addr)
+    }
+}
+
+impl IdentityDirectory for InMemoryDirectory {
+    fn resolve(&self, addr: &str) -> Result<Option<Identity>, UmbraError> {
+        Ok(self.identities.read().unwrap().get(addr).cloned())
+    }
+
+    fn reverse_lookup(&self, public_key: &[u8]) -> Result<Option<Address>, UmbraError> {
+        Ok(self
+            .identities
+            .read()
+            .unwrap()
+            .values()
+            .find(|i| i.public_key == public_key)
+            .map(|i| i.addr.clone()))
+    }
+}
+
+/// What a `KeyDirectoryVerifier` makes of a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyVerdict {
+    Accept,
+    /// The key is not used, and whatever needed it fails with
+    /// `UmbraError::KeyRejected`.
+    Reject(String),
+    /// The key is used, but the user should be warned, e.g. because it could
+    /// not be found in a transparency log.
+    Flag(String),
+}
+
+/// Checks peers' identity keys as the client learns them from the
+/// `IdentityDirectory`, so deployments can plug in key transparency logs or
+/// their own trust policy. `previous` is the key last accepted for the same
+/// address, if any.
+pub trait KeyDirectoryVerifier: Send + Sync {
+    fn verify(&self, identity: &Identity, previous: Option<&[u8]>) -> KeyVerdict;
+}
+
+/// Trust on first use: accepts the first key seen for an address and flags
+/// any other key later seen for it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TofuVerifier;
+
+impl KeyDirectoryVerifier for TofuVerifier {
+    fn verify(&self, identity: &Identity, previous: Option<&[u8]>) -> KeyVerdict {
+        match previous {
+            Some(previous) if previous != identity.public_key => {
+                KeyVerdict::Flag("key differs from the one first seen".into())
+            }
+            _ => KeyVerdict::Accept,
+        }
+    }
+}
+
+/// Reported through `UmbraClient::add_key_handler`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyEvent {
+    /// A peer's key differs from the one previously accepted for it: the
+    /// "safety number changed" warning. The new key is in use.
+    Changed {
+        addr: Address,
+        previous: Vec<u8>,
+        current: Vec<u8>,
+    },
+    /// The verifier flagged a key. It is in use.
+    Flagged {
+        addr: Address,
+        public_key: Vec<u8>,
+        reason: String,
+    },
+    /// The verifier rejected a key. The previously accepted key, if any, is
+    /// kept.
+    Rejected {
+        addr: Address,
+        public_key: Vec<u8>,
+        reason: String,
+    },
+}
+
+/// The key accepted for each peer, checked with the verifier as keys are
+/// learned.
+#[derive(Default)]
+pub(crate) struct KeyBook {
+    verifier: RwLock<Option<Arc<dyn KeyDirectoryVerifier>>>,
+    keys: RwLock<HashMap<Address, Vec<u8>>>,
+}
+
+impl KeyBook {
+    pub fn set_verifier(&self, verifier: Arc<dyn KeyDirectoryVerifier>) {
+        *self.verifier.write().unwrap() = Some(verifier);
+    }
+
+    pub fn restore(&self, keys: Vec<(Address, Vec<u8>)>) {
+        self.keys.write().unwrap().extend(keys);
+    }
+
+    /// Check `identity` and accept its key unless the verifier rejects it,
+    /// passing anything users should hear about to `report`. Returns whether
+    /// the accepted key is new or changed, and so needs saving.
+    pub fn learn(
+        &self,
+        identity: &Identity,
+        mut report: impl FnMut(KeyEvent),
+    ) -> Result<bool, UmbraError> {
+        let (addr, key) = (&identity.addr, &identity.public_key);
+        let previous = self.keys.read().unwrap().get(addr).cloned();
+        let verdict = match &*self.verifier.read().unwrap() {
+            Some(verifier) => verifier.verify(identity, previous.as_deref()),
+            None => KeyVerdict::Accept,
+        };
+        match verdict {
+            KeyVerdict::Accept => {}
+            KeyVerdict::Flag(reason) => report(KeyEvent::Flagged {
+                addr: addr.clone(),
+                public_key: key.clone(),
+                reason,
+            }),
+            KeyVerdict::Reject(reason) => {
+                report(KeyEvent::Rejected {
+                    addr: addr.clone(),
+                    public_key: key.clone(),
+                    reason: reason.clone(),
+                });
+                return Err(UmbraError::KeyRejected {
+                    addr: addr.to_string(),
+                    reason,
+                });
+            }
+        }
+
+        match previous {
+            Some(previous) if previous == *key => return Ok(false),
+            Some(previous) => report(KeyEvent::Changed {
+                addr: addr.clone(),
+                previous,
+                current: key.clone(),
+            }),
+            None => {}
+        }
+        self.keys.write().unwrap().insert(addr.clone(), key.clone());
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_and_reverse_lookup() {
+        let directory = InMemoryDirectory::new();
+        directory.insert(Identity {
+            addr: "amal".parse().unwrap(),
+            public_key: vec![1, 2, 3],
+        });
+
+        assert_eq!(
+            directory.resolve("amal").unwrap().unwrap().public_key,
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            directory.reverse_lookup(&[1, 2, 3]).unwrap(),
+            Some("amal".parse().unwrap())
+        );
+        assert_eq!(directory.resolve("bola").unwrap(), None);
+    }
+
+    #[test]
+    fn changed_keys_are_flagged_and_rejected_keys_kept_out() {
+        struct DenyList;
+        impl KeyDirectoryVerifier for DenyList {
+            fn verify(&self, identity: &Identity, previous: Option<&[u8]>) -> KeyVerdict {
+                match identity.public_key.as_slice() {
+                    [0] => KeyVerdict::Reject("revoked".into()),
+                    _ => TofuVerifier.verify(identity, previous),
+                }
+            }
+        }
+        let keys = KeyBook::default();
+        keys.set_verifier(Arc::new(DenyList));
+        let identity = |key: u8| Identity {
+            addr: "amal".parse().unwrap(),
+            public_key: vec![key],
+        };
+        let mut events = Vec::new();
+
+        assert!(keys.learn(&identity(1), |e| events.push(e)).unwrap());
+        assert!(!keys.learn(&identity(1), |e| events.push(e)).unwrap());
+        assert!(events.is_empty());
+
+        assert!(keys.learn(&identity(2), |e| events.push(e)).unwrap());
+        assert!(matches!(events[0], KeyEvent::Flagged { .. }));
+        assert!(matches!(&events[1], KeyEvent::Changed { previous, .. } if *previous == [1]));
+
+        assert!(matches!(
+            keys.learn(&identity(0), |e| events.push(e)),
+            Err(UmbraError::KeyRejected { .. })
+        ));
+        assert!(matches!(events[2], KeyEvent::Rejected { .. }));
+        assert!(!keys.learn(&identity(2), |_| {}).unwrap());
+    }
+}
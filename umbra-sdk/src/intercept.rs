@@ -0,0 +1,172 @@
+//! Hooks which see messages before the client acts on them.
+//!
+//! Inbound interceptors run on every received message after it is decoded,
+//! before it is stored or dispatched to handlers. Each can inspect it,
+//! rewrite it, or drop it, e.g. for spam filtering, analytics or policy
+//! enforcement. They run in registration order and the first to drop a
+//! message stops the chain.
+//!
+//! Outbound interceptors run on content being sent, before it is wrapped and
+//! encrypted, e.g. to strip image metadata or enforce data loss prevention
+//! rules. Returning an error rejects the send, and the caller gets that
+//! error back.
+
+use std::sync::RwLock;
+
+use umbra_types::common_frames::ContentFrame;
+
+use crate::UmbraError;
+use crate::address::Address;
+use crate::events::{EventHandlers, Registered};
+
+/// A received message on its way to history and handlers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InboundMessage {
+    pub convo_id: String,
+    pub message_id: String,
+    pub sender: Address,
+    /// Changes are kept: the rewritten content is what is stored and
+    /// dispatched.
+    pub content: ContentFrame,
+    /// Sender's clock, if stamped. Changes are kept.
+    pub sent_at: Option<u64>,
+    /// Message this one replies to. Changes are kept.
+    pub reply_to: Option<String>,
+}
+
+/// What happens to a message after an interceptor has seen it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Pass it on to the next interceptor, then on as usual.
+    Continue,
+    /// Discard it: it is not stored, acknowledged or dispatched.
+    Drop,
+}
+
+/// Content about to be sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboundMessage {
+    pub convo_id: String,
+    /// Changes are kept: the rewritten content is what is sent and stored.
+    pub content: ContentFrame,
+    /// Plain text for peers without a codec for the content. Changes are
+    /// kept.
+    pub fallback: Option<String>,
+    /// Message this one replies to.
+    pub reply_to: Option<String>,
+}
+
+pub(crate) type InboundInterceptor = Box<dyn Fn(&mut InboundMessage) -> Verdict + Send + Sync>;
+pub(crate) type OutboundInterceptor =
+    Box<dyn Fn(&mut OutboundMessage) -> Result<(), UmbraError> + Send + Sync>;
+
+#[derive(Default)]
+pub(crate) struct Interceptors {
+    inbound: RwLock<Vec<Registered<InboundInterceptor>>>,
+    outbound: RwLock<Vec<Registered<OutboundInterceptor>>>,
+}
+
+impl Interceptors {
+    pub fn add_inbound(&self, interceptor: Registered<InboundInterceptor>) {
+        self.inbound.write().unwrap().push(interceptor);
+    }
+
+    pub fn add_outbound(&self, interceptor: Registered<OutboundInterceptor>) {
+        self.outbound.write().unwrap().push(interceptor);
+    }
+
+    // Unlike inbound, a panicking interceptor rejects the send: it may be
+    // the one enforcing a policy
+    pub fn outbound(&self, message: &mut OutboundMessage) -> Result<(), UmbraError> {
+        for interceptor in self.outbound.read().unwrap().iter() {
+            let mut res = Ok(());
+            interceptor.invoke(|f| res = f(message))?;
+            res?;
+        }
+        Ok(())
+    }
+
+    // A panicking interceptor is reported and, outside strict mode, treated
+    // as `Continue`, so a bug in it does not silently lose messages
+    pub fn inbound(&self, events: &EventHandlers, message: &mut InboundMessage) -> Verdict {
+        for interceptor in self.inbound.read().unwrap().iter() {
+            let mut verdict = Verdict::Continue;
+            if let Err(e) = interceptor.invoke(|f| verdict = f(message)) {
+                events.emit_panic(&e);
+            }
+            if verdict == Verdict::Drop {
+                return Verdict::Drop;
+            }
+        }
+        Verdict::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::Location;
+
+    use super::*;
+
+    fn message(bytes: &[u8]) -> InboundMessage {
+        InboundMessage {
+            convo_id: "c".into(),
+            message_id: "m".into(),
+            sender: "amal".parse().unwrap(),
+            content: ContentFrame {
+                domain: 0,
+                tag: 1,
+                bytes: bytes.to_vec(),
+            },
+            sent_at: None,
+            reply_to: None,
+        }
+    }
+
+    #[test]
+    fn interceptors_rewrite_then_drop_in_order() {
+        let (interceptors, events) = (Interceptors::default(), EventHandlers::default());
+        let add = |f: InboundInterceptor| {
+            interceptors.add_inbound(Registered::new(Location::caller(), f));
+        };
+        add(Box::new(|_| panic!("buggy filter")));
+        add(Box::new(|m| {
+            m.content.bytes.reverse();
+            Verdict::Continue
+        }));
+        add(Box::new(|m| match m.content.bytes.as_slice() {
+            b"maps" => Verdict::Drop,
+            _ => Verdict::Continue,
+        }));
+
+        let mut kept = message(b"olleh");
+        assert_eq!(interceptors.inbound(&events, &mut kept), Verdict::Continue);
+        assert_eq!(kept.content.bytes, b"hello");
+        assert_eq!(
+            interceptors.inbound(&events, &mut message(b"spam")),
+            Verdict::Drop
+        );
+    }
+
+    #[test]
+    fn outbound_interceptors_can_reject() {
+        let interceptors = Interceptors::default();
+        let interceptor: OutboundInterceptor = Box::new(|m| match m.fallback.as_deref() {
+            Some(text) if text.contains("secret") => Err(UmbraError::SendRejected("dlp".into())),
+            _ => Ok(()),
+        });
+        interceptors.add_outbound(Registered::new(Location::caller(), interceptor));
+        let outbound = |fallback: &str| OutboundMessage {
+            convo_id: "c".into(),
+            content: message(b"").content,
+            fallback: Some(fallback.into()),
+            reply_to: None,
+        };
+
+        assert!(interceptors.outbound(&mut outbound("hello")).is_ok());
+        assert!(matches!(
+            interceptors.outbound(&mut outbound("the secret plan")),
+            Err(UmbraError::SendRejected(_))
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+//! Invite expiry and revocation.
+//!
+//! `InvitePrivateV1` belongs to the shared protocol types, which have no
+//! room for either, so an `InviteTerms` frame is appended to the encoded
+//! InboxV1Frame. Its field numbers lie far above InboxV1Frame's, so both
+//! decode from the same bytes and peers which do not know the terms accept
+//! the invite as before.
+//!
+//! Terms name the invite, so its sender can later revoke it with an inbox
+//! frame carrying only the terms. They also carry the secret the
+//! conversation's envelope hints are derived from. A revoked or expired invite is refused
+//! when it arrives; a conversation already joined through it is unaffected.
+
+use std::collections::HashMap;
+
+use prost::Message;
+use umbra_types::base::InboxV1Frame;
+
+use crate::address::Address;
+use crate::client::Blob;
+use crate::convos::ConversationId;
+use crate::error::{FrameKind, UmbraError};
+use crate::frames::types::InviteTerms;
+
+pub(crate) fn encode(frame: &InboxV1Frame, terms: &InviteTerms) -> Blob {
+    let mut bytes = frame.encode_to_vec();
+    bytes.extend(terms.encode_to_vec());
+    bytes
+}
+
+// Invites from senders which do not set terms get the defaults: no id and
+// no expiry
+pub(crate) fn decode(bytes: &[u8]) -> Result<(InboxV1Frame, InviteTerms), UmbraError> {
+    let frame = InboxV1Frame::decode(bytes).map_err(UmbraError::decoding(FrameKind::Inbox))?;
+    let terms = InviteTerms::decode(bytes).map_err(UmbraError::decoding(FrameKind::Inbox))?;
+    Ok((frame, terms))
+}
+
+/// An invite this client sent, kept so that it can be revoked.
+pub(crate) struct SentInvite {
+    pub recipient: Address,
+    pub terms: InviteTerms,
+}
+
+#[derive(Default)]
+pub(crate) struct Invites {
+    // By the conversation they invite to
+    sent: HashMap<ConversationId, SentInvite>,
+    // invite_id -> expires_at of invites revoked by their senders, 0 for
+    // invites which never expire
+    revoked: HashMap<String, u64>,
+}
+
+impl Invites {
+    pub fn record_sent(&mut self, convo_id: ConversationId, invite: SentInvite) {
+        self.sent.insert(convo_id, invite);
+    }
+
+    pub fn take_sent(&mut self, convo_id: &str) -> Option<SentInvite> {
+        self.sent.remove(convo_id)
+    }
+
+    /// Remember that the sender revoked the invite named by `terms`.
+    /// Revocations are forgotten once the invite would have expired anyway.
+    pub fn revoke(&mut self, terms: &InviteTerms, now: u64) {
+        self.revoked
+            .retain(|_, expires_at| *expires_at == 0 || *expires_at > now);
+        if !terms.invite_id.is_empty() {
+            self.revoked
+                .insert(terms.invite_id.clone(), terms.expires_at);
+        }
+    }
+
+    /// Whether an invite with `terms` may still be accepted at `now`.
+    pub fn check(&self, terms: &InviteTerms, now: u64) -> Result<(), UmbraError> {
+        if self.revoked.contains_key(&terms.invite_id) {
+            return Err(UmbraError::InviteRevoked(terms.invite_id.clone()));
+        }
+        if terms.expires_at != 0 && terms.expires_at <= now {
+            return Err(UmbraError::InviteExpired(terms.expires_at));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use umbra_types::base::inbox_v1_frame;
+    use umbra_types::invite::InvitePrivateV1;
+
+    use super::*;
+
+    #[test]
+    fn terms_travel_with_the_invite_and_are_enforced() {
+        let invite = InboxV1Frame::new(
+            "convo".into(),
+            inbox_v1_frame::FrameType::InvitePrivateV1(InvitePrivateV1 {
+                participants: vec!["amal".into(), "bola".into()],
+            }),
+        );
+        let terms = InviteTerms {
+            invite_id: "i1".into(),
+            expires_at: 100,
+            revoked: false,
+            hint_secret: vec![7; 32],
+        };
+        let (frame, decoded) = decode(&encode(&invite, &terms)).unwrap();
+        assert_eq!(frame, invite);
+        assert_eq!(decoded, terms);
+        assert_eq!(
+            decode(&invite.encode_to_vec()).unwrap().1,
+            InviteTerms::default()
+        );
+
+        let mut invites = Invites::default();
+        assert!(invites.check(&terms, 99).is_ok());
+        assert!(matches!(
+            invites.check(&terms, 100),
+            Err(UmbraError::InviteExpired(100))
+        ));
+
+        invites.revoke(&terms, 50);
+        assert!(matches!(
+            invites.check(&terms, 60),
+            Err(UmbraError::InviteRevoked(_))
+        ));
+        // Expired revocations are dropped as others arrive
+        invites.revoke(&InviteTerms::default(), 200);
+        assert!(invites.revoked.is_empty());
+    }
+}
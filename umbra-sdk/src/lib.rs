@@ -1,13 +1,129 @@
+mod address;
+mod attachment;
+mod bloom;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bot;
+mod builder;
+mod capabilities;
 mod client;
+mod codec;
+mod composite;
+mod compression;
+mod config;
+pub mod conformance;
+mod context;
 mod convos;
 mod crypto;
+#[cfg(feature = "json")]
+pub mod debug;
 mod error;
+mod events;
+#[cfg(feature = "testing")]
+pub mod fixtures;
+mod frames;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+#[cfg(feature = "harness")]
+pub mod harness;
+mod hints;
+mod identity;
+mod intercept;
+mod invites;
+mod limits;
+mod location;
+mod metrics;
+mod notify;
+mod outbox;
+mod pending;
+mod poll;
+mod pseudonym;
+mod push;
+mod ratelimit;
+mod redact;
+mod retention;
+mod rpc;
+mod schedule;
+mod sds;
+mod shared_state;
+mod snapshot;
+mod store;
+mod stream;
+mod transcript;
+mod transport;
 mod utils;
+mod watch;
+#[cfg(not(target_arch = "wasm32"))]
+mod workers;
 
 pub use crate::client::Blob;
 // pub use crate::client::{Publish, Subscribe};
 
+pub use crate::address::Address;
+pub use crate::attachment::{BlobStore, InMemoryBlobStore};
+pub use crate::builder::UmbraClientBuilder;
+pub use crate::capabilities::{Capabilities, PROTOCOL_VERSION};
 pub use crate::client::{Conversation, DeliveryService};
-pub use crate::error::UmbraError;
+#[cfg(feature = "bincode")]
+pub use crate::codec::BincodeCodec;
+#[cfg(feature = "cbor")]
+pub use crate::codec::CborCodec;
+#[cfg(feature = "json")]
+pub use crate::codec::JsonCodec;
+pub use crate::codec::{CodecRegistry, ContentCodec, ProtobufCodec};
+pub use crate::composite::{Composite, CompositeBuilder};
+pub use crate::config::ClientConfig;
+pub use crate::convos::{
+    ConversationId, ConversationInfo, ConversationKind, ConversationOptions, MessageHandle,
+    MessageRecord,
+};
+pub use crate::error::{BoxError, FrameKind, TransportOp, UmbraError};
+pub use crate::events::{
+    DeliveryStatus, DeliveryUpdate, MessageContext, MessageDeleted, MessageEdited, Presence,
+    PresenceState, SyncEvent, UmbraEvent, UnknownFrame,
+};
+pub use crate::identity::{
+    Identity, IdentityDirectory, InMemoryDirectory, KeyDirectoryVerifier, KeyEvent, KeyVerdict,
+    TofuVerifier,
+};
+pub use crate::intercept::{InboundMessage, OutboundMessage, Verdict};
+pub use crate::limits::SizeLimits;
+pub use crate::location::LiveLocationShare;
+#[cfg(feature = "metrics")]
+pub use crate::metrics::{Counter, Gauge, Histogram, MetricsRecorder, PrometheusRecorder};
+pub use crate::notify::{ContentMeta, NotificationPriority, NotifyLevel, mention_text};
+pub use crate::pending::PendingLimits;
+pub use crate::poll::{PollOption, PollResults};
+pub use crate::push::{PushNotification, PushRegistration};
+pub use crate::ratelimit::{RateLimit, RateLimitPolicy};
+pub use crate::redact::{Redaction, RedactionPolicy};
+pub use crate::retention::{MessagesPruned, Retention};
+pub use crate::rpc::PendingResponse;
+pub use crate::schedule::ScheduledMessage;
+pub use crate::shared_state::{SharedState, StateChanged};
+#[cfg(feature = "sqlite")]
+pub use crate::store::SqliteMessageStore;
+pub use crate::store::{InMemoryMessageStore, MessageStore, SearchHit, StateStore};
+pub use crate::transcript::{
+    AttachmentMetadata, PlainText, Transcript, TranscriptEntry, TranscriptFormat, TranscriptSource,
+};
+#[cfg(feature = "json")]
+pub use crate::transcript::{JsonTranscript, JsonTranscriptSource};
+#[cfg(all(feature = "browser", target_arch = "wasm32"))]
+pub use crate::transport::BrowserDeliveryService;
+#[cfg(feature = "http")]
+pub use crate::transport::HttpDeliveryService;
+#[cfg(feature = "websocket")]
+pub use crate::transport::WebSocketDeliveryService;
+pub use crate::transport::{ConnectionState, RelayFrame};
+#[cfg(feature = "test-utils")]
+pub use crate::transport::{InMemoryDeliveryService, LocalBroker};
+#[cfg(feature = "test-utils")]
+pub use crate::transport::{Latency, NetworkConditions, SimulatedDeliveryService};
+#[cfg(feature = "mqtt")]
+pub use crate::transport::{MqttConfig, MqttDeliveryService, MqttQos};
+#[cfg(feature = "waku")]
+pub use crate::transport::{WakuDeliveryService, WakuMode};
+pub use crate::watch::Diff;
 pub use client::UmbraClient;
+pub use umbra_content_types::{ContentType, TaggedContent};
 pub use umbra_types::common_frames::ContentFrame;
@@ -0,0 +1,52 @@
+use crate::error::{FrameKind, UmbraError};
+
+/// Upper bounds on message sizes, in bytes. Outgoing messages over a limit
+/// are refused with `UmbraError::PayloadTooLarge`; incoming ones are dropped
+/// before they are stored, so a hostile peer cannot exhaust memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeLimits {
+    /// A whole envelope as handed over by the DeliveryService. Only checked
+    /// on receive; outgoing envelopes are bounded through `max_frame`.
+    pub max_envelope: usize,
+    /// A conversation frame, including SDK frames such as edits and history
+    /// responses.
+    pub max_frame: usize,
+    /// Application content. Larger files belong in a `BlobStore`.
+    pub max_content: usize,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        Self {
+            max_envelope: 1024 * 1024,
+            max_frame: 512 * 1024,
+            max_content: 256 * 1024,
+        }
+    }
+}
+
+pub(crate) fn check(frame_kind: FrameKind, size: usize, limit: usize) -> Result<(), UmbraError> {
+    if size > limit {
+        return Err(UmbraError::PayloadTooLarge {
+            frame_kind,
+            size,
+            limit,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_is_inclusive() {
+        assert!(check(FrameKind::Content, 10, 10).is_ok());
+        let err = check(FrameKind::Content, 11, 10).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "content of 11 bytes exceeds the 10 byte limit"
+        );
+    }
+}
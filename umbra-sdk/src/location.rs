@@ -0,0 +1,175 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use umbra_content_types::{LiveLocation, Location};
+
+use crate::DeliveryService;
+use crate::client::Conversation;
+use crate::error::UmbraError;
+use crate::utils::{generate_random_string, now_millis};
+
+// Default minimum time between two updates
+const DEFAULT_UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+
+// Rate limits a stream of positions, keeping only the latest one held back
+#[derive(Debug)]
+pub(crate) struct Coalescer {
+    interval: u64,
+    last_sent: Option<u64>,
+    pending: Option<Location>,
+}
+
+impl Coalescer {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval: interval.as_millis() as u64,
+            last_sent: None,
+            pending: None,
+        }
+    }
+
+    /// Returns the location to send now, if the interval has passed.
+    pub fn offer(&mut self, location: Location, now: u64) -> Option<Location> {
+        self.pending = Some(location);
+        self.poll(now)
+    }
+
+    /// Returns the held back location once the interval has passed.
+    pub fn poll(&mut self, now: u64) -> Option<Location> {
+        let due = self
+            .last_sent
+            .is_none_or(|sent| now.saturating_sub(sent) >= self.interval);
+        if !due {
+            return None;
+        }
+        let location = self.pending.take()?;
+        self.last_sent = Some(now);
+        Some(location)
+    }
+
+    pub fn take_pending(&mut self) -> Option<Location> {
+        self.pending.take()
+    }
+}
+
+/// Shares a position in a conversation as a series of `LiveLocation` updates
+/// until stopped or expired. Updates faster than the interval are coalesced:
+/// only the latest is sent, by a later `update` or `tick`.
+pub struct LiveLocationShare<T: DeliveryService + Send + Sync + 'static> {
+    convo: Arc<Mutex<dyn Conversation<T> + Send + Sync>>,
+    share_id: String,
+    sequence: u64,
+    expires_at: u64,
+    coalescer: Coalescer,
+    last_location: Option<Location>,
+}
+
+impl<T> LiveLocationShare<T>
+where
+    T: DeliveryService + Send + Sync + 'static,
+{
+    /// Start sharing in `convo` for `duration`. Nothing is sent until the
+    /// first `update`.
+    pub fn start(convo: Arc<Mutex<dyn Conversation<T> + Send + Sync>>, duration: Duration) -> Self {
+        Self {
+            convo,
+            share_id: generate_random_string(16),
+            sequence: 0,
+            expires_at: now_millis() + duration.as_millis() as u64,
+            coalescer: Coalescer::new(DEFAULT_UPDATE_INTERVAL),
+            last_location: None,
+        }
+    }
+
+    /// Send updates at most once per `interval`, 5 seconds by default.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.coalescer = Coalescer::new(interval);
+        self
+    }
+
+    pub fn share_id(&self) -> &str {
+        &self.share_id
+    }
+
+    pub fn is_expired(&self) -> bool {
+        now_millis() >= self.expires_at
+    }
+
+    /// Report a new position. It is sent now if the interval allows, and
+    /// otherwise held for the next `update` or `tick`.
+    pub fn update(&mut self, location: Location) -> Result<(), UmbraError> {
+        if self.is_expired() {
+            return Ok(());
+        }
+        match self.coalescer.offer(location, now_millis()) {
+            Some(location) => self.publish(location, false),
+            None => Ok(()),
+        }
+    }
+
+    /// Send a held back position whose interval has passed. Call this
+    /// periodically, e.g. from the app's location timer.
+    pub fn tick(&mut self) -> Result<(), UmbraError> {
+        if self.is_expired() {
+            return Ok(());
+        }
+        match self.coalescer.poll(now_millis()) {
+            Some(location) => self.publish(location, false),
+            None => Ok(()),
+        }
+    }
+
+    /// Tell peers the share has ended, with the most recent position.
+    pub fn stop(mut self) -> Result<(), UmbraError> {
+        let location = self
+            .coalescer
+            .take_pending()
+            .or_else(|| self.last_location.take())
+            .unwrap_or_default();
+        self.publish(location, true)
+    }
+
+    fn publish(&mut self, location: Location, stopped: bool) -> Result<(), UmbraError> {
+        self.sequence += 1;
+        let update = LiveLocation {
+            share_id: self.share_id.clone(),
+            sequence: self.sequence,
+            location: Some(location.clone()),
+            expires_at: self.expires_at,
+            stopped,
+        };
+        self.convo.lock().unwrap().send_typed(&update)?;
+        self.last_location = Some(location);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(latitude: f64) -> Location {
+        Location {
+            latitude,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn first_update_is_sent_immediately() {
+        let mut coalescer = Coalescer::new(Duration::from_secs(5));
+        assert_eq!(coalescer.offer(at(1.0), 1_000), Some(at(1.0)));
+    }
+
+    #[test]
+    fn rapid_updates_keep_only_the_latest() {
+        let mut coalescer = Coalescer::new(Duration::from_secs(5));
+        coalescer.offer(at(1.0), 1_000);
+
+        assert_eq!(coalescer.offer(at(2.0), 2_000), None);
+        assert_eq!(coalescer.offer(at(3.0), 3_000), None);
+        assert_eq!(coalescer.poll(5_999), None);
+        assert_eq!(coalescer.poll(6_000), Some(at(3.0)));
+        assert_eq!(coalescer.poll(20_000), None);
+    }
+}
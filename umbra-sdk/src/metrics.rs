@@ -0,0 +1,252 @@
+//! Client instrumentation.
+//!
+//! The client reports counters, gauges and histograms to a
+//! [`MetricsRecorder`] set on the builder. Without one, or without the
+//! `metrics` feature, every measurement is dropped.
+//! [`PrometheusRecorder`] keeps them in memory and renders the Prometheus
+//! text exposition format, for serving from a bot's or relay's `/metrics`.
+
+use std::sync::{Arc, OnceLock};
+
+#[cfg(feature = "metrics")]
+use std::collections::BTreeMap;
+#[cfg(feature = "metrics")]
+use std::fmt::Write;
+#[cfg(feature = "metrics")]
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Counter {
+    /// Envelopes handed to the DeliveryService, including retries.
+    EnvelopesSent,
+    /// Envelopes received from the DeliveryService.
+    EnvelopesReceived,
+    /// Received data which failed to decode.
+    DecodeFailures,
+    /// Received data which failed to decrypt or authenticate.
+    DecryptFailures,
+    /// Envelopes resent because a peer reported them missing.
+    Retransmissions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Gauge {
+    /// Messages waiting in the outbox.
+    QueueDepth,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Histogram {
+    /// Seconds spent in content handlers per received message.
+    HandlerLatency,
+}
+
+#[cfg(feature = "metrics")]
+impl Counter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Counter::EnvelopesSent => "umbra_envelopes_sent_total",
+            Counter::EnvelopesReceived => "umbra_envelopes_received_total",
+            Counter::DecodeFailures => "umbra_decode_failures_total",
+            Counter::DecryptFailures => "umbra_decrypt_failures_total",
+            Counter::Retransmissions => "umbra_retransmissions_total",
+        }
+    }
+
+    fn help(&self) -> &'static str {
+        match self {
+            Counter::EnvelopesSent => "Envelopes handed to the DeliveryService.",
+            Counter::EnvelopesReceived => "Envelopes received from the DeliveryService.",
+            Counter::DecodeFailures => "Received data which failed to decode.",
+            Counter::DecryptFailures => "Received data which failed to decrypt.",
+            Counter::Retransmissions => "Envelopes resent on a peer's repair request.",
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Gauge {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Gauge::QueueDepth => "umbra_queue_depth",
+        }
+    }
+
+    fn help(&self) -> &'static str {
+        match self {
+            Gauge::QueueDepth => "Messages waiting in the outbox.",
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Histogram {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Histogram::HandlerLatency => "umbra_handler_latency_seconds",
+        }
+    }
+
+    fn help(&self) -> &'static str {
+        match self {
+            Histogram::HandlerLatency => "Time spent in content handlers per message.",
+        }
+    }
+}
+
+/// Receives the client's measurements. Called from the client's threads,
+/// so implementations must be cheap and must not block.
+pub trait MetricsRecorder: Send + Sync {
+    fn increment(&self, counter: Counter, value: u64);
+
+    fn gauge(&self, gauge: Gauge, value: f64);
+
+    fn observe(&self, histogram: Histogram, value: f64);
+}
+
+/// The client's handle on its recorder, if any.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    recorder: OnceLock<Arc<dyn MetricsRecorder>>,
+}
+
+impl Metrics {
+    #[cfg(feature = "metrics")]
+    pub fn set_recorder(&self, recorder: Arc<dyn MetricsRecorder>) {
+        // Only the builder sets it, before the client is shared
+        let _ = self.recorder.set(recorder);
+    }
+
+    pub fn increment(&self, counter: Counter, value: u64) {
+        if let Some(recorder) = self.recorder.get() {
+            recorder.increment(counter, value);
+        }
+    }
+
+    pub fn gauge(&self, gauge: Gauge, value: f64) {
+        if let Some(recorder) = self.recorder.get() {
+            recorder.gauge(gauge, value);
+        }
+    }
+
+    pub fn observe(&self, histogram: Histogram, value: f64) {
+        if let Some(recorder) = self.recorder.get() {
+            recorder.observe(histogram, value);
+        }
+    }
+}
+
+// Upper bounds in seconds; handlers are expected to return quickly
+#[cfg(feature = "metrics")]
+const BUCKETS: [f64; 10] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0,
+];
+
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default, Clone)]
+struct Buckets {
+    counts: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+struct Values {
+    counters: BTreeMap<Counter, u64>,
+    gauges: BTreeMap<Gauge, f64>,
+    histograms: BTreeMap<Histogram, Buckets>,
+}
+
+/// Keeps every measurement in memory and renders them in the Prometheus
+/// text format.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+pub struct PrometheusRecorder {
+    values: Mutex<Values>,
+}
+
+#[cfg(feature = "metrics")]
+impl PrometheusRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current values in the text exposition format, version 0.0.4.
+    pub fn render(&self) -> String {
+        let values = self.values.lock().unwrap();
+        let mut out = String::new();
+        for (counter, value) in &values.counters {
+            let name = counter.name();
+            let _ = writeln!(out, "# HELP {name} {}", counter.help());
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {value}");
+        }
+        for (gauge, value) in &values.gauges {
+            let name = gauge.name();
+            let _ = writeln!(out, "# HELP {name} {}", gauge.help());
+            let _ = writeln!(out, "# TYPE {name} gauge");
+            let _ = writeln!(out, "{name} {value}");
+        }
+        for (histogram, buckets) in &values.histograms {
+            let name = histogram.name();
+            let _ = writeln!(out, "# HELP {name} {}", histogram.help());
+            let _ = writeln!(out, "# TYPE {name} histogram");
+            for (bound, count) in BUCKETS.iter().zip(buckets.counts) {
+                let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {count}");
+            }
+            let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", buckets.count);
+            let _ = writeln!(out, "{name}_sum {}", buckets.sum);
+            let _ = writeln!(out, "{name}_count {}", buckets.count);
+        }
+        out
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl MetricsRecorder for PrometheusRecorder {
+    fn increment(&self, counter: Counter, value: u64) {
+        let mut values = self.values.lock().unwrap();
+        *values.counters.entry(counter).or_default() += value;
+    }
+
+    fn gauge(&self, gauge: Gauge, value: f64) {
+        self.values.lock().unwrap().gauges.insert(gauge, value);
+    }
+
+    fn observe(&self, histogram: Histogram, value: f64) {
+        let mut values = self.values.lock().unwrap();
+        let buckets = values.histograms.entry(histogram).or_default();
+        // Buckets are cumulative, so every bound at or above the value counts it
+        for (bound, count) in BUCKETS.iter().zip(buckets.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        buckets.count += 1;
+        buckets.sum += value;
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_text_format() {
+        let recorder = PrometheusRecorder::new();
+        recorder.increment(Counter::EnvelopesSent, 2);
+        recorder.increment(Counter::EnvelopesSent, 1);
+        recorder.gauge(Gauge::QueueDepth, 4.0);
+        recorder.observe(Histogram::HandlerLatency, 0.003);
+
+        let text = recorder.render();
+        assert!(text.contains("# TYPE umbra_envelopes_sent_total counter\n"));
+        assert!(text.contains("umbra_envelopes_sent_total 3\n"));
+        assert!(text.contains("umbra_queue_depth 4\n"));
+        assert!(text.contains("umbra_handler_latency_seconds_bucket{le=\"0.0025\"} 0\n"));
+        assert!(text.contains("umbra_handler_latency_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(text.contains("umbra_handler_latency_seconds_bucket{le=\"+Inf\"} 1\n"));
+        assert!(text.contains("umbra_handler_latency_seconds_count 1\n"));
+    }
+}
@@ -0,0 +1,153 @@
+//! Mentions and notification hints.
+//!
+//! Both travel next to the content in its Timestamped wrapper rather than
+//! inside it, so any content type can mention participants. On receipt they
+//! decide whether the message is worth a notification, as reported by
+//! `MessageContext::notify` and push previews: low priority messages never
+//! are, and conversations set to `NotifyLevel::Mentions` only notify for
+//! messages mentioning this client.
+
+use crate::address::Address;
+
+/// How urgently the sender thinks the message should be brought to the
+/// recipient's attention.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NotificationPriority {
+    /// Not worth a notification, e.g. a reaction or an automated update.
+    Low,
+    #[default]
+    Normal,
+    /// Worth interrupting for, e.g. with a sound.
+    High,
+}
+
+impl NotificationPriority {
+    pub(crate) fn to_wire(self) -> u32 {
+        match self {
+            NotificationPriority::Normal => 0,
+            NotificationPriority::Low => 1,
+            NotificationPriority::High => 2,
+        }
+    }
+
+    // Unknown values from newer peers are treated as normal
+    pub(crate) fn from_wire(priority: u32) -> Self {
+        match priority {
+            1 => NotificationPriority::Low,
+            2 => NotificationPriority::High,
+            _ => NotificationPriority::Normal,
+        }
+    }
+}
+
+/// Which received messages a conversation flags as worth a notification.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NotifyLevel {
+    #[default]
+    All,
+    /// Only messages mentioning this client.
+    Mentions,
+}
+
+impl NotifyLevel {
+    pub(crate) fn to_snapshot(self) -> u32 {
+        match self {
+            NotifyLevel::All => 0,
+            NotifyLevel::Mentions => 1,
+        }
+    }
+
+    pub(crate) fn from_snapshot(level: u32) -> Self {
+        match level {
+            1 => NotifyLevel::Mentions,
+            _ => NotifyLevel::All,
+        }
+    }
+
+    /// Whether a message carrying `meta` is worth notifying `addr` about.
+    pub(crate) fn notifies(self, meta: &ContentMeta, addr: &Address) -> bool {
+        if meta.priority == NotificationPriority::Low {
+            return false;
+        }
+        match self {
+            NotifyLevel::All => true,
+            NotifyLevel::Mentions => meta.mentions(addr),
+        }
+    }
+}
+
+/// What is sent alongside a message's content, for
+/// `Conversation::send_with_meta`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContentMeta {
+    /// Plain text for peers without a codec for the content, which they
+    /// receive as a `ChatMessage`.
+    pub fallback: Option<String>,
+    /// Participants the content mentions.
+    pub mentions: Vec<Address>,
+    pub priority: NotificationPriority,
+}
+
+impl ContentMeta {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn fallback(mut self, fallback: impl Into<String>) -> Self {
+        self.fallback = Some(fallback.into());
+        self
+    }
+
+    /// Mention `addr`. Mentioning someone twice has no further effect.
+    pub fn mention(mut self, addr: Address) -> Self {
+        if !self.mentions.contains(&addr) {
+            self.mentions.push(addr);
+        }
+        self
+    }
+
+    pub fn priority(mut self, priority: NotificationPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Whether `addr` is mentioned.
+    pub fn mentions(&self, addr: &Address) -> bool {
+        self.mentions.contains(addr)
+    }
+
+    // Bytes it adds to the frame it travels with
+    pub(crate) fn encoded_len(&self) -> usize {
+        let mentions: usize = self.mentions.iter().map(|m| m.len()).sum();
+        self.fallback.as_ref().map_or(0, String::len) + mentions
+    }
+}
+
+/// How `addr` is written when mentioned in text, e.g. in a `ChatMessage`
+/// sent with `ContentMeta::mention(addr)`.
+pub fn mention_text(addr: &Address) -> String {
+    format!("@{addr}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notify_level_filters_by_mention_and_priority() {
+        let amal: Address = "amal".parse().unwrap();
+        let bola: Address = "bola".parse().unwrap();
+        let meta = ContentMeta::new()
+            .mention(amal.clone())
+            .mention(amal.clone());
+        assert_eq!(meta.mentions, vec![amal.clone()]);
+
+        assert!(NotifyLevel::All.notifies(&meta, &bola));
+        assert!(NotifyLevel::Mentions.notifies(&meta, &amal));
+        assert!(!NotifyLevel::Mentions.notifies(&meta, &bola));
+
+        let quiet = meta.priority(NotificationPriority::Low);
+        assert!(!NotifyLevel::All.notifies(&quiet, &amal));
+        assert_eq!(mention_text(&amal), "@amal");
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+
+use crate::Blob;
+
+// Delay before the first retry, doubled on every failure
+const INITIAL_BACKOFF_MS: u64 = 1_000;
+const MAX_BACKOFF_MS: u64 = 60_000;
+// Attempts (including the original send) before a message is failed
+const MAX_ATTEMPTS: u32 = 8;
+
+/// An envelope the DeliveryService rejected, waiting to be retried.
+#[derive(Debug, Clone)]
+pub(crate) struct OutboxEntry {
+    pub convo_id: String,
+    pub message_id: String,
+    pub envelope: Blob,
+    pub attempts: u32,
+    pub next_attempt: u64,
+    // Unix ms after which the message is dropped instead of sent
+    pub expires_at: Option<u64>,
+}
+
+/// Client-wide queue of envelopes awaiting retry with exponential backoff.
+#[derive(Debug, Default)]
+pub(crate) struct Outbox {
+    entries: VecDeque<OutboxEntry>,
+}
+
+fn backoff(attempts: u32) -> u64 {
+    INITIAL_BACKOFF_MS
+        .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+        .min(MAX_BACKOFF_MS)
+}
+
+impl Outbox {
+    /// Queue an envelope whose first send failed at `now`.
+    pub fn push(
+        &mut self,
+        convo_id: &str,
+        message_id: &str,
+        envelope: Blob,
+        expires_at: Option<u64>,
+        now: u64,
+    ) {
+        self.restore(OutboxEntry {
+            convo_id: convo_id.to_string(),
+            message_id: message_id.to_string(),
+            envelope,
+            attempts: 1,
+            next_attempt: now + backoff(1),
+            expires_at,
+        });
+    }
+
+    /// Queue an envelope which was never attempted because the DeliveryService
+    /// was disconnected. It is sent as soon as connectivity returns.
+    pub fn push_offline(
+        &mut self,
+        convo_id: &str,
+        message_id: &str,
+        envelope: Blob,
+        expires_at: Option<u64>,
+        now: u64,
+    ) {
+        self.defer(convo_id, message_id, envelope, expires_at, now);
+    }
+
+    /// Queue an envelope to be sent for the first time at `send_at`, e.g.
+    /// once the rate limit allows it.
+    pub fn defer(
+        &mut self,
+        convo_id: &str,
+        message_id: &str,
+        envelope: Blob,
+        expires_at: Option<u64>,
+        send_at: u64,
+    ) {
+        self.restore(OutboxEntry {
+            convo_id: convo_id.to_string(),
+            message_id: message_id.to_string(),
+            envelope,
+            attempts: 0,
+            next_attempt: send_at,
+            expires_at,
+        });
+    }
+
+    /// Queue an entry as is, e.g. one recovered from a snapshot.
+    pub fn restore(&mut self, entry: OutboxEntry) {
+        self.entries.push_back(entry);
+    }
+
+    /// Remove and return the entries of `convo_id` whose TTL has passed.
+    pub fn take_expired(&mut self, convo_id: &str, now: u64) -> Vec<OutboxEntry> {
+        let (expired, rest): (VecDeque<OutboxEntry>, VecDeque<OutboxEntry>) = self
+            .entries
+            .drain(..)
+            .partition(|e| e.convo_id == convo_id && e.expires_at.is_some_and(|t| t <= now));
+        self.entries = rest;
+        expired.into()
+    }
+
+    /// Remove and return the entries of `convo_id` due for a retry, oldest
+    /// first.
+    pub fn take_due(&mut self, convo_id: &str, now: u64) -> Vec<OutboxEntry> {
+        let (due, rest): (VecDeque<OutboxEntry>, VecDeque<OutboxEntry>) = self
+            .entries
+            .drain(..)
+            .partition(|e| e.convo_id == convo_id && e.next_attempt <= now);
+        self.entries = rest;
+        due.into()
+    }
+
+    /// Remove and return every entry of `convo_id`, oldest first.
+    pub fn take_all(&mut self, convo_id: &str) -> Vec<OutboxEntry> {
+        self.take_due(convo_id, u64::MAX)
+    }
+
+    /// Requeue an entry after another failed attempt. Returns false once the
+    /// entry has run out of attempts and was dropped.
+    pub fn reschedule(&mut self, mut entry: OutboxEntry, now: u64) -> bool {
+        entry.attempts += 1;
+        if entry.attempts >= MAX_ATTEMPTS {
+            return false;
+        }
+        entry.next_attempt = now + backoff(entry.attempts);
+        self.entries.push_back(entry);
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn pending(&self, convo_id: &str) -> impl Iterator<Item = &OutboxEntry> {
+        self.entries.iter().filter(move |e| e.convo_id == convo_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_cap() {
+        assert_eq!(backoff(1), 1_000);
+        assert_eq!(backoff(2), 2_000);
+        assert_eq!(backoff(3), 4_000);
+        assert_eq!(backoff(30), MAX_BACKOFF_MS);
+    }
+
+    #[test]
+    fn take_due_respects_schedule_and_conversation() {
+        let mut outbox = Outbox::default();
+        outbox.push("a", "m1", vec![1], None, 0);
+        outbox.push("b", "m2", vec![2], None, 0);
+
+        assert!(outbox.take_due("a", 500).is_empty());
+
+        let due = outbox.take_due("a", 1_000);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].message_id, "m1");
+        assert_eq!(outbox.pending("a").count(), 0);
+        assert_eq!(outbox.pending("b").count(), 1);
+    }
+
+    #[test]
+    fn reschedule_gives_up_after_max_attempts() {
+        let mut outbox = Outbox::default();
+        outbox.push("a", "m1", vec![1], None, 0);
+
+        let mut now = 0;
+        let mut retries = 0;
+        loop {
+            now += MAX_BACKOFF_MS;
+            let entry = outbox.take_due("a", now).pop().unwrap();
+            if !outbox.reschedule(entry, now) {
+                break;
+            }
+            retries += 1;
+        }
+        assert_eq!(retries, MAX_ATTEMPTS - 2);
+        assert_eq!(outbox.pending("a").count(), 0);
+    }
+
+    #[test]
+    fn take_expired_drops_entries_past_ttl() {
+        let mut outbox = Outbox::default();
+        outbox.push("a", "m1", vec![1], Some(5_000), 0);
+        outbox.push_offline("a", "m2", vec![2], None, 0);
+
+        assert!(outbox.take_expired("a", 4_999).is_empty());
+
+        let expired = outbox.take_expired("a", 5_000);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].message_id, "m1");
+        assert_eq!(outbox.len(), 1);
+    }
+}
@@ -0,0 +1,156 @@
+//! Envelopes which arrived before their conversation.
+//!
+//! An invite and the first messages of its conversation are published
+//! separately and may arrive in either order. Envelopes whose hint matches
+//! no conversation are held here, for a while and up to a limit, and
+//! replayed once a conversation using the hint is created.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use umbra_types::base::UmbraEnvelopeV1;
+
+/// How many envelopes for unknown conversations are held, and for how long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingLimits {
+    /// Envelopes held at once. The oldest is dropped to make room. With 0,
+    /// envelopes for unknown conversations are dropped on arrival.
+    pub capacity: usize,
+    /// How long an envelope is held before it is dropped.
+    pub ttl: Duration,
+}
+
+impl Default for PendingLimits {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            ttl: Duration::from_secs(60),
+        }
+    }
+}
+
+struct Pending {
+    envelope: UmbraEnvelopeV1,
+    received_at: u64,
+}
+
+pub(crate) struct PendingEnvelopes {
+    limits: PendingLimits,
+    // hint -> envelopes in arrival order
+    by_hint: HashMap<String, VecDeque<Pending>>,
+    len: usize,
+}
+
+impl PendingEnvelopes {
+    pub fn new(limits: PendingLimits) -> Self {
+        Self {
+            limits,
+            by_hint: HashMap::new(),
+            len: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.limits.capacity > 0
+    }
+
+    /// Hold `envelope`, returning whether an older one was dropped for it.
+    pub fn push(&mut self, envelope: UmbraEnvelopeV1, now: u64) -> bool {
+        let evicted = self.len >= self.limits.capacity && self.evict_oldest();
+        self.by_hint
+            .entry(envelope.conversation_hint.clone())
+            .or_default()
+            .push_back(Pending {
+                envelope,
+                received_at: now,
+            });
+        self.len += 1;
+        evicted
+    }
+
+    fn evict_oldest(&mut self) -> bool {
+        let oldest = self
+            .by_hint
+            .iter()
+            .filter_map(|(hint, queue)| Some((queue.front()?.received_at, hint)))
+            .min()
+            .map(|(_, hint)| hint.clone());
+        let Some(hint) = oldest else {
+            return false;
+        };
+        let queue = self.by_hint.get_mut(&hint).expect("hint is held");
+        queue.pop_front();
+        if queue.is_empty() {
+            self.by_hint.remove(&hint);
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// Drop envelopes held longer than the TTL, returning how many.
+    pub fn expire(&mut self, now: u64) -> usize {
+        let ttl = self.limits.ttl.as_millis() as u64;
+        let before = self.len;
+        self.by_hint.retain(|_, queue| {
+            queue.retain(|pending| now.saturating_sub(pending.received_at) < ttl);
+            !queue.is_empty()
+        });
+        self.len = self.by_hint.values().map(VecDeque::len).sum();
+        before - self.len
+    }
+
+    /// Remove and return, in arrival order, the envelopes whose hint and salt
+    /// `matches` accepts.
+    pub fn take(&mut self, mut matches: impl FnMut(&str, u64) -> bool) -> Vec<UmbraEnvelopeV1> {
+        let hints: Vec<String> = self
+            .by_hint
+            .iter()
+            .filter(|(hint, queue)| {
+                queue
+                    .front()
+                    .is_some_and(|p| matches(hint, p.envelope.salt))
+            })
+            .map(|(hint, _)| hint.clone())
+            .collect();
+        let mut taken: Vec<Pending> = hints
+            .iter()
+            .filter_map(|hint| self.by_hint.remove(hint))
+            .flatten()
+            .collect();
+        self.len -= taken.len();
+        taken.sort_by_key(|pending| pending.received_at);
+        taken.into_iter().map(|pending| pending.envelope).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope(hint: &str) -> UmbraEnvelopeV1 {
+        UmbraEnvelopeV1 {
+            conversation_hint: hint.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn envelopes_are_bounded_expired_and_taken_by_hint() {
+        let mut pending = PendingEnvelopes::new(PendingLimits {
+            capacity: 2,
+            ttl: Duration::from_millis(100),
+        });
+        assert!(!pending.push(envelope("a"), 0));
+        assert!(!pending.push(envelope("b"), 10));
+        assert!(pending.push(envelope("b"), 20));
+
+        let taken = pending.take(|hint, _| hint == "b");
+        assert_eq!(taken.len(), 2);
+        assert!(pending.take(|hint, _| hint == "a").is_empty());
+
+        pending.push(envelope("c"), 30);
+        assert_eq!(pending.expire(129), 0);
+        assert_eq!(pending.expire(130), 1);
+        assert!(pending.take(|_, _| true).is_empty());
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use prost::Message;
+use umbra_content_types::{Poll, PollVote, TaggedContent};
+use umbra_types::common_frames::ContentFrame;
+
+use crate::address::Address;
+use crate::convos::MessageRecord;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PollOption {
+    pub text: String,
+    /// Participants whose current vote includes this option.
+    pub voters: Vec<Address>,
+}
+
+/// Current state of a poll, counting each participant's latest vote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PollResults {
+    pub poll_id: String,
+    pub question: String,
+    pub multiple_choice: bool,
+    pub options: Vec<PollOption>,
+}
+
+impl PollResults {
+    /// Number of participants with a current vote.
+    pub fn voter_count(&self) -> usize {
+        let mut voters: Vec<&Address> = self.options.iter().flat_map(|o| &o.voters).collect();
+        voters.sort();
+        voters.dedup();
+        voters.len()
+    }
+}
+
+fn decode<M: Message + Default + TaggedContent>(content: &ContentFrame) -> Option<M> {
+    if content.domain != M::DOMAIN || content.tag != M::TAG {
+        return None;
+    }
+    M::decode(content.bytes.as_slice()).ok()
+}
+
+/// Tally the votes on `poll_id` found in `messages`. Returns None if the poll
+/// itself is not among them.
+pub(crate) fn tally(poll_id: &str, messages: &[MessageRecord]) -> Option<PollResults> {
+    let poll: Poll = messages
+        .iter()
+        .find(|m| m.message_id == poll_id && !m.retracted)
+        .and_then(|m| decode(&m.content))?;
+
+    // A later vote replaces everything the participant voted before
+    let mut latest: HashMap<&Address, (u64, &str, PollVote)> = HashMap::new();
+    for message in messages.iter().filter(|m| !m.retracted) {
+        let Some(vote) = decode::<PollVote>(&message.content) else {
+            continue;
+        };
+        if vote.poll_id != poll_id {
+            continue;
+        }
+        let key = (message.lamport_timestamp, message.message_id.as_str());
+        let newer = latest
+            .get(&message.sender)
+            .is_none_or(|(ts, id, _)| key > (*ts, *id));
+        if newer {
+            latest.insert(&message.sender, (key.0, key.1, vote));
+        }
+    }
+
+    let mut options: Vec<PollOption> = poll
+        .options
+        .iter()
+        .map(|text| PollOption {
+            text: text.clone(),
+            voters: vec![],
+        })
+        .collect();
+    for (voter, (_, _, vote)) in latest {
+        let mut choices = vote.options;
+        choices.sort();
+        choices.dedup();
+        if !poll.multiple_choice {
+            choices.truncate(1);
+        }
+        for choice in choices {
+            if let Some(option) = options.get_mut(choice as usize) {
+                option.voters.push(voter.clone());
+            }
+        }
+    }
+    for option in options.iter_mut() {
+        option.voters.sort();
+    }
+
+    Some(PollResults {
+        poll_id: poll_id.to_string(),
+        question: poll.question,
+        multiple_choice: poll.multiple_choice,
+        options,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message<M: Message + TaggedContent>(
+        id: &str,
+        sender: &str,
+        ts: u64,
+        content: M,
+    ) -> MessageRecord {
+        MessageRecord {
+            message_id: id.to_string(),
+            convo_id: "convo".into(),
+            sender: sender.parse().unwrap(),
+            lamport_timestamp: ts,
+            timestamp: ts,
+            sent_at: Some(ts),
+            expires_at: None,
+            content: ContentFrame {
+                domain: M::DOMAIN,
+                tag: M::TAG,
+                bytes: content.encode_to_vec(),
+            },
+            reply_to: None,
+            status: None,
+            edit_history: vec![],
+            retracted: false,
+            imported: false,
+        }
+    }
+
+    fn poll(multiple_choice: bool) -> MessageRecord {
+        message(
+            "p1",
+            "amal",
+            1,
+            Poll {
+                question: "Lunch?".into(),
+                options: vec!["pizza".into(), "sushi".into()],
+                multiple_choice,
+            },
+        )
+    }
+
+    fn vote(id: &str, sender: &str, ts: u64, options: Vec<u32>) -> MessageRecord {
+        message(
+            id,
+            sender,
+            ts,
+            PollVote {
+                poll_id: "p1".into(),
+                options,
+            },
+        )
+    }
+
+    fn counts(results: &PollResults) -> Vec<usize> {
+        results.options.iter().map(|o| o.voters.len()).collect()
+    }
+
+    #[test]
+    fn latest_vote_per_participant_counts() {
+        let messages = vec![
+            poll(false),
+            vote("v1", "bola", 2, vec![0]),
+            vote("v3", "bola", 4, vec![1]),
+            vote("v2", "amal", 3, vec![1]),
+            vote("v4", "bola", 3, vec![0]),
+        ];
+
+        let results = tally("p1", &messages).unwrap();
+        assert_eq!(counts(&results), vec![0, 2]);
+        assert_eq!(results.options[1].voters, vec!["amal", "bola"]);
+        assert_eq!(results.voter_count(), 2);
+    }
+
+    #[test]
+    fn single_choice_polls_count_one_option() {
+        let messages = vec![poll(false), vote("v1", "bola", 2, vec![1, 0, 7])];
+        assert_eq!(counts(&tally("p1", &messages).unwrap()), vec![1, 0]);
+
+        let messages = vec![poll(true), vote("v1", "bola", 2, vec![1, 0, 7, 1])];
+        assert_eq!(counts(&tally("p1", &messages).unwrap()), vec![1, 1]);
+    }
+
+    #[test]
+    fn empty_vote_withdraws() {
+        let messages = vec![
+            poll(false),
+            vote("v1", "bola", 2, vec![0]),
+            vote("v2", "bola", 3, vec![]),
+        ];
+        assert_eq!(tally("p1", &messages).unwrap().voter_count(), 0);
+        assert_eq!(tally("missing", &messages), None);
+    }
+}
@@ -0,0 +1,66 @@
+//! Per-conversation pseudonyms.
+//!
+//! A pseudonymous conversation is joined under an address derived from the
+//! client's identity secret and a random nonce instead of its own address.
+//! Each such conversation gets a fresh key address, so peers cannot link two
+//! conversations to the same client, or to its inbox. Only the client holding
+//! the secret and the nonce can re-derive the key behind a pseudonym.
+
+use std::sync::RwLock;
+
+use rand::Rng;
+use sha3::{Digest, Sha3_256};
+
+use crate::UmbraError;
+use crate::address::Address;
+
+const NONCE_LEN: usize = 16;
+
+#[derive(Default)]
+pub(crate) struct Pseudonyms {
+    secret: RwLock<Option<Vec<u8>>>,
+}
+
+impl Pseudonyms {
+    pub fn set_secret(&self, secret: Vec<u8>) {
+        *self.secret.write().unwrap() = Some(secret);
+    }
+
+    /// A pseudonym never handed out before.
+    pub fn fresh(&self) -> Result<Address, UmbraError> {
+        let nonce: [u8; NONCE_LEN] = rand::rng().random();
+        self.derive(&nonce)
+    }
+
+    fn derive(&self, nonce: &[u8]) -> Result<Address, UmbraError> {
+        let secret = self.secret.read().unwrap();
+        let secret = secret.as_ref().ok_or(UmbraError::NoIdentitySecret)?;
+        let key = Sha3_256::new()
+            .chain_update(b"umbra/pseudonym/")
+            .chain_update(secret)
+            .chain_update(nonce)
+            .finalize();
+        Ok(Address::from_public_key(&key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pseudonyms_need_a_secret_and_differ_per_nonce() {
+        let pseudonyms = Pseudonyms::default();
+        assert!(matches!(
+            pseudonyms.fresh(),
+            Err(UmbraError::NoIdentitySecret)
+        ));
+
+        pseudonyms.set_secret(vec![1; 32]);
+        let first = pseudonyms.derive(&[1]).unwrap();
+        assert!(first.is_key());
+        assert_eq!(first, pseudonyms.derive(&[1]).unwrap());
+        assert_ne!(first, pseudonyms.derive(&[2]).unwrap());
+        assert_ne!(first, pseudonyms.fresh().unwrap());
+    }
+}
@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+
+use umbra_types::common_frames::ContentFrame;
+
+use crate::address::Address;
+use crate::notify::NotificationPriority;
+
+/// What a push server needs to wake this client: the topics to watch and
+/// which conversation each envelope hint belongs to. Nothing here reveals
+/// message content.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PushRegistration {
+    pub topics: Vec<String>,
+    // conversation_hint -> convo_id
+    pub hints: HashMap<String, String>,
+}
+
+/// Preview of a pushed envelope, enough to render a notification.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PushNotification {
+    Message {
+        convo_id: String,
+        message_id: String,
+        sender: Address,
+        content: ContentFrame,
+        // Sender's wall clock time, in unix ms
+        sent_at: Option<u64>,
+        reply_to: Option<String>,
+        mentions: Vec<Address>,
+        priority: NotificationPriority,
+        // Whether to show it, given its priority and the conversation's
+        // NotifyLevel
+        notify: bool,
+    },
+    /// Someone started a conversation with this client.
+    Invite { participants: Vec<Address> },
+}
@@ -0,0 +1,108 @@
+/// A send budget: bursts of up to `burst` messages, refilled at `per_second`
+/// messages per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub per_second: u32,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(per_second: u32, burst: u32) -> Self {
+        Self { per_second, burst }
+    }
+}
+
+/// What happens to a message sent over budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RateLimitPolicy {
+    /// Queue the message in the outbox and send it once budget is available.
+    #[default]
+    Delay,
+    /// Refuse the message with `UmbraError::RateLimited`.
+    Reject,
+}
+
+/// Token bucket enforcing a `RateLimit`. Tokens may go negative when sends
+/// are delayed, so that later messages queue up behind earlier ones.
+#[derive(Debug, Clone)]
+pub(crate) struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: u64,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            last_refill: 0,
+        }
+    }
+
+    fn refill(&mut self, now: u64) {
+        let elapsed = now.saturating_sub(self.last_refill) as f64;
+        self.tokens = (self.tokens + elapsed * self.rate_per_ms()).min(self.limit.burst as f64);
+        self.last_refill = self.last_refill.max(now);
+    }
+
+    fn rate_per_ms(&self) -> f64 {
+        self.limit.per_second as f64 / 1_000.0
+    }
+
+    /// Milliseconds until `n` tokens are available, 0 if they are now.
+    pub fn wait_time(&mut self, n: u32, now: u64) -> u64 {
+        self.refill(now);
+        let deficit = n as f64 - self.tokens;
+        if deficit <= 0.0 {
+            return 0;
+        }
+        if self.limit.per_second == 0 {
+            return u64::MAX;
+        }
+        (deficit / self.rate_per_ms()).ceil() as u64
+    }
+
+    /// Spend `n` tokens, going into debt if there are not enough.
+    pub fn take(&mut self, n: u32, now: u64) {
+        self.refill(now);
+        self.tokens -= n as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn burst_then_refill() {
+        let mut bucket = TokenBucket::new(RateLimit::new(10, 2));
+        let now = 1_000;
+
+        assert_eq!(bucket.wait_time(2, now), 0);
+        bucket.take(2, now);
+        assert_eq!(bucket.wait_time(1, now), 100);
+        assert_eq!(bucket.wait_time(1, now + 100), 0);
+    }
+
+    #[test]
+    fn debt_delays_later_sends() {
+        let mut bucket = TokenBucket::new(RateLimit::new(10, 1));
+        let now = 1_000;
+
+        bucket.take(1, now);
+        bucket.take(1, now);
+        bucket.take(1, now);
+        // Two messages are owed, each taking 100ms to earn back
+        assert_eq!(bucket.wait_time(0, now), 200);
+    }
+
+    #[test]
+    fn refill_is_capped_at_burst() {
+        let mut bucket = TokenBucket::new(RateLimit::new(10, 3));
+        bucket.take(3, 0);
+
+        assert_eq!(bucket.wait_time(3, 60_000), 0);
+        assert!(bucket.wait_time(4, 60_000) > 0);
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt::{self, Debug, Display};
+
+use crate::crypto;
+
+// Enough of the hash to tell values apart in a log
+const HASH_LEN: usize = 12;
+
+/// How much of a value is written to logs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Redaction {
+    /// The value itself.
+    #[default]
+    Full,
+    /// A short hash of the value, so log lines about the same value can
+    /// still be correlated.
+    Hashed,
+    /// A placeholder.
+    Hidden,
+}
+
+/// What the SDK logs about identifiers (addresses, conversation ids and
+/// hints, message ids) and payloads (decoded frames). Everything is logged
+/// in full by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RedactionPolicy {
+    pub identifiers: Redaction,
+    pub payloads: Redaction,
+}
+
+impl RedactionPolicy {
+    /// Hash identifiers and hide payloads.
+    pub fn private() -> Self {
+        Self {
+            identifiers: Redaction::Hashed,
+            payloads: Redaction::Hidden,
+        }
+    }
+
+    /// Hide identifiers and payloads.
+    pub fn hidden() -> Self {
+        Self {
+            identifiers: Redaction::Hidden,
+            payloads: Redaction::Hidden,
+        }
+    }
+
+    pub(crate) fn id<'a>(&self, id: &'a dyn Display) -> Redacted<'a> {
+        Redacted(Value::Display(id), self.identifiers)
+    }
+
+    pub(crate) fn ids<'a>(&self, ids: &'a dyn Debug) -> Redacted<'a> {
+        Redacted(Value::Debug(ids), self.identifiers)
+    }
+
+    pub(crate) fn payload<'a>(&self, payload: &'a dyn Debug) -> Redacted<'a> {
+        Redacted(Value::Debug(payload), self.payloads)
+    }
+}
+
+enum Value<'a> {
+    Display(&'a dyn Display),
+    Debug(&'a dyn Debug),
+}
+
+/// A value formatted according to a `Redaction`.
+pub(crate) struct Redacted<'a>(Value<'a>, Redaction);
+
+impl Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.1, &self.0) {
+            (Redaction::Full, Value::Display(value)) => write!(f, "{value}"),
+            (Redaction::Full, Value::Debug(value)) => write!(f, "{value:?}"),
+            (Redaction::Hashed, Value::Display(value)) => write!(f, "#{}", short_hash(value)),
+            (Redaction::Hashed, Value::Debug(value)) => {
+                write!(f, "#{}", short_hash(&format!("{value:?}")))
+            }
+            (Redaction::Hidden, _) => f.write_str("<redacted>"),
+        }
+    }
+}
+
+fn short_hash(value: &dyn Display) -> String {
+    let mut hash = crypto::hash_to_string(value.to_string());
+    hash.truncate(HASH_LEN);
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_by_policy() {
+        let hint = "/private/alice|bob";
+        assert_eq!(RedactionPolicy::default().id(&hint).to_string(), hint);
+        assert_eq!(
+            RedactionPolicy::hidden().id(&hint).to_string(),
+            "<redacted>"
+        );
+
+        let hashed = RedactionPolicy::private().id(&hint).to_string();
+        assert_eq!(hashed.len(), HASH_LEN + 1);
+        assert!(!hashed.contains("alice"));
+        assert_eq!(hashed, RedactionPolicy::private().id(&hint).to_string());
+
+        let payload = RedactionPolicy::private().payload(&vec![1u8, 2, 3]);
+        assert_eq!(payload.to_string(), "<redacted>");
+    }
+}
@@ -0,0 +1,133 @@
+//! How long local history is kept.
+//!
+//! Retention is a local choice, unlike a conversation's expiration policy
+//! which is shared with the peer. The client's `ClientConfig::retention`
+//! applies to every conversation which does not set its own through
+//! `Conversation::set_retention`. Messages it no longer allows are pruned from
+//! history and the MessageStore on every tick, or at once with
+//! `UmbraClient::purge_now`, and reported to prune handlers.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::convos::MessageRecord;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Which messages a conversation keeps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Retention {
+    #[default]
+    Forever,
+    /// Keep messages for this long after they were sent or received.
+    MaxAge(Duration),
+    /// Keep only this many of the newest messages.
+    MaxMessages(usize),
+}
+
+impl Retention {
+    pub fn days(days: u32) -> Self {
+        Retention::MaxAge(DAY * days)
+    }
+
+    // 0 is left for conversations without a policy of their own
+    pub(crate) fn to_snapshot(retention: Option<Retention>) -> (u32, u64) {
+        match retention {
+            None => (0, 0),
+            Some(Retention::Forever) => (1, 0),
+            Some(Retention::MaxAge(age)) => (2, age.as_millis() as u64),
+            Some(Retention::MaxMessages(count)) => (3, count as u64),
+        }
+    }
+
+    pub(crate) fn from_snapshot(kind: u32, limit: u64) -> Option<Retention> {
+        match kind {
+            1 => Some(Retention::Forever),
+            2 => Some(Retention::MaxAge(Duration::from_millis(limit))),
+            3 => Some(Retention::MaxMessages(limit as usize)),
+            _ => None,
+        }
+    }
+
+    /// Ids of the messages in `messages` this policy no longer allows at
+    /// `now`, in history order.
+    pub(crate) fn pruned(self, messages: &[MessageRecord], now: u64) -> Vec<String> {
+        let pruned: HashSet<&str> = match self {
+            Retention::Forever => return vec![],
+            Retention::MaxAge(age) => {
+                let cutoff = now.saturating_sub(age.as_millis() as u64);
+                messages
+                    .iter()
+                    .filter(|m| m.timestamp < cutoff)
+                    .map(|m| m.message_id.as_str())
+                    .collect()
+            }
+            Retention::MaxMessages(count) => {
+                // History is in arrival order, which need not be time order
+                let mut oldest_first: Vec<&MessageRecord> = messages.iter().collect();
+                oldest_first.sort_unstable_by_key(|m| (m.timestamp, m.lamport_timestamp));
+                let excess = messages.len().saturating_sub(count);
+                oldest_first[..excess]
+                    .iter()
+                    .map(|m| m.message_id.as_str())
+                    .collect()
+            }
+        };
+        messages
+            .iter()
+            .filter(|m| pruned.contains(m.message_id.as_str()))
+            .map(|m| m.message_id.clone())
+            .collect()
+    }
+}
+
+/// Messages pruned from a conversation's history by its retention policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessagesPruned {
+    pub convo_id: String,
+    pub message_ids: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use umbra_types::common_frames::ContentFrame;
+
+    use super::*;
+
+    fn history(timestamps: &[u64]) -> Vec<MessageRecord> {
+        timestamps
+            .iter()
+            .map(|&timestamp| MessageRecord {
+                message_id: timestamp.to_string(),
+                convo_id: "c".into(),
+                sender: "amal".parse().unwrap(),
+                lamport_timestamp: timestamp,
+                timestamp,
+                sent_at: None,
+                expires_at: None,
+                content: ContentFrame::default(),
+                reply_to: None,
+                status: None,
+                edit_history: vec![],
+                retracted: false,
+                imported: false,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn policies_prune_the_oldest_messages() {
+        let messages = history(&[30, 10, 40, 20]);
+        let age = Retention::MaxAge(Duration::from_millis(25));
+        assert_eq!(age.pruned(&messages, 50), vec!["10", "20"]);
+        assert_eq!(Retention::MaxMessages(3).pruned(&messages, 50), vec!["10"]);
+        assert_eq!(Retention::MaxMessages(0).pruned(&messages, 50).len(), 4);
+        assert!(Retention::Forever.pruned(&messages, u64::MAX).is_empty());
+
+        let (kind, limit) = Retention::to_snapshot(Some(Retention::days(7)));
+        assert_eq!(
+            Retention::from_snapshot(kind, limit),
+            Some(Retention::days(7))
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Request/response exchanges over a conversation.
+//!
+//! `Conversation::request` wraps content in a Request frame carrying a fresh
+//! correlation id and returns a [`PendingResponse`]. The peer answers it with
+//! the handler registered through `UmbraClient::on_request` for the content
+//! type, and the Response frame it sends back resolves the pending response.
+//! Requests and responses are not delivered to content handlers.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, TryRecvError, channel};
+use std::time::Duration;
+
+use umbra_content_types::ContentType;
+use umbra_types::common_frames::ContentFrame;
+
+use crate::events::{EventHandlers, Registered};
+use crate::utils::now_millis;
+use crate::{Blob, UmbraError};
+
+/// Answers a request: `(convo_id, request)` to the response content, or to
+/// an error message which is sent back to the requester.
+pub(crate) type RequestHandler =
+    Box<dyn Fn(String, ContentFrame) -> Result<(ContentType, Blob), String> + Send + Sync>;
+
+/// Handlers for incoming requests, by content type.
+#[derive(Default)]
+pub(crate) struct RequestHandlers {
+    handlers: RwLock<HashMap<ContentType, Registered<RequestHandler>>>,
+}
+
+impl RequestHandlers {
+    pub fn insert(&self, content_type: ContentType, handler: Registered<RequestHandler>) {
+        self.handlers.write().unwrap().insert(content_type, handler);
+    }
+
+    // A panicking handler is reported through `events`, which panics again
+    // in strict mode; the requester is only told that the request failed
+    pub fn handle(
+        &self,
+        events: &EventHandlers,
+        convo_id: String,
+        request: ContentFrame,
+    ) -> Result<ContentFrame, String> {
+        let content_type = ContentType::new(request.domain, request.tag);
+        let handlers = self.handlers.read().unwrap();
+        let handler = handlers
+            .get(&content_type)
+            .ok_or_else(|| format!("no handler for {content_type}"))?;
+        let mut res = Err("handler failed".to_string());
+        if let Err(e) = handler.invoke(|h| res = h(convo_id, request)) {
+            events.emit_panic(&e);
+        }
+        let (content_type, bytes) = res?;
+        Ok(ContentFrame {
+            domain: content_type.domain,
+            tag: content_type.tag,
+            bytes,
+        })
+    }
+}
+
+/// A request this conversation is waiting on, resolved by the peer's
+/// Response or failed once `deadline` passes.
+pub(crate) struct PendingRequest {
+    tx: Sender<Result<ContentFrame, UmbraError>>,
+    deadline: u64,
+}
+
+impl PendingRequest {
+    pub fn new(request_id: String, timeout: Duration) -> (Self, PendingResponse) {
+        let (tx, rx) = channel();
+        let deadline = now_millis().saturating_add(timeout.as_millis() as u64);
+        let pending = PendingResponse {
+            request_id,
+            rx,
+            deadline,
+        };
+        (Self { tx, deadline }, pending)
+    }
+
+    pub fn is_overdue(&self, now: u64) -> bool {
+        now >= self.deadline
+    }
+
+    // The requester may have dropped its PendingResponse, which is fine
+    pub fn resolve(self, res: Result<ContentFrame, UmbraError>) {
+        let _ = self.tx.send(res);
+    }
+}
+
+/// The eventual answer to `Conversation::request`.
+pub struct PendingResponse {
+    request_id: String,
+    rx: Receiver<Result<ContentFrame, UmbraError>>,
+    deadline: u64,
+}
+
+impl PendingResponse {
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// Block until the response arrives. Fails with
+    /// `UmbraError::RequestTimedOut` once the timeout passes, and with
+    /// `UmbraError::RequestFailed` if the responder reported an error.
+    /// Responses are only processed while the client is receiving, so this
+    /// must not be called from a handler.
+    pub fn wait(self) -> Result<ContentFrame, UmbraError> {
+        let remaining = self.deadline.saturating_sub(now_millis());
+        match self.rx.recv_timeout(Duration::from_millis(remaining)) {
+            Ok(res) => res,
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => {
+                Err(UmbraError::RequestTimedOut(self.request_id))
+            }
+        }
+    }
+
+    /// The response if it has arrived, without blocking, for callers which
+    /// cannot block such as wasm32. Ok(None) while still waiting.
+    pub fn try_recv(&self) -> Result<Option<ContentFrame>, UmbraError> {
+        match self.rx.try_recv() {
+            Ok(res) => res.map(Some),
+            Err(TryRecvError::Empty) if now_millis() < self.deadline => Ok(None),
+            Err(TryRecvError::Empty | TryRecvError::Disconnected) => {
+                Err(UmbraError::RequestTimedOut(self.request_id.clone()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::Location;
+
+    use super::*;
+
+    #[test]
+    fn unknown_requests_are_answered_with_an_error() {
+        let (handlers, events) = (RequestHandlers::default(), EventHandlers::default());
+        handlers.insert(
+            ContentType::new(7, 1),
+            Registered::new(
+                Location::caller(),
+                Box::new(|_, request| Ok((ContentType::new(7, 2), request.bytes))),
+            ),
+        );
+        let request = |tag| ContentFrame {
+            domain: 7,
+            tag,
+            bytes: vec![1, 2],
+        };
+
+        let response = handlers.handle(&events, "c".into(), request(1)).unwrap();
+        assert_eq!((response.tag, response.bytes), (2, vec![1, 2]));
+        assert!(handlers.handle(&events, "c".into(), request(3)).is_err());
+    }
+
+    #[test]
+    fn pending_response_resolves_once() {
+        let (pending, response) = PendingRequest::new("r".into(), Duration::from_secs(60));
+        assert!(matches!(response.try_recv(), Ok(None)));
+
+        pending.resolve(Err(UmbraError::RequestFailed("busy".into())));
+        assert!(matches!(response.wait(), Err(UmbraError::RequestFailed(_))));
+    }
+}
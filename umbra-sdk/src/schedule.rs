@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+
+use umbra_content_types::ContentType;
+
+use crate::Blob;
+use crate::snapshot::types::ScheduledSnapshot;
+use crate::utils::generate_random_string;
+
+/// A message waiting to be sent by `Conversation::send_at`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledMessage {
+    pub schedule_id: String,
+    pub convo_id: String,
+    pub content_type: ContentType,
+    pub bytes: Blob,
+    /// When to send, unix ms.
+    pub send_at: u64,
+}
+
+impl ScheduledMessage {
+    fn to_snapshot(&self) -> ScheduledSnapshot {
+        ScheduledSnapshot {
+            schedule_id: self.schedule_id.clone(),
+            domain: self.content_type.domain,
+            tag: self.content_type.tag,
+            bytes: self.bytes.clone(),
+            send_at: self.send_at,
+        }
+    }
+}
+
+// A conversation's scheduled messages, ordered by when they are due. They
+// are persisted with the conversation, so survive restarts.
+#[derive(Debug, Default)]
+pub(crate) struct Schedule {
+    // Keyed by (send_at, schedule_id) so due messages come first
+    entries: BTreeMap<(u64, String), ScheduledMessage>,
+}
+
+impl Schedule {
+    pub fn add(
+        &mut self,
+        convo_id: &str,
+        content_type: ContentType,
+        bytes: Blob,
+        send_at: u64,
+    ) -> String {
+        let schedule_id = generate_random_string(16);
+        self.insert(ScheduledMessage {
+            schedule_id: schedule_id.clone(),
+            convo_id: convo_id.to_string(),
+            content_type,
+            bytes,
+            send_at,
+        });
+        schedule_id
+    }
+
+    pub fn insert(&mut self, message: ScheduledMessage) {
+        let key = (message.send_at, message.schedule_id.clone());
+        self.entries.insert(key, message);
+    }
+
+    pub fn cancel(&mut self, schedule_id: &str) -> Option<ScheduledMessage> {
+        let key = self
+            .entries
+            .keys()
+            .find(|(_, id)| id == schedule_id)?
+            .clone();
+        self.entries.remove(&key)
+    }
+
+    // Removes and returns every message due at `now`, oldest first
+    pub fn take_due(&mut self, now: u64) -> Vec<ScheduledMessage> {
+        let later = self
+            .entries
+            .split_off(&(now.saturating_add(1), String::new()));
+        std::mem::replace(&mut self.entries, later)
+            .into_values()
+            .collect()
+    }
+
+    pub fn list(&self) -> Vec<ScheduledMessage> {
+        self.entries.values().cloned().collect()
+    }
+
+    pub fn to_snapshot(&self) -> Vec<ScheduledSnapshot> {
+        self.entries
+            .values()
+            .map(ScheduledMessage::to_snapshot)
+            .collect()
+    }
+
+    pub fn from_snapshot(convo_id: &str, entries: Vec<ScheduledSnapshot>) -> Self {
+        let mut schedule = Self::default();
+        for entry in entries {
+            schedule.insert(ScheduledMessage {
+                schedule_id: entry.schedule_id,
+                convo_id: convo_id.to_string(),
+                content_type: ContentType::new(entry.domain, entry.tag),
+                bytes: entry.bytes,
+                send_at: entry.send_at,
+            });
+        }
+        schedule
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn due_messages_are_taken_in_order() {
+        let mut schedule = Schedule::default();
+        let chat = ContentType::new(0, 1);
+        let late = schedule.add("c", chat, vec![3], 300);
+        schedule.add("c", chat, vec![2], 200);
+        schedule.add("c", chat, vec![1], 100);
+        assert!(schedule.cancel(&late).is_some());
+
+        assert!(schedule.take_due(99).is_empty());
+        let due: Vec<Blob> = schedule
+            .take_due(200)
+            .into_iter()
+            .map(|m| m.bytes)
+            .collect();
+        assert_eq!(due, vec![vec![1], vec![2]]);
+        assert!(schedule.list().is_empty());
+    }
+}
@@ -0,0 +1,443 @@
+//! Scalable Data Sync bookkeeping for a single conversation: the Lamport
+//! clock, which messages have been seen, and frames held back until their
+//! causal dependencies arrive.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use umbra_types::base::ReliableBytes;
+
+use crate::Blob;
+use crate::bloom::BloomFilter;
+
+// Number of message ids remembered for duplicate detection
+const SEEN_CAPACITY: usize = 4096;
+// Number of recent message ids attached to outgoing frames
+const CAUSAL_HISTORY_LEN: usize = 10;
+// Frames held waiting for dependencies before the oldest is forced out
+const MAX_PENDING: usize = 256;
+// Number of recently seen message ids summarized in the bloom filter
+const BLOOM_WINDOW: usize = 256;
+// Number of our own messages tracked until a peer's bloom filter covers them
+const MAX_UNACKED: usize = 256;
+// Number of sent envelopes kept for retransmission
+const SEND_CACHE_LEN: usize = 256;
+// Most envelopes returned for a single history query
+const MAX_HISTORY_RESPONSE: usize = 64;
+// Minimum time between repair requests for the same message
+const REPAIR_INTERVAL_MS: u64 = 5_000;
+// How long a frame waits for missing dependencies before delivery anyway
+const DEPENDENCY_TIMEOUT_MS: u64 = 30_000;
+
+struct PendingFrame {
+    frame: ReliableBytes,
+    received_at: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct SdsState {
+    lamport_timestamp: u64,
+    seen: HashSet<String>,
+    // Seen ids, oldest first, bounding `seen`
+    seen_order: VecDeque<String>,
+    // Most recent non-ephemeral message ids, oldest first
+    recent: VecDeque<String>,
+    pending: VecDeque<PendingFrame>,
+    // Recently seen ids, oldest first, used to build the outgoing bloom filter
+    bloom_window: VecDeque<String>,
+    // Our own messages (id, lamport) not yet seen in a peer's bloom filter
+    unacked: VecDeque<(String, u64)>,
+    // Our messages a peer's bloom filter suggests it never received
+    peer_missing: HashSet<String>,
+    // Encoded envelopes of our recent messages, oldest first
+    send_cache: VecDeque<(String, u64, Blob)>,
+    // When each missing message was last requested from peers
+    repair_requested: HashMap<String, u64>,
+    // Highest lamport timestamp of a delivered peer message
+    delivered: u64,
+    // Lamport timestamp covered by the last cumulative ack we sent
+    acked: u64,
+}
+
+impl SdsState {
+    pub fn lamport_timestamp(&self) -> u64 {
+        self.lamport_timestamp
+    }
+
+    pub fn set_lamport_timestamp(&mut self, lamport_timestamp: u64) {
+        self.lamport_timestamp = lamport_timestamp;
+    }
+
+    pub fn next_lamport_timestamp(&mut self) -> u64 {
+        self.lamport_timestamp = self.lamport_timestamp.saturating_add(1);
+        self.lamport_timestamp
+    }
+
+    pub fn observe_lamport_timestamp(&mut self, lamport_timestamp: u64) {
+        // Saturates, so a peer claiming u64::MAX cannot overflow the clock
+        self.lamport_timestamp = self
+            .lamport_timestamp
+            .max(lamport_timestamp)
+            .saturating_add(1);
+    }
+
+    pub fn causal_history(&self) -> Vec<String> {
+        self.recent.iter().cloned().collect()
+    }
+
+    // Ephemeral frames (receipts, presence) are never depended upon, so a
+    // lost one cannot hold up later messages.
+    pub fn mark_seen(&mut self, message_id: &str, ephemeral: bool) {
+        if !self.seen.insert(message_id.to_string()) {
+            return;
+        }
+
+        // Lamport timestamps say nothing about what was delivered, as a peer
+        // which was offline sends new messages below our clock, so
+        // forgotten ids are simply forgotten
+        self.seen_order.push_back(message_id.to_string());
+        let overflow = self.seen_order.len().saturating_sub(SEEN_CAPACITY);
+        for evicted in self.seen_order.drain(..overflow) {
+            self.seen.remove(&evicted);
+        }
+
+        self.bloom_window.push_back(message_id.to_string());
+        if self.bloom_window.len() > BLOOM_WINDOW {
+            self.bloom_window.pop_front();
+        }
+
+        if ephemeral {
+            return;
+        }
+
+        self.recent.push_back(message_id.to_string());
+        if self.recent.len() > CAUSAL_HISTORY_LEN {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Ids seen, oldest first, for persisting duplicate detection across
+    /// restarts.
+    pub fn seen_ids(&self) -> Vec<String> {
+        self.seen_order.iter().cloned().collect()
+    }
+
+    /// Restore ids from `seen_ids` as older than any seen since, keeping the
+    /// newest if they no longer fit. Unlike `mark_seen` they do not become
+    /// causal history.
+    pub fn restore_seen(&mut self, ids: Vec<String>) {
+        for id in ids.into_iter().rev() {
+            if self.seen_order.len() >= SEEN_CAPACITY {
+                break;
+            }
+            if self.seen.insert(id.clone()) {
+                self.seen_order.push_front(id);
+            }
+        }
+    }
+
+    /// Track one of our own messages until a peer confirms it via bloom filter.
+    pub fn record_sent(&mut self, message_id: &str, lamport_timestamp: u64) {
+        self.unacked
+            .push_back((message_id.to_string(), lamport_timestamp));
+        if self.unacked.len() > MAX_UNACKED {
+            self.unacked.pop_front();
+        }
+    }
+
+    pub fn cache_sent(&mut self, message_id: &str, lamport_timestamp: u64, envelope: Blob) {
+        self.send_cache
+            .push_back((message_id.to_string(), lamport_timestamp, envelope));
+        if self.send_cache.len() > SEND_CACHE_LEN {
+            self.send_cache.pop_front();
+        }
+    }
+
+    /// Ids of our messages still cached for retransmission, oldest first.
+    pub fn sent_ids(&self) -> Vec<String> {
+        self.send_cache
+            .iter()
+            .map(|(id, _, _)| id.clone())
+            .collect()
+    }
+
+    pub fn cached_envelope(&self, message_id: &str) -> Option<&Blob> {
+        self.send_cache
+            .iter()
+            .find(|(id, _, _)| id == message_id)
+            .map(|(_, _, envelope)| envelope)
+    }
+
+    /// Cached envelopes sent after `since`, oldest first.
+    pub fn history_since(&self, since: u64, limit: usize) -> Vec<Blob> {
+        self.send_cache
+            .iter()
+            .filter(|(_, lamport, _)| *lamport > since)
+            .take(limit.min(MAX_HISTORY_RESPONSE))
+            .map(|(_, _, envelope)| envelope.clone())
+            .collect()
+    }
+
+    /// The `limit` most recently sent cached envelopes, oldest first. 0 for
+    /// as many as a history response may carry.
+    pub fn recent_history(&self, limit: usize) -> Vec<Blob> {
+        let limit = match limit {
+            0 => MAX_HISTORY_RESPONSE,
+            limit => limit.min(MAX_HISTORY_RESPONSE),
+        };
+        let skip = self.send_cache.len().saturating_sub(limit);
+        self.send_cache
+            .iter()
+            .skip(skip)
+            .map(|(_, _, envelope)| envelope.clone())
+            .collect()
+    }
+
+    /// Dependencies still missing for buffered frames.
+    pub fn pending_missing(&self) -> Vec<String> {
+        let mut missing: Vec<String> = self
+            .pending
+            .iter()
+            .flat_map(|p| self.missing_dependencies(&p.frame))
+            .collect();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+
+    /// Filter `missing` down to ids not requested within the repair interval
+    /// and note them as requested.
+    pub fn repair_candidates(&mut self, missing: Vec<String>, now: u64) -> Vec<String> {
+        self.repair_requested
+            .retain(|id, _| !self.seen.contains(id));
+
+        missing
+            .into_iter()
+            .filter(|id| {
+                let due = self
+                    .repair_requested
+                    .get(id)
+                    .is_none_or(|t| now.saturating_sub(*t) >= REPAIR_INTERVAL_MS);
+                if due {
+                    self.repair_requested.insert(id.clone(), now);
+                }
+                due
+            })
+            .collect()
+    }
+
+    pub fn note_delivered(&mut self, lamport_timestamp: u64) {
+        self.delivered = self.delivered.max(lamport_timestamp);
+    }
+
+    /// The lamport timestamp to cumulatively acknowledge, if it moved since
+    /// the last ack. Held below any frame still waiting on dependencies.
+    pub fn ack_due(&mut self) -> Option<u64> {
+        let held_back = self
+            .pending
+            .iter()
+            .map(|p| p.frame.lamport_timestamp.saturating_sub(1))
+            .min()
+            .unwrap_or(u64::MAX);
+        let watermark = self.delivered.min(held_back);
+        if watermark <= self.acked {
+            return None;
+        }
+        self.acked = watermark;
+        Some(watermark)
+    }
+
+    /// Forget our messages a peer acknowledged up to `lamport_timestamp`.
+    pub fn prune_acked(&mut self, lamport_timestamp: u64) {
+        self.send_cache
+            .retain(|(_, lamport, _)| *lamport > lamport_timestamp);
+        self.unacked
+            .retain(|(_, lamport)| *lamport > lamport_timestamp);
+    }
+
+    pub fn bloom_filter(&self) -> Vec<u8> {
+        let mut filter = BloomFilter::default();
+        for message_id in &self.bloom_window {
+            filter.insert(message_id);
+        }
+        filter.as_bytes().to_vec()
+    }
+
+    /// Compare our unacknowledged messages against a peer's bloom filter.
+    /// Messages the peer evidently saw are dropped from tracking; those it
+    /// should have seen by now but did not are flagged and returned.
+    pub fn scan_bloom_filter(&mut self, frame: &ReliableBytes) -> Vec<String> {
+        let Some(filter) = BloomFilter::from_bytes(&frame.bloom_filter) else {
+            return vec![];
+        };
+        let peer_lamport = frame.lamport_timestamp;
+
+        self.unacked.retain(|(id, _)| !filter.contains(id));
+
+        // Messages sent after the peer's frame may simply be in flight
+        let flagged: Vec<String> = self
+            .unacked
+            .iter()
+            .filter(|(id, lamport)| *lamport < peer_lamport && !self.peer_missing.contains(id))
+            .map(|(id, _)| id.clone())
+            .collect();
+        self.peer_missing.extend(flagged.iter().cloned());
+        flagged
+    }
+
+    /// True if the message was already delivered or is waiting in the buffer.
+    pub fn is_known(&self, message_id: &str) -> bool {
+        self.seen.contains(message_id)
+            || self
+                .pending
+                .iter()
+                .any(|p| p.frame.message_id == message_id)
+    }
+
+    pub fn missing_dependencies(&self, frame: &ReliableBytes) -> Vec<String> {
+        frame
+            .causal_history
+            .iter()
+            .filter(|id| !self.seen.contains(*id))
+            .cloned()
+            .collect()
+    }
+
+    /// Hold a frame until its dependencies arrive. If the buffer is full the
+    /// oldest frame is returned so it can be delivered with a gap.
+    pub fn buffer(&mut self, frame: ReliableBytes, now: u64) -> Option<ReliableBytes> {
+        self.pending.push_back(PendingFrame {
+            frame,
+            received_at: now,
+        });
+
+        if self.pending.len() > MAX_PENDING {
+            return self.pending.pop_front().map(|p| p.frame);
+        }
+        None
+    }
+
+    /// The next buffered frame whose dependencies have all been seen.
+    pub fn take_ready(&mut self) -> Option<ReliableBytes> {
+        let idx = self
+            .pending
+            .iter()
+            .position(|p| self.missing_dependencies(&p.frame).is_empty())?;
+        self.pending.remove(idx).map(|p| p.frame)
+    }
+
+    /// Buffered frames which have waited longer than the dependency timeout.
+    pub fn take_expired(&mut self, now: u64) -> Vec<ReliableBytes> {
+        let (expired, waiting): (VecDeque<PendingFrame>, VecDeque<PendingFrame>) =
+            std::mem::take(&mut self.pending)
+                .into_iter()
+                .partition(|p| now.saturating_sub(p.received_at) >= DEPENDENCY_TIMEOUT_MS);
+        self.pending = waiting;
+        expired.into_iter().map(|p| p.frame).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: &str, deps: &[&str]) -> ReliableBytes {
+        ReliableBytes {
+            message_id: id.to_string(),
+            causal_history: deps.iter().map(|d| d.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_frames_wait_for_dependencies() {
+        let mut sds = SdsState::default();
+        let child = frame("b", &["a"]);
+
+        assert_eq!(sds.missing_dependencies(&child), vec!["a".to_string()]);
+        assert!(sds.buffer(child, 0).is_none());
+        assert!(sds.take_ready().is_none());
+
+        sds.mark_seen("a", false);
+        assert_eq!(sds.take_ready().unwrap().message_id, "b");
+    }
+
+    #[test]
+    fn test_ephemeral_frames_excluded_from_history() {
+        let mut sds = SdsState::default();
+        sds.mark_seen("a", false);
+        sds.mark_seen("receipt", true);
+
+        assert_eq!(sds.causal_history(), vec!["a".to_string()]);
+        assert!(sds.is_known("receipt"));
+    }
+
+    #[test]
+    fn test_hostile_lamport_timestamp_saturates() {
+        let mut sds = SdsState::default();
+        sds.observe_lamport_timestamp(u64::MAX);
+        assert_eq!(sds.next_lamport_timestamp(), u64::MAX);
+    }
+
+    #[test]
+    fn test_pending_frames_time_out() {
+        let mut sds = SdsState::default();
+        sds.buffer(frame("b", &["a"]), 0);
+
+        assert!(sds.take_expired(1).is_empty());
+        assert_eq!(sds.take_expired(DEPENDENCY_TIMEOUT_MS).len(), 1);
+    }
+
+    #[test]
+    fn test_duplicates_detected_by_id_only() {
+        let mut sds = SdsState::default();
+        for i in 0..=SEEN_CAPACITY {
+            sds.mark_seen(&format!("receipt-{i}"), true);
+        }
+
+        // The oldest id is forgotten, the rest are still known
+        assert!(!sds.is_known("receipt-0"));
+        assert!(sds.is_known(&format!("receipt-{SEEN_CAPACITY}")));
+        // A lagging peer's new message is not mistaken for a duplicate
+        assert!(!sds.is_known("late"));
+    }
+
+    #[test]
+    fn test_restored_ids_are_evicted_first() {
+        let mut sds = SdsState::default();
+        sds.mark_seen("new", false);
+        sds.restore_seen(vec!["old-0".into(), "old-1".into(), "new".into()]);
+        assert_eq!(sds.seen_ids(), ["old-0", "old-1", "new"]);
+        assert_eq!(sds.causal_history(), ["new"]);
+
+        for i in 0..SEEN_CAPACITY - 2 {
+            sds.mark_seen(&format!("receipt-{i}"), true);
+        }
+        assert!(!sds.is_known("old-0"));
+        assert!(sds.is_known("old-1") && sds.is_known("new"));
+    }
+
+    #[test]
+    fn test_ack_held_below_pending_frames() {
+        let mut sds = SdsState::default();
+        sds.note_delivered(5);
+        assert_eq!(sds.ack_due(), Some(5));
+        assert_eq!(sds.ack_due(), None);
+
+        let mut gap = frame("b", &["a"]);
+        gap.lamport_timestamp = 7;
+        sds.buffer(gap, 0);
+        sds.note_delivered(9);
+        assert_eq!(sds.ack_due(), Some(6));
+    }
+
+    #[test]
+    fn test_prune_acked() {
+        let mut sds = SdsState::default();
+        sds.cache_sent("a", 1, vec![1]);
+        sds.cache_sent("b", 2, vec![2]);
+        sds.prune_acked(1);
+
+        assert!(sds.cached_envelope("a").is_none());
+        assert!(sds.cached_envelope("b").is_some());
+    }
+}
@@ -0,0 +1,189 @@
+//! Key-value state shared by the participants of a conversation, e.g. its
+//! title or settings.
+//!
+//! Each key is a last-writer-wins register: a write carries the writer's
+//! clock, and the write with the highest `(timestamp, writer)` is kept, so
+//! every participant converges on the same value whatever order the writes
+//! arrive in. Removals are kept as tombstones so an older write cannot bring
+//! a key back.
+
+use std::collections::BTreeMap;
+
+use crate::address::Address;
+use crate::snapshot::parse_address;
+use crate::snapshot::types::StateEntrySnapshot;
+use crate::{Blob, UmbraError};
+
+/// A change to a conversation's shared state, local or from a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateChanged {
+    pub convo_id: String,
+    pub key: String,
+    /// None if the key was removed.
+    pub value: Option<Blob>,
+    pub writer: Address,
+}
+
+// Orders concurrent writes; ties on the clock are broken by address
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Version {
+    pub timestamp: u64,
+    pub writer: Address,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    value: Option<Blob>,
+    version: Version,
+}
+
+/// A conversation's shared state. See `Conversation::set_state`.
+#[derive(Debug, Clone, Default)]
+pub struct SharedState {
+    entries: BTreeMap<String, Entry>,
+}
+
+impl SharedState {
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key)?.value.as_deref()
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Every key currently set, in order, with its value.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.entries
+            .iter()
+            .filter_map(|(key, entry)| Some((key.as_str(), entry.value.as_deref()?)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // A version for a local write to `key` which supersedes whatever is
+    // there, even if the writer's clock is behind
+    pub(crate) fn next_version(&self, key: &str, writer: &Address, now: u64) -> Version {
+        let after = self
+            .entries
+            .get(key)
+            .map_or(0, |entry| entry.version.timestamp + 1);
+        Version {
+            timestamp: now.max(after),
+            writer: writer.clone(),
+        }
+    }
+
+    // Returns whether the write won, i.e. changed the state
+    pub(crate) fn apply(&mut self, key: &str, value: Option<Blob>, version: Version) -> bool {
+        if self
+            .entries
+            .get(key)
+            .is_some_and(|entry| entry.version >= version)
+        {
+            return false;
+        }
+        self.entries
+            .insert(key.to_string(), Entry { value, version });
+        true
+    }
+
+    // Keys whose latest write is by `writer`, with the value (None if
+    // removed) and the write's timestamp
+    pub(crate) fn written_by<'a>(
+        &'a self,
+        writer: &'a Address,
+    ) -> impl Iterator<Item = (&'a str, Option<&'a [u8]>, u64)> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(_, entry)| entry.version.writer == *writer)
+            .map(|(key, entry)| {
+                let value = entry.value.as_deref();
+                (key.as_str(), value, entry.version.timestamp)
+            })
+    }
+
+    pub(crate) fn to_snapshot(&self) -> Vec<StateEntrySnapshot> {
+        self.entries
+            .iter()
+            .map(|(key, entry)| StateEntrySnapshot {
+                key: key.clone(),
+                value: entry.value.clone().unwrap_or_default(),
+                removed: entry.value.is_none(),
+                timestamp: entry.version.timestamp,
+                writer: entry.version.writer.to_string(),
+            })
+            .collect()
+    }
+
+    pub(crate) fn from_snapshot(entries: Vec<StateEntrySnapshot>) -> Result<Self, UmbraError> {
+        let mut state = Self::default();
+        for entry in entries {
+            let value = (!entry.removed).then_some(entry.value);
+            let version = Version {
+                timestamp: entry.timestamp,
+                writer: parse_address(entry.writer)?,
+            };
+            state.apply(&entry.key, value, version);
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(timestamp: u64, writer: &str) -> Version {
+        Version {
+            timestamp,
+            writer: writer.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn replicas_converge_whatever_the_order() {
+        let writes = [
+            ("title", Some(b"a".to_vec()), version(5, "alice")),
+            ("title", Some(b"b".to_vec()), version(5, "bob")),
+            ("title", None, version(3, "alice")),
+            ("topic", Some(b"c".to_vec()), version(1, "bob")),
+        ];
+        let mut forward = SharedState::default();
+        let mut backward = SharedState::default();
+        for (key, value, version) in writes.iter().cloned() {
+            forward.apply(key, value, version);
+        }
+        for (key, value, version) in writes.iter().rev().cloned() {
+            backward.apply(key, value, version);
+        }
+
+        assert_eq!(forward.get("title"), Some(&b"b"[..]));
+        assert_eq!(
+            forward.iter().collect::<Vec<_>>(),
+            backward.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn removals_are_not_undone_by_older_writes() {
+        let mut state = SharedState::default();
+        state.apply("k", Some(vec![1]), version(1, "a"));
+        let removal = state.next_version("k", &"a".parse().unwrap(), 0);
+        assert!(state.apply("k", None, removal));
+
+        assert!(!state.apply("k", Some(vec![2]), version(1, "b")));
+        assert!(state.is_empty());
+
+        // Tombstones are still re-sent to a peer resyncing
+        let writer = "a".parse().unwrap();
+        let written: Vec<_> = state.written_by(&writer).collect();
+        assert_eq!(written, vec![("k", None, 2)]);
+    }
+}
@@ -0,0 +1,106 @@
+use umbra_types::common_frames::ContentFrame;
+
+use crate::UmbraError;
+use crate::address::Address;
+use crate::convos::MessageRecord;
+use crate::error::FrameKind;
+use crate::events::DeliveryStatus;
+
+pub mod types {
+    include!(concat!(env!("OUT_DIR"), "/umbra.sdk.snapshot.rs"));
+}
+
+use types::{ContentSnapshot, MessageSnapshot};
+
+// Bump on incompatible changes to snapshot.proto
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+impl From<&ContentFrame> for ContentSnapshot {
+    fn from(frame: &ContentFrame) -> Self {
+        Self {
+            domain: frame.domain,
+            tag: frame.tag,
+            bytes: frame.bytes.clone(),
+        }
+    }
+}
+
+impl From<ContentSnapshot> for ContentFrame {
+    fn from(snapshot: ContentSnapshot) -> Self {
+        Self {
+            domain: snapshot.domain,
+            tag: snapshot.tag,
+            bytes: snapshot.bytes,
+        }
+    }
+}
+
+fn status_to_wire(status: Option<DeliveryStatus>) -> u32 {
+    match status {
+        None => 0,
+        Some(DeliveryStatus::Sent) => 1,
+        Some(DeliveryStatus::Delivered) => 2,
+        Some(DeliveryStatus::Failed) => 3,
+        Some(DeliveryStatus::Pending) => 4,
+        Some(DeliveryStatus::Expired) => 5,
+    }
+}
+
+fn status_from_wire(status: u32) -> Option<DeliveryStatus> {
+    match status {
+        1 => Some(DeliveryStatus::Sent),
+        2 => Some(DeliveryStatus::Delivered),
+        3 => Some(DeliveryStatus::Failed),
+        4 => Some(DeliveryStatus::Pending),
+        5 => Some(DeliveryStatus::Expired),
+        _ => None,
+    }
+}
+
+impl From<&MessageRecord> for MessageSnapshot {
+    fn from(record: &MessageRecord) -> Self {
+        Self {
+            message_id: record.message_id.clone(),
+            sender: record.sender.to_string(),
+            lamport_timestamp: record.lamport_timestamp,
+            timestamp: record.timestamp,
+            sent_at: record.sent_at.unwrap_or(0),
+            expires_at: record.expires_at.unwrap_or(0),
+            content: Some((&record.content).into()),
+            reply_to: record.reply_to.clone().unwrap_or_default(),
+            status: status_to_wire(record.status),
+            edit_history: record.edit_history.iter().map(Into::into).collect(),
+            retracted: record.retracted,
+            imported: record.imported,
+        }
+    }
+}
+
+impl MessageRecord {
+    pub(crate) fn from_snapshot(
+        convo_id: &str,
+        snapshot: MessageSnapshot,
+    ) -> Result<Self, UmbraError> {
+        Ok(Self {
+            message_id: snapshot.message_id,
+            convo_id: convo_id.to_string(),
+            sender: parse_address(snapshot.sender)?,
+            lamport_timestamp: snapshot.lamport_timestamp,
+            timestamp: snapshot.timestamp,
+            sent_at: (snapshot.sent_at != 0).then_some(snapshot.sent_at),
+            expires_at: (snapshot.expires_at != 0).then_some(snapshot.expires_at),
+            content: snapshot.content.unwrap_or_default().into(),
+            reply_to: (!snapshot.reply_to.is_empty()).then_some(snapshot.reply_to),
+            status: status_from_wire(snapshot.status),
+            edit_history: snapshot.edit_history.into_iter().map(Into::into).collect(),
+            retracted: snapshot.retracted,
+            imported: snapshot.imported,
+        })
+    }
+}
+
+// Snapshots can be imported from elsewhere, so addresses are checked again
+pub(crate) fn parse_address(addr: String) -> Result<Address, UmbraError> {
+    addr.parse()
+        .map_err(UmbraError::decoding(FrameKind::Snapshot))
+}
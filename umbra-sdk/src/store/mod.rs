@@ -0,0 +1,357 @@
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use prost::Message;
+use umbra_content_types::{
+    ChatMessage, CompositeContent, ImageMessage, STANDARD_DOMAIN, TaggedContent, VideoMessage,
+};
+use umbra_types::common_frames::ContentFrame;
+
+use crate::address::Address;
+use crate::composite;
+use crate::convos::MessageRecord;
+use crate::{Blob, UmbraError};
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteMessageStore;
+
+/// A stored message matching a search query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+    pub convo_id: String,
+    pub message_id: String,
+    /// Excerpt of the message text around the match.
+    pub snippet: String,
+}
+
+/// The text a message is searchable by, if its content type has any.
+/// Retracted messages are never searchable.
+pub(crate) fn searchable_text(message: &MessageRecord) -> Option<String> {
+    if message.retracted {
+        return None;
+    }
+    content_text(&message.content)
+}
+
+fn content_text(content: &ContentFrame) -> Option<String> {
+    if content.domain != STANDARD_DOMAIN {
+        return None;
+    }
+    match content.tag {
+        ChatMessage::TAG => ChatMessage::decode(content.bytes.as_slice())
+            .ok()
+            .map(|m| m.text),
+        ImageMessage::TAG => ImageMessage::decode(content.bytes.as_slice())
+            .ok()
+            .map(|m| m.caption),
+        VideoMessage::TAG => VideoMessage::decode(content.bytes.as_slice())
+            .ok()
+            .map(|m| m.caption),
+        CompositeContent::TAG => CompositeContent::decode(content.bytes.as_slice())
+            .ok()
+            .map(|m| {
+                composite::parts(m)
+                    .iter()
+                    .filter_map(content_text)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }),
+        _ => None,
+    }
+    .filter(|text| !text.is_empty())
+}
+
+/// Persistent message history. The client appends every message it sends or
+/// receives and writes it again whenever it changes (status, edits,
+/// retraction), so `append` must replace any record with the same id.
+pub trait MessageStore: Send + Sync {
+    /// Insert `message`, replacing a stored message with the same id.
+    fn append(&self, message: &MessageRecord) -> Result<(), UmbraError>;
+    fn get_by_id(&self, message_id: &str) -> Result<Option<MessageRecord>, UmbraError>;
+    /// Up to `limit` messages of `convo_id` with `from <= timestamp < to`,
+    /// oldest first.
+    fn range(
+        &self,
+        convo_id: &str,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<MessageRecord>, UmbraError>;
+    /// Returns whether a message was removed.
+    fn delete(&self, message_id: &str) -> Result<bool, UmbraError>;
+    /// Up to `limit` messages across all conversations whose text contains
+    /// every word of `query`, best matches first.
+    fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, UmbraError>;
+    /// Replace the unsent draft of `convo_id`, or remove it if None.
+    fn save_draft(&self, convo_id: &str, draft: Option<&[u8]>) -> Result<(), UmbraError>;
+    fn load_draft(&self, convo_id: &str) -> Result<Option<Blob>, UmbraError>;
+}
+
+/// Persistent client state: the client's address and the conversations it
+/// belongs to, stored as `Conversation::export` snapshots without history.
+/// Used by `UmbraClient::load` to rebuild a client after a restart.
+pub trait StateStore: Send + Sync {
+    fn save_address(&self, addr: &Address) -> Result<(), UmbraError>;
+    fn load_address(&self) -> Result<Option<Address>, UmbraError>;
+    /// Insert or replace the snapshot of `convo_id`.
+    fn save_conversation(&self, convo_id: &str, snapshot: &[u8]) -> Result<(), UmbraError>;
+    fn load_conversations(&self) -> Result<Vec<Vec<u8>>, UmbraError>;
+    fn delete_conversation(&self, convo_id: &str) -> Result<(), UmbraError>;
+    /// Insert or replace the identity key accepted for `addr`.
+    fn save_peer_key(&self, addr: &Address, public_key: &[u8]) -> Result<(), UmbraError>;
+    fn load_peer_keys(&self) -> Result<Vec<(Address, Vec<u8>)>, UmbraError>;
+}
+
+/// Store which keeps history and client state for the lifetime of the
+/// process.
+#[derive(Default)]
+pub struct InMemoryMessageStore {
+    messages: RwLock<HashMap<String, MessageRecord>>,
+    address: RwLock<Option<Address>>,
+    conversations: RwLock<HashMap<String, Vec<u8>>>,
+    peer_keys: RwLock<HashMap<Address, Vec<u8>>>,
+    drafts: RwLock<HashMap<String, Blob>>,
+}
+
+impl InMemoryMessageStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MessageStore for InMemoryMessageStore {
+    fn append(&self, message: &MessageRecord) -> Result<(), UmbraError> {
+        self.messages
+            .write()
+            .unwrap()
+            .insert(message.message_id.clone(), message.clone());
+        Ok(())
+    }
+
+    fn get_by_id(&self, message_id: &str) -> Result<Option<MessageRecord>, UmbraError> {
+        Ok(self.messages.read().unwrap().get(message_id).cloned())
+    }
+
+    fn range(
+        &self,
+        convo_id: &str,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<MessageRecord>, UmbraError> {
+        let mut messages: Vec<MessageRecord> = self
+            .messages
+            .read()
+            .unwrap()
+            .values()
+            .filter(|m| m.convo_id == convo_id && (from..to).contains(&m.timestamp))
+            .cloned()
+            .collect();
+        messages.sort_by(|a, b| (a.timestamp, &a.message_id).cmp(&(b.timestamp, &b.message_id)));
+        messages.truncate(limit);
+        Ok(messages)
+    }
+
+    fn delete(&self, message_id: &str) -> Result<bool, UmbraError> {
+        Ok(self.messages.write().unwrap().remove(message_id).is_some())
+    }
+
+    // Case-insensitive substring match, newest first
+    fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, UmbraError> {
+        let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
+        let messages = self.messages.read().unwrap();
+        let mut matches: Vec<(&MessageRecord, String)> = messages
+            .values()
+            .filter_map(|m| searchable_text(m).map(|text| (m, text)))
+            .filter(|(_, text)| {
+                let text = text.to_lowercase();
+                words.iter().all(|w| text.contains(w.as_str()))
+            })
+            .collect();
+        matches.sort_by(|(a, _), (b, _)| {
+            (b.timestamp, &b.message_id).cmp(&(a.timestamp, &a.message_id))
+        });
+        Ok(matches
+            .into_iter()
+            .take(limit)
+            .map(|(m, text)| SearchHit {
+                convo_id: m.convo_id.clone(),
+                message_id: m.message_id.clone(),
+                snippet: text,
+            })
+            .collect())
+    }
+
+    fn save_draft(&self, convo_id: &str, draft: Option<&[u8]>) -> Result<(), UmbraError> {
+        let mut drafts = self.drafts.write().unwrap();
+        match draft {
+            Some(draft) => drafts.insert(convo_id.to_string(), draft.to_vec()),
+            None => drafts.remove(convo_id),
+        };
+        Ok(())
+    }
+
+    fn load_draft(&self, convo_id: &str) -> Result<Option<Blob>, UmbraError> {
+        Ok(self.drafts.read().unwrap().get(convo_id).cloned())
+    }
+}
+
+impl StateStore for InMemoryMessageStore {
+    fn save_address(&self, addr: &Address) -> Result<(), UmbraError> {
+        *self.address.write().unwrap() = Some(addr.clone());
+        Ok(())
+    }
+
+    fn load_address(&self) -> Result<Option<Address>, UmbraError> {
+        Ok(self.address.read().unwrap().clone())
+    }
+
+    fn save_conversation(&self, convo_id: &str, snapshot: &[u8]) -> Result<(), UmbraError> {
+        self.conversations
+            .write()
+            .unwrap()
+            .insert(convo_id.to_string(), snapshot.to_vec());
+        Ok(())
+    }
+
+    fn load_conversations(&self) -> Result<Vec<Vec<u8>>, UmbraError> {
+        Ok(self
+            .conversations
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    fn delete_conversation(&self, convo_id: &str) -> Result<(), UmbraError> {
+        self.conversations.write().unwrap().remove(convo_id);
+        Ok(())
+    }
+
+    fn save_peer_key(&self, addr: &Address, public_key: &[u8]) -> Result<(), UmbraError> {
+        self.peer_keys
+            .write()
+            .unwrap()
+            .insert(addr.clone(), public_key.to_vec());
+        Ok(())
+    }
+
+    fn load_peer_keys(&self) -> Result<Vec<(Address, Vec<u8>)>, UmbraError> {
+        Ok(self
+            .peer_keys
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(addr, key)| (addr.clone(), key.clone()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use umbra_types::common_frames::ContentFrame;
+
+    use super::*;
+
+    pub(super) fn record(convo_id: &str, message_id: &str, timestamp: u64) -> MessageRecord {
+        MessageRecord {
+            message_id: message_id.to_string(),
+            convo_id: convo_id.to_string(),
+            sender: "amal".parse().unwrap(),
+            lamport_timestamp: timestamp,
+            timestamp,
+            sent_at: None,
+            expires_at: None,
+            content: ContentFrame {
+                domain: 0,
+                tag: 1,
+                bytes: message_id.as_bytes().to_vec(),
+            },
+            reply_to: None,
+            status: None,
+            edit_history: vec![],
+            retracted: false,
+            imported: false,
+        }
+    }
+
+    #[test]
+    fn range_filters_by_conversation_and_time() {
+        let store = InMemoryMessageStore::new();
+        for (convo, id, ts) in [
+            ("a", "m3", 30),
+            ("a", "m1", 10),
+            ("b", "m2", 20),
+            ("a", "m4", 40),
+        ] {
+            store.append(&record(convo, id, ts)).unwrap();
+        }
+
+        let ids: Vec<String> = store
+            .range("a", 10, 40, 10)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.message_id)
+            .collect();
+        assert_eq!(ids, vec!["m1", "m3"]);
+        assert_eq!(store.range("a", 0, u64::MAX, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn append_replaces_and_delete_removes() {
+        let store = InMemoryMessageStore::new();
+        store.append(&record("a", "m1", 10)).unwrap();
+
+        let mut retracted = record("a", "m1", 10);
+        retracted.retracted = true;
+        store.append(&retracted).unwrap();
+        assert!(store.get_by_id("m1").unwrap().unwrap().retracted);
+
+        assert!(store.delete("m1").unwrap());
+        assert!(!store.delete("m1").unwrap());
+        assert_eq!(store.get_by_id("m1").unwrap(), None);
+    }
+
+    pub(super) fn chat(
+        convo_id: &str,
+        message_id: &str,
+        timestamp: u64,
+        text: &str,
+    ) -> MessageRecord {
+        let mut message = record(convo_id, message_id, timestamp);
+        message.content = ContentFrame {
+            domain: 0,
+            tag: ChatMessage::TAG,
+            bytes: ChatMessage::new(text.into()).encode_to_vec(),
+        };
+        message
+    }
+
+    #[test]
+    fn search_matches_chat_text() {
+        let store = InMemoryMessageStore::new();
+        store
+            .append(&chat("a", "m1", 10, "Lunch at noon?"))
+            .unwrap();
+        store.append(&chat("b", "m2", 20, "lunch is late")).unwrap();
+        store.append(&record("a", "m3", 30)).unwrap();
+
+        let hits = store.search("LUNCH", 10).unwrap();
+        let ids: Vec<&str> = hits.iter().map(|h| h.message_id.as_str()).collect();
+        assert_eq!(ids, vec!["m2", "m1"]);
+        assert_eq!(hits[1].convo_id, "a");
+        assert_eq!(store.search("lunch noon", 10).unwrap().len(), 1);
+
+        let mut retracted = chat("a", "m1", 10, "");
+        retracted.retracted = true;
+        store.append(&retracted).unwrap();
+        assert_eq!(store.search("noon", 10).unwrap(), vec![]);
+    }
+}
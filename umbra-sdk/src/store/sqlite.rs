@@ -0,0 +1,429 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use prost::Message;
+use rusqlite::{Connection, OptionalExtension, params};
+
+use super::{MessageStore, SearchHit, StateStore, searchable_text};
+use crate::address::Address;
+use crate::convos::MessageRecord;
+use crate::error::FrameKind;
+use crate::snapshot::types::MessageSnapshot;
+use crate::{Blob, UmbraError};
+
+// Records are stored as encoded MessageSnapshots next to the columns they are
+// queried by, so the schema does not change with MessageRecord
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS messages (
+        message_id TEXT PRIMARY KEY,
+        convo_id   TEXT NOT NULL,
+        timestamp  INTEGER NOT NULL,
+        record     BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS messages_by_convo ON messages (convo_id, timestamp);
+    CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5 (
+        text,
+        message_id UNINDEXED,
+        convo_id   UNINDEXED
+    );
+    CREATE TABLE IF NOT EXISTS conversations (
+        convo_id TEXT PRIMARY KEY,
+        snapshot BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS peer_keys (
+        addr       TEXT PRIMARY KEY,
+        public_key BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS drafts (
+        convo_id TEXT PRIMARY KEY,
+        draft    BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS client (
+        key   TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+";
+
+/// MessageStore and StateStore backed by a SQLite database.
+pub struct SqliteMessageStore {
+    conn: Mutex<Connection>,
+}
+
+fn storage_error(e: rusqlite::Error) -> UmbraError {
+    UmbraError::StorageError(e.into())
+}
+
+// SQLite integers are signed; clamp so range bounds like u64::MAX still work
+fn to_sql(n: u64) -> i64 {
+    n.min(i64::MAX as u64) as i64
+}
+
+// Quote every word so the query is matched literally rather than parsed as
+// FTS5 syntax; the words are implicitly ANDed
+fn fts_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn from_row(convo_id: String, record: Vec<u8>) -> Result<MessageRecord, UmbraError> {
+    let snapshot = MessageSnapshot::decode(record.as_slice())
+        .map_err(UmbraError::decoding(FrameKind::Snapshot))?;
+    MessageRecord::from_snapshot(&convo_id, snapshot)
+}
+
+impl SqliteMessageStore {
+    /// Open or create the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, UmbraError> {
+        Self::init(Connection::open(path).map_err(storage_error)?)
+    }
+
+    /// A database which lives only as long as this store, e.g. for tests.
+    pub fn open_in_memory() -> Result<Self, UmbraError> {
+        Self::init(Connection::open_in_memory().map_err(storage_error)?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, UmbraError> {
+        conn.execute_batch(SCHEMA).map_err(storage_error)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+impl MessageStore for SqliteMessageStore {
+    fn append(&self, message: &MessageRecord) -> Result<(), UmbraError> {
+        let record = MessageSnapshot::from(message).encode_to_vec();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(storage_error)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO messages (message_id, convo_id, timestamp, record)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                message.message_id,
+                message.convo_id,
+                to_sql(message.timestamp),
+                record
+            ],
+        )
+        .map_err(storage_error)?;
+        // Reindex, as edits and retractions change the text
+        tx.execute(
+            "DELETE FROM messages_fts WHERE message_id = ?1",
+            params![message.message_id],
+        )
+        .map_err(storage_error)?;
+        if let Some(text) = searchable_text(message) {
+            tx.execute(
+                "INSERT INTO messages_fts (text, message_id, convo_id) VALUES (?1, ?2, ?3)",
+                params![text, message.message_id, message.convo_id],
+            )
+            .map_err(storage_error)?;
+        }
+        tx.commit().map_err(storage_error)
+    }
+
+    fn get_by_id(&self, message_id: &str) -> Result<Option<MessageRecord>, UmbraError> {
+        let row = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT convo_id, record FROM messages WHERE message_id = ?1",
+                params![message_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(storage_error)?;
+        row.map(|(convo_id, record)| from_row(convo_id, record))
+            .transpose()
+    }
+
+    fn range(
+        &self,
+        convo_id: &str,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<MessageRecord>, UmbraError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT record FROM messages
+                 WHERE convo_id = ?1 AND timestamp >= ?2 AND timestamp < ?3
+                 ORDER BY timestamp, message_id
+                 LIMIT ?4",
+            )
+            .map_err(storage_error)?;
+        let rows = stmt
+            .query_map(
+                params![convo_id, to_sql(from), to_sql(to), to_sql(limit as u64)],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .map_err(storage_error)?;
+
+        rows.map(|record| from_row(convo_id.to_string(), record.map_err(storage_error)?))
+            .collect()
+    }
+
+    fn delete(&self, message_id: &str) -> Result<bool, UmbraError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(storage_error)?;
+        let removed = tx
+            .execute(
+                "DELETE FROM messages WHERE message_id = ?1",
+                params![message_id],
+            )
+            .map_err(storage_error)?;
+        tx.execute(
+            "DELETE FROM messages_fts WHERE message_id = ?1",
+            params![message_id],
+        )
+        .map_err(storage_error)?;
+        tx.commit().map_err(storage_error)?;
+        Ok(removed > 0)
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, UmbraError> {
+        let query = fts_query(query);
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT convo_id, message_id, snippet(messages_fts, 0, '', '', '…', 16)
+                 FROM messages_fts WHERE messages_fts MATCH ?1
+                 ORDER BY rank LIMIT ?2",
+            )
+            .map_err(storage_error)?;
+        let hits = stmt
+            .query_map(params![query, to_sql(limit as u64)], |row| {
+                Ok(SearchHit {
+                    convo_id: row.get(0)?,
+                    message_id: row.get(1)?,
+                    snippet: row.get(2)?,
+                })
+            })
+            .map_err(storage_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(storage_error)?;
+        Ok(hits)
+    }
+
+    fn save_draft(&self, convo_id: &str, draft: Option<&[u8]>) -> Result<(), UmbraError> {
+        let conn = self.conn.lock().unwrap();
+        match draft {
+            Some(draft) => conn.execute(
+                "INSERT OR REPLACE INTO drafts (convo_id, draft) VALUES (?1, ?2)",
+                params![convo_id, draft],
+            ),
+            None => conn.execute("DELETE FROM drafts WHERE convo_id = ?1", params![convo_id]),
+        }
+        .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn load_draft(&self, convo_id: &str) -> Result<Option<Blob>, UmbraError> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT draft FROM drafts WHERE convo_id = ?1",
+                params![convo_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(storage_error)
+    }
+}
+
+impl StateStore for SqliteMessageStore {
+    fn save_address(&self, addr: &Address) -> Result<(), UmbraError> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO client (key, value) VALUES ('address', ?1)",
+                params![addr.as_str()],
+            )
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn load_address(&self) -> Result<Option<Address>, UmbraError> {
+        let addr: Option<String> = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT value FROM client WHERE key = 'address'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(storage_error)?;
+        addr.map(|addr| addr.parse()).transpose()
+    }
+
+    fn save_conversation(&self, convo_id: &str, snapshot: &[u8]) -> Result<(), UmbraError> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO conversations (convo_id, snapshot) VALUES (?1, ?2)",
+                params![convo_id, snapshot],
+            )
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn load_conversations(&self) -> Result<Vec<Vec<u8>>, UmbraError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT snapshot FROM conversations")
+            .map_err(storage_error)?;
+        let rows = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(storage_error)?;
+        rows.map(|snapshot| snapshot.map_err(storage_error))
+            .collect()
+    }
+
+    fn delete_conversation(&self, convo_id: &str) -> Result<(), UmbraError> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "DELETE FROM conversations WHERE convo_id = ?1",
+                params![convo_id],
+            )
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn save_peer_key(&self, addr: &Address, public_key: &[u8]) -> Result<(), UmbraError> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO peer_keys (addr, public_key) VALUES (?1, ?2)",
+                params![addr.as_str(), public_key],
+            )
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn load_peer_keys(&self) -> Result<Vec<(Address, Vec<u8>)>, UmbraError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT addr, public_key FROM peer_keys")
+            .map_err(storage_error)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))
+            .map_err(storage_error)?;
+        rows.map(|row| {
+            let (addr, public_key) = row.map_err(storage_error)?;
+            Ok((addr.parse()?, public_key))
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::DeliveryStatus;
+    use crate::store::tests::{chat, record};
+
+    #[test]
+    fn round_trips_records() {
+        let store = SqliteMessageStore::open_in_memory().unwrap();
+        let mut message = record("a", "m1", 10);
+        message.status = Some(DeliveryStatus::Delivered);
+        message.reply_to = Some("m0".into());
+        store.append(&message).unwrap();
+
+        assert_eq!(store.get_by_id("m1").unwrap(), Some(message));
+        assert_eq!(store.get_by_id("missing").unwrap(), None);
+    }
+
+    #[test]
+    fn range_and_delete() {
+        let store = SqliteMessageStore::open_in_memory().unwrap();
+        for (convo, id, ts) in [("a", "m2", 20), ("a", "m1", 10), ("b", "m3", 15)] {
+            store.append(&record(convo, id, ts)).unwrap();
+        }
+
+        let ids: Vec<String> = store
+            .range("a", 0, u64::MAX, 10)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.message_id)
+            .collect();
+        assert_eq!(ids, vec!["m1", "m2"]);
+
+        assert!(store.delete("m1").unwrap());
+        assert_eq!(store.range("a", 0, u64::MAX, 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn persists_client_state() {
+        let store = SqliteMessageStore::open_in_memory().unwrap();
+        assert_eq!(store.load_address().unwrap(), None);
+
+        let amal: Address = "amal".parse().unwrap();
+        store.save_address(&amal).unwrap();
+        store.save_conversation("a", &[1]).unwrap();
+        store.save_conversation("a", &[2]).unwrap();
+        store.save_conversation("b", &[3]).unwrap();
+        store.delete_conversation("b").unwrap();
+        store.save_peer_key(&amal, &[4]).unwrap();
+        store.save_peer_key(&amal, &[5]).unwrap();
+
+        assert_eq!(store.load_address().unwrap(), Some(amal.clone()));
+        assert_eq!(store.load_conversations().unwrap(), vec![vec![2]]);
+        assert_eq!(store.load_peer_keys().unwrap(), vec![(amal, vec![5])]);
+    }
+
+    #[test]
+    fn drafts_are_replaced_and_cleared() {
+        let store = SqliteMessageStore::open_in_memory().unwrap();
+        store.save_draft("a", Some(&[1])).unwrap();
+        store.save_draft("a", Some(&[2])).unwrap();
+        assert_eq!(store.load_draft("a").unwrap(), Some(vec![2]));
+
+        store.save_draft("a", None).unwrap();
+        assert_eq!(store.load_draft("a").unwrap(), None);
+    }
+
+    #[test]
+    fn search_follows_edits_and_deletes() {
+        let store = SqliteMessageStore::open_in_memory().unwrap();
+        store
+            .append(&chat("a", "m1", 10, "see you at the \"station\""))
+            .unwrap();
+        store
+            .append(&chat("b", "m2", 20, "the train is late"))
+            .unwrap();
+        store.append(&record("a", "m3", 30)).unwrap();
+
+        let hits = store.search("Station", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(
+            (hits[0].convo_id.as_str(), hits[0].message_id.as_str()),
+            ("a", "m1")
+        );
+        assert_eq!(store.search("the", 10).unwrap().len(), 2);
+        assert_eq!(store.search("\"the OR", 10).unwrap(), vec![]);
+
+        store
+            .append(&chat("a", "m1", 10, "see you at the airport"))
+            .unwrap();
+        assert_eq!(store.search("station", 10).unwrap(), vec![]);
+        assert!(store.delete("m2").unwrap());
+        assert_eq!(store.search("train", 10).unwrap(), vec![]);
+    }
+}
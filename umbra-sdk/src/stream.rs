@@ -0,0 +1,280 @@
+//! Chunked authenticated encryption (the STREAM construction) for blobs too
+//! large to hold in memory at once.
+//!
+//! Plaintext is split into `chunk_size` chunks, each sealed separately with a
+//! nonce derived from the chunk counter. A chunk shorter than `chunk_size`,
+//! possibly empty, is sealed as the last one, so truncating, reordering or
+//! extending the ciphertext fails to decrypt.
+
+use std::io::{self, Read};
+
+use chacha20poly1305::aead::generic_array::GenericArray;
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305};
+use sha3::{Digest, Sha3_256};
+
+use crate::error::UmbraError;
+
+pub(crate) const KEY_LEN: usize = 32;
+// XChaCha20's 24 byte nonce, less the 4 byte counter and last-chunk flag
+pub(crate) const NONCE_LEN: usize = 19;
+const TAG_LEN: usize = 16;
+
+/// Plaintext bytes per chunk, and so roughly the memory a stream needs.
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+// Bounds the buffer a peer's attachment can make us allocate
+pub(crate) const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+fn crypto_error(reason: &'static str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        UmbraError::CryptoError(reason.into()),
+    )
+}
+
+/// Unwraps errors raised by the readers here, so callers see the original
+/// `UmbraError` rather than an io::Error around it.
+pub(crate) fn io_error(e: io::Error) -> UmbraError {
+    if !e.get_ref().is_some_and(|inner| inner.is::<UmbraError>()) {
+        return UmbraError::StorageError(e.into());
+    }
+    match e.into_inner().map(|inner| inner.downcast::<UmbraError>()) {
+        Some(Ok(err)) => *err,
+        _ => UmbraError::StorageError("unreadable stream".into()),
+    }
+}
+
+// Like `read_exact`, but a short read at the end of the stream is not an
+// error. Returns how much of `buf` was filled.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+// Serves `chunk` to `buf`, refilling it through `next` once drained
+fn serve(
+    chunk: &mut Vec<u8>,
+    pos: &mut usize,
+    buf: &mut [u8],
+    mut next: impl FnMut() -> io::Result<Option<Vec<u8>>>,
+) -> io::Result<usize> {
+    while *pos == chunk.len() {
+        match next()? {
+            Some(refill) => {
+                *chunk = refill;
+                *pos = 0;
+            }
+            None => return Ok(0),
+        }
+    }
+    let n = buf.len().min(chunk.len() - *pos);
+    buf[..n].copy_from_slice(&chunk[*pos..*pos + n]);
+    *pos += n;
+    Ok(n)
+}
+
+/// Reads plaintext from `inner` and yields ciphertext.
+pub(crate) struct EncryptingReader<R> {
+    inner: R,
+    encryptor: Option<EncryptorBE32<XChaCha20Poly1305>>,
+    chunk_size: usize,
+    chunk: Vec<u8>,
+    pos: usize,
+    digest: Sha3_256,
+    plaintext_len: u64,
+}
+
+impl<R: Read> EncryptingReader<R> {
+    pub fn new(inner: R, key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], chunk_size: usize) -> Self {
+        let cipher = XChaCha20Poly1305::new(GenericArray::from_slice(key));
+        Self {
+            inner,
+            encryptor: Some(EncryptorBE32::from_aead(
+                cipher,
+                GenericArray::from_slice(nonce),
+            )),
+            chunk_size,
+            chunk: vec![],
+            pos: 0,
+            digest: Sha3_256::new(),
+            plaintext_len: 0,
+        }
+    }
+
+    /// SHA3-256 of the ciphertext and the length of the plaintext, once
+    /// everything has been read.
+    pub fn finish(self) -> (Vec<u8>, u64) {
+        (self.digest.finalize().to_vec(), self.plaintext_len)
+    }
+
+    fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.encryptor.is_none() {
+            return Ok(None);
+        }
+        let mut plaintext = vec![0; self.chunk_size];
+        let n = read_full(&mut self.inner, &mut plaintext)?;
+        plaintext.truncate(n);
+        self.plaintext_len += n as u64;
+
+        let sealed = if n == self.chunk_size {
+            self.encryptor
+                .as_mut()
+                .and_then(|e| e.encrypt_next(plaintext.as_slice()).ok())
+        } else {
+            self.encryptor
+                .take()
+                .and_then(|e| e.encrypt_last(plaintext.as_slice()).ok())
+        };
+        let sealed = sealed.ok_or_else(|| crypto_error("failed to encrypt chunk"))?;
+        self.digest.update(&sealed);
+        Ok(Some(sealed))
+    }
+}
+
+impl<R: Read> Read for EncryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut chunk = std::mem::take(&mut self.chunk);
+        let mut pos = self.pos;
+        let res = serve(&mut chunk, &mut pos, buf, || self.next_chunk());
+        self.chunk = chunk;
+        self.pos = pos;
+        res
+    }
+}
+
+/// Reads ciphertext from `inner` and yields plaintext. Each chunk is
+/// authenticated before any of it is returned; the digest and length are
+/// checked once the stream ends.
+pub(crate) struct DecryptingReader<R> {
+    inner: R,
+    decryptor: Option<DecryptorBE32<XChaCha20Poly1305>>,
+    chunk_size: usize,
+    chunk: Vec<u8>,
+    pos: usize,
+    digest: Sha3_256,
+    expected_digest: Vec<u8>,
+    remaining: u64,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    pub fn new(
+        inner: R,
+        key: &[u8; KEY_LEN],
+        nonce: &[u8; NONCE_LEN],
+        chunk_size: usize,
+        expected_digest: Vec<u8>,
+        expected_len: u64,
+    ) -> Self {
+        let cipher = XChaCha20Poly1305::new(GenericArray::from_slice(key));
+        Self {
+            inner,
+            decryptor: Some(DecryptorBE32::from_aead(
+                cipher,
+                GenericArray::from_slice(nonce),
+            )),
+            chunk_size,
+            chunk: vec![],
+            pos: 0,
+            digest: Sha3_256::new(),
+            expected_digest,
+            remaining: expected_len,
+        }
+    }
+
+    fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.decryptor.is_none() {
+            return Ok(None);
+        }
+        let full = self.chunk_size + TAG_LEN;
+        let mut sealed = vec![0; full];
+        let n = read_full(&mut self.inner, &mut sealed)?;
+        sealed.truncate(n);
+        self.digest.update(&sealed);
+
+        let opened = if n == full {
+            self.decryptor
+                .as_mut()
+                .and_then(|d| d.decrypt_next(sealed.as_slice()).ok())
+        } else {
+            self.decryptor
+                .take()
+                .and_then(|d| d.decrypt_last(sealed.as_slice()).ok())
+        };
+        let plaintext = opened.ok_or_else(|| crypto_error("failed to decrypt attachment"))?;
+
+        self.remaining = self
+            .remaining
+            .checked_sub(plaintext.len() as u64)
+            .ok_or_else(|| crypto_error("attachment length mismatch"))?;
+        if self.decryptor.is_none() {
+            if self.remaining != 0 {
+                return Err(crypto_error("attachment length mismatch"));
+            }
+            let digest = std::mem::take(&mut self.digest).finalize();
+            if digest.as_slice() != self.expected_digest {
+                return Err(crypto_error("attachment digest mismatch"));
+            }
+        }
+        Ok(Some(plaintext))
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut chunk = std::mem::take(&mut self.chunk);
+        let mut pos = self.pos;
+        let res = serve(&mut chunk, &mut pos, buf, || self.next_chunk());
+        self.chunk = chunk;
+        self.pos = pos;
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; KEY_LEN] = [7; KEY_LEN];
+    const NONCE: [u8; NONCE_LEN] = [9; NONCE_LEN];
+
+    fn encrypt(plaintext: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut reader = EncryptingReader::new(plaintext, &KEY, &NONCE, 4);
+        let mut ciphertext = vec![];
+        reader.read_to_end(&mut ciphertext).unwrap();
+        let (digest, len) = reader.finish();
+        assert_eq!(len, plaintext.len() as u64);
+        (ciphertext, digest)
+    }
+
+    fn decrypt(ciphertext: &[u8], digest: Vec<u8>, len: u64) -> io::Result<Vec<u8>> {
+        let mut plaintext = vec![];
+        DecryptingReader::new(ciphertext, &KEY, &NONCE, 4, digest, len)
+            .read_to_end(&mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn round_trip_across_chunk_boundaries() {
+        for plaintext in [&b""[..], b"abc", b"abcd", b"abcdefghij"] {
+            let (ciphertext, digest) = encrypt(plaintext);
+            let len = plaintext.len() as u64;
+            assert_eq!(decrypt(&ciphertext, digest, len).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_streams() {
+        let (ciphertext, digest) = encrypt(b"abcdefghij");
+        // Dropping the last chunk leaves a stream of only full chunks
+        let truncated = &ciphertext[..2 * (4 + TAG_LEN)];
+        assert!(decrypt(truncated, digest, 8).is_err());
+    }
+}
@@ -0,0 +1,530 @@
+//! Readable exports of a conversation's history, for compliance archives and
+//! moving history between apps.
+//!
+//! A `Transcript` is the history with the standard content types resolved
+//! into text. Attachments are described by their metadata only: their keys
+//! are left out, so a transcript never grants access to the files it lists.
+//! `PlainText` and, with the `json` feature, `JsonTranscript` render it;
+//! other formats implement `TranscriptFormat`.
+//!
+//! Going the other way, a `TranscriptSource` reads history exported by
+//! another messenger for `UmbraClient::import_transcript`. Imported messages
+//! keep only their text, and are marked as imported in history.
+
+use std::fmt::Write;
+
+use prost::Message;
+use umbra_content_types::{
+    ChatMessage, CompositeContent, ContentType, ImageMessage, LiveLocation, Location, Poll,
+    PollVote, Profile, RemoteAttachment, TaggedContent, VideoMessage,
+};
+use umbra_types::common_frames::ContentFrame;
+
+use crate::address::Address;
+use crate::client::Blob;
+use crate::codec::CodecRegistry;
+use crate::composite;
+use crate::convos::{ConversationInfo, MessageRecord};
+use crate::error::{FrameKind, UmbraError};
+use crate::utils::{generate_random_string, now_millis};
+
+/// Renders a transcript, e.g. as a document or an archive format.
+pub trait TranscriptFormat {
+    fn render(&self, transcript: &Transcript) -> Result<Blob, UmbraError>;
+}
+
+/// Reads a transcript exported by another messenger, e.g. by parsing its
+/// export format.
+pub trait TranscriptSource {
+    fn read(&self) -> Result<Transcript, UmbraError>;
+}
+
+/// A conversation's history, oldest first. Retracted messages are left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    pub convo_id: String,
+    pub participants: Vec<Address>,
+    // When the transcript was taken (unix ms)
+    pub exported_at: u64,
+    pub entries: Vec<TranscriptEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptEntry {
+    pub message_id: String,
+    pub sender: Address,
+    // The message's `MessageRecord::display_time` (unix ms)
+    pub time: u64,
+    pub reply_to: Option<String>,
+    pub edited: bool,
+    pub content_type: ContentType,
+    // The content as text, or a placeholder naming its type when it has no
+    // readable form
+    pub text: String,
+    pub attachments: Vec<AttachmentMetadata>,
+}
+
+/// What a transcript records of an attachment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachmentMetadata {
+    pub filename: String,
+    pub mime_type: String,
+    // Size of the decrypted file in bytes
+    pub content_length: u64,
+    pub url: String,
+}
+
+impl From<&RemoteAttachment> for AttachmentMetadata {
+    fn from(attachment: &RemoteAttachment) -> Self {
+        Self {
+            filename: attachment.filename.clone(),
+            mime_type: attachment.mime_type.clone(),
+            content_length: attachment.content_length,
+            url: attachment.url.clone(),
+        }
+    }
+}
+
+impl Transcript {
+    pub(crate) fn new(
+        info: &ConversationInfo,
+        messages: &[MessageRecord],
+        codecs: &CodecRegistry,
+    ) -> Self {
+        let mut messages: Vec<&MessageRecord> = messages.iter().filter(|m| !m.retracted).collect();
+        messages.sort_by_key(|m| (m.display_time(), m.lamport_timestamp));
+        Self {
+            convo_id: info.convo_id.to_string(),
+            participants: info.participants.clone(),
+            exported_at: now_millis(),
+            entries: messages
+                .into_iter()
+                .map(|m| TranscriptEntry::new(m, codecs))
+                .collect(),
+        }
+    }
+}
+
+impl Transcript {
+    // Imported entries become chat messages under their original ids, or
+    // fresh ones for sources without ids
+    pub(crate) fn into_records(self) -> Vec<MessageRecord> {
+        let convo_id = self.convo_id;
+        self.entries
+            .into_iter()
+            .map(|entry| MessageRecord {
+                message_id: match entry.message_id.as_str() {
+                    "" => generate_random_string(16),
+                    _ => entry.message_id,
+                },
+                convo_id: convo_id.clone(),
+                sender: entry.sender,
+                lamport_timestamp: 0,
+                timestamp: entry.time,
+                sent_at: Some(entry.time),
+                expires_at: None,
+                content: ContentFrame {
+                    domain: ChatMessage::DOMAIN,
+                    tag: ChatMessage::TAG,
+                    bytes: ChatMessage::new(entry.text).encode_to_vec(),
+                },
+                reply_to: entry.reply_to,
+                status: None,
+                edit_history: vec![],
+                retracted: false,
+                imported: true,
+            })
+            .collect()
+    }
+}
+
+impl TranscriptEntry {
+    fn new(record: &MessageRecord, codecs: &CodecRegistry) -> Self {
+        let mut lines = vec![];
+        let mut attachments = vec![];
+        resolve(codecs, &record.content, &mut lines, &mut attachments);
+        Self {
+            message_id: record.message_id.clone(),
+            sender: record.sender.clone(),
+            time: record.display_time(),
+            reply_to: record.reply_to.clone(),
+            edited: !record.edit_history.is_empty(),
+            content_type: ContentType::new(record.content.domain, record.content.tag),
+            text: lines.join("\n"),
+            attachments,
+        }
+    }
+}
+
+// Appends the readable form of `frame` to `lines`, and the attachments it
+// carries to `attachments`
+fn resolve(
+    codecs: &CodecRegistry,
+    frame: &ContentFrame,
+    lines: &mut Vec<String>,
+    attachments: &mut Vec<AttachmentMetadata>,
+) {
+    let content_type = ContentType::new(frame.domain, frame.tag);
+    let line = match content_type {
+        ChatMessage::CONTENT_TYPE => decoded(codecs, frame, |m: ChatMessage| m.text),
+        RemoteAttachment::CONTENT_TYPE => decoded(codecs, frame, |a: RemoteAttachment| {
+            attachments.push(AttachmentMetadata::from(&a));
+            format!("[file: {}]", a.filename)
+        }),
+        ImageMessage::CONTENT_TYPE => decoded(codecs, frame, |image: ImageMessage| {
+            attachments.extend(image.attachment.as_ref().map(AttachmentMetadata::from));
+            captioned(
+                format!("[image {}x{}]", image.width, image.height),
+                &image.caption,
+            )
+        }),
+        VideoMessage::CONTENT_TYPE => decoded(codecs, frame, |video: VideoMessage| {
+            attachments.extend(video.attachment.as_ref().map(AttachmentMetadata::from));
+            captioned(
+                format!("[video {}s]", video.duration_ms / 1000),
+                &video.caption,
+            )
+        }),
+        Profile::CONTENT_TYPE => decoded(codecs, frame, |profile: Profile| {
+            format!("[profile: {}]", profile.display_name)
+        }),
+        Location::CONTENT_TYPE => decoded(codecs, frame, |l: Location| {
+            format!("[location: {}]", location(&l))
+        }),
+        LiveLocation::CONTENT_TYPE => decoded(codecs, frame, |live: LiveLocation| {
+            match (live.stopped, &live.location) {
+                (false, Some(l)) => format!("[live location: {}]", location(l)),
+                _ => "[stopped sharing location]".into(),
+            }
+        }),
+        Poll::CONTENT_TYPE => decoded(codecs, frame, |poll: Poll| {
+            format!("[poll] {} ({})", poll.question, poll.options.join(" / "))
+        }),
+        PollVote::CONTENT_TYPE => decoded(codecs, frame, |vote: PollVote| {
+            format!("[vote on {}: {:?}]", vote.poll_id, vote.options)
+        }),
+        CompositeContent::CONTENT_TYPE => match codecs.decode::<CompositeContent>(&frame.bytes) {
+            Ok(content) => {
+                for part in composite::parts(content) {
+                    resolve(codecs, &part, lines, attachments);
+                }
+                return;
+            }
+            Err(_) => format!("[undecodable {content_type}]"),
+        },
+        _ => format!("[content {content_type}]"),
+    };
+    lines.push(line);
+}
+
+fn decoded<M: TaggedContent + 'static>(
+    codecs: &CodecRegistry,
+    frame: &ContentFrame,
+    render: impl FnOnce(M) -> String,
+) -> String {
+    match codecs.decode::<M>(&frame.bytes) {
+        Ok(content) => render(content),
+        Err(_) => format!("[undecodable {}]", M::CONTENT_TYPE),
+    }
+}
+
+fn captioned(placeholder: String, caption: &str) -> String {
+    match caption {
+        "" => placeholder,
+        caption => format!("{placeholder} {caption}"),
+    }
+}
+
+fn location(location: &Location) -> String {
+    let coordinates = format!("{}, {}", location.latitude, location.longitude);
+    match location.label.as_str() {
+        "" => coordinates,
+        label => format!("{coordinates} ({label})"),
+    }
+}
+
+/// One line per message: time in UTC, sender and text, with attachments
+/// listed below it.
+pub struct PlainText;
+
+impl TranscriptFormat for PlainText {
+    fn render(&self, transcript: &Transcript) -> Result<Blob, UmbraError> {
+        let mut out = format!(
+            "Conversation {}\nParticipants: {}\nExported: {}\n\n",
+            transcript.convo_id,
+            transcript
+                .participants
+                .iter()
+                .map(Address::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+            utc(transcript.exported_at),
+        );
+        for entry in &transcript.entries {
+            let _ = write!(out, "[{}] {}: ", utc(entry.time), entry.sender);
+            if let Some(parent) = &entry.reply_to {
+                let _ = write!(out, "(reply to {parent}) ");
+            }
+            out.push_str(&entry.text.replace('\n', "\n    "));
+            if entry.edited {
+                out.push_str(" (edited)");
+            }
+            out.push('\n');
+            for a in &entry.attachments {
+                let _ = writeln!(
+                    out,
+                    "    attachment: {} ({}, {} bytes) {}",
+                    a.filename, a.mime_type, a.content_length, a.url
+                );
+            }
+        }
+        Ok(out.into_bytes())
+    }
+}
+
+// unix ms as "YYYY-MM-DD HH:MM:SS", without pulling in a date library
+fn utc(ms: u64) -> String {
+    let secs = ms / 1000;
+    let (days, secs) = ((secs / 86_400) as i64, secs % 86_400);
+    // Howard Hinnant's days_from_civil, inverted
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// The transcript as a JSON document, with times as unix ms.
+#[cfg(feature = "json")]
+pub struct JsonTranscript;
+
+#[cfg(feature = "json")]
+impl TranscriptFormat for JsonTranscript {
+    fn render(&self, transcript: &Transcript) -> Result<Blob, UmbraError> {
+        use serde_json::json;
+
+        let entries: Vec<serde_json::Value> = transcript
+            .entries
+            .iter()
+            .map(|entry| {
+                let attachments: Vec<serde_json::Value> = entry
+                    .attachments
+                    .iter()
+                    .map(|a| {
+                        json!({
+                            "filename": a.filename,
+                            "mime_type": a.mime_type,
+                            "content_length": a.content_length,
+                            "url": a.url,
+                        })
+                    })
+                    .collect();
+                json!({
+                    "message_id": entry.message_id,
+                    "sender": entry.sender.to_string(),
+                    "time": entry.time,
+                    "reply_to": entry.reply_to,
+                    "edited": entry.edited,
+                    "content_type": entry.content_type.to_string(),
+                    "text": entry.text,
+                    "attachments": attachments,
+                })
+            })
+            .collect();
+        let participants: Vec<String> = transcript
+            .participants
+            .iter()
+            .map(Address::to_string)
+            .collect();
+        serde_json::to_vec_pretty(&json!({
+            "convo_id": transcript.convo_id,
+            "participants": participants,
+            "exported_at": transcript.exported_at,
+            "entries": entries,
+        }))
+        .map_err(UmbraError::encoding(FrameKind::Transcript))
+    }
+}
+
+/// Reads the generic JSON schema, the document `JsonTranscript` writes:
+///
+/// ```json
+/// {
+///   "convo_id": "...",
+///   "participants": ["..."],
+///   "entries": [
+///     { "message_id": "...", "sender": "...", "time": 1700000000000,
+///       "text": "...", "reply_to": "..." }
+///   ]
+/// }
+/// ```
+///
+/// `convo_id`, and each entry's `sender`, `time` (unix ms) and `text`, are
+/// required. Other fields may be left out; unknown fields are ignored.
+#[cfg(feature = "json")]
+pub struct JsonTranscriptSource {
+    bytes: Blob,
+}
+
+#[cfg(feature = "json")]
+impl JsonTranscriptSource {
+    pub fn new(bytes: Blob) -> Self {
+        Self { bytes }
+    }
+}
+
+#[cfg(feature = "json")]
+impl TranscriptSource for JsonTranscriptSource {
+    fn read(&self) -> Result<Transcript, UmbraError> {
+        use serde_json::Value;
+
+        fn malformed(reason: &'static str) -> UmbraError {
+            UmbraError::malformed(FrameKind::Transcript, reason)
+        }
+        fn string<'a>(value: &'a Value, field: &str) -> Option<&'a str> {
+            value.get(field).and_then(Value::as_str)
+        }
+        fn address(addr: &str) -> Result<Address, UmbraError> {
+            addr.parse()
+                .map_err(UmbraError::decoding(FrameKind::Transcript))
+        }
+
+        let doc: Value = serde_json::from_slice(&self.bytes)
+            .map_err(UmbraError::decoding(FrameKind::Transcript))?;
+        let convo_id = string(&doc, "convo_id").ok_or_else(|| malformed("missing convo_id"))?;
+        let participants = match doc.get("participants").and_then(Value::as_array) {
+            Some(participants) => participants
+                .iter()
+                .filter_map(Value::as_str)
+                .map(address)
+                .collect::<Result<_, _>>()?,
+            None => vec![],
+        };
+        let entries = doc
+            .get("entries")
+            .and_then(Value::as_array)
+            .ok_or_else(|| malformed("missing entries"))?;
+        let entries = entries
+            .iter()
+            .map(|entry| {
+                let sender = string(entry, "sender").ok_or_else(|| malformed("missing sender"))?;
+                let time = entry.get("time").and_then(Value::as_u64);
+                let text = string(entry, "text").ok_or_else(|| malformed("missing text"))?;
+                Ok(TranscriptEntry {
+                    message_id: string(entry, "message_id").unwrap_or_default().into(),
+                    sender: address(sender)?,
+                    time: time.ok_or_else(|| malformed("missing time"))?,
+                    reply_to: string(entry, "reply_to").map(Into::into),
+                    edited: false,
+                    content_type: ChatMessage::CONTENT_TYPE,
+                    text: text.into(),
+                    attachments: vec![],
+                })
+            })
+            .collect::<Result<_, UmbraError>>()?;
+        Ok(Transcript {
+            convo_id: convo_id.into(),
+            participants,
+            exported_at: doc
+                .get("exported_at")
+                .and_then(Value::as_u64)
+                .unwrap_or_default(),
+            entries,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use umbra_content_types::ChatMessage;
+
+    use super::*;
+    use crate::composite::CompositeBuilder;
+
+    #[test]
+    fn content_is_resolved_into_text_and_attachments() {
+        let codecs = CodecRegistry::default();
+        let attachment = RemoteAttachment {
+            url: "memory://a".into(),
+            secret: vec![7; 32],
+            filename: "cat.png".into(),
+            mime_type: "image/png".into(),
+            content_length: 42,
+            ..Default::default()
+        };
+        let image = ImageMessage {
+            attachment: Some(attachment),
+            width: 4,
+            height: 3,
+            caption: "look".into(),
+            ..Default::default()
+        };
+        let content = CompositeBuilder::new(&codecs)
+            .part(&ChatMessage::new("hi".into()))
+            .unwrap()
+            .part(&image)
+            .unwrap()
+            .build();
+        let frame = ContentFrame {
+            domain: CompositeContent::DOMAIN,
+            tag: CompositeContent::TAG,
+            bytes: codecs.encode(&content).unwrap(),
+        };
+
+        let (mut lines, mut attachments) = (vec![], vec![]);
+        resolve(&codecs, &frame, &mut lines, &mut attachments);
+        assert_eq!(lines, vec!["hi", "[image 4x3] look"]);
+        assert_eq!(attachments[0].filename, "cat.png");
+        assert_eq!(attachments[0].content_length, 42);
+
+        assert_eq!(utc(0), "1970-01-01 00:00:00");
+        assert_eq!(utc(1_709_210_096_000), "2024-02-29 12:34:56");
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_transcripts_import_as_chat_messages() {
+        let entry = TranscriptEntry {
+            message_id: "m1".into(),
+            sender: "amal".parse().unwrap(),
+            time: 1000,
+            reply_to: None,
+            edited: false,
+            content_type: ChatMessage::CONTENT_TYPE,
+            text: "hello".into(),
+            attachments: vec![],
+        };
+        let transcript = Transcript {
+            convo_id: "c".into(),
+            participants: vec!["amal".parse().unwrap()],
+            exported_at: 2000,
+            entries: vec![entry],
+        };
+        let bytes = JsonTranscript.render(&transcript).unwrap();
+        let read = JsonTranscriptSource::new(bytes).read().unwrap();
+        assert_eq!(read, transcript);
+
+        let records = read.into_records();
+        assert!(records[0].imported);
+        assert_eq!(records[0].sent_at, Some(1000));
+        let text = ChatMessage::decode(records[0].content.bytes.as_slice()).unwrap();
+        assert_eq!(text.text, "hello");
+
+        let missing_sender = br#"{"convo_id": "c", "entries": [{"time": 1, "text": "x"}]}"#;
+        assert!(
+            JsonTranscriptSource::new(missing_sender.to_vec())
+                .read()
+                .is_err()
+        );
+    }
+}
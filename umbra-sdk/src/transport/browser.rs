@@ -0,0 +1,186 @@
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use send_wrapper::SendWrapper;
+use tracing::{debug, warn};
+use wasm_bindgen::JsCast;
+use wasm_bindgen::closure::Closure;
+use web_sys::{BinaryType, CloseEvent, MessageEvent, WebSocket};
+
+use super::relay::RelayFrame;
+use super::{ConnectionState, envelope_topic};
+use crate::error::TransportOp;
+use crate::{Blob, DeliveryService, UmbraError};
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<Option<ConnectionState>>,
+    received: Mutex<VecDeque<Blob>>,
+    subscriptions: Mutex<HashSet<String>>,
+}
+
+impl Shared {
+    fn set_state(&self, state: ConnectionState) {
+        debug!("Relay connection: {:?}", state);
+        *self.state.lock().unwrap() = Some(state);
+    }
+}
+
+/// DeliveryService connected to an Umbra relay through the browser's
+/// WebSocket, for clients compiled to wasm32.
+///
+/// The browser owns the connection and delivers frames through callbacks,
+/// so there is no worker: received envelopes are queued until
+/// `UmbraClient::poll` drains them. Subscriptions are sent once the socket
+/// opens. A lost connection is not re-established until `reconnect`.
+pub struct BrowserDeliveryService {
+    url: String,
+    shared: Arc<Shared>,
+    // JS objects never leave the browser's single thread
+    socket: SendWrapper<RefCell<Option<WebSocket>>>,
+}
+
+impl BrowserDeliveryService {
+    /// Connect to the relay at `url`, e.g. `wss://relay.example.org`.
+    /// Failures are reported as a `Disconnected` state rather than an error.
+    pub fn connect(url: &str) -> Self {
+        let service = Self {
+            url: url.to_string(),
+            shared: Arc::new(Shared::default()),
+            socket: SendWrapper::new(RefCell::new(None)),
+        };
+        service.reconnect();
+        service
+    }
+
+    /// Replace the connection with a new one, restoring subscriptions once
+    /// it opens.
+    pub fn reconnect(&self) {
+        if let Some(old) = self.socket.borrow_mut().take() {
+            // Its late close event must not mark the new socket disconnected
+            old.set_onclose(None);
+            old.set_onmessage(None);
+            let _ = old.close();
+        }
+        match open(&self.url, &self.shared) {
+            Ok(socket) => *self.socket.borrow_mut() = Some(socket),
+            Err(reason) => self
+                .shared
+                .set_state(ConnectionState::Disconnected { reason }),
+        }
+    }
+
+    fn send_frame(&self, op: TransportOp, frame: RelayFrame) -> Result<(), UmbraError> {
+        let socket = self.socket.borrow();
+        let socket = socket.as_ref().ok_or(UmbraError::Disconnected)?;
+        socket
+            .send_with_u8_array(&frame.encode())
+            .map_err(|e| UmbraError::transport(op, format!("{e:?}")))
+    }
+}
+
+// Opens a socket whose callbacks feed `shared`
+fn open(url: &str, shared: &Arc<Shared>) -> Result<WebSocket, String> {
+    let socket = WebSocket::new(url).map_err(|e| format!("{e:?}"))?;
+    socket.set_binary_type(BinaryType::Arraybuffer);
+
+    let on_open = {
+        let (shared, socket) = (shared.clone(), socket.clone());
+        Closure::<dyn FnMut()>::new(move || {
+            shared.set_state(ConnectionState::Connected);
+            for topic in shared.subscriptions.lock().unwrap().iter() {
+                let frame = RelayFrame::Subscribe {
+                    topic: topic.clone(),
+                };
+                if let Err(e) = socket.send_with_u8_array(&frame.encode()) {
+                    warn!("Failed to restore subscription: {:?}", e);
+                }
+            }
+        })
+    };
+    let on_message = {
+        let shared = shared.clone();
+        Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            let Ok(buffer) = event.data().dyn_into::<js_sys::ArrayBuffer>() else {
+                warn!("Ignoring non-binary relay message");
+                return;
+            };
+            match RelayFrame::decode(&js_sys::Uint8Array::new(&buffer).to_vec()) {
+                Ok(RelayFrame::Publish { payload, .. }) => {
+                    shared.received.lock().unwrap().push_back(payload)
+                }
+                Ok(frame) => warn!("Unexpected frame from relay: {:?}", frame),
+                Err(e) => warn!("Dropping malformed relay frame: {}", e),
+            }
+        })
+    };
+    let on_close = {
+        let shared = shared.clone();
+        Closure::<dyn FnMut(CloseEvent)>::new(move |event: CloseEvent| {
+            shared.set_state(ConnectionState::Disconnected {
+                reason: format!("closed with code {}: {}", event.code(), event.reason()),
+            });
+        })
+    };
+
+    socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+    socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+    // The callbacks live as long as the socket, which JS keeps alive
+    on_open.forget();
+    on_message.forget();
+    on_close.forget();
+    Ok(socket)
+}
+
+impl DeliveryService for BrowserDeliveryService {
+    fn send(&self, message: Blob) -> Result<(), UmbraError> {
+        if !self.is_connected() {
+            return Err(UmbraError::Disconnected);
+        }
+        let frame = RelayFrame::Publish {
+            topic: envelope_topic(&message)?,
+            payload: message,
+        };
+        self.send_frame(TransportOp::Publish, frame)
+    }
+
+    fn recv(&self) -> Result<Option<Blob>, UmbraError> {
+        Ok(self.shared.received.lock().unwrap().pop_front())
+    }
+
+    fn is_connected(&self) -> bool {
+        matches!(
+            *self.shared.state.lock().unwrap(),
+            Some(ConnectionState::Connected)
+        )
+    }
+
+    fn subscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        self.shared
+            .subscriptions
+            .lock()
+            .unwrap()
+            .insert(topic.to_string());
+        // Otherwise sent when the socket opens
+        if !self.is_connected() {
+            return Ok(());
+        }
+        let frame = RelayFrame::Subscribe {
+            topic: topic.to_string(),
+        };
+        self.send_frame(TransportOp::Subscribe, frame)
+    }
+
+    fn unsubscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        self.shared.subscriptions.lock().unwrap().remove(topic);
+        if !self.is_connected() {
+            return Ok(());
+        }
+        let frame = RelayFrame::Unsubscribe {
+            topic: topic.to_string(),
+        };
+        self.send_frame(TransportOp::Unsubscribe, frame)
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::Deserialize;
+use tracing::warn;
+
+use super::envelope_topic;
+use crate::error::TransportOp;
+use crate::{Blob, DeliveryService, UmbraError};
+
+// How long the server may hold a poll open waiting for messages
+const LONG_POLL_SECS: u64 = 20;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+// Idle wait when there is nothing subscribed yet
+const IDLE_DELAY: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Deserialize)]
+struct PollResponse {
+    cursor: u64,
+    messages: Vec<String>,
+}
+
+#[derive(Default)]
+struct Shared {
+    subscriptions: Mutex<HashSet<String>>,
+    // Up to where the client has taken messages, not where the poller is
+    cursor: AtomicU64,
+    connected: AtomicBool,
+}
+
+// Each poll's messages are followed by the cursor past them
+enum Polled {
+    Message(Blob),
+    Cursor(u64),
+}
+
+/// DeliveryService over plain HTTP for networks which block raw sockets.
+///
+/// Messages are published with `POST {base}/v1/publish?topic=..` and
+/// received by long-polling `GET {base}/v1/messages?topic=..&cursor=..`,
+/// which answers `{"cursor": u64, "messages": [base64]}`. Persist `cursor()`
+/// and pass it to `with_cursor` to resume without gaps after a restart. The
+/// cursor only moves past a poll's messages once the client has taken all of
+/// them and asked for more, so some may be received again after a restart.
+pub struct HttpDeliveryService {
+    base_url: String,
+    agent: ureq::Agent,
+    shared: Arc<Shared>,
+    received: Mutex<Receiver<Polled>>,
+}
+
+impl HttpDeliveryService {
+    pub fn new(base_url: &str) -> Self {
+        Self::with_cursor(base_url, 0)
+    }
+
+    /// Resume receiving after `cursor`, as previously returned by `cursor()`.
+    pub fn with_cursor(base_url: &str, cursor: u64) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        let agent = ureq::AgentBuilder::new()
+            .timeout_read(Duration::from_secs(LONG_POLL_SECS + 10))
+            .build();
+        let shared = Arc::new(Shared {
+            cursor: AtomicU64::new(cursor),
+            connected: AtomicBool::new(true),
+            ..Default::default()
+        });
+        let (tx, rx) = mpsc::channel();
+
+        let poller = Poller {
+            url: format!("{}/v1/messages", base_url),
+            agent: agent.clone(),
+            shared: shared.clone(),
+            cursor,
+            received: tx,
+        };
+        thread::spawn(move || poller.run());
+
+        Self {
+            base_url,
+            agent,
+            shared,
+            received: Mutex::new(rx),
+        }
+    }
+
+    /// Position in the server's message log up to which messages have been
+    /// taken by the client.
+    pub fn cursor(&self) -> u64 {
+        self.shared.cursor.load(Ordering::Relaxed)
+    }
+}
+
+impl DeliveryService for HttpDeliveryService {
+    fn send(&self, message: Blob) -> Result<(), UmbraError> {
+        let res = self
+            .agent
+            .post(&format!("{}/v1/publish", self.base_url))
+            .query("topic", &envelope_topic(&message)?)
+            .set("Content-Type", "application/octet-stream")
+            .send_bytes(&message);
+        self.shared.connected.store(
+            !matches!(res, Err(ureq::Error::Transport(_))),
+            Ordering::Relaxed,
+        );
+        res.map(|_| ())
+            .map_err(|e| UmbraError::transport(TransportOp::Publish, e))
+    }
+
+    fn recv(&self) -> Result<Option<Blob>, UmbraError> {
+        let received = self.received.lock().unwrap();
+        loop {
+            match received.try_recv() {
+                Ok(Polled::Message(blob)) => return Ok(Some(blob)),
+                // The client is back for more, so it is done with the
+                // messages before the cursor
+                Ok(Polled::Cursor(cursor)) => self.shared.cursor.store(cursor, Ordering::Relaxed),
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => {
+                    return Err(UmbraError::transport(
+                        TransportOp::Receive,
+                        "HTTP poller stopped",
+                    ));
+                }
+            }
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::Relaxed)
+    }
+
+    // Subscriptions are client side; they take effect from the next poll
+    fn subscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        self.shared
+            .subscriptions
+            .lock()
+            .unwrap()
+            .insert(topic.to_string());
+        Ok(())
+    }
+
+    fn unsubscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        self.shared.subscriptions.lock().unwrap().remove(topic);
+        Ok(())
+    }
+}
+
+struct Poller {
+    url: String,
+    agent: ureq::Agent,
+    shared: Arc<Shared>,
+    // Where the next poll starts
+    cursor: u64,
+    received: Sender<Polled>,
+}
+
+impl Poller {
+    fn run(mut self) {
+        loop {
+            let topics: Vec<String> = self
+                .shared
+                .subscriptions
+                .lock()
+                .unwrap()
+                .iter()
+                .cloned()
+                .collect();
+            if topics.is_empty() {
+                thread::sleep(IDLE_DELAY);
+                continue;
+            }
+
+            match self.poll(&topics) {
+                Ok((messages, cursor)) => {
+                    self.shared.connected.store(true, Ordering::Relaxed);
+                    self.cursor = cursor;
+                    let polled = messages.into_iter().map(Polled::Message);
+                    for item in polled.chain([Polled::Cursor(cursor)]) {
+                        if self.received.send(item).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("HTTP poll failed: {}", e);
+                    self.shared.connected.store(false, Ordering::Relaxed);
+                    thread::sleep(RETRY_DELAY);
+                }
+            }
+        }
+    }
+
+    // The messages after the cursor, and the cursor past them
+    fn poll(&self, topics: &[String]) -> Result<(Vec<Blob>, u64), UmbraError> {
+        let mut req = self
+            .agent
+            .get(&self.url)
+            .query("cursor", &self.cursor.to_string())
+            .query("timeout", &LONG_POLL_SECS.to_string());
+        for topic in topics {
+            req = req.query("topic", topic);
+        }
+
+        let res: PollResponse = req
+            .call()
+            .map_err(|e| UmbraError::transport(TransportOp::Receive, e))?
+            .into_json()
+            .map_err(|e| UmbraError::transport(TransportOp::Receive, e))?;
+
+        let messages = res
+            .messages
+            .iter()
+            .filter_map(|m| match STANDARD.decode(m) {
+                Ok(blob) => Some(blob),
+                Err(e) => {
+                    warn!("Dropping undecodable message: {}", e);
+                    None
+                }
+            })
+            .collect();
+        Ok((messages, res.cursor))
+    }
+}
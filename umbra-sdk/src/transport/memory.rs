@@ -0,0 +1,163 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+
+use super::envelope_topic;
+use crate::error::TransportOp;
+use crate::{Blob, DeliveryService, UmbraError};
+
+struct Subscriber {
+    topics: HashSet<String>,
+    tx: Sender<Blob>,
+}
+
+#[derive(Default)]
+struct BrokerState {
+    next_id: usize,
+    subscribers: HashMap<usize, Subscriber>,
+}
+
+/// In-process pub/sub broker for tests and demos. Envelopes are delivered to
+/// every connected service subscribed to their topic, the sender included.
+#[derive(Clone, Default)]
+pub struct LocalBroker {
+    state: Arc<Mutex<BrokerState>>,
+}
+
+impl LocalBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new DeliveryService attached to this broker.
+    pub fn connect(&self) -> InMemoryDeliveryService {
+        let (tx, rx) = mpsc::channel();
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.subscribers.insert(
+            id,
+            Subscriber {
+                topics: HashSet::new(),
+                tx,
+            },
+        );
+
+        InMemoryDeliveryService {
+            id,
+            broker: self.clone(),
+            received: Mutex::new(rx),
+        }
+    }
+
+    fn publish(&self, topic: &str, message: &Blob) {
+        self.state
+            .lock()
+            .unwrap()
+            .subscribers
+            .values()
+            .filter(|s| s.topics.contains(topic))
+            .for_each(|s| {
+                // Disconnected receivers are removed when their service drops
+                let _ = s.tx.send(message.clone());
+            });
+    }
+
+    fn update_topics(&self, id: usize, f: impl FnOnce(&mut HashSet<String>)) {
+        if let Some(subscriber) = self.state.lock().unwrap().subscribers.get_mut(&id) {
+            f(&mut subscriber.topics);
+        }
+    }
+}
+
+/// DeliveryService connected to a `LocalBroker`.
+pub struct InMemoryDeliveryService {
+    id: usize,
+    broker: LocalBroker,
+    received: Mutex<Receiver<Blob>>,
+}
+
+impl DeliveryService for InMemoryDeliveryService {
+    fn send(&self, message: Blob) -> Result<(), UmbraError> {
+        self.broker.publish(&envelope_topic(&message)?, &message);
+        Ok(())
+    }
+
+    fn recv(&self) -> Result<Option<Blob>, UmbraError> {
+        match self.received.lock().unwrap().try_recv() {
+            Ok(blob) => Ok(Some(blob)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(UmbraError::transport(
+                TransportOp::Receive,
+                "disconnected from local broker",
+            )),
+        }
+    }
+
+    fn subscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        self.broker.update_topics(self.id, |topics| {
+            topics.insert(topic.to_string());
+        });
+        Ok(())
+    }
+
+    fn unsubscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        self.broker.update_topics(self.id, |topics| {
+            topics.remove(topic);
+        });
+        Ok(())
+    }
+}
+
+impl Drop for InMemoryDeliveryService {
+    fn drop(&mut self) {
+        self.broker
+            .state
+            .lock()
+            .unwrap()
+            .subscribers
+            .remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+    use umbra_types::base::UmbraEnvelopeV1;
+
+    use super::*;
+
+    fn envelope(topic: &str) -> Blob {
+        UmbraEnvelopeV1 {
+            conversation_hint: topic.to_string(),
+            salt: 0,
+            payload: vec![1, 2, 3],
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn routes_by_topic() {
+        let broker = LocalBroker::new();
+        let a = broker.connect();
+        let b = broker.connect();
+        a.subscribe("/inbox/a").unwrap();
+        b.subscribe("/inbox/b").unwrap();
+
+        a.send(envelope("/inbox/b")).unwrap();
+
+        assert!(a.recv().unwrap().is_none());
+        assert_eq!(b.recv().unwrap(), Some(envelope("/inbox/b")));
+    }
+
+    #[test]
+    fn unsubscribed_topics_are_not_delivered() {
+        let broker = LocalBroker::new();
+        let a = broker.connect();
+        a.subscribe("/topic").unwrap();
+        a.unsubscribe("/topic").unwrap();
+
+        a.send(envelope("/topic")).unwrap();
+        assert!(a.recv().unwrap().is_none());
+    }
+}
@@ -0,0 +1,61 @@
+//! DeliveryService implementations for common networks, each behind its own
+//! feature flag.
+
+#[cfg(all(feature = "browser", target_arch = "wasm32"))]
+mod browser;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "test-utils")]
+mod memory;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod relay;
+#[cfg(feature = "test-utils")]
+mod simulated;
+#[cfg(feature = "waku")]
+mod waku;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+#[cfg(all(feature = "browser", target_arch = "wasm32"))]
+pub use browser::BrowserDeliveryService;
+#[cfg(feature = "http")]
+pub use http::HttpDeliveryService;
+#[cfg(feature = "test-utils")]
+pub use memory::{InMemoryDeliveryService, LocalBroker};
+#[cfg(feature = "mqtt")]
+pub use mqtt::{MqttConfig, MqttDeliveryService};
+pub use relay::RelayFrame;
+#[cfg(feature = "mqtt")]
+pub use rumqttc::QoS as MqttQos;
+#[cfg(feature = "test-utils")]
+pub use simulated::{Latency, NetworkConditions, SimulatedDeliveryService};
+#[cfg(feature = "waku")]
+pub use waku::{WakuDeliveryService, WakuMode};
+#[cfg(feature = "websocket")]
+pub use websocket::WebSocketDeliveryService;
+
+use prost::Message;
+use umbra_types::base::UmbraEnvelopeV1;
+
+use crate::UmbraError;
+use crate::error::FrameKind;
+
+/// Health of a transport's connection to the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// Connected, but keepalives are going unanswered.
+    Degraded,
+    Disconnected {
+        reason: String,
+    },
+}
+
+// Envelopes are published on the topic named by their conversation hint
+#[allow(dead_code)]
+pub(crate) fn envelope_topic(blob: &[u8]) -> Result<String, UmbraError> {
+    let envelope =
+        UmbraEnvelopeV1::decode(blob).map_err(UmbraError::decoding(FrameKind::Envelope))?;
+    Ok(envelope.conversation_hint)
+}
@@ -0,0 +1,186 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+use tracing::{debug, warn};
+
+use super::{ConnectionState, envelope_topic};
+use crate::error::TransportOp;
+use crate::utils::generate_random_string;
+use crate::{Blob, DeliveryService, UmbraError, crypto};
+
+// Requests buffered between the client handle and the event loop
+const CHANNEL_CAPACITY: usize = 64;
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Connection settings for `MqttDeliveryService`.
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    /// Must be stable across restarts for the broker to resume the session.
+    pub client_id: String,
+    pub qos: QoS,
+    pub keep_alive: Duration,
+    /// Prepended to every MQTT topic, e.g. to share a broker between apps.
+    pub topic_prefix: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".into(),
+            port: 1883,
+            client_id: format!("umbra-{}", generate_random_string(8)),
+            qos: QoS::AtLeastOnce,
+            keep_alive: Duration::from_secs(30),
+            topic_prefix: "umbra/v1".into(),
+        }
+    }
+}
+
+/// DeliveryService backed by an MQTT broker.
+///
+/// Sessions are persistent, so the broker keeps subscriptions and queues
+/// messages while the client is offline. The connection is re-established
+/// automatically and topics are resubscribed if the broker lost the session.
+pub struct MqttDeliveryService {
+    client: Client,
+    qos: QoS,
+    topic_prefix: String,
+    subscriptions: Arc<Mutex<HashSet<String>>>,
+    received: Mutex<Receiver<Blob>>,
+    connected: Arc<AtomicBool>,
+    state_listeners: Arc<Mutex<Vec<Sender<ConnectionState>>>>,
+}
+
+fn notify(listeners: &Mutex<Vec<Sender<ConnectionState>>>, state: ConnectionState) {
+    listeners
+        .lock()
+        .unwrap()
+        .retain(|tx| tx.send(state.clone()).is_ok());
+}
+
+impl MqttDeliveryService {
+    pub fn new(config: MqttConfig) -> Self {
+        let mut options = MqttOptions::new(config.client_id, config.host, config.port);
+        options.set_keep_alive(config.keep_alive);
+        options.set_clean_session(false);
+
+        let (client, mut connection) = Client::new(options, CHANNEL_CAPACITY);
+        let (tx, rx) = mpsc::channel();
+        let subscriptions = Arc::new(Mutex::new(HashSet::new()));
+        let connected = Arc::new(AtomicBool::new(false));
+        let state_listeners = Arc::new(Mutex::new(Vec::new()));
+
+        let event_client = client.clone();
+        let event_subscriptions = subscriptions.clone();
+        let event_connected = connected.clone();
+        let event_listeners = state_listeners.clone();
+        let qos = config.qos;
+        thread::spawn(move || {
+            // Iterating after an error reconnects
+            for notification in connection.iter() {
+                match notification {
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        if tx.send(publish.payload.to_vec()).is_err() {
+                            debug!("MQTT receiver dropped, stopping event loop");
+                            return;
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::ConnAck(ack))) => {
+                        debug!("MQTT connected, session present: {}", ack.session_present);
+                        event_connected.store(true, Ordering::Relaxed);
+                        notify(&event_listeners, ConnectionState::Connected);
+                        if !ack.session_present {
+                            for topic in event_subscriptions.lock().unwrap().iter() {
+                                if let Err(e) = event_client.try_subscribe(topic, qos) {
+                                    warn!("Failed to resubscribe to {}: {}", topic, e);
+                                }
+                            }
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("MQTT connection error: {}", e);
+                        if event_connected.swap(false, Ordering::Relaxed) {
+                            notify(
+                                &event_listeners,
+                                ConnectionState::Disconnected {
+                                    reason: e.to_string(),
+                                },
+                            );
+                        }
+                        thread::sleep(RECONNECT_DELAY);
+                    }
+                }
+            }
+        });
+
+        Self {
+            client,
+            qos: config.qos,
+            topic_prefix: config.topic_prefix,
+            subscriptions,
+            received: Mutex::new(rx),
+            connected,
+            state_listeners,
+        }
+    }
+
+    // Umbra topics contain characters MQTT reserves, so they are hashed
+    fn mqtt_topic(&self, topic: &str) -> String {
+        format!("{}/{}", self.topic_prefix, crypto::hash_to_string(topic))
+    }
+}
+
+impl DeliveryService for MqttDeliveryService {
+    fn send(&self, message: Blob) -> Result<(), UmbraError> {
+        let topic = self.mqtt_topic(&envelope_topic(&message)?);
+        self.client
+            .publish(topic, self.qos, false, message)
+            .map_err(|e| UmbraError::transport(TransportOp::Publish, e))
+    }
+
+    fn recv(&self) -> Result<Option<Blob>, UmbraError> {
+        match self.received.lock().unwrap().try_recv() {
+            Ok(blob) => Ok(Some(blob)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(UmbraError::transport(
+                TransportOp::Receive,
+                "MQTT event loop stopped",
+            )),
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    fn connection_states(&self) -> Option<Receiver<ConnectionState>> {
+        let (tx, rx) = mpsc::channel();
+        self.state_listeners.lock().unwrap().push(tx);
+        Some(rx)
+    }
+
+    fn subscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        let topic = self.mqtt_topic(topic);
+        self.client
+            .subscribe(topic.clone(), self.qos)
+            .map_err(|e| UmbraError::transport(TransportOp::Subscribe, e))?;
+        self.subscriptions.lock().unwrap().insert(topic);
+        Ok(())
+    }
+
+    fn unsubscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        let topic = self.mqtt_topic(topic);
+        self.subscriptions.lock().unwrap().remove(&topic);
+        self.client
+            .unsubscribe(topic)
+            .map_err(|e| UmbraError::transport(TransportOp::Unsubscribe, e))
+    }
+}
@@ -0,0 +1,120 @@
+//! Wire format spoken between clients and an Umbra relay. Every WebSocket
+//! binary message carries exactly one frame:
+//!
+//! `kind: u8 | topic_len: u32 | topic | payload_len: u32 | payload`
+//!
+//! with lengths big endian. Subscribe and unsubscribe frames have an empty
+//! payload.
+
+use crate::error::FrameKind;
+use crate::{Blob, UmbraError};
+
+const KIND_PUBLISH: u8 = 1;
+const KIND_SUBSCRIBE: u8 = 2;
+const KIND_UNSUBSCRIBE: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelayFrame {
+    /// Sent by clients to publish, and by the relay to deliver.
+    Publish {
+        topic: String,
+        payload: Blob,
+    },
+    Subscribe {
+        topic: String,
+    },
+    Unsubscribe {
+        topic: String,
+    },
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn take_bytes<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], UmbraError> {
+    let truncated = || UmbraError::malformed(FrameKind::Relay, "truncated relay frame");
+    let (len, rest) = buf.split_first_chunk::<4>().ok_or_else(truncated)?;
+    let len = u32::from_be_bytes(*len) as usize;
+    if rest.len() < len {
+        return Err(truncated());
+    }
+    let (bytes, rest) = rest.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+impl RelayFrame {
+    pub fn encode(&self) -> Vec<u8> {
+        let (kind, topic, payload) = match self {
+            RelayFrame::Publish { topic, payload } => (KIND_PUBLISH, topic, payload.as_slice()),
+            RelayFrame::Subscribe { topic } => (KIND_SUBSCRIBE, topic, &[][..]),
+            RelayFrame::Unsubscribe { topic } => (KIND_UNSUBSCRIBE, topic, &[][..]),
+        };
+
+        let mut buf = Vec::with_capacity(9 + topic.len() + payload.len());
+        buf.push(kind);
+        put_bytes(&mut buf, topic.as_bytes());
+        put_bytes(&mut buf, payload);
+        buf
+    }
+
+    pub fn decode(mut buf: &[u8]) -> Result<Self, UmbraError> {
+        let (&kind, rest) = buf
+            .split_first()
+            .ok_or_else(|| UmbraError::malformed(FrameKind::Relay, "empty relay frame"))?;
+        buf = rest;
+
+        let topic = String::from_utf8(take_bytes(&mut buf)?.to_vec())
+            .map_err(UmbraError::decoding(FrameKind::Relay))?;
+        let payload = take_bytes(&mut buf)?.to_vec();
+
+        match kind {
+            KIND_PUBLISH => Ok(RelayFrame::Publish { topic, payload }),
+            KIND_SUBSCRIBE => Ok(RelayFrame::Subscribe { topic }),
+            KIND_UNSUBSCRIBE => Ok(RelayFrame::Unsubscribe { topic }),
+            other => Err(UmbraError::decoding(FrameKind::Relay)(format!(
+                "unknown relay frame kind {}",
+                other
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip() {
+        let frames = [
+            RelayFrame::Publish {
+                topic: "/private/a|b".into(),
+                payload: vec![1, 2, 3],
+            },
+            RelayFrame::Subscribe {
+                topic: "/inbox/a".into(),
+            },
+            RelayFrame::Unsubscribe {
+                topic: "/inbox/a".into(),
+            },
+        ];
+
+        for frame in frames {
+            assert_eq!(RelayFrame::decode(&frame.encode()).unwrap(), frame);
+        }
+    }
+
+    #[test]
+    fn truncated_frames_are_rejected() {
+        let bytes = RelayFrame::Publish {
+            topic: "t".into(),
+            payload: vec![1, 2, 3],
+        }
+        .encode();
+
+        assert!(RelayFrame::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(RelayFrame::decode(&[]).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::Mutex;
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::ConnectionState;
+use crate::utils::now_millis;
+use crate::{Blob, DeliveryService, UmbraError};
+
+// Extra delay applied to envelopes picked for reordering
+const REORDER_DELAY: Duration = Duration::from_millis(200);
+
+/// Delay distribution for simulated envelopes.
+#[derive(Debug, Clone, Default)]
+pub enum Latency {
+    #[default]
+    None,
+    Fixed(Duration),
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+    /// Mostly fast with a long tail, as seen on real networks.
+    Exponential {
+        mean: Duration,
+    },
+}
+
+/// Adverse conditions applied by `SimulatedDeliveryService`. Rates are
+/// probabilities between 0 and 1.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkConditions {
+    pub latency: Latency,
+    pub drop_rate: f64,
+    pub duplicate_rate: f64,
+    /// Chance an envelope is held back so later ones overtake it.
+    pub reorder_rate: f64,
+    /// Seed the random source so a run can be replayed exactly.
+    pub seed: Option<u64>,
+}
+
+struct InFlight {
+    // Unix ms, so delays follow a test harness's virtual clock
+    deliver_at: u64,
+    seq: u64,
+    blob: Blob,
+}
+
+impl PartialEq for InFlight {
+    fn eq(&self, other: &Self) -> bool {
+        (self.deliver_at, self.seq) == (other.deliver_at, other.seq)
+    }
+}
+
+impl Eq for InFlight {}
+
+impl PartialOrd for InFlight {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InFlight {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.deliver_at, self.seq).cmp(&(other.deliver_at, other.seq))
+    }
+}
+
+struct SimState {
+    conditions: NetworkConditions,
+    rng: StdRng,
+    seq: u64,
+    dropped: u64,
+    in_flight: BinaryHeap<Reverse<InFlight>>,
+}
+
+/// Wraps a DeliveryService and subjects outgoing envelopes to latency,
+/// loss, duplication and reordering, to exercise the reliability layer.
+///
+/// Delayed envelopes are handed to the inner service the next time `send` or
+/// `recv` is called after they fall due.
+pub struct SimulatedDeliveryService<D: DeliveryService> {
+    inner: D,
+    state: Mutex<SimState>,
+}
+
+impl<D: DeliveryService> SimulatedDeliveryService<D> {
+    pub fn new(inner: D, conditions: NetworkConditions) -> Self {
+        let rng = match conditions.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        Self {
+            inner,
+            state: Mutex::new(SimState {
+                conditions,
+                rng,
+                seq: 0,
+                dropped: 0,
+                in_flight: BinaryHeap::new(),
+            }),
+        }
+    }
+
+    /// Apply `conditions` to envelopes sent from now on, e.g. to heal the
+    /// network. The random source is not reseeded.
+    pub fn set_conditions(&self, conditions: NetworkConditions) {
+        self.state.lock().unwrap().conditions = conditions;
+    }
+
+    /// Envelopes dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.state.lock().unwrap().dropped
+    }
+
+    fn sample_latency(latency: &Latency, rng: &mut StdRng) -> Duration {
+        match *latency {
+            Latency::None => Duration::ZERO,
+            Latency::Fixed(d) => d,
+            Latency::Uniform { min, max } if max > min => rng.random_range(min..=max),
+            Latency::Uniform { min, .. } => min,
+            Latency::Exponential { mean } => {
+                let u: f64 = rng.random_range(f64::EPSILON..1.0);
+                mean.mul_f64(-u.ln())
+            }
+        }
+    }
+
+    // Forward every envelope whose delay has elapsed
+    fn pump(&self) -> Result<(), UmbraError> {
+        let now = now_millis();
+        let mut due = vec![];
+        {
+            let mut state = self.state.lock().unwrap();
+            while state
+                .in_flight
+                .peek()
+                .is_some_and(|Reverse(m)| m.deliver_at <= now)
+            {
+                if let Some(Reverse(m)) = state.in_flight.pop() {
+                    due.push(m.blob);
+                }
+            }
+        }
+
+        for blob in due {
+            self.inner.send(blob)?;
+        }
+        Ok(())
+    }
+}
+
+impl<D: DeliveryService> DeliveryService for SimulatedDeliveryService<D> {
+    fn send(&self, message: Blob) -> Result<(), UmbraError> {
+        {
+            let mut state = self.state.lock().unwrap();
+            let state = &mut *state;
+            let conditions = &state.conditions;
+            if state.rng.random_bool(conditions.drop_rate.clamp(0.0, 1.0)) {
+                state.dropped += 1;
+                return Ok(());
+            }
+
+            let copies = if state
+                .rng
+                .random_bool(conditions.duplicate_rate.clamp(0.0, 1.0))
+            {
+                2
+            } else {
+                1
+            };
+            for _ in 0..copies {
+                let mut delay = Self::sample_latency(&conditions.latency, &mut state.rng);
+                if state
+                    .rng
+                    .random_bool(conditions.reorder_rate.clamp(0.0, 1.0))
+                {
+                    delay += REORDER_DELAY;
+                }
+                state.seq += 1;
+                let seq = state.seq;
+                state.in_flight.push(Reverse(InFlight {
+                    deliver_at: now_millis() + delay.as_millis() as u64,
+                    seq,
+                    blob: message.clone(),
+                }));
+            }
+        }
+        self.pump()
+    }
+
+    fn recv(&self) -> Result<Option<Blob>, UmbraError> {
+        self.pump()?;
+        self.inner.recv()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn connection_states(&self) -> Option<Receiver<ConnectionState>> {
+        self.inner.connection_states()
+    }
+
+    fn subscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        self.inner.subscribe(topic)
+    }
+
+    fn unsubscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        self.inner.unsubscribe(topic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+    use umbra_types::base::UmbraEnvelopeV1;
+
+    use super::*;
+    use crate::transport::LocalBroker;
+
+    fn envelope(n: u8) -> Blob {
+        UmbraEnvelopeV1 {
+            conversation_hint: "/topic".into(),
+            salt: 0,
+            payload: vec![n],
+        }
+        .encode_to_vec()
+    }
+
+    fn received(sim: &SimulatedDeliveryService<impl DeliveryService>) -> Vec<Blob> {
+        std::iter::from_fn(|| sim.recv().unwrap()).collect()
+    }
+
+    fn simulate(conditions: NetworkConditions) -> SimulatedDeliveryService<impl DeliveryService> {
+        let inner = LocalBroker::new().connect();
+        inner.subscribe("/topic").unwrap();
+        SimulatedDeliveryService::new(inner, conditions)
+    }
+
+    #[test]
+    fn drops_and_duplicates() {
+        let lossy = simulate(NetworkConditions {
+            drop_rate: 1.0,
+            ..Default::default()
+        });
+        lossy.send(envelope(1)).unwrap();
+        assert!(received(&lossy).is_empty());
+
+        let noisy = simulate(NetworkConditions {
+            duplicate_rate: 1.0,
+            ..Default::default()
+        });
+        noisy.send(envelope(1)).unwrap();
+        assert_eq!(received(&noisy).len(), 2);
+    }
+
+    #[test]
+    fn seeded_runs_are_repeatable() {
+        let run = || {
+            let sim = simulate(NetworkConditions {
+                drop_rate: 0.5,
+                seed: Some(7),
+                ..Default::default()
+            });
+            for n in 0..32 {
+                sim.send(envelope(n)).unwrap();
+            }
+            received(&sim)
+        };
+
+        assert_eq!(run(), run());
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use super::envelope_topic;
+use crate::error::TransportOp;
+use crate::utils::{generate_random_string, now_millis};
+use crate::{Blob, DeliveryService, UmbraError, crypto};
+
+// Minimum time between polls of the node for new messages
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How the client takes part in the Waku network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakuMode {
+    /// Publish and receive through relay. The node must run relay.
+    Relay,
+    /// Publish through light push and receive through filter, for
+    /// resource restricted clients.
+    Light,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WakuMessage {
+    payload: String,
+    content_topic: String,
+    #[serde(default)]
+    timestamp: Option<u64>,
+}
+
+/// DeliveryService backed by the REST API of a nwaku node.
+///
+/// Umbra topics are hashed into Waku content topics of the form
+/// `/umbra/1/<hash>/proto`, keeping participant addresses off the network.
+pub struct WakuDeliveryService {
+    node_url: String,
+    mode: WakuMode,
+    agent: ureq::Agent,
+    content_topics: Mutex<HashSet<String>>,
+    received: Mutex<VecDeque<Blob>>,
+    last_poll: Mutex<Option<Instant>>,
+    connected: AtomicBool,
+}
+
+impl WakuDeliveryService {
+    /// Connect to the node REST API at `node_url`, e.g. `http://127.0.0.1:8645`.
+    pub fn new(node_url: &str, mode: WakuMode) -> Self {
+        Self {
+            node_url: node_url.trim_end_matches('/').to_string(),
+            mode,
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+            content_topics: Mutex::new(HashSet::new()),
+            received: Mutex::new(VecDeque::new()),
+            last_poll: Mutex::new(None),
+            connected: AtomicBool::new(true),
+        }
+    }
+
+    /// The Waku content topic an Umbra topic is mapped to.
+    pub fn content_topic(topic: &str) -> String {
+        format!("/umbra/1/{}/proto", crypto::hash_to_string(topic))
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.node_url, path)
+    }
+
+    // Content topics are path segments in the message endpoints
+    fn topic_url(&self, path: &str, content_topic: &str) -> String {
+        self.url(&format!("{}/{}", path, content_topic.replace('/', "%2F")))
+    }
+
+    // Track reachability so sends are queued while the node is down
+    fn track<T>(&self, res: Result<T, ureq::Error>) -> Result<T, ureq::Error> {
+        let reachable = !matches!(res, Err(ureq::Error::Transport(_)));
+        if self.connected.swap(reachable, Ordering::Relaxed) != reachable {
+            debug!("Waku node reachable: {}", reachable);
+        }
+        res
+    }
+
+    fn poll(&self) -> Result<(), UmbraError> {
+        let path = match self.mode {
+            WakuMode::Relay => "/relay/v1/auto/messages",
+            WakuMode::Light => "/filter/v2/messages",
+        };
+
+        let topics: Vec<String> = self
+            .content_topics
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect();
+        for content_topic in topics {
+            let res = self
+                .track(self.agent.get(&self.topic_url(path, &content_topic)).call())
+                .map_err(|e| UmbraError::transport(TransportOp::Receive, e))?;
+            let messages: Vec<WakuMessage> = res
+                .into_json()
+                .map_err(|e| UmbraError::transport(TransportOp::Receive, e))?;
+
+            let mut received = self.received.lock().unwrap();
+            for message in messages {
+                match STANDARD.decode(&message.payload) {
+                    Ok(payload) => received.push_back(payload),
+                    Err(e) => warn!("Dropping undecodable Waku payload: {}", e),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DeliveryService for WakuDeliveryService {
+    fn send(&self, message: Blob) -> Result<(), UmbraError> {
+        let waku_message = WakuMessage {
+            payload: STANDARD.encode(&message),
+            content_topic: Self::content_topic(&envelope_topic(&message)?),
+            timestamp: Some(now_millis().saturating_mul(1_000_000)),
+        };
+
+        let req = match self.mode {
+            WakuMode::Relay => self
+                .agent
+                .post(&self.url("/relay/v1/auto/messages"))
+                .send_json(&waku_message),
+            WakuMode::Light => self
+                .agent
+                .post(&self.url("/lightpush/v1/message"))
+                .send_json(json!({ "message": waku_message })),
+        };
+        self.track(req)
+            .map(|_| ())
+            .map_err(|e| UmbraError::transport(TransportOp::Publish, e))
+    }
+
+    fn recv(&self) -> Result<Option<Blob>, UmbraError> {
+        if let Some(blob) = self.received.lock().unwrap().pop_front() {
+            return Ok(Some(blob));
+        }
+
+        {
+            let mut last_poll = self.last_poll.lock().unwrap();
+            if last_poll.is_some_and(|t| t.elapsed() < POLL_INTERVAL) {
+                return Ok(None);
+            }
+            *last_poll = Some(Instant::now());
+        }
+
+        self.poll()?;
+        Ok(self.received.lock().unwrap().pop_front())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    fn subscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        let content_topic = Self::content_topic(topic);
+        let req = match self.mode {
+            WakuMode::Relay => self
+                .agent
+                .post(&self.url("/relay/v1/auto/subscriptions"))
+                .send_json(json!([content_topic])),
+            WakuMode::Light => self
+                .agent
+                .post(&self.url("/filter/v2/subscriptions"))
+                .send_json(json!({
+                    "requestId": generate_random_string(16),
+                    "contentFilters": [content_topic],
+                })),
+        };
+        self.track(req)
+            .map_err(|e| UmbraError::transport(TransportOp::Subscribe, e))?;
+
+        self.content_topics.lock().unwrap().insert(content_topic);
+        Ok(())
+    }
+
+    fn unsubscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        let content_topic = Self::content_topic(topic);
+        let req = match self.mode {
+            WakuMode::Relay => self
+                .agent
+                .delete(&self.url("/relay/v1/auto/subscriptions"))
+                .send_json(json!([content_topic])),
+            WakuMode::Light => self
+                .agent
+                .delete(&self.url("/filter/v2/subscriptions"))
+                .send_json(json!({
+                    "requestId": generate_random_string(16),
+                    "contentFilters": [content_topic],
+                })),
+        };
+        self.content_topics.lock().unwrap().remove(&content_topic);
+        self.track(req)
+            .map(|_| ())
+            .map_err(|e| UmbraError::transport(TransportOp::Unsubscribe, e))
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, warn};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use super::relay::RelayFrame;
+use super::{ConnectionState, envelope_topic};
+use crate::error::TransportOp;
+use crate::{Blob, DeliveryService, UmbraError};
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+// How long a read blocks before the worker services outgoing frames
+const READ_TIMEOUT: Duration = Duration::from_millis(50);
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+// Without a pong for this long the connection is reported degraded
+const DEGRADED_AFTER: Duration = Duration::from_secs(30);
+// Without a pong for this long the connection is considered dead
+const PONG_TIMEOUT: Duration = Duration::from_secs(45);
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<Option<ConnectionState>>,
+    listeners: Mutex<Vec<Sender<ConnectionState>>>,
+    subscriptions: Mutex<HashSet<String>>,
+}
+
+impl Shared {
+    fn set_state(&self, state: ConnectionState) {
+        let mut current = self.state.lock().unwrap();
+        if current.as_ref() == Some(&state) {
+            return;
+        }
+        debug!("Relay connection: {:?}", state);
+        *current = Some(state.clone());
+        self.listeners
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(state.clone()).is_ok());
+    }
+}
+
+/// DeliveryService connected to an Umbra relay over WebSocket.
+///
+/// A background worker owns the socket: it keeps the connection alive with
+/// pings, reconnects with exponential backoff and restores subscriptions
+/// after every reconnect.
+pub struct WebSocketDeliveryService {
+    shared: Arc<Shared>,
+    outgoing: Sender<RelayFrame>,
+    received: Mutex<Receiver<Blob>>,
+}
+
+impl WebSocketDeliveryService {
+    /// Connect to the relay at `url`, e.g. `ws://127.0.0.1:9000`.
+    pub fn connect(url: &str) -> Self {
+        let shared = Arc::new(Shared::default());
+        let (outgoing, outgoing_rx) = mpsc::channel();
+        let (received_tx, received) = mpsc::channel();
+
+        let worker = Worker {
+            url: url.to_string(),
+            shared: shared.clone(),
+            outgoing: outgoing_rx,
+            received: received_tx,
+        };
+        thread::spawn(move || worker.run());
+
+        Self {
+            shared,
+            outgoing,
+            received: Mutex::new(received),
+        }
+    }
+
+    /// Streams connection state changes, starting with the current state.
+    pub fn watch_state(&self) -> Receiver<ConnectionState> {
+        let (tx, rx) = mpsc::channel();
+        if let Some(state) = self.shared.state.lock().unwrap().clone() {
+            let _ = tx.send(state);
+        }
+        self.shared.listeners.lock().unwrap().push(tx);
+        rx
+    }
+
+    fn queue(&self, frame: RelayFrame) -> Result<(), UmbraError> {
+        let op = match frame {
+            RelayFrame::Publish { .. } => TransportOp::Publish,
+            RelayFrame::Subscribe { .. } => TransportOp::Subscribe,
+            RelayFrame::Unsubscribe { .. } => TransportOp::Unsubscribe,
+        };
+        self.outgoing
+            .send(frame)
+            .map_err(|_| UmbraError::transport(op, "relay worker stopped"))
+    }
+}
+
+impl DeliveryService for WebSocketDeliveryService {
+    fn send(&self, message: Blob) -> Result<(), UmbraError> {
+        if !self.is_connected() {
+            return Err(UmbraError::Disconnected);
+        }
+        self.queue(RelayFrame::Publish {
+            topic: envelope_topic(&message)?,
+            payload: message,
+        })
+    }
+
+    fn recv(&self) -> Result<Option<Blob>, UmbraError> {
+        match self.received.lock().unwrap().try_recv() {
+            Ok(blob) => Ok(Some(blob)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(UmbraError::transport(
+                TransportOp::Receive,
+                "relay worker stopped",
+            )),
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        matches!(
+            *self.shared.state.lock().unwrap(),
+            Some(ConnectionState::Connected | ConnectionState::Degraded)
+        )
+    }
+
+    fn connection_states(&self) -> Option<Receiver<ConnectionState>> {
+        Some(self.watch_state())
+    }
+
+    fn subscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        self.shared
+            .subscriptions
+            .lock()
+            .unwrap()
+            .insert(topic.to_string());
+        self.queue(RelayFrame::Subscribe {
+            topic: topic.to_string(),
+        })
+    }
+
+    fn unsubscribe(&self, topic: &str) -> Result<(), UmbraError> {
+        self.shared.subscriptions.lock().unwrap().remove(topic);
+        self.queue(RelayFrame::Unsubscribe {
+            topic: topic.to_string(),
+        })
+    }
+}
+
+struct Worker {
+    url: String,
+    shared: Arc<Shared>,
+    outgoing: Receiver<RelayFrame>,
+    received: Sender<Blob>,
+}
+
+impl Worker {
+    fn run(self) {
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            let reason = match tungstenite::connect(&self.url) {
+                Ok((socket, _)) => {
+                    backoff = INITIAL_BACKOFF;
+                    match self.serve(socket) {
+                        Ok(()) => return,
+                        Err(reason) => reason,
+                    }
+                }
+                Err(e) => e.to_string(),
+            };
+
+            warn!("Relay connection lost: {}", reason);
+            self.shared
+                .set_state(ConnectionState::Disconnected { reason });
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    // Pumps frames both ways until the connection fails. Returns Ok once the
+    // service has been dropped and the worker should exit.
+    fn serve(&self, mut socket: Socket) -> Result<(), String> {
+        if let MaybeTlsStream::Plain(stream) = socket.get_mut() {
+            stream
+                .set_read_timeout(Some(READ_TIMEOUT))
+                .map_err(|e| e.to_string())?;
+        }
+
+        // Subscriptions do not survive a reconnect
+        let topics: Vec<String> = self
+            .shared
+            .subscriptions
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect();
+        for topic in topics {
+            Self::write(&mut socket, &RelayFrame::Subscribe { topic })?;
+        }
+        self.shared.set_state(ConnectionState::Connected);
+
+        let mut last_ping = Instant::now();
+        let mut last_pong = Instant::now();
+        loop {
+            loop {
+                match self.outgoing.try_recv() {
+                    Ok(frame) => Self::write(&mut socket, &frame)?,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        let _ = socket.close(None);
+                        return Ok(());
+                    }
+                }
+            }
+
+            if last_ping.elapsed() >= PING_INTERVAL {
+                socket
+                    .send(Message::Ping(vec![]))
+                    .map_err(|e| e.to_string())?;
+                last_ping = Instant::now();
+            }
+            if last_pong.elapsed() >= PONG_TIMEOUT {
+                return Err("keepalive timed out".into());
+            }
+            if last_pong.elapsed() >= DEGRADED_AFTER {
+                self.shared.set_state(ConnectionState::Degraded);
+            }
+
+            match socket.read() {
+                Ok(Message::Binary(bytes)) => match RelayFrame::decode(&bytes) {
+                    Ok(RelayFrame::Publish { payload, .. }) => {
+                        if self.received.send(payload).is_err() {
+                            let _ = socket.close(None);
+                            return Ok(());
+                        }
+                    }
+                    Ok(frame) => warn!("Unexpected frame from relay: {:?}", frame),
+                    Err(e) => warn!("Dropping malformed relay frame: {}", e),
+                },
+                Ok(Message::Pong(_)) => {
+                    last_pong = Instant::now();
+                    self.shared.set_state(ConnectionState::Connected);
+                }
+                Ok(Message::Close(_)) => return Err("closed by relay".into()),
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e))
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+
+    fn write(socket: &mut Socket, frame: &RelayFrame) -> Result<(), String> {
+        socket
+            .send(Message::Binary(frame.encode()))
+            .map_err(|e| e.to_string())
+    }
+}
@@ -1,5 +1,11 @@
 use rand::{self, Rng, distr::Alphanumeric};
 
+// std's clocks panic on wasm32-unknown-unknown; web-time reads the browser's
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(target_arch = "wasm32")]
+pub use web_time::{Instant, SystemTime, UNIX_EPOCH};
+
 #[allow(dead_code)]
 pub fn generate_random_string(length: u8) -> String {
     rand::rng()
@@ -8,3 +14,22 @@ pub fn generate_random_string(length: u8) -> String {
         .map(char::from) // Convert each byte to a char
         .collect() // Collect into a String
 }
+
+#[cfg(feature = "testing")]
+thread_local! {
+    // Set by a test harness driving clients on this thread
+    pub(crate) static VIRTUAL_NOW: std::cell::Cell<Option<u64>> =
+        const { std::cell::Cell::new(None) };
+}
+
+// Wall clock time in milliseconds since the unix epoch
+pub fn now_millis() -> u64 {
+    #[cfg(feature = "testing")]
+    if let Some(now) = VIRTUAL_NOW.with(|now| now.get()) {
+        return now;
+    }
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,73 @@
+use std::sync::{
+    Mutex,
+    mpsc::{Receiver, Sender, channel},
+};
+
+/// A change to an observed collection.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diff<T> {
+    Added(T),
+    Updated(T),
+    Removed(T),
+}
+
+/// Fan-out of diffs to every live watcher. Watchers whose receiver has been
+/// dropped are pruned on the next notification.
+pub(crate) struct Watchers<T: Clone> {
+    senders: Mutex<Vec<Sender<Diff<T>>>>,
+}
+
+impl<T: Clone> Default for Watchers<T> {
+    fn default() -> Self {
+        Self {
+            senders: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<T: Clone> Watchers<T> {
+    // The returned stream starts with the current state as `Added` diffs so
+    // consumers never need a separate initial query.
+    pub fn watch<I: IntoIterator<Item = T>>(&self, current: I) -> Receiver<Diff<T>> {
+        let (tx, rx) = channel();
+        for item in current {
+            let _ = tx.send(Diff::Added(item));
+        }
+        self.senders.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub fn notify(&self, diff: Diff<T>) {
+        self.senders
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(diff.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watch_replays_current_state() {
+        let watchers = Watchers::default();
+        let rx = watchers.watch(vec![1, 2]);
+        watchers.notify(Diff::Updated(2));
+
+        let diffs: Vec<Diff<u32>> = rx.try_iter().collect();
+        assert_eq!(
+            diffs,
+            vec![Diff::Added(1), Diff::Added(2), Diff::Updated(2)]
+        );
+    }
+
+    #[test]
+    fn test_dropped_watchers_are_pruned() {
+        let watchers = Watchers::default();
+        drop(watchers.watch(Vec::<u32>::new()));
+        watchers.notify(Diff::Added(1));
+
+        assert!(watchers.senders.lock().unwrap().is_empty());
+    }
+}
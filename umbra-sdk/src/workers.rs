@@ -0,0 +1,84 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use tracing::{Level, error, span};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Fixed set of threads running jobs keyed by conversation. Jobs with the
+/// same key always run on the same thread, in the order they were
+/// dispatched; jobs with different keys may run in parallel.
+pub(crate) struct WorkerPool {
+    workers: Vec<Sender<Job>>,
+}
+
+impl WorkerPool {
+    pub fn new(size: usize, addr: &str) -> Self {
+        let workers = (0..size.max(1))
+            .map(|id| {
+                let (tx, rx) = mpsc::channel::<Job>();
+                let addr = addr.to_string();
+                thread::spawn(move || {
+                    let span = span!(Level::INFO, "RecvWorker", addr = addr, id = id);
+                    let _enter = span.enter();
+                    // Exits once the pool, and so the sender, is dropped.
+                    // Jobs report their own failures; a panic must not stop
+                    // the worker and every conversation it serves with it
+                    for job in rx {
+                        if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                            error!("Receive job panicked");
+                        }
+                    }
+                });
+                tx
+            })
+            .collect();
+        Self { workers }
+    }
+
+    pub fn dispatch(&self, key: &str, job: impl FnOnce() + Send + 'static) {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let worker = hasher.finish() as usize % self.workers.len();
+        // Workers only stop when the pool is dropped
+        let _ = self.workers[worker].send(Box::new(job));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn jobs_with_the_same_key_run_in_order() {
+        let pool = WorkerPool::new(4, "test");
+        let seen = Arc::new(Mutex::new(vec![]));
+        let (done_tx, done_rx) = mpsc::channel();
+        for i in 0..100 {
+            let seen = seen.clone();
+            let done_tx = done_tx.clone();
+            pool.dispatch("convo", move || {
+                seen.lock().unwrap().push(i);
+                let _ = done_tx.send(());
+            });
+        }
+        for _ in 0..100 {
+            done_rx.recv().unwrap();
+        }
+        assert_eq!(*seen.lock().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn workers_survive_panicking_jobs() {
+        let pool = WorkerPool::new(1, "test");
+        let (done_tx, done_rx) = mpsc::channel();
+        pool.dispatch("convo", || panic!("bad envelope"));
+        pool.dispatch("convo", move || done_tx.send(()).unwrap());
+        done_rx.recv().unwrap();
+    }
+}